@@ -2,7 +2,7 @@ use std::env;
 
 use askama::Template;
 use axum::{
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{Html, IntoResponse},
     routing::get,
     Json, Router,
@@ -10,6 +10,7 @@ use axum::{
 use axum_extra::extract::CookieJar;
 use serde::Serialize;
 use tower_http::services::ServeDir;
+use uuid::Uuid;
 
 #[tokio::main]
 async fn main() {
@@ -46,7 +47,7 @@ async fn root() -> impl IntoResponse {
     Html(template.render().unwrap())
 }
 
-async fn protected(jar: CookieJar) -> impl IntoResponse {
+async fn protected(jar: CookieJar, headers: HeaderMap) -> impl IntoResponse {
     let jwt_cookie = match jar.get("jwt") {
         Some(cookie) => cookie,
         None => {
@@ -63,7 +64,15 @@ async fn protected(jar: CookieJar) -> impl IntoResponse {
     let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
     let url = format!("http://{}:3000/verify-token", auth_hostname);
 
-    let response = match api_client.post(&url).json(&verify_token_body).send().await {
+    let (traceparent, request_id) = trace_headers(&headers);
+    let response = match api_client
+        .post(&url)
+        .header("traceparent", traceparent)
+        .header("x-request-id", request_id)
+        .json(&verify_token_body)
+        .send()
+        .await
+    {
         Ok(response) => response,
         Err(_) => {
             return StatusCode::INTERNAL_SERVER_ERROR.into_response();
@@ -82,6 +91,27 @@ async fn protected(jar: CookieJar) -> impl IntoResponse {
     }
 }
 
+// The W3C `traceparent` and `X-Request-Id` to send to auth-service, so its spans and logs for
+// the call belong to the same trace as this request. They are passed on from the caller, or
+// started here when it didn't send any.
+fn trace_headers(incoming: &HeaderMap) -> (String, String) {
+    let header = |name: &str| {
+        incoming
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+    };
+
+    let traceparent = header("traceparent").unwrap_or_else(|| {
+        let trace_id = Uuid::new_v4().simple().to_string();
+        let span_id = Uuid::new_v4().simple().to_string();
+        format!("00-{}-{}-01", trace_id, &span_id[..16])
+    });
+    let request_id = header("x-request-id").unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    (traceparent, request_id)
+}
+
 #[derive(Serialize)]
 pub struct ProtectedRouteResponse {
     pub img_url: String,
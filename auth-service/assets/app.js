@@ -6,6 +6,14 @@ const signupLink = document.getElementById("signup-link");
 const twoFALoginLink = document.getElementById("2fa-login-link");
 const signupLoginLink = document.getElementById("signup-login-link");
 
+// Errors come back as problem+json. Invalid fields are listed one by one.
+function problemMessage(problem) {
+    if (Array.isArray(problem.errors) && problem.errors.length > 0) {
+        return problem.errors.map(error => `${error.field}: ${error.message}`).join("<br>");
+    }
+    return problem.detail;
+}
+
 signupLink.addEventListener("click", (e) => {
     e.preventDefault();
 
@@ -69,7 +77,7 @@ loginButton.addEventListener("click", (e) => {
             alert("You have successfully logged in.");
         } else {
             response.json().then(data => {
-                let error_msg = data.error;
+                let error_msg = problemMessage(data);
                 if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
                     loginErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
                     loginErrAlter.style.display = "block";
@@ -81,6 +89,37 @@ loginButton.addEventListener("click", (e) => {
     });
 });
 
+const magicLinkLink = document.getElementById("magic-link-link");
+
+magicLinkLink.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = loginForm.email.value;
+
+    fetch('/login/magic-link', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email }),
+    }).then(response => {
+        response.json().then(data => {
+            if (response.ok) {
+                loginErrAlter.style.display = "none";
+                alert(data.message);
+            } else {
+                let error_msg = problemMessage(data);
+                if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
+                    loginErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
+                    loginErrAlter.style.display = "block";
+                } else {
+                    loginErrAlter.style.display = "none";
+                }
+            }
+        });
+    });
+});
+
 const signupForm = document.getElementById("signup-form");
 const signupButton = document.getElementById("signup-form-submit");
 const signupErrAlter = document.getElementById("signup-err-alert");
@@ -110,7 +149,7 @@ signupButton.addEventListener("click", (e) => {
             signupSection.style.display = "none";
         } else {
             response.json().then(data => {
-                let error_msg = data.error;
+                let error_msg = problemMessage(data);
                 if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
                     signupErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
                     signupErrAlter.style.display = "block";
@@ -151,7 +190,7 @@ TwoFAButton.addEventListener("click", (e) => {
             signupSection.style.display = "none";
         } else {
             response.json().then(data => {
-                let error_msg = data.error;
+                let error_msg = problemMessage(data);
                 if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
                     TwoFAErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
                     TwoFAErrAlter.style.display = "block";
@@ -0,0 +1,143 @@
+// Logs in many users at once against the in-memory stores, to show how login throughput
+// scales with concurrent requests now that none of the stores are behind a global lock.
+//
+//     cargo bench --bench concurrent_logins
+
+use std::{collections::HashMap, sync::Arc};
+
+use auth_service::{
+    app_state::{AppState, EmailClientType, UserStoreType},
+    domain::{Email, Password, User},
+    services::{
+        data_stores::{
+            HashmapEmailOutboxStore, HashmapPasskeyStore, HashmapPhoneVerificationStore,
+            HashmapSessionStore, HashmapTwoFACodeStore, HashmapUserStore, HashmapWebhookStore,
+            HashsetBannedTokenStore, VecAuditLogStore,
+        },
+        MockEmailClient, MockSmsClient,
+    },
+    settings::Settings,
+    utils::{
+        constants::test, metrics::prometheus_handle, shutdown::Shutdown, webauthn::build_webauthn,
+    },
+    Application,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use secrecy::Secret;
+use tokio::runtime::Runtime;
+
+const USERS: usize = 256;
+const CONCURRENCY: [usize; 4] = [1, 16, 64, 256];
+const PASSWORD: &str = "password123";
+
+async fn settings() -> Settings {
+    let vars: HashMap<String, String> = [
+        ("JWT_SECRET", "bench-secret"),
+        ("DATABASE_URL", "postgres://localhost"),
+        ("EMAIL_PROVIDERS", "mock"),
+        ("SMS_PROVIDER", "mock"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_owned(), value.to_owned()))
+    .collect();
+
+    let mut settings = Settings::from_env(vars)
+        .await
+        .expect("Failed to load settings");
+    settings.application.address = test::APP_ADDRESS.to_owned();
+    settings
+}
+
+// Starts the service with every store in memory and returns its address and the users'
+// emails. Half the users log in with 2FA, so the 2FA store and the email client see traffic too.
+async fn start_app() -> (String, Vec<String>) {
+    let settings = Arc::new(settings().await);
+
+    let user_store: UserStoreType = Arc::new(HashmapUserStore::default());
+    let mut emails = Vec::with_capacity(USERS);
+    for i in 0..USERS {
+        let email = format!("user{}@example.com", i);
+        let user = User::new(
+            Email::parse(Secret::new(email.clone())).unwrap(),
+            Password::parse(Secret::new(PASSWORD.to_owned())).unwrap(),
+            i % 2 == 0,
+        );
+        user_store.add_user(user).await.unwrap();
+        emails.push(email);
+    }
+
+    let email_client: EmailClientType = Arc::new(MockEmailClient);
+    let app_state = AppState {
+        user_store,
+        banned_token_store: Arc::new(HashsetBannedTokenStore::default()),
+        two_fa_code_store: Arc::new(HashmapTwoFACodeStore::default()),
+        session_store: Arc::new(HashmapSessionStore::default()),
+        passkey_store: Arc::new(HashmapPasskeyStore::default()),
+        audit_log_store: Arc::new(VecAuditLogStore::default()),
+        webhook_store: Arc::new(HashmapWebhookStore::default()),
+        email_outbox_store: Arc::new(HashmapEmailOutboxStore::default()),
+        email_client,
+        phone_verification_store: Arc::new(HashmapPhoneVerificationStore::default()),
+        sms_client: Arc::new(MockSmsClient),
+        health_checks: vec![],
+        shutdown: Shutdown::new(),
+        metrics: prometheus_handle(),
+        settings: settings.clone(),
+        webauthn: Arc::new(build_webauthn(&settings.application.base_url)),
+    };
+
+    let app = Application::build(app_state)
+        .await
+        .expect("Failed to build app");
+    let address = format!("http://{}", app.address);
+    tokio::spawn(app.run());
+
+    (address, emails)
+}
+
+async fn login_concurrently(
+    http_client: &reqwest::Client,
+    address: &str,
+    emails: &[String],
+    concurrency: usize,
+) {
+    let logins: Vec<_> = emails
+        .iter()
+        .take(concurrency)
+        .map(|email| {
+            let http_client = http_client.clone();
+            let url = format!("{}/login", address);
+            let body = serde_json::json!({ "email": email, "password": PASSWORD });
+            tokio::spawn(async move {
+                let response = http_client.post(url).json(&body).send().await.unwrap();
+                assert!(response.status().is_success(), "{}", response.status());
+            })
+        })
+        .collect();
+    for login in logins {
+        login.await.unwrap();
+    }
+}
+
+fn concurrent_logins(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("Failed to build the runtime");
+    let (address, emails) = runtime.block_on(start_app());
+    let http_client = reqwest::Client::new();
+
+    let mut group = c.benchmark_group("concurrent_logins");
+    for concurrency in CONCURRENCY {
+        group.throughput(Throughput::Elements(concurrency as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(concurrency),
+            &concurrency,
+            |b, &concurrency| {
+                b.to_async(&runtime)
+                    .iter(|| login_concurrently(&http_client, &address, &emails, concurrency))
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, concurrent_logins);
+criterion_main!(benches);
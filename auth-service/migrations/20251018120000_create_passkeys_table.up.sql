@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS passkeys(
+   credential_id BYTEA NOT NULL PRIMARY KEY,
+   email TEXT NOT NULL REFERENCES users(email) ON DELETE CASCADE ON UPDATE CASCADE,
+   public_key JSONB NOT NULL,
+   sign_count BIGINT NOT NULL DEFAULT 0,
+   passkey JSONB NOT NULL,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS passkeys_email_idx ON passkeys(email);
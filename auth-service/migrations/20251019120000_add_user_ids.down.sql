@@ -0,0 +1,13 @@
+ALTER TABLE passkeys ADD COLUMN email TEXT;
+UPDATE passkeys SET email = users.email FROM users WHERE users.id = passkeys.user_id;
+ALTER TABLE passkeys ALTER COLUMN email SET NOT NULL;
+ALTER TABLE passkeys DROP COLUMN user_id;
+
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+ALTER TABLE users DROP CONSTRAINT users_pkey;
+ALTER TABLE users ADD PRIMARY KEY (email);
+ALTER TABLE users DROP COLUMN id;
+
+ALTER TABLE passkeys
+   ADD CONSTRAINT passkeys_email_fkey FOREIGN KEY (email) REFERENCES users(email) ON DELETE CASCADE ON UPDATE CASCADE;
+CREATE INDEX IF NOT EXISTS passkeys_email_idx ON passkeys(email);
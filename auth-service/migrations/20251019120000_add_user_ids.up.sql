@@ -0,0 +1,19 @@
+-- Users get a stable UUID primary key. The email stays unique but is no longer the key,
+-- so it can change without touching anything that refers to the user.
+ALTER TABLE users ADD COLUMN id UUID;
+UPDATE users SET id = gen_random_uuid();
+ALTER TABLE users ALTER COLUMN id SET NOT NULL;
+
+-- Passkeys refer to the user id instead of the email.
+ALTER TABLE passkeys ADD COLUMN user_id UUID;
+UPDATE passkeys SET user_id = users.id FROM users WHERE users.email = passkeys.email;
+ALTER TABLE passkeys ALTER COLUMN user_id SET NOT NULL;
+ALTER TABLE passkeys DROP COLUMN email;
+
+ALTER TABLE users DROP CONSTRAINT users_pkey;
+ALTER TABLE users ADD PRIMARY KEY (id);
+ALTER TABLE users ADD CONSTRAINT users_email_key UNIQUE (email);
+
+ALTER TABLE passkeys
+   ADD CONSTRAINT passkeys_user_id_fkey FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
+CREATE INDEX IF NOT EXISTS passkeys_user_id_idx ON passkeys(user_id);
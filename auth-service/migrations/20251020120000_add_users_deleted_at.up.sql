@@ -0,0 +1,3 @@
+-- Deleted accounts are kept for a grace period so they can be restored, then purged.
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMPTZ;
+CREATE INDEX IF NOT EXISTS users_deleted_at_idx ON users(deleted_at) WHERE deleted_at IS NOT NULL;
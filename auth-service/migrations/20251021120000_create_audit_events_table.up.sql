@@ -0,0 +1,47 @@
+-- Append-only log of authentication events. Every row carries the hash of the row before it,
+-- see `AuditEvent` for how the hash is built.
+CREATE TABLE IF NOT EXISTS audit_events (
+   id BIGINT PRIMARY KEY,
+   created_at TIMESTAMPTZ NOT NULL,
+   event_type TEXT NOT NULL,
+   user_id UUID,
+   ip_address TEXT,
+   user_agent TEXT,
+   request_id TEXT,
+   outcome TEXT NOT NULL,
+   reason TEXT,
+   pii_salt TEXT,
+   pii_digest TEXT NOT NULL,
+   prev_hash TEXT NOT NULL,
+   hash TEXT NOT NULL UNIQUE
+);
+
+CREATE INDEX IF NOT EXISTS audit_events_user_id_idx ON audit_events(user_id);
+CREATE INDEX IF NOT EXISTS audit_events_event_type_created_at_idx ON audit_events(event_type, created_at);
+CREATE INDEX IF NOT EXISTS audit_events_created_at_idx ON audit_events(created_at);
+
+-- Rows can't be deleted or changed. The only update allowed is anonymization, which clears
+-- the personal data and its salt and leaves everything else as it was.
+CREATE OR REPLACE FUNCTION audit_events_append_only() RETURNS TRIGGER AS $$
+BEGIN
+   IF TG_OP = 'UPDATE'
+      AND NEW.user_id IS NULL
+      AND NEW.ip_address IS NULL
+      AND NEW.user_agent IS NULL
+      AND NEW.pii_salt IS NULL
+      AND (NEW.id, NEW.created_at, NEW.event_type, NEW.request_id, NEW.outcome, NEW.reason,
+           NEW.pii_digest, NEW.prev_hash, NEW.hash)
+          IS NOT DISTINCT FROM
+          (OLD.id, OLD.created_at, OLD.event_type, OLD.request_id, OLD.outcome, OLD.reason,
+           OLD.pii_digest, OLD.prev_hash, OLD.hash)
+   THEN
+      RETURN NEW;
+   END IF;
+
+   RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+   BEFORE UPDATE OR DELETE ON audit_events
+   FOR EACH ROW EXECUTE FUNCTION audit_events_append_only();
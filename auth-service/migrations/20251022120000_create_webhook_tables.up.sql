@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS webhook_subscriptions(
+   id UUID PRIMARY KEY,
+   url TEXT NOT NULL,
+   secret TEXT NOT NULL,
+   event_types TEXT[] NOT NULL,
+   created_at TIMESTAMPTZ NOT NULL
+);
+
+-- The payload is stored as text rather than JSONB so the exact bytes that were signed are
+-- sent on every attempt.
+CREATE TABLE IF NOT EXISTS webhook_deliveries(
+   id UUID PRIMARY KEY,
+   subscription_id UUID NOT NULL REFERENCES webhook_subscriptions(id) ON DELETE CASCADE,
+   event_id UUID NOT NULL,
+   event_type TEXT NOT NULL,
+   payload TEXT NOT NULL,
+   status TEXT NOT NULL,
+   attempts INTEGER NOT NULL DEFAULT 0,
+   next_attempt_at TIMESTAMPTZ NOT NULL,
+   last_error TEXT,
+   created_at TIMESTAMPTZ NOT NULL,
+   delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_due_idx ON webhook_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS webhook_deliveries_status_created_at_idx ON webhook_deliveries(status, created_at);
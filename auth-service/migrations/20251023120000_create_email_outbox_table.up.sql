@@ -0,0 +1,18 @@
+-- Every email goes through here. The content is cleared once an email is sent, since it can
+-- carry a 2FA code or a login link.
+CREATE TABLE IF NOT EXISTS email_outbox(
+   id UUID PRIMARY KEY,
+   kind TEXT NOT NULL,
+   recipient TEXT NOT NULL,
+   subject TEXT NOT NULL,
+   content TEXT NOT NULL,
+   status TEXT NOT NULL,
+   attempts INTEGER NOT NULL DEFAULT 0,
+   next_attempt_at TIMESTAMPTZ NOT NULL,
+   last_error TEXT,
+   created_at TIMESTAMPTZ NOT NULL,
+   sent_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS email_outbox_due_idx ON email_outbox(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS email_outbox_status_created_at_idx ON email_outbox(status, created_at);
@@ -0,0 +1,5 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locale TEXT NOT NULL DEFAULT 'en';
+
+-- Emails are rendered from templates into separate HTML and plain-text bodies.
+ALTER TABLE email_outbox RENAME COLUMN content TO text_body;
+ALTER TABLE email_outbox ADD COLUMN IF NOT EXISTS html_body TEXT NOT NULL DEFAULT '';
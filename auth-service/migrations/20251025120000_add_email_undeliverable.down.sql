@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS users_email_undeliverable_at_idx;
+
+ALTER TABLE users
+    DROP COLUMN email_undeliverable_at,
+    DROP COLUMN email_undeliverable_reason;
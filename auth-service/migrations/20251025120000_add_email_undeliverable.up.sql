@@ -0,0 +1,6 @@
+ALTER TABLE users
+    ADD COLUMN email_undeliverable_reason TEXT,
+    ADD COLUMN email_undeliverable_at TIMESTAMPTZ;
+
+CREATE INDEX users_email_undeliverable_at_idx ON users (email_undeliverable_at)
+    WHERE email_undeliverable_at IS NOT NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE users
+    DROP COLUMN two_fa_channel,
+    DROP COLUMN phone_number;
@@ -0,0 +1,14 @@
+-- The last event of the audit chain. Appends lock this single row, so each new event links to
+-- the one that really is the last without locking audit_events itself.
+CREATE TABLE IF NOT EXISTS audit_chain_head (
+   singleton BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (singleton),
+   last_id BIGINT NOT NULL,
+   last_hash TEXT NOT NULL
+);
+
+INSERT INTO audit_chain_head (last_id, last_hash)
+VALUES (0, '0000000000000000000000000000000000000000000000000000000000000000');
+
+UPDATE audit_chain_head
+SET (last_id, last_hash) = (SELECT id, hash FROM audit_events ORDER BY id DESC LIMIT 1)
+WHERE EXISTS (SELECT 1 FROM audit_events);
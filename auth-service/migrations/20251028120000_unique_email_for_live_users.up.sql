@@ -0,0 +1,4 @@
+-- Deleted accounts don't hold on to their email, so someone can sign up with it during the
+-- grace period. The signup purges the deleted account.
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_key ON users(email) WHERE deleted_at IS NULL;
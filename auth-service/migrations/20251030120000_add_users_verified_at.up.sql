@@ -0,0 +1,3 @@
+-- Set the first time the user completes a second factor. Users from before this column get it
+-- on their next 2FA login.
+ALTER TABLE users ADD COLUMN verified_at TIMESTAMPTZ;
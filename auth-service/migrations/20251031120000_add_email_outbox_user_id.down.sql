@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS email_outbox_user_id_idx;
+ALTER TABLE email_outbox DROP COLUMN user_id;
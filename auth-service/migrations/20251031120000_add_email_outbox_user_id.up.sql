@@ -0,0 +1,9 @@
+-- Links every email to its user, so the emails of an account are removed when it is purged.
+-- Existing emails are matched by address. The ones that match no user are already orphaned and
+-- are dropped.
+ALTER TABLE email_outbox ADD COLUMN user_id UUID;
+UPDATE email_outbox SET user_id = users.id FROM users WHERE users.email = email_outbox.recipient;
+DELETE FROM email_outbox WHERE user_id IS NULL;
+ALTER TABLE email_outbox ALTER COLUMN user_id SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS email_outbox_user_id_idx ON email_outbox(user_id);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS webhook_deliveries_user_id_idx;
+ALTER TABLE webhook_deliveries DROP COLUMN user_id;
@@ -0,0 +1,11 @@
+-- Links every delivery to the user of its event, so the email in its payload can be cleared
+-- when the account is purged. Deliveries of accounts that are already gone are cleared now.
+ALTER TABLE webhook_deliveries ADD COLUMN user_id UUID;
+UPDATE webhook_deliveries SET user_id = (payload::jsonb -> 'data' ->> 'userId')::uuid;
+ALTER TABLE webhook_deliveries ALTER COLUMN user_id SET NOT NULL;
+
+UPDATE webhook_deliveries
+SET payload = jsonb_set(payload::jsonb, '{data,email}', 'null')::text
+WHERE NOT EXISTS (SELECT 1 FROM users WHERE users.id = webhook_deliveries.user_id);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_user_id_idx ON webhook_deliveries(user_id);
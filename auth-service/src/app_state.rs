@@ -1,34 +1,91 @@
+use metrics_exporter_prometheus::PrometheusHandle;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use webauthn_rs::Webauthn;
 
-use crate::domain::{BannedTokenStore, EmailClient, TwoFACodeStore, UserStore};
+use crate::{
+    domain::{
+        AuditLogStore, BannedTokenStore, EmailClient, EmailOutboxStore, HealthCheck, PasskeyStore,
+        PhoneVerificationStore, SessionStore, SmsClient, TwoFACodeStore, UserStore, WebhookStore,
+    },
+    settings::Settings,
+    utils::shutdown::Shutdown,
+};
 
 // Using a type alias to improve readability!
-pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
-pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
-pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
-pub type EmailClientType = Arc<RwLock<dyn EmailClient + Send + Sync>>;
+pub type UserStoreType = Arc<dyn UserStore + Send + Sync>;
+pub type BannedTokenStoreType = Arc<dyn BannedTokenStore + Send + Sync>;
+pub type TwoFACodeStoreType = Arc<dyn TwoFACodeStore + Send + Sync>;
+pub type SessionStoreType = Arc<dyn SessionStore + Send + Sync>;
+pub type PasskeyStoreType = Arc<dyn PasskeyStore + Send + Sync>;
+pub type AuditLogStoreType = Arc<dyn AuditLogStore + Send + Sync>;
+pub type WebhookStoreType = Arc<dyn WebhookStore + Send + Sync>;
+pub type EmailOutboxStoreType = Arc<dyn EmailOutboxStore + Send + Sync>;
+pub type EmailClientType = Arc<dyn EmailClient + Send + Sync>;
+pub type PhoneVerificationStoreType = Arc<dyn PhoneVerificationStore + Send + Sync>;
+pub type SmsClientType = Arc<dyn SmsClient + Send + Sync>;
+pub type HealthCheckType = Arc<dyn HealthCheck + Send + Sync>;
 
 #[derive(Clone)]
 pub struct AppState {
     pub user_store: UserStoreType,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub session_store: SessionStoreType,
+    pub passkey_store: PasskeyStoreType,
+    pub audit_log_store: AuditLogStoreType,
+    pub webhook_store: WebhookStoreType,
+    pub email_outbox_store: EmailOutboxStoreType,
     pub email_client: EmailClientType,
+    pub phone_verification_store: PhoneVerificationStoreType,
+    pub sms_client: SmsClientType,
+    // Run by the readiness endpoint.
+    pub health_checks: Vec<HealthCheckType>,
+    // Triggered on SIGTERM, which fails the readiness check while requests drain.
+    pub shutdown: Shutdown,
+    // Renders the metrics for `/metrics`.
+    pub metrics: PrometheusHandle,
+    pub settings: Arc<Settings>,
+    // Built from the settings once, since it is the same for every ceremony.
+    pub webauthn: Arc<Webauthn>,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         user_store: UserStoreType,
         banned_token_store: BannedTokenStoreType,
         two_fa_code_store: TwoFACodeStoreType,
+        session_store: SessionStoreType,
+        passkey_store: PasskeyStoreType,
+        audit_log_store: AuditLogStoreType,
+        webhook_store: WebhookStoreType,
+        email_outbox_store: EmailOutboxStoreType,
         email_client: EmailClientType,
+        phone_verification_store: PhoneVerificationStoreType,
+        sms_client: SmsClientType,
+        health_checks: Vec<HealthCheckType>,
+        shutdown: Shutdown,
+        metrics: PrometheusHandle,
+        settings: Arc<Settings>,
+        webauthn: Arc<Webauthn>,
     ) -> Self {
         Self {
             user_store,
             banned_token_store,
             two_fa_code_store,
+            session_store,
+            passkey_store,
+            audit_log_store,
+            webhook_store,
+            email_outbox_store,
             email_client,
+            phone_verification_store,
+            sms_client,
+            health_checks,
+            shutdown,
+            metrics,
+            settings,
+            webauthn,
         }
     }
 }
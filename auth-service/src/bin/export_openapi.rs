@@ -0,0 +1,11 @@
+// Prints the OpenAPI description of the service. The checked-in `openapi.json` is its output:
+// `cargo run --bin export_openapi > openapi.json`.
+use auth_service::routes::ApiDoc;
+use utoipa::OpenApi;
+
+fn main() {
+    let openapi = ApiDoc::openapi()
+        .to_pretty_json()
+        .expect("Failed to serialize the OpenAPI description");
+    println!("{}", openapi);
+}
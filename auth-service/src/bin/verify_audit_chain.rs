@@ -0,0 +1,44 @@
+// Walks the audit log from the first event and checks every hash and link. Run it on a
+// schedule and keep the printed head hash somewhere the database can't reach: events cut off
+// the end of the log leave a valid chain behind, and only a head hash that went missing shows it.
+use std::process::ExitCode;
+
+use auth_service::{
+    domain::{data_stores::AuditLogStoreError, AuditLogStore},
+    get_postgres_pool,
+    services::data_stores::PostgresAuditLogStore,
+    settings::Settings,
+};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    color_eyre::install().expect("Failed to install color_eyre");
+
+    let settings = Settings::load().await.expect("Failed to load settings");
+    let connect_options = settings
+        .database
+        .connect_options()
+        .expect("Failed to parse the database url");
+    let pg_pool = get_postgres_pool(connect_options)
+        .await
+        .expect("Failed to create Postgres connection pool!");
+
+    match PostgresAuditLogStore::new(pg_pool).verify_chain().await {
+        Ok(verifier) => {
+            println!(
+                "audit chain intact: {} events, head hash {}",
+                verifier.verified_events(),
+                verifier.head_hash()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(AuditLogStoreError::ChainBroken(e)) => {
+            eprintln!("audit chain broken: {}", e);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("failed to verify audit chain: {:?}", e);
+            ExitCode::from(2)
+        }
+    }
+}
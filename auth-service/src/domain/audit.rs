@@ -0,0 +1,511 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use color_eyre::eyre::{eyre, Result};
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::UserId;
+
+// The first event of the chain links to this hash.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEventType {
+    Signup,
+    Login,
+    Verify2FA,
+    Logout,
+    VerifyToken,
+    MagicLinkRequest,
+    MagicLinkLogin,
+    PasskeyRegistrationStart,
+    PasskeyRegistration,
+    PasskeyAuthenticationStart,
+    PasskeyLogin,
+    PasskeyVerify2FA,
+    PasswordChange,
+    EmailChangeRequest,
+    EmailChangeConfirm,
+    EmailChangeCancel,
+    AccountDeletion,
+    AccountRestore,
+    AccountExport,
+    AuditLogQuery,
+    WebhookSubscriptionCreate,
+    WebhookSubscriptionList,
+    WebhookSubscriptionDelete,
+    WebhookDeliveryList,
+    WebhookDeliveryReplay,
+    AccountView,
+    EmailUndeliverableReport,
+    UndeliverableEmailList,
+    EmailUndeliverableClear,
+    PhoneVerificationRequest,
+    PhoneVerification,
+    TwoFAChannelChange,
+}
+
+impl AuditEventType {
+    const ALL: [Self; 32] = [
+        Self::Signup,
+        Self::Login,
+        Self::Verify2FA,
+        Self::Logout,
+        Self::VerifyToken,
+        Self::MagicLinkRequest,
+        Self::MagicLinkLogin,
+        Self::PasskeyRegistrationStart,
+        Self::PasskeyRegistration,
+        Self::PasskeyAuthenticationStart,
+        Self::PasskeyLogin,
+        Self::PasskeyVerify2FA,
+        Self::PasswordChange,
+        Self::EmailChangeRequest,
+        Self::EmailChangeConfirm,
+        Self::EmailChangeCancel,
+        Self::AccountDeletion,
+        Self::AccountRestore,
+        Self::AccountExport,
+        Self::AuditLogQuery,
+        Self::WebhookSubscriptionCreate,
+        Self::WebhookSubscriptionList,
+        Self::WebhookSubscriptionDelete,
+        Self::WebhookDeliveryList,
+        Self::WebhookDeliveryReplay,
+        Self::AccountView,
+        Self::EmailUndeliverableReport,
+        Self::UndeliverableEmailList,
+        Self::EmailUndeliverableClear,
+        Self::PhoneVerificationRequest,
+        Self::PhoneVerification,
+        Self::TwoFAChannelChange,
+    ];
+
+    pub fn parse(event_type: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.as_str() == event_type)
+            .ok_or(eyre!("Invalid audit event type"))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Signup => "signup",
+            Self::Login => "login",
+            Self::Verify2FA => "verify_2fa",
+            Self::Logout => "logout",
+            Self::VerifyToken => "verify_token",
+            Self::MagicLinkRequest => "magic_link_request",
+            Self::MagicLinkLogin => "magic_link_login",
+            Self::PasskeyRegistrationStart => "passkey_registration_start",
+            Self::PasskeyRegistration => "passkey_registration",
+            Self::PasskeyAuthenticationStart => "passkey_authentication_start",
+            Self::PasskeyLogin => "passkey_login",
+            Self::PasskeyVerify2FA => "passkey_verify_2fa",
+            Self::PasswordChange => "password_change",
+            Self::EmailChangeRequest => "email_change_request",
+            Self::EmailChangeConfirm => "email_change_confirm",
+            Self::EmailChangeCancel => "email_change_cancel",
+            Self::AccountDeletion => "account_deletion",
+            Self::AccountRestore => "account_restore",
+            Self::AccountExport => "account_export",
+            Self::AuditLogQuery => "audit_log_query",
+            Self::WebhookSubscriptionCreate => "webhook_subscription_create",
+            Self::WebhookSubscriptionList => "webhook_subscription_list",
+            Self::WebhookSubscriptionDelete => "webhook_subscription_delete",
+            Self::WebhookDeliveryList => "webhook_delivery_list",
+            Self::WebhookDeliveryReplay => "webhook_delivery_replay",
+            Self::AccountView => "account_view",
+            Self::EmailUndeliverableReport => "email_undeliverable_report",
+            Self::UndeliverableEmailList => "undeliverable_email_list",
+            Self::EmailUndeliverableClear => "email_undeliverable_clear",
+            Self::PhoneVerificationRequest => "phone_verification_request",
+            Self::PhoneVerification => "phone_verification",
+            Self::TwoFAChannelChange => "two_fa_channel_change",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOutcome {
+    Success,
+    // The first factor was accepted and a second one is pending.
+    Pending,
+    Failure,
+}
+
+impl AuditOutcome {
+    pub fn parse(outcome: &str) -> Result<Self> {
+        match outcome {
+            "success" => Ok(Self::Success),
+            "pending" => Ok(Self::Pending),
+            "failure" => Ok(Self::Failure),
+            _ => Err(eyre!("Invalid audit outcome")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Pending => "pending",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+// An event that hasn't been added to the chain yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewAuditEvent {
+    pub event_type: AuditEventType,
+    pub user_id: Option<UserId>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    pub outcome: AuditOutcome,
+    pub reason: Option<String>,
+}
+
+impl NewAuditEvent {
+    pub fn new(event_type: AuditEventType, outcome: AuditOutcome) -> Self {
+        Self {
+            event_type,
+            user_id: None,
+            ip_address: None,
+            user_agent: None,
+            request_id: None,
+            outcome,
+            reason: None,
+        }
+    }
+
+    // Turns the event into the one with `id` that follows the event hashed as `prev_hash`.
+    pub fn seal(self, id: i64, prev_hash: String) -> AuditEvent {
+        // Postgres keeps microseconds, so anything finer would change the hash on the way back.
+        let now = Utc::now();
+        let created_at = DateTime::from_timestamp_micros(now.timestamp_micros()).unwrap_or(now);
+
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let pii_salt = hex::encode(salt);
+
+        let pii_digest = personal_data_digest(
+            &pii_salt,
+            self.user_id.as_ref(),
+            self.ip_address.as_deref(),
+            self.user_agent.as_deref(),
+        );
+
+        let mut event = AuditEvent {
+            id,
+            created_at,
+            event_type: self.event_type,
+            user_id: self.user_id,
+            ip_address: self.ip_address,
+            user_agent: self.user_agent,
+            request_id: self.request_id,
+            outcome: self.outcome,
+            reason: self.reason,
+            pii_salt: Some(pii_salt),
+            pii_digest,
+            prev_hash,
+            hash: String::new(),
+        };
+        event.hash = event.compute_hash();
+        event
+    }
+}
+
+// A recorded event. Each event's hash covers the hash of the event before it, so changing,
+// removing or reordering events breaks every link after that point.
+//
+// Personal data (user id, IP address and user agent) only enters the hash through a salted
+// digest. Anonymizing an event clears the personal data and the salt but keeps the digest,
+// so the chain still verifies while nothing links the event back to the person.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub event_type: AuditEventType,
+    pub user_id: Option<UserId>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    pub outcome: AuditOutcome,
+    pub reason: Option<String>,
+    pub pii_salt: Option<String>,
+    pub pii_digest: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEvent {
+    pub fn is_anonymized(&self) -> bool {
+        self.pii_salt.is_none()
+    }
+
+    pub fn anonymize(&mut self) {
+        self.user_id = None;
+        self.ip_address = None;
+        self.user_agent = None;
+        self.pii_salt = None;
+    }
+
+    fn compute_hash(&self) -> String {
+        #[derive(Serialize)]
+        struct HashedFields<'a> {
+            id: i64,
+            created_at: String,
+            event_type: &'a str,
+            request_id: Option<&'a str>,
+            outcome: &'a str,
+            reason: Option<&'a str>,
+            pii_digest: &'a str,
+            prev_hash: &'a str,
+        }
+
+        let fields = HashedFields {
+            id: self.id,
+            created_at: self.created_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            event_type: self.event_type.as_str(),
+            request_id: self.request_id.as_deref(),
+            outcome: self.outcome.as_str(),
+            reason: self.reason.as_deref(),
+            pii_digest: &self.pii_digest,
+            prev_hash: &self.prev_hash,
+        };
+
+        sha256_hex(&fields)
+    }
+}
+
+fn personal_data_digest(
+    salt: &str,
+    user_id: Option<&UserId>,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+) -> String {
+    #[derive(Serialize)]
+    struct PersonalData<'a> {
+        salt: &'a str,
+        user_id: Option<String>,
+        ip_address: Option<&'a str>,
+        user_agent: Option<&'a str>,
+    }
+
+    sha256_hex(&PersonalData {
+        salt,
+        user_id: user_id.map(|id| id.to_string()),
+        ip_address,
+        user_agent,
+    })
+}
+
+fn sha256_hex<T: Serialize>(value: &T) -> String {
+    // Serializing a struct of strings and integers can't fail.
+    let bytes = serde_json::to_vec(value).unwrap_or_default();
+    hex::encode(Sha256::digest(bytes))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditEventFilter {
+    pub event_type: Option<AuditEventType>,
+    pub user_id: Option<UserId>,
+    pub outcome: Option<AuditOutcome>,
+    pub ip_address: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    // Only events older than this id, for paging backwards.
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl AuditEventFilter {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.event_type.is_none_or(|t| t == event.event_type)
+            && self.user_id.is_none_or(|id| Some(id) == event.user_id)
+            && self.outcome.is_none_or(|o| o == event.outcome)
+            && self
+                .ip_address
+                .as_ref()
+                .is_none_or(|ip| Some(ip) == event.ip_address.as_ref())
+            && self.since.is_none_or(|since| event.created_at >= since)
+            && self.until.is_none_or(|until| event.created_at < until)
+            && self.before_id.is_none_or(|id| event.id < id)
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AuditChainError {
+    #[error("Expected audit event {expected} but found {found}")]
+    MissingEvent { expected: i64, found: i64 },
+    #[error("Audit event {0} does not link to the event before it")]
+    BrokenLink(i64),
+    #[error("Audit event {0} does not match its hash")]
+    HashMismatch(i64),
+    #[error("Personal data of audit event {0} does not match its digest")]
+    PersonalDataMismatch(i64),
+}
+
+// Checks the chain one event at a time. Events have to be passed in id order, starting with
+// the first one. Removing events from the end of the chain can't be detected this way, so the
+// head hash should be recorded somewhere else to compare against.
+#[derive(Debug)]
+pub struct AuditChainVerifier {
+    next_id: i64,
+    head_hash: String,
+}
+
+impl Default for AuditChainVerifier {
+    fn default() -> Self {
+        Self {
+            next_id: 1,
+            head_hash: GENESIS_HASH.to_owned(),
+        }
+    }
+}
+
+impl AuditChainVerifier {
+    pub fn verify(&mut self, event: &AuditEvent) -> Result<(), AuditChainError> {
+        if event.id != self.next_id {
+            return Err(AuditChainError::MissingEvent {
+                expected: self.next_id,
+                found: event.id,
+            });
+        }
+
+        if event.prev_hash != self.head_hash {
+            return Err(AuditChainError::BrokenLink(event.id));
+        }
+
+        if let Some(salt) = &event.pii_salt {
+            let digest = personal_data_digest(
+                salt,
+                event.user_id.as_ref(),
+                event.ip_address.as_deref(),
+                event.user_agent.as_deref(),
+            );
+            if digest != event.pii_digest {
+                return Err(AuditChainError::PersonalDataMismatch(event.id));
+            }
+        }
+
+        if event.compute_hash() != event.hash {
+            return Err(AuditChainError::HashMismatch(event.id));
+        }
+
+        self.next_id += 1;
+        self.head_hash = event.hash.clone();
+        Ok(())
+    }
+
+    pub fn verified_events(&self) -> i64 {
+        self.next_id - 1
+    }
+
+    pub fn head_hash(&self) -> &str {
+        &self.head_hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(length: i64) -> Vec<AuditEvent> {
+        let mut events: Vec<AuditEvent> = vec![];
+        for id in 1..=length {
+            let prev_hash = events
+                .last()
+                .map(|event| event.hash.clone())
+                .unwrap_or(GENESIS_HASH.to_owned());
+            let mut event = NewAuditEvent::new(AuditEventType::Login, AuditOutcome::Failure);
+            event.user_id = Some(UserId::default());
+            event.ip_address = Some("127.0.0.1".to_owned());
+            event.reason = Some("Incorrect credentials".to_owned());
+            events.push(event.seal(id, prev_hash));
+        }
+        events
+    }
+
+    fn verify_all(events: &[AuditEvent]) -> Result<(), AuditChainError> {
+        let mut verifier = AuditChainVerifier::default();
+        events.iter().try_for_each(|event| verifier.verify(event))
+    }
+
+    #[test]
+    fn test_event_type_round_trips() {
+        for event_type in AuditEventType::ALL {
+            assert_eq!(
+                AuditEventType::parse(event_type.as_str()).unwrap(),
+                event_type
+            );
+        }
+        assert!(AuditEventType::parse("unknown").is_err());
+    }
+
+    #[test]
+    fn test_intact_chain_verifies() {
+        let events = chain(3);
+        let mut verifier = AuditChainVerifier::default();
+
+        for event in &events {
+            assert_eq!(verifier.verify(event), Ok(()));
+        }
+
+        assert_eq!(verifier.verified_events(), 3);
+        assert_eq!(verifier.head_hash(), events[2].hash);
+    }
+
+    #[test]
+    fn test_changed_event_is_detected() {
+        let mut events = chain(3);
+        events[1].reason = Some("Nothing to see here".to_owned());
+
+        assert_eq!(verify_all(&events), Err(AuditChainError::HashMismatch(2)));
+    }
+
+    #[test]
+    fn test_changed_personal_data_is_detected() {
+        let mut events = chain(3);
+        events[1].ip_address = Some("10.0.0.1".to_owned());
+
+        assert_eq!(
+            verify_all(&events),
+            Err(AuditChainError::PersonalDataMismatch(2))
+        );
+    }
+
+    #[test]
+    fn test_removed_event_is_detected() {
+        let mut events = chain(3);
+        events.remove(1);
+
+        assert_eq!(
+            verify_all(&events),
+            Err(AuditChainError::MissingEvent {
+                expected: 2,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_rehashed_event_breaks_the_next_link() {
+        let mut events = chain(3);
+        events[1].reason = Some("Nothing to see here".to_owned());
+        events[1].hash = events[1].compute_hash();
+
+        assert_eq!(verify_all(&events), Err(AuditChainError::BrokenLink(3)));
+    }
+
+    #[test]
+    fn test_anonymized_chain_still_verifies() {
+        let mut events = chain(3);
+        events[1].anonymize();
+
+        assert!(events[1].is_anonymized());
+        assert_eq!(events[1].user_id, None);
+        assert_eq!(verify_all(&events), Ok(()));
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CircuitBreakerConfig {
+    // How many of the most recent calls the error rate is computed over.
+    pub window: usize,
+    // No decision is made on fewer calls than this, so one early failure can't open the circuit.
+    pub min_calls: usize,
+    // The circuit opens once this share of the calls in the window failed.
+    pub failure_rate_threshold: f64,
+    // How long an open circuit rejects calls before letting a trial call through.
+    pub open_duration: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    // Calls are rejected without being made.
+    Open,
+    // A single trial call decides whether the circuit closes or opens again.
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+// Tracks the error rate of calls to a dependency and stops calling it while it is failing.
+// Callers ask `allow` before every call and report how it went.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    state: CircuitState,
+    // Most recent last. `true` for a failed call.
+    outcomes: VecDeque<bool>,
+    open_until: Option<Instant>,
+    trial_in_flight: bool,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            state: CircuitState::Closed,
+            outcomes: VecDeque::with_capacity(config.window),
+            open_until: None,
+            trial_in_flight: false,
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    pub fn allow(&mut self, now: Instant) -> bool {
+        match self.state {
+            CircuitState::Closed => true,
+            CircuitState::Open if self.open_until.is_none_or(|until| now >= until) => {
+                self.state = CircuitState::HalfOpen;
+                self.trial_in_flight = true;
+                true
+            }
+            CircuitState::Open => false,
+            CircuitState::HalfOpen if !self.trial_in_flight => {
+                self.trial_in_flight = true;
+                true
+            }
+            CircuitState::HalfOpen => false,
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        if self.state == CircuitState::HalfOpen {
+            self.close();
+        } else {
+            self.record(false);
+        }
+    }
+
+    pub fn record_failure(&mut self, now: Instant) {
+        if self.state == CircuitState::HalfOpen {
+            self.open(now);
+            return;
+        }
+
+        self.record(true);
+        let failures = self.outcomes.iter().filter(|failed| **failed).count();
+        if self.outcomes.len() >= self.config.min_calls
+            && failures as f64 / self.outcomes.len() as f64 >= self.config.failure_rate_threshold
+        {
+            self.open(now);
+        }
+    }
+
+    // Gives up a call without an outcome, like one that was cancelled. If it was the trial call,
+    // the next call becomes the trial instead.
+    pub fn abandon(&mut self) {
+        if self.state == CircuitState::HalfOpen {
+            self.trial_in_flight = false;
+        }
+    }
+
+    fn record(&mut self, failed: bool) {
+        if self.outcomes.len() == self.config.window {
+            self.outcomes.pop_front();
+        }
+        self.outcomes.push_back(failed);
+    }
+
+    fn open(&mut self, now: Instant) {
+        self.state = CircuitState::Open;
+        self.open_until = Some(now + self.config.open_duration);
+        self.trial_in_flight = false;
+    }
+
+    fn close(&mut self) {
+        self.state = CircuitState::Closed;
+        self.outcomes.clear();
+        self.open_until = None;
+        self.trial_in_flight = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            window: 10,
+            min_calls: 4,
+            failure_rate_threshold: 0.5,
+            open_duration: Duration::from_secs(30),
+        })
+    }
+
+    #[test]
+    fn test_opens_once_the_error_rate_crosses_the_threshold() {
+        let mut breaker = breaker();
+        let now = Instant::now();
+
+        // Three failures are below `min_calls`.
+        for _ in 0..3 {
+            breaker.record_failure(now);
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        breaker.record_failure(now);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow(now));
+    }
+
+    #[test]
+    fn test_stays_closed_while_most_calls_succeed() {
+        let mut breaker = breaker();
+        let now = Instant::now();
+
+        for _ in 0..20 {
+            breaker.record_success();
+            breaker.record_success();
+            breaker.record_failure(now);
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_half_open_trial_decides_the_next_state() {
+        let mut breaker = breaker();
+        let now = Instant::now();
+        for _ in 0..4 {
+            breaker.record_failure(now);
+        }
+
+        let later = now + Duration::from_secs(30);
+        assert!(breaker.allow(later));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        // Only one trial at a time.
+        assert!(!breaker.allow(later));
+
+        breaker.record_failure(later);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow(later + Duration::from_secs(29)));
+
+        let even_later = later + Duration::from_secs(30);
+        assert!(breaker.allow(even_later));
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allow(even_later));
+    }
+
+    #[test]
+    fn test_abandoned_trial_lets_the_next_call_through() {
+        let mut breaker = breaker();
+        let now = Instant::now();
+        for _ in 0..4 {
+            breaker.record_failure(now);
+        }
+
+        let later = now + Duration::from_secs(30);
+        assert!(breaker.allow(later));
+        breaker.abandon();
+
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.allow(later));
+        assert!(!breaker.allow(later));
+    }
+}
@@ -1,17 +1,73 @@
-use crate::domain::{email::Email, password::Password};
+use crate::domain::{
+    audit::{AuditChainError, AuditChainVerifier, AuditEvent, AuditEventFilter, NewAuditEvent},
+    email::Email,
+    email_outbox::{OutboxEmail, OutboxEmailStatus},
+    password::Password,
+    webhook::{WebhookDelivery, WebhookDeliveryStatus, WebhookEvent, WebhookSubscription},
+    UserId,
+};
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::{eyre, Context, Report, Result};
 use rand::Rng;
 use thiserror::Error;
 use uuid::Uuid;
+use webauthn_rs::prelude::Passkey;
 
-use super::User;
+use super::{EmailUndeliverable, PhoneNumber, TwoFAChannel, User};
 
 #[async_trait::async_trait]
 pub trait UserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError>;
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
+    async fn get_user_by_id(&self, id: &UserId) -> Result<User, UserStoreError>;
     async fn validate_user(&self, email: &Email, password: &Password)
         -> Result<(), UserStoreError>;
+    async fn update_password(&self, id: &UserId, password: Password) -> Result<(), UserStoreError>;
+    async fn update_email(&self, id: &UserId, new_email: &Email) -> Result<(), UserStoreError>;
+    // A deleted user is hidden from every other method until it is restored or purged.
+    async fn delete_user(&self, id: &UserId) -> Result<(), UserStoreError>;
+    async fn restore_user(
+        &self,
+        email: &Email,
+        password: &Password,
+    ) -> Result<UserId, UserStoreError>;
+    // Removes users deleted before `deleted_before` for good and returns their ids.
+    async fn purge_deleted_users(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> Result<Vec<UserId>, UserStoreError>;
+    // Removes the deleted users with this email for good, whatever is left of their grace
+    // period, and returns their ids.
+    async fn purge_deleted_users_with_email(
+        &self,
+        email: &Email,
+    ) -> Result<Vec<UserId>, UserStoreError>;
+    // Flags the user with this email address and returns their id. `update_email` clears the
+    // flag, since the new address hasn't failed yet.
+    async fn mark_email_undeliverable(
+        &self,
+        email: &Email,
+        undeliverable: EmailUndeliverable,
+    ) -> Result<UserId, UserStoreError>;
+    async fn clear_email_undeliverable(&self, id: &UserId) -> Result<(), UserStoreError>;
+    // Most recently flagged first.
+    async fn get_users_with_undeliverable_email(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<User>, UserStoreError>;
+    // Only called with a number the user verified.
+    async fn update_phone_number(
+        &self,
+        id: &UserId,
+        phone_number: &PhoneNumber,
+    ) -> Result<(), UserStoreError>;
+    async fn update_two_fa_channel(
+        &self,
+        id: &UserId,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError>;
+    // Records that the user completed a second factor. Returns true only the first time.
+    async fn mark_verified(&self, id: &UserId) -> Result<bool, UserStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -44,7 +100,8 @@ impl PartialEq for UserStoreError {
 
 #[async_trait::async_trait]
 pub trait BannedTokenStore {
-    async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError>;
+    // Returns false if the token was already banned.
+    async fn add_token(&self, token: String) -> Result<bool, BannedTokenStoreError>;
     async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError>;
 }
 
@@ -58,19 +115,35 @@ pub enum BannedTokenStoreError {
     UnexpectedError(#[source] Report),
 }
 
+// Tracks the auth tokens issued to each user so they can be revoked together.
+#[async_trait::async_trait]
+pub trait SessionStore {
+    async fn add_session(&self, user_id: &UserId, token: String) -> Result<(), SessionStoreError>;
+    async fn remove_session(&self, user_id: &UserId, token: &str) -> Result<(), SessionStoreError>;
+    async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<String>, SessionStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum SessionStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
 // This trait represents the interface all concrete 2FA code stores should implement
 #[async_trait::async_trait]
 pub trait TwoFACodeStore {
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
+        user_id: UserId,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError>;
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    // Fails with `LoginAttemptIdNotFound` when there is no code, so of two requests removing
+    // the same code only one succeeds.
+    async fn remove_code(&self, user_id: &UserId) -> Result<(), TwoFACodeStoreError>;
     async fn get_code(
         &self,
-        email: &Email,
+        user_id: &UserId,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
 }
 
@@ -98,6 +171,258 @@ impl PartialEq for TwoFACodeStoreError {
     }
 }
 
+// Holds the code sent to a phone number the user wants to add, until they enter it. A user has
+// at most one number pending, so a new one replaces the last.
+#[async_trait::async_trait]
+pub trait PhoneVerificationStore {
+    // Counts a code sent to the user, failing with `TooManyCodes` during the cooldown after the
+    // last one or once the user is out of codes for the day.
+    async fn record_send(&self, user_id: &UserId) -> Result<(), PhoneVerificationStoreError>;
+    async fn add_code(
+        &self,
+        user_id: UserId,
+        phone_number: PhoneNumber,
+        code: TwoFACode,
+    ) -> Result<(), PhoneVerificationStoreError>;
+    // Fails with `VerificationNotFound` when there is no code, so of two requests removing the
+    // same code only one succeeds.
+    async fn remove_code(&self, user_id: &UserId) -> Result<(), PhoneVerificationStoreError>;
+    async fn get_code(
+        &self,
+        user_id: &UserId,
+    ) -> Result<(PhoneNumber, TwoFACode), PhoneVerificationStoreError>;
+}
+
+// Every code is a paid text message, so a user can't have them sent to any number they like
+// as often as they like.
+pub const PHONE_VERIFICATION_RESEND_COOLDOWN_SECONDS: u64 = 60;
+pub const PHONE_VERIFICATION_MAX_CODES_PER_DAY: u64 = 5;
+
+#[derive(Debug, Error)]
+pub enum PhoneVerificationStoreError {
+    #[error("Phone verification not found")]
+    VerificationNotFound,
+    #[error("Too many phone verification codes")]
+    TooManyCodes,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for PhoneVerificationStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::VerificationNotFound, Self::VerificationNotFound)
+                | (Self::TooManyCodes, Self::TooManyCodes)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// This trait represents the interface all concrete WebAuthn credential stores should implement
+#[async_trait::async_trait]
+pub trait PasskeyStore {
+    async fn add_passkey(
+        &self,
+        user_id: &UserId,
+        passkey: Passkey,
+    ) -> Result<(), PasskeyStoreError>;
+    async fn get_passkeys(&self, user_id: &UserId)
+        -> Result<Vec<StoredPasskey>, PasskeyStoreError>;
+    // Fails with `SignCountRegressed` unless `sign_count` is above the stored counter, or both
+    // are 0. The check and the update are one step, so of two assertions racing with the same
+    // counter only one is accepted.
+    async fn update_passkey(
+        &self,
+        user_id: &UserId,
+        passkey: &Passkey,
+        sign_count: u32,
+    ) -> Result<(), PasskeyStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum PasskeyStoreError {
+    #[error("Passkey already exists")]
+    PasskeyAlreadyExists,
+    #[error("Passkey not found")]
+    PasskeyNotFound,
+    #[error("Passkey sign count regressed")]
+    SignCountRegressed,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for PasskeyStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::PasskeyAlreadyExists, Self::PasskeyAlreadyExists)
+                | (Self::PasskeyNotFound, Self::PasskeyNotFound)
+                | (Self::SignCountRegressed, Self::SignCountRegressed)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Events are only ever appended. The one exception is anonymization, which clears the personal
+// data of a user's events without breaking the hash chain.
+#[async_trait::async_trait]
+pub trait AuditLogStore {
+    async fn append(&self, event: NewAuditEvent) -> Result<AuditEvent, AuditLogStoreError>;
+    // Returns the matching events, newest first.
+    async fn get_events(
+        &self,
+        filter: &AuditEventFilter,
+    ) -> Result<Vec<AuditEvent>, AuditLogStoreError>;
+    // Returns up to `limit` events with an id above `after_id`, oldest first.
+    async fn get_events_after(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<AuditEvent>, AuditLogStoreError>;
+    async fn anonymize_user(&self, user_id: &UserId) -> Result<u64, AuditLogStoreError>;
+
+    // Walks the whole chain and returns the verifier, which knows the head of the chain.
+    async fn verify_chain(&self) -> Result<AuditChainVerifier, AuditLogStoreError> {
+        let mut verifier = AuditChainVerifier::default();
+        loop {
+            let events = self
+                .get_events_after(verifier.verified_events(), AUDIT_CHAIN_BATCH_SIZE)
+                .await?;
+            if events.is_empty() {
+                return Ok(verifier);
+            }
+            for event in &events {
+                verifier.verify(event)?;
+            }
+        }
+    }
+}
+
+const AUDIT_CHAIN_BATCH_SIZE: i64 = 1000;
+
+#[derive(Debug, Error)]
+pub enum AuditLogStoreError {
+    #[error("Audit chain is broken")]
+    ChainBroken(#[from] AuditChainError),
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+#[async_trait::async_trait]
+pub trait WebhookStore {
+    async fn add_subscription(
+        &self,
+        subscription: WebhookSubscription,
+    ) -> Result<(), WebhookStoreError>;
+    async fn get_subscription(&self, id: &Uuid) -> Result<WebhookSubscription, WebhookStoreError>;
+    async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscription>, WebhookStoreError>;
+    // Pending deliveries of the subscription are dropped with it.
+    async fn delete_subscription(&self, id: &Uuid) -> Result<(), WebhookStoreError>;
+    // Queues a delivery of the event to every subscription to its type.
+    async fn enqueue_event(&self, event: &WebhookEvent) -> Result<(), WebhookStoreError>;
+    // Returns up to `limit` pending deliveries that are due at `now`, and pushes their next
+    // attempt back to `claimed_until` so no other worker picks them up in the meantime.
+    async fn claim_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        claimed_until: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError>;
+    async fn get_delivery(&self, id: &Uuid) -> Result<WebhookDelivery, WebhookStoreError>;
+    // Returns the deliveries with the given status, or all of them, newest first.
+    async fn get_deliveries(
+        &self,
+        status: Option<WebhookDeliveryStatus>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError>;
+    // Returns every delivery about the user, newest first.
+    async fn get_user_deliveries(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError>;
+    // Saves the status, attempts and schedule of a delivery.
+    async fn update_delivery(&self, delivery: &WebhookDelivery) -> Result<(), WebhookStoreError>;
+    // Redacts the payloads of every delivery about the user, and returns how many there were.
+    async fn redact_user_deliveries(&self, user_id: &UserId) -> Result<u64, WebhookStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum WebhookStoreError {
+    #[error("Webhook subscription not found")]
+    SubscriptionNotFound,
+    #[error("Webhook delivery not found")]
+    DeliveryNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for WebhookStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::SubscriptionNotFound, Self::SubscriptionNotFound)
+                | (Self::DeliveryNotFound, Self::DeliveryNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[async_trait::async_trait]
+pub trait EmailOutboxStore {
+    async fn add_email(&self, email: &OutboxEmail) -> Result<(), EmailOutboxStoreError>;
+    // Returns up to `limit` pending emails that are due at `now`, and pushes their next attempt
+    // back to `claimed_until` so no other worker picks them up in the meantime.
+    async fn claim_due_emails(
+        &self,
+        now: DateTime<Utc>,
+        claimed_until: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError>;
+    async fn get_email(&self, id: &Uuid) -> Result<OutboxEmail, EmailOutboxStoreError>;
+    // Returns the emails with the given status, or all of them, newest first.
+    async fn get_emails(
+        &self,
+        status: Option<OutboxEmailStatus>,
+        limit: i64,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError>;
+    // Returns every email of the user, newest first.
+    async fn get_user_emails(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError>;
+    // Saves the status, attempts and schedule of an email.
+    async fn update_email(&self, email: &OutboxEmail) -> Result<(), EmailOutboxStoreError>;
+    // Removes every email of the user, whatever its status, and returns how many there were.
+    async fn delete_user_emails(&self, user_id: &UserId) -> Result<u64, EmailOutboxStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum EmailOutboxStoreError {
+    #[error("Outbox email not found")]
+    EmailNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for EmailOutboxStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::EmailNotFound, Self::EmailNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// A registered credential together with the last signature counter the authenticator reported.
+// The counter is kept next to the credential so a counter that goes backwards can be detected.
+#[derive(Debug, Clone)]
+pub struct StoredPasskey {
+    pub passkey: Passkey,
+    pub sign_count: u32,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct LoginAttemptId(String);
 
@@ -41,23 +41,17 @@ mod tests {
 
     #[test]
     fn test_parse_returns_ok_given_valid_email() {
-        assert_eq!(
-            Email::parse(Secret::new("foo@example.com".to_string())).is_ok(),
-            true
-        );
+        assert!(Email::parse(Secret::new("foo@example.com".to_string())).is_ok());
     }
 
     #[test]
     fn test_parse_returns_err_given_invalid_email() {
-        assert_eq!(
-            Email::parse(Secret::new("fooexample.com".to_string())).is_err(),
-            true
-        );
+        assert!(Email::parse(Secret::new("fooexample.com".to_string())).is_err());
     }
 
     #[test]
     fn test_parse_returns_err_given_empty_email() {
-        assert_eq!(Email::parse(Secret::new("".to_string())).is_err(), true);
+        assert!(Email::parse(Secret::new("".to_string())).is_err());
     }
 
     #[test]
@@ -1,9 +1,69 @@
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
+use secrecy::Secret;
+use thiserror::Error;
 
 use super::Email;
 
+// A rendered email. The bodies can carry codes and login links, so they are kept secret.
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub subject: String,
+    pub html_body: Secret<String>,
+    pub text_body: Secret<String>,
+}
+
+impl EmailMessage {
+    pub fn new(subject: String, html_body: String, text_body: String) -> Self {
+        Self {
+            subject,
+            html_body: Secret::new(html_body),
+            text_body: Secret::new(text_body),
+        }
+    }
+}
+
+// An email client the service can send through. The ones to use are picked at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailProvider {
+    Postmark,
+    Smtp,
+    // Only logs emails. For local development.
+    Mock,
+}
+
+impl EmailProvider {
+    pub fn parse(provider: &str) -> Result<Self> {
+        match provider {
+            "postmark" => Ok(Self::Postmark),
+            "smtp" => Ok(Self::Smtp),
+            "mock" => Ok(Self::Mock),
+            _ => Err(eyre!("Invalid email provider")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Postmark => "postmark",
+            Self::Smtp => "smtp",
+            Self::Mock => "mock",
+        }
+    }
+}
+
+// Returned instead of sending to an address that is known to be undeliverable. Unlike other
+// send errors it is final, so the email isn't retried.
+#[derive(Debug, Error)]
+#[error("Email address is undeliverable")]
+pub struct EmailSuppressed;
+
 // This trait represents the interface all concrete email clients should implement
 #[async_trait::async_trait]
 pub trait EmailClient {
-    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()>;
+    async fn send_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()>;
+
+    // Whether sending is likely to work, without sending anything. Clients that can't tell
+    // without trying are assumed to be fine.
+    async fn check_health(&self) -> Result<()> {
+        Ok(())
+    }
 }
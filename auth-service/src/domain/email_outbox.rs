@@ -0,0 +1,327 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Result};
+use secrecy::Secret;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use super::{backoff_delay, retry::now_micros, Email, EmailMessage, UserId};
+
+// An email that still fails after this many attempts is marked as failed and left alone.
+pub const OUTBOX_EMAIL_MAX_ATTEMPTS: i32 = 6;
+const OUTBOX_EMAIL_INITIAL_RETRY_DELAY_SECONDS: i64 = 10;
+const OUTBOX_EMAIL_MAX_RETRY_DELAY_SECONDS: i64 = 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmailKind {
+    TwoFACode,
+    MagicLink,
+    PasswordChanged,
+    EmailChangeConfirmation,
+    // Sent to the old address while an email change is pending.
+    EmailChangeNotice,
+    AccountDeleted,
+}
+
+impl EmailKind {
+    pub const ALL: [Self; 6] = [
+        Self::TwoFACode,
+        Self::MagicLink,
+        Self::PasswordChanged,
+        Self::EmailChangeConfirmation,
+        Self::EmailChangeNotice,
+        Self::AccountDeleted,
+    ];
+
+    pub fn parse(kind: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.as_str() == kind)
+            .ok_or(eyre!("Invalid email kind"))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TwoFACode => "two_fa_code",
+            Self::MagicLink => "magic_link",
+            Self::PasswordChanged => "password_changed",
+            Self::EmailChangeConfirmation => "email_change_confirmation",
+            Self::EmailChangeNotice => "email_change_notice",
+            Self::AccountDeleted => "account_deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailDelivery {
+    // Sent while the request waits. The request fails if the email can't be sent.
+    Synchronous,
+    // Left to the background dispatcher, which retries until it goes through.
+    BestEffort,
+}
+
+// Which kinds of email are sent synchronously. Everything else is best-effort.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailDeliveryPolicy {
+    synchronous: HashSet<EmailKind>,
+}
+
+impl EmailDeliveryPolicy {
+    pub fn new(synchronous: impl IntoIterator<Item = EmailKind>) -> Self {
+        Self {
+            synchronous: synchronous.into_iter().collect(),
+        }
+    }
+
+    // Parses a comma-separated list of the kinds to send synchronously, e.g.
+    // `two_fa_code,magic_link`. An empty list makes every email best-effort.
+    pub fn parse(synchronous: &str) -> Result<Self> {
+        let kinds = synchronous
+            .split(',')
+            .map(str::trim)
+            .filter(|kind| !kind.is_empty())
+            .map(EmailKind::parse)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(kinds))
+    }
+
+    pub fn delivery_for(&self, kind: EmailKind) -> EmailDelivery {
+        if self.synchronous.contains(&kind) {
+            EmailDelivery::Synchronous
+        } else {
+            EmailDelivery::BestEffort
+        }
+    }
+}
+
+// A 2FA code is useless if it arrives late, so the login fails rather than leaving the user
+// waiting for it. Magic links stay best-effort: failing the request only for known addresses
+// would tell callers which addresses have accounts.
+impl Default for EmailDeliveryPolicy {
+    fn default() -> Self {
+        Self::new([EmailKind::TwoFACode])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboxEmailStatus {
+    Pending,
+    Sent,
+    // Out of attempts, a synchronous send that failed, or an undeliverable address. Never
+    // retried.
+    Failed,
+}
+
+impl OutboxEmailStatus {
+    pub fn parse(status: &str) -> Result<Self> {
+        match status {
+            "pending" => Ok(Self::Pending),
+            "sent" => Ok(Self::Sent),
+            "failed" => Ok(Self::Failed),
+            _ => Err(eyre!("Invalid outbox email status")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Sent => "sent",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboxEmail {
+    pub id: Uuid,
+    pub kind: EmailKind,
+    // The user the email is about, so it can be removed with their account.
+    pub user_id: UserId,
+    pub recipient: Email,
+    // The bodies can carry a 2FA code or a login link, so they are dropped once the email is
+    // sent.
+    pub message: EmailMessage,
+    pub status: OutboxEmailStatus,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+impl OutboxEmail {
+    pub fn new(
+        kind: EmailKind,
+        user_id: &UserId,
+        recipient: &Email,
+        message: EmailMessage,
+    ) -> Self {
+        let now = now_micros();
+        Self {
+            id: Uuid::new_v4(),
+            kind,
+            user_id: *user_id,
+            recipient: recipient.clone(),
+            message,
+            status: OutboxEmailStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+            created_at: now,
+            sent_at: None,
+        }
+    }
+
+    pub fn record_sent(&mut self, now: DateTime<Utc>) {
+        self.attempts += 1;
+        self.last_error = None;
+        self.sent_at = Some(now);
+        self.finish(OutboxEmailStatus::Sent);
+    }
+
+    // Schedules the next attempt with exponential backoff, or gives up once the email is out
+    // of attempts.
+    pub fn record_failure(&mut self, error: String, now: DateTime<Utc>) {
+        self.attempts += 1;
+        self.last_error = Some(error);
+
+        match backoff_delay(
+            self.attempts,
+            OUTBOX_EMAIL_MAX_ATTEMPTS,
+            OUTBOX_EMAIL_INITIAL_RETRY_DELAY_SECONDS,
+            OUTBOX_EMAIL_MAX_RETRY_DELAY_SECONDS,
+        ) {
+            Some(delay) => self.next_attempt_at = now + delay,
+            None => self.finish(OutboxEmailStatus::Failed),
+        }
+    }
+
+    // For a synchronous email, which is only attempted once because the caller has already
+    // been told it failed, and for an email to an undeliverable address.
+    pub fn record_permanent_failure(&mut self, error: String) {
+        self.attempts += 1;
+        self.last_error = Some(error);
+        self.finish(OutboxEmailStatus::Failed);
+    }
+
+    // An email that won't be attempted again has no use for its content, which can hold
+    // login links and codes.
+    fn finish(&mut self, status: OutboxEmailStatus) {
+        self.status = status;
+        self.message.html_body = Secret::new(String::new());
+        self.message.text_body = Secret::new(String::new());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use secrecy::ExposeSecret;
+
+    fn email() -> OutboxEmail {
+        OutboxEmail::new(
+            EmailKind::PasswordChanged,
+            &UserId::default(),
+            &Email::parse(Secret::new("user@example.com".to_owned())).unwrap(),
+            EmailMessage::new(
+                "Your password was changed".to_owned(),
+                "<p>content</p>".to_owned(),
+                "content".to_owned(),
+            ),
+        )
+    }
+
+    #[test]
+    fn test_kind_round_trips() {
+        for kind in EmailKind::ALL {
+            assert_eq!(EmailKind::parse(kind.as_str()).unwrap(), kind);
+        }
+        assert!(EmailKind::parse("newsletter").is_err());
+    }
+
+    #[test]
+    fn test_policy_parses_synchronous_kinds() {
+        let policy = EmailDeliveryPolicy::parse("two_fa_code, magic_link").unwrap();
+        assert_eq!(
+            policy.delivery_for(EmailKind::TwoFACode),
+            EmailDelivery::Synchronous
+        );
+        assert_eq!(
+            policy.delivery_for(EmailKind::MagicLink),
+            EmailDelivery::Synchronous
+        );
+        assert_eq!(
+            policy.delivery_for(EmailKind::AccountDeleted),
+            EmailDelivery::BestEffort
+        );
+
+        let policy = EmailDeliveryPolicy::parse("").unwrap();
+        assert_eq!(
+            policy.delivery_for(EmailKind::TwoFACode),
+            EmailDelivery::BestEffort
+        );
+
+        assert!(EmailDeliveryPolicy::parse("two_fa_code,newsletter").is_err());
+    }
+
+    #[test]
+    fn test_failures_back_off_then_fail() {
+        let mut email = email();
+        let now = Utc::now();
+
+        email.record_failure("HTTP 500".to_owned(), now);
+        assert_eq!(email.status, OutboxEmailStatus::Pending);
+        assert_eq!(
+            email.next_attempt_at,
+            now + Duration::try_seconds(10).unwrap()
+        );
+
+        for _ in 1..OUTBOX_EMAIL_MAX_ATTEMPTS - 1 {
+            email.record_failure("HTTP 500".to_owned(), now);
+        }
+        assert_eq!(email.status, OutboxEmailStatus::Pending);
+
+        email.record_failure("HTTP 500".to_owned(), now);
+        assert_eq!(email.status, OutboxEmailStatus::Failed);
+        assert_eq!(email.attempts, OUTBOX_EMAIL_MAX_ATTEMPTS);
+    }
+
+    #[test]
+    fn test_failed_email_drops_its_content_once_it_gives_up() {
+        let mut email = email();
+        let now = Utc::now();
+
+        // A retry still needs the content.
+        email.record_failure("HTTP 500".to_owned(), now);
+        assert_eq!(email.message.text_body.expose_secret(), "content");
+
+        for _ in 1..OUTBOX_EMAIL_MAX_ATTEMPTS {
+            email.record_failure("HTTP 500".to_owned(), now);
+        }
+        assert_eq!(email.status, OutboxEmailStatus::Failed);
+        assert!(email.message.html_body.expose_secret().is_empty());
+        assert!(email.message.text_body.expose_secret().is_empty());
+    }
+
+    #[test]
+    fn test_permanently_failed_email_drops_its_content() {
+        let mut email = email();
+
+        email.record_permanent_failure("address is undeliverable".to_owned());
+        assert_eq!(email.status, OutboxEmailStatus::Failed);
+        assert!(email.message.html_body.expose_secret().is_empty());
+        assert!(email.message.text_body.expose_secret().is_empty());
+    }
+
+    #[test]
+    fn test_sent_email_drops_its_content() {
+        let mut email = email();
+        let now = Utc::now();
+
+        email.record_sent(now);
+        assert_eq!(email.status, OutboxEmailStatus::Sent);
+        assert_eq!(email.sent_at, Some(now));
+        assert!(email.message.html_body.expose_secret().is_empty());
+        assert!(email.message.text_body.expose_secret().is_empty());
+    }
+}
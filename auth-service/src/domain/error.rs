@@ -1,5 +1,8 @@
+use axum::extract::rejection::JsonRejection;
 use color_eyre::eyre::Report;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use utoipa::ToSchema;
 
 #[derive(Debug, Error)]
 pub enum AuthAPIError {
@@ -18,6 +21,92 @@ pub enum AuthAPIError {
     #[error("Invalid token")]
     InvalidToken,
 
+    #[error("Passkey already registered")]
+    PasskeyAlreadyExists,
+
+    #[error("Invalid query")]
+    InvalidQuery,
+
+    #[error("Invalid webhook subscription")]
+    InvalidWebhookSubscription,
+
+    #[error("Not found")]
+    NotFound,
+
+    #[error("Failed to send email")]
+    EmailDeliveryFailed,
+
+    #[error("Email address is undeliverable")]
+    EmailUndeliverable,
+
+    #[error("Failed to send SMS")]
+    SmsDeliveryFailed,
+
+    #[error("Phone number not verified")]
+    PhoneNumberNotVerified,
+
+    #[error("Too many verification codes requested")]
+    TooManyPhoneVerificationCodes,
+
+    #[error("Validation failed")]
+    ValidationFailed(Vec<FieldError>),
+
+    #[error("Malformed request body")]
+    MalformedBody(#[from] JsonRejection),
+
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
+
+// One invalid field of a request body. `field` is the JSON name the client sent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn invalid_email(field: &str) -> Self {
+        Self {
+            field: field.to_owned(),
+            code: "invalid_email".to_owned(),
+            message: "Must be a valid email address".to_owned(),
+        }
+    }
+
+    pub fn invalid_password(field: &str) -> Self {
+        Self {
+            field: field.to_owned(),
+            code: "invalid_password".to_owned(),
+            message: "Must be at least 8 characters long".to_owned(),
+        }
+    }
+
+    pub fn invalid_phone_number(field: &str) -> Self {
+        Self {
+            field: field.to_owned(),
+            code: "invalid_phone_number".to_owned(),
+            message: "Must be a phone number in international format, e.g. +14155552671".to_owned(),
+        }
+    }
+}
+
+impl From<FieldError> for AuthAPIError {
+    fn from(error: FieldError) -> Self {
+        Self::ValidationFailed(vec![error])
+    }
+}
+
+// Checks two fields at once, so the client hears about both when both are wrong.
+pub fn validate_fields<A, B>(
+    a: Result<A, FieldError>,
+    b: Result<B, FieldError>,
+) -> Result<(A, B), AuthAPIError> {
+    match (a, b) {
+        (Ok(a), Ok(b)) => Ok((a, b)),
+        (a, b) => Err(AuthAPIError::ValidationFailed(
+            a.err().into_iter().chain(b.err()).collect(),
+        )),
+    }
+}
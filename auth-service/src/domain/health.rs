@@ -0,0 +1,9 @@
+use color_eyre::eyre::Result;
+
+// This trait represents a dependency the service can't serve requests without, like the
+// database. The readiness endpoint runs every check and reports them by name.
+#[async_trait::async_trait]
+pub trait HealthCheck {
+    fn name(&self) -> &'static str;
+    async fn check(&self) -> Result<()>;
+}
@@ -0,0 +1,77 @@
+use color_eyre::eyre::{eyre, Result};
+
+// The languages emails are translated into. Anything else falls back to English.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Fr,
+}
+
+impl Locale {
+    pub const ALL: [Self; 3] = [Self::En, Self::De, Self::Fr];
+
+    // Accepts a language tag like `de` or `de-CH`. Only the primary language is used.
+    pub fn parse(tag: &str) -> Result<Self> {
+        let language = tag.split(['-', '_']).next().unwrap_or_default().trim();
+        Self::ALL
+            .into_iter()
+            .find(|locale| locale.as_str().eq_ignore_ascii_case(language))
+            .ok_or(eyre!("Unsupported locale"))
+    }
+
+    // Picks the supported language the client prefers most from an `Accept-Language` header.
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut candidates: Vec<(f32, Self)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let locale = Self::parse(parts.next()?).ok()?;
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+                (quality > 0.0).then_some((quality, locale))
+            })
+            .collect();
+        // Stable, so ranges with the same quality keep the client's order.
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        candidates.first().map(|(_, locale)| *locale)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::De => "de",
+            Self::Fr => "fr",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uses_the_primary_language() {
+        assert_eq!(Locale::parse("de").unwrap(), Locale::De);
+        assert_eq!(Locale::parse("de-CH").unwrap(), Locale::De);
+        assert_eq!(Locale::parse("FR").unwrap(), Locale::Fr);
+        assert!(Locale::parse("pt-BR").is_err());
+        assert!(Locale::parse("").is_err());
+    }
+
+    #[test]
+    fn test_accept_language_picks_the_preferred_supported_language() {
+        assert_eq!(
+            Locale::from_accept_language("pt-BR, fr;q=0.8, de;q=0.9"),
+            Some(Locale::De)
+        );
+        assert_eq!(
+            Locale::from_accept_language("fr-CA,fr;q=0.9,en;q=0.8"),
+            Some(Locale::Fr)
+        );
+        assert_eq!(Locale::from_accept_language("de;q=0, en"), Some(Locale::En));
+        assert_eq!(Locale::from_accept_language("pt-BR, es"), None);
+    }
+}
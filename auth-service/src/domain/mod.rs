@@ -1,13 +1,35 @@
+pub mod audit;
+pub mod circuit_breaker;
 pub mod data_stores;
 pub mod email;
 pub mod email_client;
+pub mod email_outbox;
 mod error;
+pub mod health;
+pub mod locale;
 pub mod password;
+pub mod phone_number;
+pub mod retry;
+pub mod secret_provider;
+pub mod sms_client;
 pub mod user;
+pub mod user_id;
+pub mod webhook;
 
+pub use audit::*;
+pub use circuit_breaker::*;
 pub use data_stores::*;
 pub use email::*;
 pub use email_client::*;
+pub use email_outbox::*;
 pub use error::*;
+pub use health::*;
+pub use locale::*;
 pub use password::*;
+pub use phone_number::*;
+pub use retry::*;
+pub use secret_provider::*;
+pub use sms_client::*;
 pub use user::*;
+pub use user_id::*;
+pub use webhook::*;
@@ -0,0 +1,68 @@
+use color_eyre::eyre::{eyre, Result};
+use secrecy::{ExposeSecret, Secret};
+
+// A phone number in E.164 format, like `+4915123456789`. Numbers are stored and sent to the
+// SMS provider exactly as parsed.
+#[derive(Clone, Debug)]
+pub struct PhoneNumber(Secret<String>);
+
+impl PartialEq for PhoneNumber {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+impl Eq for PhoneNumber {}
+
+impl PhoneNumber {
+    pub fn parse(phone_number: Secret<String>) -> Result<Self> {
+        let digits = phone_number
+            .expose_secret()
+            .strip_prefix('+')
+            .ok_or(eyre!("Phone number must start with +"))?;
+
+        let valid = (8..=15).contains(&digits.len())
+            && digits.chars().all(|c| c.is_ascii_digit())
+            && !digits.starts_with('0');
+        match valid {
+            true => Ok(Self(phone_number)),
+            false => Err(eyre!("Invalid phone number")),
+        }
+    }
+}
+
+impl AsRef<Secret<String>> for PhoneNumber {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(phone_number: &str) -> Result<PhoneNumber> {
+        PhoneNumber::parse(Secret::new(phone_number.to_owned()))
+    }
+
+    #[test]
+    fn test_parse_returns_ok_given_e164_number() {
+        assert!(parse("+4915123456789").is_ok());
+        assert!(parse("+14155552671").is_ok());
+    }
+
+    #[test]
+    fn test_parse_returns_err_without_country_code() {
+        assert!(parse("015123456789").is_err());
+        assert!(parse("+015123456789").is_err());
+    }
+
+    #[test]
+    fn test_parse_returns_err_given_formatting_or_wrong_length() {
+        assert!(parse("+49 151 23456789").is_err());
+        assert!(parse("+49-151-23456789").is_err());
+        assert!(parse("+4915").is_err());
+        assert!(parse("+4915123456789012345").is_err());
+        assert!(parse("").is_err());
+    }
+}
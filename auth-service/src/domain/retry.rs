@@ -0,0 +1,40 @@
+use chrono::{DateTime, Duration, Utc};
+
+// Exponential backoff for background deliveries: `initial_seconds` after the first failed
+// attempt, doubling from there up to `max_seconds`. Returns `None` once `max_attempts` have
+// been made.
+pub fn backoff_delay(
+    attempts: i32,
+    max_attempts: i32,
+    initial_seconds: i64,
+    max_seconds: i64,
+) -> Option<Duration> {
+    if attempts >= max_attempts {
+        return None;
+    }
+
+    let seconds = initial_seconds
+        .saturating_mul(1 << (attempts - 1).clamp(0, 30))
+        .min(max_seconds);
+    Duration::try_seconds(seconds)
+}
+
+// Postgres keeps microseconds, so stored times are truncated to match what comes back.
+pub(crate) fn now_micros() -> DateTime<Utc> {
+    let now = Utc::now();
+    DateTime::from_timestamp_micros(now.timestamp_micros()).unwrap_or(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff_delay(1, 5, 10, 60), Duration::try_seconds(10));
+        assert_eq!(backoff_delay(2, 5, 10, 60), Duration::try_seconds(20));
+        assert_eq!(backoff_delay(3, 5, 10, 60), Duration::try_seconds(40));
+        assert_eq!(backoff_delay(4, 5, 10, 60), Duration::try_seconds(60));
+        assert_eq!(backoff_delay(5, 5, 10, 60), None);
+    }
+}
@@ -0,0 +1,90 @@
+use arc_swap::ArcSwap;
+use color_eyre::eyre::{eyre, Result};
+use secrecy::Secret;
+use serde::{Deserialize, Deserializer};
+use std::sync::Arc;
+
+// Where secrets are read from besides the environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretBackend {
+    // Only environment variables and the files their `*_FILE` variants point to.
+    Env,
+    // One file per secret in a directory, like Docker and Kubernetes secrets.
+    File,
+    // A HashiCorp Vault KV version 2 secret.
+    Vault,
+}
+
+impl SecretBackend {
+    pub fn parse(backend: &str) -> Result<Self> {
+        match backend {
+            "env" => Ok(Self::Env),
+            "file" => Ok(Self::File),
+            "vault" => Ok(Self::Vault),
+            _ => Err(eyre!("Invalid secret backend")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Env => "env",
+            Self::File => "file",
+            Self::Vault => "vault",
+        }
+    }
+}
+
+// This trait represents the interface all secret backends should implement. Secrets are looked
+// up by name, e.g. `jwt_secret`, and are `None` when the backend doesn't have them.
+#[async_trait::async_trait]
+pub trait SecretProvider {
+    async fn get_secret(&self, name: &str) -> Result<Option<Secret<String>>>;
+}
+
+// A secret that can be replaced while the service runs. Clones share the value, so replacing it
+// through one clone is seen by every other.
+#[derive(Debug, Clone)]
+pub struct ReloadableSecret(Arc<ArcSwap<Secret<String>>>);
+
+impl ReloadableSecret {
+    pub fn new(secret: Secret<String>) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(secret)))
+    }
+
+    pub fn load(&self) -> Arc<Secret<String>> {
+        self.0.load_full()
+    }
+
+    pub fn store(&self, secret: Arc<Secret<String>>) {
+        self.0.store(secret);
+    }
+}
+
+impl From<Secret<String>> for ReloadableSecret {
+    fn from(secret: Secret<String>) -> Self {
+        Self::new(secret)
+    }
+}
+
+impl<'de> Deserialize<'de> for ReloadableSecret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Secret::<String>::deserialize(deserializer).map(Self::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::*;
+
+    #[test]
+    fn test_clones_see_a_stored_secret() {
+        let secret = ReloadableSecret::new(Secret::new("old".to_owned()));
+        let clone = secret.clone();
+
+        secret.store(Arc::new(Secret::new("new".to_owned())));
+
+        assert_eq!(clone.load().expose_secret(), "new");
+    }
+}
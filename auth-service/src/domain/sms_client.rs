@@ -0,0 +1,36 @@
+use color_eyre::eyre::{eyre, Result};
+use secrecy::Secret;
+
+use super::PhoneNumber;
+
+// An SMS client the service can send through. The one to use is picked at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmsProvider {
+    Twilio,
+    // Only logs messages. For local development.
+    Mock,
+}
+
+impl SmsProvider {
+    pub fn parse(provider: &str) -> Result<Self> {
+        match provider {
+            "twilio" => Ok(Self::Twilio),
+            "mock" => Ok(Self::Mock),
+            _ => Err(eyre!("Invalid SMS provider")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Twilio => "twilio",
+            Self::Mock => "mock",
+        }
+    }
+}
+
+// This trait represents the interface all concrete SMS clients should implement. The body
+// carries one-time codes, so it is kept secret.
+#[async_trait::async_trait]
+pub trait SmsClient {
+    async fn send_sms(&self, recipient: &PhoneNumber, body: &Secret<String>) -> Result<()>;
+}
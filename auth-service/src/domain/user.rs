@@ -1,41 +1,127 @@
-use crate::domain::{Email, Password};
-use secrecy::Secret;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Result};
+
+use crate::domain::{Email, Locale, Password, PhoneNumber, UserId};
 
 #[derive(PartialEq, Clone, Debug)]
 pub struct User {
+    pub id: UserId,
     pub email: Email,
     pub password: Password,
     pub requires_2fa: bool,
+    // The language emails to the user are written in.
+    pub locale: Locale,
+    // Set when the email provider reported the address as unable to receive email. Nothing
+    // is sent to it until the user changes their address or an admin clears the flag.
+    pub email_undeliverable: Option<EmailUndeliverable>,
+    // Only set once the user proved they receive SMS at this number.
+    pub phone_number: Option<PhoneNumber>,
+    // Where login codes are sent.
+    pub two_fa_channel: TwoFAChannel,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TwoFAChannel {
+    #[default]
+    Email,
+    // Requires a verified phone number.
+    Sms,
+}
+
+impl TwoFAChannel {
+    pub fn parse(channel: &str) -> Result<Self> {
+        match channel {
+            "email" => Ok(Self::Email),
+            "sms" => Ok(Self::Sms),
+            _ => Err(eyre!("Invalid 2FA channel")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Email => "email",
+            Self::Sms => "sms",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndeliverableReason {
+    HardBounce,
+    SpamComplaint,
+}
+
+impl UndeliverableReason {
+    pub fn parse(reason: &str) -> Result<Self> {
+        match reason {
+            "hard_bounce" => Ok(Self::HardBounce),
+            "spam_complaint" => Ok(Self::SpamComplaint),
+            _ => Err(eyre!("Invalid undeliverable reason")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::HardBounce => "hard_bounce",
+            Self::SpamComplaint => "spam_complaint",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailUndeliverable {
+    pub reason: UndeliverableReason,
+    pub since: DateTime<Utc>,
 }
 
 impl User {
     pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
         Self {
+            id: UserId::default(),
             email,
             password,
             requires_2fa,
+            locale: Locale::default(),
+            email_undeliverable: None,
+            phone_number: None,
+            two_fa_channel: TwoFAChannel::default(),
         }
     }
+
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use secrecy::Secret;
 
     #[test]
     fn test_new_returns_a_user() {
-        let expected = User::new(
+        let actual = User::new(
             Email::parse(Secret::new("foo@example.com".to_string())).unwrap(),
             Password::parse(Secret::new("password123".to_string())).unwrap(),
             true,
         );
 
-        let actual = User::new(
-            Email::parse(Secret::new("foo@example.com".to_string())).unwrap(),
-            Password::parse(Secret::new("password123".to_string())).unwrap(),
-            true,
+        assert_eq!(
+            actual.email,
+            Email::parse(Secret::new("foo@example.com".to_string())).unwrap()
         );
+        assert!(actual.requires_2fa);
+    }
+
+    #[test]
+    fn test_new_assigns_a_fresh_id() {
+        let email = Email::parse(Secret::new("foo@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+
+        let first = User::new(email.clone(), password.clone(), true);
+        let second = User::new(email, password, true);
 
-        assert_eq!(actual, expected);
+        assert_ne!(first.id, second.id);
     }
 }
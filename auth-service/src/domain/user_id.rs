@@ -0,0 +1,54 @@
+use color_eyre::eyre::{Context, Result};
+use uuid::Uuid;
+
+// A user's stable identifier. Unlike the email it never changes and carries no personal data,
+// so it is what tokens and keyed stores refer to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UserId(Uuid);
+
+impl UserId {
+    pub fn parse(id: &str) -> Result<Self> {
+        let parsed_id = Uuid::parse_str(id).wrap_err("Invalid user id")?;
+        Ok(Self(parsed_id))
+    }
+}
+
+impl Default for UserId {
+    fn default() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl From<Uuid> for UserId {
+    fn from(id: Uuid) -> Self {
+        Self(id)
+    }
+}
+
+impl AsRef<Uuid> for UserId {
+    fn as_ref(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_returns_ok_given_valid_id() {
+        let id = UserId::default();
+        assert_eq!(UserId::parse(&id.to_string()).unwrap(), id);
+    }
+
+    #[test]
+    fn test_parse_returns_err_given_invalid_id() {
+        assert!(UserId::parse("not-a-uuid").is_err());
+    }
+}
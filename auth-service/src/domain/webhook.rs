@@ -0,0 +1,370 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Result};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use super::{backoff_delay, retry::now_micros, Email, UserId};
+
+// A delivery that still fails after this many attempts is dead-lettered. It is only sent again
+// when it is replayed.
+pub const WEBHOOK_MAX_ATTEMPTS: i32 = 8;
+const WEBHOOK_INITIAL_RETRY_DELAY_SECONDS: i64 = 30;
+const WEBHOOK_MAX_RETRY_DELAY_SECONDS: i64 = 6 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEventType {
+    UserSignedUp,
+    // The user completed a second factor for the first time, either the emailed code or a
+    // passkey. Later 2FA logins don't send it again.
+    UserVerified,
+    EmailChanged,
+    AccountDeleted,
+    AccountRestored,
+}
+
+impl WebhookEventType {
+    pub const ALL: [Self; 5] = [
+        Self::UserSignedUp,
+        Self::UserVerified,
+        Self::EmailChanged,
+        Self::AccountDeleted,
+        Self::AccountRestored,
+    ];
+
+    pub fn parse(event_type: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.as_str() == event_type)
+            .ok_or(eyre!("Invalid webhook event type"))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UserSignedUp => "user.signed_up",
+            Self::UserVerified => "user.verified",
+            Self::EmailChanged => "user.email_changed",
+            Self::AccountDeleted => "user.deleted",
+            Self::AccountRestored => "user.restored",
+        }
+    }
+}
+
+// Something that happened to an account. Every subscription to its type gets its own delivery
+// of the same payload.
+#[derive(Debug, Clone)]
+pub struct WebhookEvent {
+    pub id: Uuid,
+    pub event_type: WebhookEventType,
+    pub created_at: DateTime<Utc>,
+    pub user_id: UserId,
+    pub email: Email,
+}
+
+impl WebhookEvent {
+    pub fn new(event_type: WebhookEventType, user_id: &UserId, email: &Email) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            event_type,
+            created_at: Utc::now(),
+            user_id: *user_id,
+            email: email.clone(),
+        }
+    }
+
+    // The payload is rendered once and stored, so the bytes that are signed on every attempt
+    // are the same.
+    pub fn payload(&self) -> Result<String> {
+        let payload = WebhookPayload {
+            id: self.id.to_string(),
+            event_type: self.event_type.as_str(),
+            created_at: self.created_at,
+            data: WebhookPayloadData {
+                user_id: self.user_id.to_string(),
+                email: self.email.as_ref().expose_secret(),
+            },
+        };
+        Ok(serde_json::to_string(&payload)?)
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    id: String,
+    #[serde(rename = "type")]
+    event_type: &'a str,
+    #[serde(rename = "createdAt")]
+    created_at: DateTime<Utc>,
+    data: WebhookPayloadData<'a>,
+}
+
+#[derive(Serialize)]
+struct WebhookPayloadData<'a> {
+    #[serde(rename = "userId")]
+    user_id: String,
+    email: &'a str,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookSubscription {
+    pub id: Uuid,
+    pub url: String,
+    // Shared with the subscriber when the subscription is created, and used to sign every
+    // payload sent to it.
+    pub secret: Secret<String>,
+    pub event_types: Vec<WebhookEventType>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebhookSubscription {
+    pub fn new(url: String, event_types: Vec<WebhookEventType>) -> Self {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+
+        Self {
+            id: Uuid::new_v4(),
+            url,
+            secret: Secret::new(format!("whsec_{}", hex::encode(secret))),
+            event_types,
+            created_at: now_micros(),
+        }
+    }
+
+    pub fn subscribes_to(&self, event_type: WebhookEventType) -> bool {
+        self.event_types.contains(&event_type)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    // Every attempt failed. The delivery waits for a replay.
+    Dead,
+}
+
+impl WebhookDeliveryStatus {
+    pub fn parse(status: &str) -> Result<Self> {
+        match status {
+            "pending" => Ok(Self::Pending),
+            "delivered" => Ok(Self::Delivered),
+            "dead" => Ok(Self::Dead),
+            _ => Err(eyre!("Invalid webhook delivery status")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Delivered => "delivered",
+            Self::Dead => "dead",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: WebhookEventType,
+    // The user the event is about.
+    pub user_id: UserId,
+    pub payload: String,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl WebhookDelivery {
+    pub fn new(subscription_id: Uuid, event: &WebhookEvent, payload: String) -> Self {
+        let now = now_micros();
+        Self {
+            id: Uuid::new_v4(),
+            subscription_id,
+            event_id: event.id,
+            event_type: event.event_type,
+            user_id: event.user_id,
+            payload,
+            status: WebhookDeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+            created_at: now,
+            delivered_at: None,
+        }
+    }
+
+    pub fn record_success(&mut self, now: DateTime<Utc>) {
+        self.attempts += 1;
+        self.status = WebhookDeliveryStatus::Delivered;
+        self.last_error = None;
+        self.delivered_at = Some(now);
+    }
+
+    // Schedules the next attempt with exponential backoff, or dead-letters the delivery once
+    // it is out of attempts.
+    pub fn record_failure(&mut self, error: String, now: DateTime<Utc>) {
+        self.attempts += 1;
+        self.last_error = Some(error);
+
+        match backoff_delay(
+            self.attempts,
+            WEBHOOK_MAX_ATTEMPTS,
+            WEBHOOK_INITIAL_RETRY_DELAY_SECONDS,
+            WEBHOOK_MAX_RETRY_DELAY_SECONDS,
+        ) {
+            Some(delay) => self.next_attempt_at = now + delay,
+            None => self.status = WebhookDeliveryStatus::Dead,
+        }
+    }
+
+    // Clears the email from the payload once the account is purged. The event itself is kept,
+    // so a pending delivery still tells the subscriber what happened.
+    pub fn redact(&mut self) -> Result<()> {
+        let mut payload: serde_json::Value = serde_json::from_str(&self.payload)?;
+        payload["data"]["email"] = serde_json::Value::Null;
+        self.payload = serde_json::to_string(&payload)?;
+        Ok(())
+    }
+
+    // Sends the delivery again from scratch, whatever happened to it so far.
+    pub fn replay(&mut self, now: DateTime<Utc>) {
+        self.status = WebhookDeliveryStatus::Pending;
+        self.attempts = 0;
+        self.next_attempt_at = now;
+        self.last_error = None;
+        self.delivered_at = None;
+    }
+}
+
+// Signs `{timestamp}.{payload}` with HMAC-SHA256. Covering the timestamp lets subscribers
+// reject replayed requests.
+pub fn sign_webhook_payload(secret: &Secret<String>, timestamp: i64, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn event() -> WebhookEvent {
+        WebhookEvent::new(
+            WebhookEventType::UserSignedUp,
+            &UserId::default(),
+            &Email::parse(Secret::new("user@example.com".to_owned())).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_event_type_round_trips() {
+        for event_type in WebhookEventType::ALL {
+            assert_eq!(
+                WebhookEventType::parse(event_type.as_str()).unwrap(),
+                event_type
+            );
+        }
+        assert!(WebhookEventType::parse("user.unknown").is_err());
+    }
+
+    #[test]
+    fn test_payload_contains_event_and_user() {
+        let event = event();
+        let payload: serde_json::Value = serde_json::from_str(&event.payload().unwrap()).unwrap();
+
+        assert_eq!(payload["id"], event.id.to_string());
+        assert_eq!(payload["type"], "user.signed_up");
+        assert_eq!(payload["data"]["userId"], event.user_id.to_string());
+        assert_eq!(payload["data"]["email"], "user@example.com");
+    }
+
+    #[test]
+    fn test_redact_clears_only_the_email() {
+        let event = event();
+        let mut delivery = WebhookDelivery::new(Uuid::new_v4(), &event, event.payload().unwrap());
+
+        delivery.redact().unwrap();
+
+        let payload: serde_json::Value = serde_json::from_str(&delivery.payload).unwrap();
+        assert_eq!(payload["id"], event.id.to_string());
+        assert_eq!(payload["data"]["userId"], event.user_id.to_string());
+        assert!(payload["data"]["email"].is_null());
+    }
+
+    #[test]
+    fn test_signature_depends_on_secret_timestamp_and_payload() {
+        let secret = Secret::new("whsec_test".to_owned());
+        let signature = sign_webhook_payload(&secret, 1700000000, "{}");
+
+        assert_eq!(signature.len(), 64);
+        assert_eq!(signature, sign_webhook_payload(&secret, 1700000000, "{}"));
+        assert_ne!(
+            signature,
+            sign_webhook_payload(&Secret::new("whsec_other".to_owned()), 1700000000, "{}")
+        );
+        assert_ne!(signature, sign_webhook_payload(&secret, 1700000001, "{}"));
+        assert_ne!(signature, sign_webhook_payload(&secret, 1700000000, "[]"));
+    }
+
+    #[test]
+    fn test_failures_back_off_then_dead_letter() {
+        let event = event();
+        let mut delivery = WebhookDelivery::new(Uuid::new_v4(), &event, "{}".to_owned());
+        let now = Utc::now();
+
+        delivery.record_failure("HTTP 500".to_owned(), now);
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(
+            delivery.next_attempt_at,
+            now + Duration::try_seconds(30).unwrap()
+        );
+
+        delivery.record_failure("HTTP 500".to_owned(), now);
+        assert_eq!(
+            delivery.next_attempt_at,
+            now + Duration::try_seconds(60).unwrap()
+        );
+
+        for _ in 2..WEBHOOK_MAX_ATTEMPTS - 1 {
+            delivery.record_failure("HTTP 500".to_owned(), now);
+        }
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+        assert!(delivery.next_attempt_at <= now + Duration::try_hours(6).unwrap());
+
+        delivery.record_failure("HTTP 500".to_owned(), now);
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Dead);
+        assert_eq!(delivery.attempts, WEBHOOK_MAX_ATTEMPTS);
+        assert_eq!(delivery.last_error.as_deref(), Some("HTTP 500"));
+    }
+
+    #[test]
+    fn test_replay_resets_delivery() {
+        let event = event();
+        let mut delivery = WebhookDelivery::new(Uuid::new_v4(), &event, "{}".to_owned());
+        for _ in 0..WEBHOOK_MAX_ATTEMPTS {
+            delivery.record_failure("timeout".to_owned(), Utc::now());
+        }
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Dead);
+
+        let now = Utc::now();
+        delivery.replay(now);
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(delivery.attempts, 0);
+        assert_eq!(delivery.next_attempt_at, now);
+        assert_eq!(delivery.last_error, None);
+
+        delivery.record_success(now);
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Delivered);
+        assert_eq!(delivery.delivered_at, Some(now));
+    }
+}
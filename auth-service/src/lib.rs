@@ -1,34 +1,65 @@
 use axum::{
-    http::{Method, StatusCode},
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo},
+    handler::Handler,
+    http::{HeaderName, HeaderValue, Method, StatusCode},
+    middleware::{self, AddExtension},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{delete, get, post},
     serve::Serve,
-    Json, Router,
+    Extension, Router,
 };
+use redis::aio::ConnectionManager;
 use redis::Client;
 use redis::RedisResult;
-use serde::{Deserialize, Serialize};
-use sqlx::postgres::PgPoolOptions;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use sqlx::PgPool;
-use std::error::Error;
-use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
-use utils::tracing::{make_span_with_request_id, on_request, on_response};
+use std::{error::Error, net::SocketAddr, time::Duration};
+use tower_http::{
+    cors::CorsLayer,
+    request_id::{PropagateRequestIdLayer, SetRequestIdLayer},
+    services::ServeDir,
+    trace::TraceLayer,
+};
+use utils::{
+    audit::{record_audit_event, AuditReason, REQUEST_ID_HEADER},
+    constants::prod,
+    problem::{attach_request_id, ProblemDetails},
+    shutdown::Shutdown,
+    tracing::{
+        make_span_with_request_id, on_request, on_response, record_route, MakeRequestIdFromTrace,
+    },
+};
 
 use crate::{
     app_state::AppState,
-    domain::AuthAPIError,
-    routes::{login, logout, signup, verify_2fa, verify_token},
+    domain::{AuditEventType, AuthAPIError},
+    routes::{
+        api_docs_page, cancel_email_change, change_password, clear_email_undeliverable,
+        confirm_email_change, create_webhook_subscription, delete_account,
+        delete_webhook_subscription, email_change_page, export_account,
+        finish_passkey_registration, get_account, list_audit_events, list_undeliverable_emails,
+        list_webhook_deliveries, list_webhook_subscriptions, liveness, login, login_with_passkey,
+        logout, magic_link_callback, magic_link_callback_page, openapi_spec, readiness,
+        receive_postmark_event, render_metrics, replay_webhook_delivery, request_email_change,
+        request_magic_link, request_phone_verification, restore_account, signup,
+        start_passkey_authentication, start_passkey_registration, update_two_fa_channel,
+        verify_2fa, verify_2fa_with_passkey, verify_phone_number, verify_token,
+    },
 };
 
 pub mod app_state;
 pub mod domain;
 pub mod routes;
 pub mod services;
+pub mod settings;
 pub mod utils;
 
 // This struct encapsulates our application-related logic.
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: Serve<
+        IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+        AddExtension<Router, ConnectInfo<SocketAddr>>,
+    >,
 
     // address is exposed as a public field
     // so we have access to it in tests.
@@ -36,29 +67,193 @@ pub struct Application {
 }
 
 impl Application {
-    pub async fn build(app_state: AppState, address: &str) -> Result<Self, Box<dyn Error>> {
+    // The address to listen on and the allowed origins come from `app_state.settings`.
+    pub async fn build(app_state: AppState) -> Result<Self, Box<dyn Error>> {
+        let settings = app_state.settings.clone();
+
         // Allow the app service(running on our local machine and in production) to call the auth service
-        let allowed_origins = [
-            "http://localhost:8000".parse()?,
-            // TODO: Replace [YOUR_DROPLET_IP] with your Droplet IP address
-            "http://[YOUR_DROPLET_IP]:8000".parse()?,
-        ];
+        let allowed_origins = settings
+            .application
+            .allowed_origins
+            .iter()
+            .map(|origin| origin.parse())
+            .collect::<Result<Vec<HeaderValue>, _>>()?;
 
         let cors = CorsLayer::new()
-            // Allow GET and POST requests
-            .allow_methods([Method::GET, Method::POST])
+            // Allow GET, POST and DELETE requests
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
             // Allow cookies to be included in requests
             .allow_credentials(true)
             .allow_origin(allowed_origins);
 
+        let request_id_header = HeaderName::from_static(REQUEST_ID_HEADER);
+
+        // Probes and scrapes come every few seconds, so they stay out of the request logs, the
+        // request metrics and the audit log.
+        let operations_router = Router::new()
+            .route("/health/live", get(liveness))
+            .route("/health/ready", get(readiness))
+            .route("/metrics", get(render_metrics))
+            .with_state(app_state.clone());
+
+        // Every route in `routes/` records an audit event of its own type.
+        let audited = |event_type| {
+            middleware::from_fn_with_state((app_state.clone(), event_type), record_audit_event)
+        };
+
         let router = Router::new()
             .nest_service("/", ServeDir::new("assets"))
-            .route("/signup", post(signup))
-            .route("/login", post(login))
-            .route("/logout", post(logout))
-            .route("/verify-2fa", post(verify_2fa))
-            .route("/verify-token", post(verify_token))
+            .route(
+                "/signup",
+                post(signup.layer(audited(AuditEventType::Signup))),
+            )
+            .route("/openapi.json", get(openapi_spec))
+            .route("/docs", get(api_docs_page))
+            .route(
+                "/admin/audit-events",
+                get(list_audit_events.layer(audited(AuditEventType::AuditLogQuery))),
+            )
+            .route(
+                "/admin/webhooks",
+                get(list_webhook_subscriptions
+                    .layer(audited(AuditEventType::WebhookSubscriptionList)))
+                .post(
+                    create_webhook_subscription
+                        .layer(audited(AuditEventType::WebhookSubscriptionCreate)),
+                ),
+            )
+            .route(
+                "/admin/webhooks/:id",
+                delete(
+                    delete_webhook_subscription
+                        .layer(audited(AuditEventType::WebhookSubscriptionDelete)),
+                ),
+            )
+            .route(
+                "/admin/webhooks/deliveries",
+                get(list_webhook_deliveries.layer(audited(AuditEventType::WebhookDeliveryList))),
+            )
+            .route(
+                "/admin/webhooks/deliveries/:id/replay",
+                post(replay_webhook_delivery.layer(audited(AuditEventType::WebhookDeliveryReplay))),
+            )
+            .route(
+                "/admin/undeliverable-emails",
+                get(list_undeliverable_emails
+                    .layer(audited(AuditEventType::UndeliverableEmailList))),
+            )
+            .route(
+                "/admin/users/:id/email-undeliverable",
+                delete(
+                    clear_email_undeliverable
+                        .layer(audited(AuditEventType::EmailUndeliverableClear)),
+                ),
+            )
+            .route(
+                "/account",
+                get(get_account.layer(audited(AuditEventType::AccountView)))
+                    .delete(delete_account.layer(audited(AuditEventType::AccountDeletion))),
+            )
+            .route(
+                "/account/export",
+                get(export_account.layer(audited(AuditEventType::AccountExport))),
+            )
+            .route(
+                "/account/restore",
+                post(restore_account.layer(audited(AuditEventType::AccountRestore))),
+            )
+            .route(
+                "/account/password",
+                post(change_password.layer(audited(AuditEventType::PasswordChange))),
+            )
+            .route(
+                "/account/email",
+                post(request_email_change.layer(audited(AuditEventType::EmailChangeRequest))),
+            )
+            .route(
+                "/account/phone",
+                post(
+                    request_phone_verification
+                        .layer(audited(AuditEventType::PhoneVerificationRequest)),
+                ),
+            )
+            .route(
+                "/account/phone/verify",
+                post(verify_phone_number.layer(audited(AuditEventType::PhoneVerification))),
+            )
+            .route(
+                "/account/2fa-channel",
+                post(update_two_fa_channel.layer(audited(AuditEventType::TwoFAChannelChange))),
+            )
+            .route(
+                "/account/email/confirm",
+                get(email_change_page)
+                    .post(confirm_email_change.layer(audited(AuditEventType::EmailChangeConfirm))),
+            )
+            .route(
+                "/account/email/cancel",
+                get(email_change_page)
+                    .post(cancel_email_change.layer(audited(AuditEventType::EmailChangeCancel))),
+            )
+            .route("/login", post(login.layer(audited(AuditEventType::Login))))
+            .route(
+                "/login/magic-link",
+                post(request_magic_link.layer(audited(AuditEventType::MagicLinkRequest))),
+            )
+            .route(
+                "/login/magic-link/callback",
+                get(magic_link_callback_page)
+                    .post(magic_link_callback.layer(audited(AuditEventType::MagicLinkLogin))),
+            )
+            .route(
+                "/login/passkey",
+                post(login_with_passkey.layer(audited(AuditEventType::PasskeyLogin))),
+            )
+            .route(
+                "/logout",
+                post(logout.layer(audited(AuditEventType::Logout))),
+            )
+            .route(
+                "/passkeys/register/start",
+                post(
+                    start_passkey_registration
+                        .layer(audited(AuditEventType::PasskeyRegistrationStart)),
+                ),
+            )
+            .route(
+                "/passkeys/register/finish",
+                post(
+                    finish_passkey_registration.layer(audited(AuditEventType::PasskeyRegistration)),
+                ),
+            )
+            .route(
+                "/passkeys/authenticate/start",
+                post(
+                    start_passkey_authentication
+                        .layer(audited(AuditEventType::PasskeyAuthenticationStart)),
+                ),
+            )
+            .route(
+                "/verify-2fa",
+                post(verify_2fa.layer(audited(AuditEventType::Verify2FA))),
+            )
+            .route(
+                "/verify-2fa/passkey",
+                post(verify_2fa_with_passkey.layer(audited(AuditEventType::PasskeyVerify2FA))),
+            )
+            .route(
+                "/verify-token",
+                post(verify_token.layer(audited(AuditEventType::VerifyToken))),
+            )
+            .route(
+                "/webhooks/postmark",
+                post(
+                    receive_postmark_event.layer(audited(AuditEventType::EmailUndeliverableReport)),
+                ),
+            )
+            .route_layer(middleware::from_fn(record_route))
             .with_state(app_state)
+            .layer(middleware::from_fn(attach_request_id))
             .layer(cors)
             .layer(
                 // Add a TraceLayer for HTTP requests to enable detailed tracing
@@ -68,11 +263,22 @@ impl Application {
                     .make_span_with(make_span_with_request_id)
                     .on_request(on_request)
                     .on_response(on_response),
-            );
+            )
+            .merge(operations_router)
+            // Every request gets an id, unless the caller already sent one. It's echoed back
+            // in the response and ties together the logs and audit events of the request.
+            .layer(PropagateRequestIdLayer::new(request_id_header.clone()))
+            .layer(SetRequestIdLayer::new(
+                request_id_header,
+                MakeRequestIdFromTrace,
+            ));
 
-        let listener = tokio::net::TcpListener::bind(address).await?;
+        let listener = tokio::net::TcpListener::bind(&settings.application.address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        let server = axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        );
 
         Ok(Application { server, address })
     }
@@ -81,35 +287,144 @@ impl Application {
         tracing::info!("listening on {}", &self.address);
         self.server.await
     }
-}
 
-#[derive(Serialize, Deserialize)]
-pub struct ErrorResponse {
-    pub error: String,
+    // Like `run`, but once `shutdown` is triggered, which fails readiness, it keeps serving for
+    // `drain_delay` so load balancers notice before connections are refused. Then it stops
+    // accepting connections and waits for the in-flight requests, for at most `drain_timeout`.
+    pub async fn run_until_shutdown(
+        self,
+        shutdown: Shutdown,
+        drain_delay: Duration,
+        drain_timeout: Duration,
+    ) -> Result<(), std::io::Error> {
+        tracing::info!("listening on {}", &self.address);
+        let signal = shutdown.clone();
+        let server = self.server.with_graceful_shutdown(async move {
+            signal.triggered().await;
+            tokio::time::sleep(drain_delay).await;
+        });
+
+        tokio::select! {
+            result = async { server.await } => result,
+            _ = async {
+                shutdown.triggered().await;
+                tokio::time::sleep(drain_delay + drain_timeout).await;
+            } => {
+                tracing::warn!("gave up waiting for in-flight requests after {:?}", drain_timeout);
+                Ok(())
+            }
+        }
+    }
 }
 
 impl IntoResponse for AuthAPIError {
     fn into_response(self) -> Response {
         log_error_chain(&self);
-        let (status, error_message) = match self {
-            AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
-            AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
-            AuthAPIError::UnexpectedError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error")
-            }
+        let mut detail = None;
+        let mut errors = Vec::new();
+        let (status, code, error_message) = match self {
+            AuthAPIError::UserAlreadyExists => (
+                StatusCode::CONFLICT,
+                "auth.user_already_exists",
+                "User already exists",
+            ),
+            AuthAPIError::InvalidCredentials => (
+                StatusCode::BAD_REQUEST,
+                "auth.invalid_credentials",
+                "Invalid credentials",
+            ),
+            AuthAPIError::UnexpectedError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal.unexpected_error",
+                "Unexpected error",
+            ),
             //AuthAPIError::UnexpectedError => {
             //    (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error")
             //}
-            AuthAPIError::IncorrectCredentials => {
-                (StatusCode::UNAUTHORIZED, "Incorrect credentials")
+            AuthAPIError::IncorrectCredentials => (
+                StatusCode::UNAUTHORIZED,
+                "auth.incorrect_credentials",
+                "Incorrect credentials",
+            ),
+            AuthAPIError::MissingToken => (
+                StatusCode::BAD_REQUEST,
+                "auth.missing_token",
+                "Missing token",
+            ),
+            AuthAPIError::InvalidToken => (
+                StatusCode::UNAUTHORIZED,
+                "auth.invalid_token",
+                "Invalid auth token",
+            ),
+            AuthAPIError::PasskeyAlreadyExists => (
+                StatusCode::CONFLICT,
+                "passkey.already_registered",
+                "Passkey already registered",
+            ),
+            AuthAPIError::InvalidQuery => (
+                StatusCode::BAD_REQUEST,
+                "request.invalid_query",
+                "Invalid query",
+            ),
+            AuthAPIError::InvalidWebhookSubscription => (
+                StatusCode::BAD_REQUEST,
+                "webhook.invalid_subscription",
+                "Invalid webhook subscription",
+            ),
+            AuthAPIError::NotFound => (StatusCode::NOT_FOUND, "request.not_found", "Not found"),
+            AuthAPIError::EmailDeliveryFailed => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "email.delivery_failed",
+                "Failed to send email",
+            ),
+            AuthAPIError::EmailUndeliverable => (
+                StatusCode::CONFLICT,
+                "email.undeliverable",
+                "Email address is undeliverable",
+            ),
+            AuthAPIError::SmsDeliveryFailed => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "sms.delivery_failed",
+                "Failed to send SMS",
+            ),
+            AuthAPIError::PhoneNumberNotVerified => (
+                StatusCode::CONFLICT,
+                "phone.not_verified",
+                "Phone number not verified",
+            ),
+            AuthAPIError::TooManyPhoneVerificationCodes => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "phone.too_many_codes",
+                "Too many verification codes requested",
+            ),
+            AuthAPIError::ValidationFailed(field_errors) => {
+                errors = field_errors;
+                (
+                    StatusCode::BAD_REQUEST,
+                    "request.validation_failed",
+                    "Validation failed",
+                )
+            }
+            // axum picks the status: 415 without a JSON content type, 400 for broken JSON and
+            // 422 when the JSON doesn't fit the request type.
+            AuthAPIError::MalformedBody(rejection) => {
+                detail = Some(rejection.body_text());
+                (
+                    rejection.status(),
+                    "request.malformed_body",
+                    "Malformed request body",
+                )
             }
-            AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Missing token"),
-            AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid auth token"),
         };
-        let body = Json(ErrorResponse {
-            error: error_message.to_string(),
-        });
-        (status, body).into_response()
+        let problem = ProblemDetails {
+            errors,
+            ..ProblemDetails::new(
+                status,
+                code,
+                detail.unwrap_or_else(|| error_message.to_owned()),
+            )
+        };
+        (Extension(AuditReason(error_message)), problem).into_response()
     }
 }
 
@@ -127,12 +442,29 @@ fn log_error_chain(e: &(dyn Error + 'static)) {
     tracing::error!("{}", report);
 }
 
-pub async fn get_postgres_pool(url: &str) -> Result<PgPool, sqlx::Error> {
+pub async fn get_postgres_pool(options: PgConnectOptions) -> Result<PgPool, sqlx::Error> {
     // Create a new PostgreSQL connection pool
-    PgPoolOptions::new().max_connections(5).connect(url).await
+    PgPoolOptions::new()
+        .max_connections(5)
+        .connect_with(options)
+        .await
 }
 
 pub fn get_redis_client(redis_hostname: String) -> RedisResult<Client> {
     let redis_url = format!("redis://{}/", redis_hostname);
     redis::Client::open(redis_url)
 }
+
+// One multiplexed connection for every store. It reconnects on its own after Redis goes away, and
+// commands fail after a timeout instead of hanging the request.
+pub async fn get_redis_connection(client: Client) -> RedisResult<ConnectionManager> {
+    ConnectionManager::new_with_backoff_and_timeouts(
+        client,
+        prod::redis::RETRY_EXPONENT_BASE,
+        prod::redis::RETRY_FACTOR,
+        prod::redis::RETRIES,
+        prod::redis::RESPONSE_TIMEOUT,
+        prod::redis::CONNECTION_TIMEOUT,
+    )
+    .await
+}
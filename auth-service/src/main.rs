@@ -1,71 +1,234 @@
+use redis::aio::ConnectionManager;
 use reqwest::Client;
-use secrecy::Secret;
 use sqlx::PgPool;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::{process::ExitCode, sync::Arc};
 
 use auth_service::{
-    app_state::{AppState, BannedTokenStoreType, TwoFACodeStoreType},
-    domain::Email,
+    app_state::{
+        AppState, AuditLogStoreType, BannedTokenStoreType, EmailClientType, EmailOutboxStoreType,
+        HealthCheckType, PasskeyStoreType, PhoneVerificationStoreType, SessionStoreType,
+        SmsClientType, TwoFACodeStoreType, UserStoreType, WebhookStoreType,
+    },
+    domain::{EmailProvider, SmsProvider},
     get_postgres_pool,
     get_redis_client,
+    get_redis_connection,
     //services::data_stores::hashmap_two_fa_code_store::HashmapTwoFACodeStore,
     //services::data_stores::hashmap_user_store::HashmapUserStore,
     //services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore,
+    services::account_purger::spawn_account_purger,
+    services::data_stores::postgres_audit_log_store::PostgresAuditLogStore,
+    services::data_stores::postgres_email_outbox_store::PostgresEmailOutboxStore,
+    services::data_stores::postgres_passkey_store::PostgresPasskeyStore,
     services::data_stores::postgres_user_store::PostgresUserStore,
+    services::data_stores::postgres_webhook_store::PostgresWebhookStore,
     services::data_stores::redis_banned_token_store::RedisBannedTokenStore,
+    services::data_stores::redis_phone_verification_store::RedisPhoneVerificationStore,
+    services::data_stores::redis_session_store::RedisSessionStore,
     services::data_stores::redis_two_fa_code_store::RedisTwoFACodeStore,
-    //services::mock_email_client::MockEmailClient,
+    services::email_outbox::spawn_email_dispatcher,
+    services::failover_email_client::FailoverEmailClient,
+    services::health_checks::{EmailHealthCheck, PostgresHealthCheck, RedisHealthCheck},
+    services::metrics_sampler::spawn_metrics_sampler,
+    services::mock_email_client::MockEmailClient,
+    services::mock_sms_client::MockSmsClient,
     services::postmark_email_client::PostmarkEmailClient,
+    services::secret_reloader::spawn_secret_reloader,
+    services::smtp_email_client::SmtpEmailClient,
+    services::suppressing_email_client::SuppressingEmailClient,
+    services::twilio_sms_client::TwilioSmsClient,
+    services::webhooks::spawn_webhook_dispatcher,
+    settings::{EmailSettings, Settings, SmsSettings},
     utils::{
-        constants::{prod, DATABASE_URL, POSTMARK_AUTH_TOKEN, REDIS_HOST_NAME},
+        constants::prod,
         init_tracing,
+        metrics::prometheus_handle,
+        shutdown::{wait_for_shutdown_signal, Shutdown},
+        webauthn::build_webauthn,
     },
     Application,
 };
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     color_eyre::install().expect("Failed to install color_eyre");
-    init_tracing().expect("Failed to initialize tracing");
+
+    // Tracing is configured by the settings, so an invalid setting can only go to stderr.
+    let settings = match Settings::load().await {
+        Ok(settings) => Arc::new(settings),
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let tracer_provider =
+        init_tracing(&settings.logging, &settings.telemetry).expect("Failed to initialize tracing");
+    // Before anything records metrics, which would be lost otherwise.
+    let metrics = prometheus_handle();
     //init_tracing();
 
-    let pg_pool = configure_postgresql().await;
-    //let user_store = Arc::new(RwLock::new(HashmapUserStore::default()));
-    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+    let pg_pool = configure_postgresql(&settings).await;
+    //let user_store = Arc::new(HashmapUserStore::default());
+    let user_store: UserStoreType = Arc::new(PostgresUserStore::new(
+        pg_pool.clone(),
+        settings.password_hashing.params(),
+    ));
 
-    //let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-    let redis_connection = Arc::new(RwLock::new(configure_redis()));
-    let banned_token_store: BannedTokenStoreType = Arc::new(RwLock::new(
-        RedisBannedTokenStore::new(redis_connection.clone()),
+    //let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+    // Every Redis store and the health check share this connection.
+    let redis_connection = configure_redis(&settings).await;
+    let banned_token_store: BannedTokenStoreType = Arc::new(RedisBannedTokenStore::new(
+        redis_connection.clone(),
+        settings.jwt.token_ttl_seconds,
     ));
 
-    //let two_fa_code_store = Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
-    let redis_connection2 = Arc::new(RwLock::new(configure_redis()));
-    let two_fa_code_store: TwoFACodeStoreType = Arc::new(RwLock::new(RedisTwoFACodeStore::new(
-        redis_connection2.clone(),
-    )));
+    //let two_fa_code_store = Arc::new(HashmapTwoFACodeStore::default());
+    let two_fa_code_store: TwoFACodeStoreType =
+        Arc::new(RedisTwoFACodeStore::new(redis_connection.clone()));
+
+    let session_store: SessionStoreType = Arc::new(RedisSessionStore::new(
+        redis_connection.clone(),
+        settings.jwt.token_ttl_seconds,
+    ));
+
+    let phone_verification_store: PhoneVerificationStoreType =
+        Arc::new(RedisPhoneVerificationStore::new(redis_connection.clone()));
+
+    let passkey_store: PasskeyStoreType = Arc::new(PostgresPasskeyStore::new(pg_pool.clone()));
 
-    //let email_client = Arc::new(RwLock::new(MockEmailClient));
-    let email_client = Arc::new(RwLock::new(configure_postmark_email_client()));
+    let audit_log_store: AuditLogStoreType = Arc::new(PostgresAuditLogStore::new(pg_pool.clone()));
+
+    let webhook_store: WebhookStoreType = Arc::new(PostgresWebhookStore::new(pg_pool.clone()));
+
+    let email_outbox_store: EmailOutboxStoreType =
+        Arc::new(PostgresEmailOutboxStore::new(pg_pool.clone()));
+
+    let email_client = configure_email_client(&settings.email, user_store.clone());
+    let sms_client = configure_sms_client(&settings.sms);
+    let health_checks = configure_health_checks(
+        &settings,
+        pg_pool.clone(),
+        redis_connection,
+        email_client.clone(),
+    );
+
+    let shutdown = Shutdown::new();
+    let background_tasks = [
+        spawn_account_purger(
+            user_store.clone(),
+            audit_log_store.clone(),
+            email_outbox_store.clone(),
+            webhook_store.clone(),
+            prod::ACCOUNT_PURGE_INTERVAL,
+            shutdown.clone(),
+        ),
+        spawn_webhook_dispatcher(
+            webhook_store.clone(),
+            configure_webhook_http_client(),
+            prod::webhooks::DISPATCH_INTERVAL,
+            shutdown.clone(),
+        ),
+        spawn_email_dispatcher(
+            email_outbox_store.clone(),
+            email_client.clone(),
+            prod::email_outbox::DISPATCH_INTERVAL,
+            shutdown.clone(),
+        ),
+        spawn_metrics_sampler(
+            metrics.clone(),
+            pg_pool.clone(),
+            prod::METRICS_SAMPLE_INTERVAL,
+            shutdown.clone(),
+        ),
+    ];
+    spawn_secret_reloader(settings.clone(), pg_pool.clone()).expect("Failed to listen for SIGHUP");
+    let shutdown_drain_delay = settings.application.shutdown_drain_delay();
+    let shutdown_timeout = settings.application.shutdown_timeout();
 
     let app_state = AppState {
         user_store,
         banned_token_store,
         two_fa_code_store,
+        session_store,
+        passkey_store,
+        audit_log_store,
+        webhook_store,
+        email_outbox_store,
         email_client,
+        phone_verification_store,
+        sms_client,
+        health_checks,
+        shutdown: shutdown.clone(),
+        metrics,
+        webauthn: Arc::new(build_webauthn(&settings.application.base_url)),
+        settings,
     };
 
-    let app = Application::build(app_state, prod::APP_ADDRESS)
+    let app = Application::build(app_state)
         .await
         .expect("Failed to build app");
 
-    app.run().await.expect("Failed to run app");
+    tokio::spawn(trigger_shutdown_on_signal(shutdown.clone()));
+    if let Err(e) = app
+        .run_until_shutdown(shutdown.clone(), shutdown_drain_delay, shutdown_timeout)
+        .await
+    {
+        tracing::error!("server failed: {}", e);
+    }
+
+    // The stores and their Redis connection went away with the server. What's left is the
+    // background work, which still needs the database.
+    shutdown.trigger();
+    let finish_background_tasks = async {
+        for task in background_tasks {
+            if let Err(e) = task.await {
+                tracing::error!("background task failed: {}", e);
+            }
+        }
+    };
+    if tokio::time::timeout(shutdown_timeout, finish_background_tasks)
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            "gave up waiting for background work after {:?}",
+            shutdown_timeout
+        );
+    }
+    pg_pool.close().await;
+
+    // Sends the spans that are still queued, which blocks until the collector answered.
+    if let Some(tracer_provider) = tracer_provider {
+        match tokio::task::spawn_blocking(move || tracer_provider.shutdown()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!("failed to export the last spans: {}", e),
+            Err(e) => tracing::error!("failed to export the last spans: {}", e),
+        }
+    }
+
+    tracing::info!("shut down");
+    ExitCode::SUCCESS
+}
+
+async fn trigger_shutdown_on_signal(shutdown: Shutdown) {
+    match wait_for_shutdown_signal().await {
+        Ok(()) => {
+            tracing::info!("shutting down");
+            shutdown.trigger();
+        }
+        Err(e) => tracing::error!("failed to listen for shutdown signals: {}", e),
+    }
 }
 
-async fn configure_postgresql() -> PgPool {
+async fn configure_postgresql(settings: &Settings) -> PgPool {
     // Create a new database connection pool
-    let pg_pool = get_postgres_pool(&DATABASE_URL)
+    // Checked when the settings are loaded.
+    let connect_options = settings
+        .database
+        .connect_options()
+        .expect("Database url must be valid");
+    let pg_pool = get_postgres_pool(connect_options)
         .await
         .expect("Failed to create Postgres connection pool!");
 
@@ -78,14 +241,93 @@ async fn configure_postgresql() -> PgPool {
     pg_pool
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
+async fn configure_redis(settings: &Settings) -> ConnectionManager {
+    let client =
+        get_redis_client(settings.redis.host_name.to_owned()).expect("Failed to get Redis client");
+    get_redis_connection(client)
+        .await
         .expect("Failed to get Redis connection")
 }
 
-fn configure_postmark_email_client() -> PostmarkEmailClient {
+fn configure_health_checks(
+    settings: &Settings,
+    pg_pool: PgPool,
+    redis_connection: ConnectionManager,
+    email_client: EmailClientType,
+) -> Vec<HealthCheckType> {
+    let mut health_checks: Vec<HealthCheckType> = vec![
+        Arc::new(PostgresHealthCheck::new(pg_pool)),
+        Arc::new(RedisHealthCheck::new(redis_connection)),
+    ];
+    if settings.health.check_email_provider {
+        health_checks.push(Arc::new(EmailHealthCheck::new(email_client)));
+    }
+    health_checks
+}
+
+fn configure_webhook_http_client() -> Client {
+    Client::builder()
+        .timeout(prod::webhooks::TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client")
+}
+
+// Every provider sits behind a circuit breaker, and later ones take over when the earlier
+// ones fail. Nothing is sent to addresses that bounced or complained about spam.
+fn configure_email_client(settings: &EmailSettings, user_store: UserStoreType) -> EmailClientType {
+    tracing::info!("Sending emails with {:?}", settings.providers);
+    let mut email_client = FailoverEmailClient::new(prod::email_failover::CIRCUIT_BREAKER);
+    for provider in settings.providers.iter() {
+        email_client = match provider {
+            EmailProvider::Postmark => email_client
+                .with_provider(provider.as_str(), configure_postmark_email_client(settings)),
+            EmailProvider::Smtp => {
+                email_client.with_provider(provider.as_str(), configure_smtp_email_client(settings))
+            }
+            EmailProvider::Mock => email_client.with_provider(provider.as_str(), MockEmailClient),
+        };
+    }
+    Arc::new(SuppressingEmailClient::new(email_client, user_store))
+}
+
+fn configure_sms_client(settings: &SmsSettings) -> SmsClientType {
+    tracing::info!("Sending SMS with {}", settings.provider.as_str());
+    match settings.provider {
+        SmsProvider::Twilio => {
+            // Checked when the settings are loaded.
+            let twilio = settings
+                .twilio
+                .as_ref()
+                .expect("Twilio settings must be set");
+
+            let http_client = Client::builder()
+                .timeout(prod::sms_client::TIMEOUT)
+                .build()
+                .expect("Failed to build HTTP client");
+
+            Arc::new(TwilioSmsClient::new(
+                prod::sms_client::BASE_URL.to_owned(),
+                twilio.account_sid.clone(),
+                twilio.auth_token.clone(),
+                twilio.sender.clone(),
+                http_client,
+            ))
+        }
+        SmsProvider::Mock => Arc::new(MockSmsClient),
+    }
+}
+
+fn configure_smtp_email_client(settings: &EmailSettings) -> SmtpEmailClient {
+    // Checked when the settings are loaded.
+    let smtp = settings
+        .smtp
+        .smtp_settings()
+        .expect("SMTP settings must be set");
+
+    SmtpEmailClient::new(&smtp, settings.sender.clone()).expect("Failed to build SMTP email client")
+}
+
+fn configure_postmark_email_client(settings: &EmailSettings) -> PostmarkEmailClient {
     let http_client = Client::builder()
         .timeout(prod::email_client::TIMEOUT)
         .build()
@@ -93,8 +335,13 @@ fn configure_postmark_email_client() -> PostmarkEmailClient {
 
     PostmarkEmailClient::new(
         prod::email_client::BASE_URL.to_owned(),
-        Email::parse(Secret::new(prod::email_client::SENDER.to_owned())).unwrap(),
-        POSTMARK_AUTH_TOKEN.to_owned(),
+        settings.sender.clone(),
+        // Checked when the settings are loaded.
+        settings
+            .postmark
+            .auth_token
+            .clone()
+            .expect("Postmark auth token must be set"),
         http_client,
     )
 }
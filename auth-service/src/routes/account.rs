@@ -0,0 +1,503 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, EmailUndeliverable, FieldError, Password, TwoFACodeStoreError, User,
+        UserId, UserStoreError, WebhookEvent, WebhookEventType,
+    },
+    services::{
+        email_outbox::send_email,
+        email_templates::{EmailTemplate, SecurityNotice},
+        webhooks::publish_webhook_event,
+    },
+    utils::{
+        audit::AuditUser,
+        auth::{
+            consume_token, generate_email_change_cancel_token, generate_email_change_confirm_token,
+            revoke_all_sessions, revoke_other_sessions, validate_email_change_token,
+            validate_token, EMAIL_CHANGE_CANCEL_AUDIENCE, EMAIL_CHANGE_CONFIRM_AUDIENCE,
+        },
+        constants::JWT_COOKIE_NAME,
+        extract::ApiJson,
+        problem::ProblemDetails,
+    },
+};
+
+// Returns the user and auth token of the session in the jwt cookie, and records the user as
+// the one the request acts on.
+pub(super) async fn authenticate_session(
+    state: &AppState,
+    jar: &CookieJar,
+    audit_user: &AuditUser,
+) -> Result<(User, String), AuthAPIError> {
+    let token = jar
+        .get(JWT_COOKIE_NAME)
+        .ok_or(AuthAPIError::MissingToken)?
+        .value()
+        .to_owned();
+
+    let claims = validate_token(
+        &token,
+        state.banned_token_store.clone(),
+        &state.settings.jwt,
+    )
+    .await
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user = match state.user_store.get_user_by_id(&user_id).await {
+        Ok(user) => user,
+        Err(UserStoreError::UnexpectedError(e)) => return Err(AuthAPIError::UnexpectedError(e)),
+        Err(_) => return Err(AuthAPIError::InvalidToken),
+    };
+    audit_user.set(&user.id);
+
+    Ok((user, token))
+}
+
+// Fails with `IncorrectCredentials` unless `password` is the user's current password.
+pub(super) async fn check_password(
+    state: &AppState,
+    email: &Email,
+    password: Secret<String>,
+) -> Result<(), AuthAPIError> {
+    let password = Password::parse(password).map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    match state.user_store.validate_user(email, &password).await {
+        Ok(()) => Ok(()),
+        Err(UserStoreError::UnexpectedError(e)) => Err(AuthAPIError::UnexpectedError(e)),
+        Err(_) => Err(AuthAPIError::IncorrectCredentials),
+    }
+}
+
+// Returns the logged in user's account, including whether their email address stopped
+// accepting email, so they know to change it.
+#[utoipa::path(
+    get,
+    path = "/account",
+    tag = "account",
+    security(("session" = [])),
+    responses(
+        (status = 200, description = "The logged in user's account", body = AccountResponse),
+        (status = 400, description = "Missing auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "get_account", skip_all)]
+pub async fn get_account(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user, _) = authenticate_session(&state, &jar, &audit_user).await?;
+
+    Ok((StatusCode::OK, Json(AccountResponse::from(user))))
+}
+
+#[utoipa::path(
+    post,
+    path = "/account/password",
+    tag = "account",
+    request_body = ChangePasswordRequest,
+    security(("session" = [])),
+    responses(
+        (status = 200, description = "Password changed. Other sessions are logged out."),
+        (status = 400, description = "Missing auth cookie or invalid new password", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid auth cookie or incorrect current password", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "The security notice could not be sent", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "change_password", skip_all)]
+pub async fn change_password(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<ChangePasswordRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user, token) = authenticate_session(&state, &jar, &audit_user).await?;
+
+    check_password(&state, &user.email, request.current_password).await?;
+
+    let new_password = Password::parse(request.new_password)
+        .map_err(|_| FieldError::invalid_password("newPassword"))?;
+
+    state
+        .user_store
+        .update_password(&user.id, new_password)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // Anyone who got in with the old password is logged out. The session making the change stays.
+    revoke_other_sessions(
+        &user.id,
+        &token,
+        state.session_store.clone(),
+        state.banned_token_store.clone(),
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    send_email(
+        &state,
+        &user.id,
+        &user.email,
+        user.locale,
+        EmailTemplate::SecurityNotice(SecurityNotice::PasswordChanged),
+    )
+    .await?;
+
+    Ok(StatusCode::OK)
+}
+
+// Nothing changes until the link sent to the new address is used. The old address is told
+// about the request and gets a link to cancel it.
+#[utoipa::path(
+    post,
+    path = "/account/email",
+    tag = "account",
+    request_body = ChangeEmailRequest,
+    security(("session" = [])),
+    responses(
+        (status = 202, description = "A confirmation link was sent to the new address"),
+        (status = 400, description = "Missing auth cookie or invalid new email", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid auth cookie or incorrect password", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The new address is taken or undeliverable", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "The confirmation link could not be sent", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "request_email_change", skip_all)]
+pub async fn request_email_change(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<ChangeEmailRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user, _) = authenticate_session(&state, &jar, &audit_user).await?;
+
+    check_password(&state, &user.email, request.password).await?;
+
+    let new_email =
+        Email::parse(request.new_email).map_err(|_| FieldError::invalid_email("newEmail"))?;
+
+    if new_email == user.email {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    if state.user_store.get_user(&new_email).await.is_ok() {
+        return Err(AuthAPIError::UserAlreadyExists);
+    }
+
+    // Each link has its own id. Cancelling also bans the confirm link's id, and changes the
+    // address back if the change was already confirmed.
+    let change_id = uuid::Uuid::new_v4().to_string();
+    let cancel_id = uuid::Uuid::new_v4().to_string();
+    let confirm_token =
+        generate_email_change_confirm_token(&user.id, &new_email, &change_id, &state.settings.jwt)
+            .map_err(AuthAPIError::UnexpectedError)?;
+    let cancel_token = generate_email_change_cancel_token(
+        &user.id,
+        &user.email,
+        &new_email,
+        &change_id,
+        &cancel_id,
+        &state.settings.jwt,
+    )
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    let confirm_link = format!(
+        "{}/account/email/confirm?token={}",
+        state.settings.application.base_url.as_str(),
+        confirm_token
+    );
+    let cancel_link = format!(
+        "{}/account/email/cancel?token={}",
+        state.settings.application.base_url.as_str(),
+        cancel_token
+    );
+
+    send_email(
+        &state,
+        &user.id,
+        &new_email,
+        user.locale,
+        EmailTemplate::Verification {
+            link: &confirm_link,
+            ttl_minutes: state.settings.jwt.token_ttl_seconds / 60,
+        },
+    )
+    .await?;
+    send_email(
+        &state,
+        &user.id,
+        &user.email,
+        user.locale,
+        EmailTemplate::SecurityNotice(SecurityNotice::EmailChangeRequested {
+            new_email: new_email.as_ref().expose_secret(),
+            cancel_link: &cancel_link,
+        }),
+    )
+    .await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+// Like the magic link, the emailed links only serve a page, which POSTs the token once the
+// user clicks. Link scanners can't confirm or cancel a change.
+#[tracing::instrument(name = "email_change_page", skip_all)]
+pub async fn email_change_page() -> Html<&'static str> {
+    Html(include_str!("../../assets/email_change.html"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/account/email/confirm",
+    tag = "account",
+    request_body = EmailChangeTokenRequest,
+    responses(
+        (status = 200, description = "Email address changed. All sessions are logged out."),
+        (status = 401, description = "Invalid or used link", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The new address was taken in the meantime", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "confirm_email_change", skip_all)]
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<EmailChangeTokenRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let claims = validate_email_change_token(
+        &request.token,
+        EMAIL_CHANGE_CONFIRM_AUDIENCE,
+        &state.settings.jwt,
+    )
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+    audit_user.set(&user_id);
+    let new_email =
+        Email::parse(Secret::new(claims.new_email)).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // The link is only used up once the change went through, so a failed attempt can be
+    // retried.
+    ensure_link_unused(&state, &claims.change_id).await?;
+
+    apply_email_change(&state, &user_id, &new_email).await?;
+
+    consume_link_id(&state, &claims.change_id).await?;
+
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(
+    post,
+    path = "/account/email/cancel",
+    tag = "account",
+    request_body = EmailChangeTokenRequest,
+    responses(
+        (status = 200, description = "Email change cancelled. If it was already confirmed, the old address is restored and all sessions are logged out."),
+        (status = 401, description = "Invalid or used link", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The old address was taken in the meantime", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "cancel_email_change", skip_all)]
+pub async fn cancel_email_change(
+    State(state): State<AppState>,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<EmailChangeTokenRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let claims = validate_email_change_token(
+        &request.token,
+        EMAIL_CHANGE_CANCEL_AUDIENCE,
+        &state.settings.jwt,
+    )
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+    audit_user.set(&user_id);
+    let (Some(cancel_id), Some(old_email)) = (claims.cancel_id, claims.old_email) else {
+        return Err(AuthAPIError::InvalidToken);
+    };
+    let old_email = Email::parse(Secret::new(old_email)).map_err(|_| AuthAPIError::InvalidToken)?;
+    let new_email =
+        Email::parse(Secret::new(claims.new_email)).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    ensure_link_unused(&state, &cancel_id).await?;
+
+    // From here on the confirm link can't be used, whether or not it already was.
+    consume_token(
+        &link_key(&claims.change_id),
+        state.banned_token_store.clone(),
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    let user = state
+        .user_store
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    // The change was confirmed, so whoever controls the new address may be logged in.
+    if user.email == new_email {
+        apply_email_change(&state, &user_id, &old_email).await?;
+    }
+
+    consume_link_id(&state, &cancel_id).await?;
+
+    Ok(StatusCode::OK)
+}
+
+// Moves the user to `email` and logs out every session, which was started with the previous
+// address. A pending 2FA code was sent to the previous address and is dropped too.
+async fn apply_email_change(
+    state: &AppState,
+    user_id: &UserId,
+    email: &Email,
+) -> Result<(), AuthAPIError> {
+    state
+        .user_store
+        .update_email(user_id, email)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserAlreadyExists => AuthAPIError::UserAlreadyExists,
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    revoke_all_sessions(
+        user_id,
+        state.session_store.clone(),
+        state.banned_token_store.clone(),
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    match state.two_fa_code_store.remove_code(user_id).await {
+        Ok(()) | Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {}
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    publish_webhook_event(
+        &state.webhook_store,
+        WebhookEvent::new(WebhookEventType::EmailChanged, user_id, email),
+    )
+    .await;
+
+    Ok(())
+}
+
+fn link_key(link_id: &str) -> String {
+    format!("email_change:{}", link_id)
+}
+
+async fn ensure_link_unused(state: &AppState, link_id: &str) -> Result<(), AuthAPIError> {
+    match state
+        .banned_token_store
+        .contains_token(&link_key(link_id))
+        .await
+    {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(AuthAPIError::InvalidToken),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// Fails if the link was used in the meantime, by a request that raced this one.
+async fn consume_link_id(state: &AppState, link_id: &str) -> Result<(), AuthAPIError> {
+    match consume_token(&link_key(link_id), state.banned_token_store.clone()).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(AuthAPIError::InvalidToken),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e)),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AccountResponse {
+    pub id: String,
+    pub email: String,
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+    pub locale: String,
+    #[serde(rename = "emailUndeliverable")]
+    pub email_undeliverable: Option<EmailUndeliverableResponse>,
+    #[serde(rename = "phoneNumber")]
+    pub phone_number: Option<String>,
+    #[serde(rename = "twoFAChannel")]
+    pub two_fa_channel: String,
+}
+
+impl From<User> for AccountResponse {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id.to_string(),
+            email: user.email.as_ref().expose_secret().to_owned(),
+            requires_2fa: user.requires_2fa,
+            locale: user.locale.as_str().to_owned(),
+            email_undeliverable: user
+                .email_undeliverable
+                .map(EmailUndeliverableResponse::from),
+            phone_number: user
+                .phone_number
+                .map(|phone_number| phone_number.as_ref().expose_secret().to_owned()),
+            two_fa_channel: user.two_fa_channel.as_str().to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct EmailUndeliverableResponse {
+    pub reason: String,
+    pub since: DateTime<Utc>,
+}
+
+impl From<EmailUndeliverable> for EmailUndeliverableResponse {
+    fn from(undeliverable: EmailUndeliverable) -> Self {
+        Self {
+            reason: undeliverable.reason.as_str().to_owned(),
+            since: undeliverable.since,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    #[schema(value_type = String)]
+    pub current_password: Secret<String>,
+    #[serde(rename = "newPassword")]
+    #[schema(value_type = String)]
+    pub new_password: Secret<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ChangeEmailRequest {
+    #[serde(rename = "newEmail")]
+    #[schema(value_type = String)]
+    pub new_email: Secret<String>,
+    #[schema(value_type = String)]
+    pub password: Secret<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct EmailChangeTokenRequest {
+    pub token: String,
+}
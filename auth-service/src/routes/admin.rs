@@ -0,0 +1,269 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuditEvent, AuditEventFilter, AuditEventType, AuditOutcome, AuthAPIError, UserId,
+        UserStoreError,
+    },
+    utils::{audit::AuditUser, problem::ProblemDetails},
+};
+
+use super::account::AccountResponse;
+
+const DEFAULT_AUDIT_EVENT_LIMIT: i64 = 100;
+const MAX_AUDIT_EVENT_LIMIT: i64 = 1000;
+
+// Admin requests carry the configured token as a bearer token.
+pub(super) fn authorize_admin(state: &AppState, headers: &HeaderMap) -> Result<(), AuthAPIError> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AuthAPIError::MissingToken)?;
+
+    let expected = state
+        .settings
+        .admin
+        .api_token
+        .as_ref()
+        .ok_or(AuthAPIError::InvalidToken)?
+        .load();
+
+    if bool::from(token.as_bytes().ct_eq(expected.expose_secret().as_bytes())) {
+        Ok(())
+    } else {
+        Err(AuthAPIError::InvalidToken)
+    }
+}
+
+// Lists audit events, newest first. Older pages are fetched by passing the smallest id seen
+// so far as `beforeId`.
+#[utoipa::path(
+    get,
+    path = "/admin/audit-events",
+    tag = "admin",
+    params(AuditEventQuery),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Audit events, newest first", body = AuditEventsResponse),
+        (status = 400, description = "Missing admin token or invalid query", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid admin token", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "list_audit_events", skip_all)]
+pub async fn list_audit_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AuditEventQuery>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+
+    let filter = query.into_filter()?;
+
+    let events = state
+        .audit_log_store
+        .get_events(&filter)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(AuditEventResponse::from)
+        .collect();
+
+    Ok((StatusCode::OK, Json(AuditEventsResponse { events })))
+}
+
+// Lists the users whose email address was reported as undeliverable, most recent first.
+#[utoipa::path(
+    get,
+    path = "/admin/undeliverable-emails",
+    tag = "admin",
+    params(UndeliverableEmailQuery),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Users whose email address is undeliverable", body = UndeliverableEmailsResponse),
+        (status = 400, description = "Missing admin token or invalid query", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid admin token", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "list_undeliverable_emails", skip_all)]
+pub async fn list_undeliverable_emails(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<UndeliverableEmailQuery>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_AUDIT_EVENT_LIMIT);
+    if !(1..=MAX_AUDIT_EVENT_LIMIT).contains(&limit) {
+        return Err(AuthAPIError::InvalidQuery);
+    }
+
+    let users = state
+        .user_store
+        .get_users_with_undeliverable_email(limit)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(AccountResponse::from)
+        .collect();
+
+    Ok((StatusCode::OK, Json(UndeliverableEmailsResponse { users })))
+}
+
+// Lets email go to the user's address again, e.g. after they fixed their mailbox.
+#[utoipa::path(
+    delete,
+    path = "/admin/users/{id}/email-undeliverable",
+    tag = "admin",
+    params(("id" = String, Path, description = "User id")),
+    security(("admin_token" = [])),
+    responses(
+        (status = 204, description = "The address receives email again"),
+        (status = 400, description = "Missing admin token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid admin token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown user", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "clear_email_undeliverable", skip_all)]
+pub async fn clear_email_undeliverable(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    audit_user: AuditUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+
+    let user_id = UserId::parse(&id).map_err(|_| AuthAPIError::NotFound)?;
+    audit_user.set(&user_id);
+
+    match state.user_store.clear_email_undeliverable(&user_id).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(UserStoreError::UserNotFound) => Err(AuthAPIError::NotFound),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UndeliverableEmailQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UndeliverableEmailsResponse {
+    pub users: Vec<AccountResponse>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditEventQuery {
+    #[serde(rename = "eventType")]
+    pub event_type: Option<String>,
+    #[serde(rename = "userId")]
+    pub user_id: Option<String>,
+    pub outcome: Option<String>,
+    #[serde(rename = "ipAddress")]
+    pub ip_address: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    #[serde(rename = "beforeId")]
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl AuditEventQuery {
+    fn into_filter(self) -> Result<AuditEventFilter, AuthAPIError> {
+        let parse_time = |time: Option<String>| {
+            time.map(|time| {
+                DateTime::parse_from_rfc3339(&time)
+                    .map(|time| time.with_timezone(&Utc))
+                    .map_err(|_| AuthAPIError::InvalidQuery)
+            })
+            .transpose()
+        };
+
+        let limit = self.limit.unwrap_or(DEFAULT_AUDIT_EVENT_LIMIT);
+        if !(1..=MAX_AUDIT_EVENT_LIMIT).contains(&limit) {
+            return Err(AuthAPIError::InvalidQuery);
+        }
+
+        Ok(AuditEventFilter {
+            event_type: self
+                .event_type
+                .map(|event_type| AuditEventType::parse(&event_type))
+                .transpose()
+                .map_err(|_| AuthAPIError::InvalidQuery)?,
+            user_id: self
+                .user_id
+                .map(|user_id| UserId::parse(&user_id))
+                .transpose()
+                .map_err(|_| AuthAPIError::InvalidQuery)?,
+            outcome: self
+                .outcome
+                .map(|outcome| AuditOutcome::parse(&outcome))
+                .transpose()
+                .map_err(|_| AuthAPIError::InvalidQuery)?,
+            ip_address: self.ip_address,
+            since: parse_time(self.since)?,
+            until: parse_time(self.until)?,
+            before_id: self.before_id,
+            limit: Some(limit),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AuditEventsResponse {
+    pub events: Vec<AuditEventResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AuditEventResponse {
+    pub id: i64,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "eventType")]
+    pub event_type: String,
+    #[serde(rename = "userId")]
+    pub user_id: Option<String>,
+    #[serde(rename = "ipAddress")]
+    pub ip_address: Option<String>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    #[serde(rename = "requestId")]
+    pub request_id: Option<String>,
+    pub outcome: String,
+    pub reason: Option<String>,
+    #[serde(rename = "prevHash")]
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl From<AuditEvent> for AuditEventResponse {
+    fn from(event: AuditEvent) -> Self {
+        Self {
+            id: event.id,
+            created_at: event.created_at,
+            event_type: event.event_type.as_str().to_owned(),
+            user_id: event.user_id.map(|id| id.to_string()),
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            request_id: event.request_id,
+            outcome: event.outcome.as_str().to_owned(),
+            reason: event.reason,
+            prev_hash: event.prev_hash,
+            hash: event.hash,
+        }
+    }
+}
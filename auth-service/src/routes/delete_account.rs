@@ -0,0 +1,165 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::{cookie, CookieJar};
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        validate_fields, AuthAPIError, Email, FieldError, Password, TwoFACodeStoreError,
+        UserStoreError, WebhookEvent, WebhookEventType,
+    },
+    services::{
+        email_outbox::send_email,
+        email_templates::{EmailTemplate, SecurityNotice},
+        webhooks::publish_webhook_event,
+    },
+    utils::{
+        audit::AuditUser,
+        auth::revoke_all_sessions,
+        constants::{ACCOUNT_DELETION_GRACE_PERIOD_DAYS, JWT_COOKIE_NAME},
+        extract::ApiJson,
+        problem::ProblemDetails,
+    },
+};
+
+use super::account::{authenticate_session, check_password};
+
+// The account is only marked as deleted. It can be restored with `restore_account` until the
+// grace period is over, after which the account purger removes it for good.
+#[utoipa::path(
+    delete,
+    path = "/account",
+    tag = "account",
+    request_body = DeleteAccountRequest,
+    security(("session" = [])),
+    responses(
+        (status = 200, description = "Account scheduled for deletion. The auth cookie is removed.", body = DeleteAccountResponse),
+        (status = 400, description = "Missing auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid auth cookie or incorrect password", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "The security notice could not be sent", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "delete_account", skip_all)]
+pub async fn delete_account(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<DeleteAccountRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user, _) = authenticate_session(&state, &jar, &audit_user).await?;
+
+    check_password(&state, &user.email, request.password).await?;
+
+    state
+        .user_store
+        .delete_user(&user.id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // Nothing the user started before the deletion outlives it, including this session.
+    revoke_all_sessions(
+        &user.id,
+        state.session_store.clone(),
+        state.banned_token_store.clone(),
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    match state.two_fa_code_store.remove_code(&user.id).await {
+        Ok(()) | Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {}
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    send_email(
+        &state,
+        &user.id,
+        &user.email,
+        user.locale,
+        EmailTemplate::SecurityNotice(SecurityNotice::AccountDeleted {
+            grace_period_days: ACCOUNT_DELETION_GRACE_PERIOD_DAYS,
+        }),
+    )
+    .await?;
+
+    publish_webhook_event(
+        &state.webhook_store,
+        WebhookEvent::new(WebhookEventType::AccountDeleted, &user.id, &user.email),
+    )
+    .await;
+
+    let jar = jar.remove(cookie::Cookie::from(JWT_COOKIE_NAME));
+
+    Ok((
+        jar,
+        (
+            StatusCode::OK,
+            Json(DeleteAccountResponse {
+                grace_period_days: ACCOUNT_DELETION_GRACE_PERIOD_DAYS,
+            }),
+        ),
+    ))
+}
+
+// Restoring doesn't log the user in. They log in as usual afterwards.
+#[utoipa::path(
+    post,
+    path = "/account/restore",
+    tag = "account",
+    request_body = RestoreAccountRequest,
+    responses(
+        (status = 200, description = "Account restored"),
+        (status = 400, description = "Invalid email or password", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Incorrect credentials or no deletion pending", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The email was taken by another account in the meantime", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "restore_account", skip_all)]
+pub async fn restore_account(
+    State(state): State<AppState>,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<RestoreAccountRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (email, password) = validate_fields(
+        Email::parse(request.email).map_err(|_| FieldError::invalid_email("email")),
+        Password::parse(request.password).map_err(|_| FieldError::invalid_password("password")),
+    )?;
+
+    match state.user_store.restore_user(&email, &password).await {
+        Ok(user_id) => {
+            audit_user.set(&user_id);
+            publish_webhook_event(
+                &state.webhook_store,
+                WebhookEvent::new(WebhookEventType::AccountRestored, &user_id, &email),
+            )
+            .await;
+            Ok(StatusCode::OK)
+        }
+        Err(UserStoreError::UserAlreadyExists) => Err(AuthAPIError::UserAlreadyExists),
+        Err(UserStoreError::UnexpectedError(e)) => Err(AuthAPIError::UnexpectedError(e)),
+        Err(_) => Err(AuthAPIError::IncorrectCredentials),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeleteAccountRequest {
+    #[schema(value_type = String)]
+    pub password: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct DeleteAccountResponse {
+    #[serde(rename = "gracePeriodDays")]
+    pub grace_period_days: i64,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RestoreAccountRequest {
+    #[schema(value_type = String)]
+    pub email: Secret<String>,
+    #[schema(value_type = String)]
+    pub password: Secret<String>,
+}
@@ -0,0 +1,106 @@
+use axum::{response::Html, Json};
+use utoipa::{
+    openapi::{
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+        OpenApi as OpenApiDocument,
+    },
+    Modify, OpenApi,
+};
+
+use crate::utils::constants::JWT_COOKIE_NAME;
+
+use super::*;
+
+// The API description is generated from the handlers and their request and response types.
+// `openapi.json` in the repository is a copy of it, kept up to date by a test. Regenerate it
+// with `cargo run --bin export_openapi > openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Authentication Service API",
+        description = "An authentication service using JWT cookies, optional 2FA by email or SMS, \
+            magic links and passkeys.\n\nErrors are RFC 7807 `application/problem+json` documents. \
+            Match on their `code`, which is stable, rather than on `detail`. Any endpoint can also \
+            fail with 500 `internal.unexpected_error`, and a body that can't be read fails with \
+            400, 415 or 422 `request.malformed_body`."
+    ),
+    paths(
+        signup,
+        login,
+        logout,
+        verify_2fa,
+        verify_token,
+        request_magic_link,
+        magic_link_callback,
+        start_passkey_registration,
+        finish_passkey_registration,
+        start_passkey_authentication,
+        login_with_passkey,
+        verify_2fa_with_passkey,
+        get_account,
+        delete_account,
+        export_account,
+        restore_account,
+        change_password,
+        request_email_change,
+        confirm_email_change,
+        cancel_email_change,
+        request_phone_verification,
+        verify_phone_number,
+        update_two_fa_channel,
+        list_audit_events,
+        list_undeliverable_emails,
+        clear_email_undeliverable,
+        create_webhook_subscription,
+        list_webhook_subscriptions,
+        delete_webhook_subscription,
+        list_webhook_deliveries,
+        replay_webhook_delivery,
+        receive_postmark_event,
+        liveness,
+        readiness,
+        render_metrics,
+    ),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "auth", description = "Signing up and logging in"),
+        (name = "passkeys", description = "WebAuthn passkeys"),
+        (name = "account", description = "The logged in user's account"),
+        (name = "admin", description = "Operator endpoints, authorized by the admin API token"),
+        (name = "webhooks", description = "Outgoing webhook subscriptions and incoming provider events"),
+        (name = "operations", description = "Probes for the orchestrator and metrics for monitoring"),
+    )
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut OpenApiDocument) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(JWT_COOKIE_NAME))),
+        );
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "postmark",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(
+                POSTMARK_WEBHOOK_SECRET_HEADER,
+            ))),
+        );
+    }
+}
+
+#[tracing::instrument(name = "openapi_spec", skip_all)]
+pub async fn openapi_spec() -> Json<OpenApiDocument> {
+    Json(ApiDoc::openapi())
+}
+
+#[tracing::instrument(name = "api_docs_page", skip_all)]
+pub async fn api_docs_page() -> Html<&'static str> {
+    Html(include_str!("../../assets/api_docs.html"))
+}
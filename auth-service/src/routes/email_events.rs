@@ -0,0 +1,136 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+use utoipa::ToSchema;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, EmailUndeliverable, UndeliverableReason, UserStoreError},
+    utils::{audit::AuditUser, extract::ApiJson, problem::ProblemDetails},
+};
+
+pub const POSTMARK_WEBHOOK_SECRET_HEADER: &str = "x-postmark-webhook-secret";
+
+// Postmark can send the shared secret either as the password of basic auth credentials in the
+// webhook URL, or in a custom header.
+fn authorize_postmark(state: &AppState, headers: &HeaderMap) -> Result<(), AuthAPIError> {
+    let expected = state
+        .settings
+        .email
+        .postmark
+        .webhook_secret
+        .as_ref()
+        .ok_or(AuthAPIError::InvalidToken)?
+        .load();
+
+    let provided = headers
+        .get(POSTMARK_WEBHOOK_SECRET_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+        .or_else(|| basic_auth_password(headers))
+        .ok_or(AuthAPIError::InvalidToken)?;
+
+    if bool::from(
+        provided
+            .as_bytes()
+            .ct_eq(expected.expose_secret().as_bytes()),
+    ) {
+        Ok(())
+    } else {
+        Err(AuthAPIError::InvalidToken)
+    }
+}
+
+fn basic_auth_password(headers: &HeaderMap) -> Option<String> {
+    let encoded = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(BASE64_STANDARD.decode(encoded).ok()?).ok()?;
+    let (_, password) = decoded.split_once(':')?;
+    Some(password.to_owned())
+}
+
+// Receives Postmark bounce and spam complaint webhooks. Addresses that can never receive
+// email are flagged on their user, and nothing more is sent to them. Temporary problems like
+// soft bounces are ignored. Postmark retries anything but a 2xx, so events about unknown
+// addresses are accepted too.
+#[utoipa::path(
+    post,
+    path = "/webhooks/postmark",
+    tag = "webhooks",
+    request_body = PostmarkEvent,
+    security(("postmark" = [])),
+    responses(
+        (status = 200, description = "Event processed or ignored"),
+        (status = 401, description = "Missing or invalid secret", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "receive_postmark_event", skip_all)]
+pub async fn receive_postmark_event(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    audit_user: AuditUser,
+    ApiJson(event): ApiJson<PostmarkEvent>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_postmark(&state, &headers)?;
+
+    let Some(reason) = event.undeliverable_reason() else {
+        return Ok(StatusCode::OK);
+    };
+    let Ok(email) = Email::parse(event.email) else {
+        return Ok(StatusCode::OK);
+    };
+
+    let undeliverable = EmailUndeliverable {
+        reason,
+        since: Utc::now(),
+    };
+    match state
+        .user_store
+        .mark_email_undeliverable(&email, undeliverable)
+        .await
+    {
+        Ok(user_id) => {
+            audit_user.set(&user_id);
+            tracing::info!(user_id = %user_id, reason = reason.as_str(), "email marked undeliverable");
+        }
+        Err(UserStoreError::UserNotFound) => {}
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    Ok(StatusCode::OK)
+}
+
+// The fields of Postmark's bounce and spam complaint webhooks that matter here. See
+// https://postmarkapp.com/developer/webhooks/bounce-webhook
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "PascalCase")]
+pub struct PostmarkEvent {
+    pub record_type: String,
+    #[serde(rename = "Type")]
+    pub bounce_type: Option<String>,
+    #[schema(value_type = String)]
+    pub email: Secret<String>,
+}
+
+impl PostmarkEvent {
+    fn undeliverable_reason(&self) -> Option<UndeliverableReason> {
+        match (self.record_type.as_str(), self.bounce_type.as_deref()) {
+            ("SpamComplaint", _) => Some(UndeliverableReason::SpamComplaint),
+            ("Bounce", Some("HardBounce" | "BadEmailAddress" | "ManuallyDeactivated")) => {
+                Some(UndeliverableReason::HardBounce)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use webauthn_rs::prelude::CredentialID;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuditEventFilter, AuthAPIError, OutboxEmail},
+    utils::{audit::AuditUser, problem::ProblemDetails},
+};
+
+use super::{
+    account::{authenticate_session, EmailUndeliverableResponse},
+    admin::AuditEventResponse,
+    webhooks::WebhookDeliveryResponse,
+};
+
+// Returns everything stored about the logged in user as one JSON document. Secrets such as
+// the password hash, passkey private state and the codes and links in email bodies are left
+// out.
+#[utoipa::path(
+    get,
+    path = "/account/export",
+    tag = "account",
+    security(("session" = [])),
+    responses(
+        (status = 200, description = "Everything stored about the logged in user", body = AccountExport),
+        (status = 400, description = "Missing auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "export_account", skip_all)]
+pub async fn export_account(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user, _) = authenticate_session(&state, &jar, &audit_user).await?;
+
+    let passkeys = state
+        .passkey_store
+        .get_passkeys(&user.id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(|stored| ExportedPasskey {
+            credential_id: stored.passkey.cred_id().clone(),
+            sign_count: stored.sign_count,
+        })
+        .collect();
+
+    let active_sessions = state
+        .session_store
+        .get_sessions(&user.id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .len();
+
+    let audit_events = state
+        .audit_log_store
+        .get_events(&AuditEventFilter {
+            user_id: Some(user.id),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(AuditEventResponse::from)
+        .collect();
+
+    let emails = state
+        .email_outbox_store
+        .get_user_emails(&user.id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(ExportedEmail::from)
+        .collect();
+
+    let webhook_deliveries = state
+        .webhook_store
+        .get_user_deliveries(&user.id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(WebhookDeliveryResponse::from)
+        .collect();
+
+    let export = AccountExport {
+        user: ExportedUser {
+            id: user.id.to_string(),
+            email: user.email.as_ref().expose_secret().to_owned(),
+            requires_2fa: user.requires_2fa,
+            locale: user.locale.as_str().to_owned(),
+            email_undeliverable: user
+                .email_undeliverable
+                .map(EmailUndeliverableResponse::from),
+            phone_number: user
+                .phone_number
+                .map(|phone_number| phone_number.as_ref().expose_secret().to_owned()),
+            two_fa_channel: user.two_fa_channel.as_str().to_owned(),
+        },
+        passkeys,
+        active_sessions,
+        audit_events,
+        emails,
+        webhook_deliveries,
+    };
+
+    Ok((
+        StatusCode::OK,
+        [(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"account-export.json\"",
+        )],
+        Json(export),
+    ))
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AccountExport {
+    pub user: ExportedUser,
+    pub passkeys: Vec<ExportedPasskey>,
+    #[serde(rename = "activeSessions")]
+    pub active_sessions: usize,
+    #[serde(rename = "auditEvents")]
+    pub audit_events: Vec<AuditEventResponse>,
+    pub emails: Vec<ExportedEmail>,
+    #[serde(rename = "webhookDeliveries")]
+    pub webhook_deliveries: Vec<WebhookDeliveryResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExportedUser {
+    pub id: String,
+    pub email: String,
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+    pub locale: String,
+    #[serde(rename = "emailUndeliverable")]
+    pub email_undeliverable: Option<EmailUndeliverableResponse>,
+    #[serde(rename = "phoneNumber")]
+    pub phone_number: Option<String>,
+    #[serde(rename = "twoFAChannel")]
+    pub two_fa_channel: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExportedPasskey {
+    #[serde(rename = "credentialId")]
+    #[schema(value_type = String)]
+    pub credential_id: CredentialID,
+    #[serde(rename = "signCount")]
+    pub sign_count: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExportedEmail {
+    pub id: Uuid,
+    pub kind: String,
+    pub recipient: String,
+    pub subject: String,
+    pub status: String,
+    pub attempts: i32,
+    #[serde(rename = "nextAttemptAt")]
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "sentAt")]
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+impl From<OutboxEmail> for ExportedEmail {
+    fn from(email: OutboxEmail) -> Self {
+        Self {
+            id: email.id,
+            kind: email.kind.as_str().to_owned(),
+            recipient: email.recipient.as_ref().expose_secret().to_owned(),
+            subject: email.message.subject,
+            status: email.status.as_str().to_owned(),
+            attempts: email.attempts,
+            next_attempt_at: email.next_attempt_at,
+            last_error: email.last_error,
+            created_at: email.created_at,
+            sent_at: email.sent_at,
+        }
+    }
+}
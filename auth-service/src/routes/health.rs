@@ -0,0 +1,140 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::Instant};
+use tokio::task::JoinSet;
+use utoipa::ToSchema;
+
+use crate::{app_state::HealthCheckType, utils::constants::prod, AppState};
+
+// Answers as long as the process can serve requests at all. Restart the instance when it
+// doesn't.
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "operations",
+    responses(
+        (status = 200, description = "The service is running", body = HealthResponse),
+    )
+)]
+pub async fn liveness() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: HealthStatus::Ok,
+        checks: BTreeMap::new(),
+    })
+}
+
+// Checks every dependency at once. Stop sending traffic to the instance while it fails, which
+// it also does as soon as the instance starts shutting down.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Every dependency is available", body = HealthResponse),
+        (status = 503, description = "A dependency is unavailable or the service is shutting down", body = HealthResponse),
+    )
+)]
+pub async fn readiness(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    if state.shutdown.is_triggered() {
+        let response = HealthResponse {
+            status: HealthStatus::Failing,
+            checks: BTreeMap::new(),
+        };
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(response));
+    }
+
+    let mut checks = JoinSet::new();
+    for health_check in state.health_checks.iter().cloned() {
+        checks.spawn(run_check(health_check));
+    }
+
+    let mut response = HealthResponse {
+        status: HealthStatus::Ok,
+        checks: BTreeMap::new(),
+    };
+    while let Some(result) = checks.join_next().await {
+        let (name, dependency) = match result {
+            Ok(check) => check,
+            Err(e) => {
+                tracing::error!("health check panicked: {:?}", e);
+                response.status = HealthStatus::Failing;
+                continue;
+            }
+        };
+        if dependency.status == HealthStatus::Failing {
+            response.status = HealthStatus::Failing;
+        }
+        response.checks.insert(name.to_owned(), dependency);
+    }
+
+    let status = match response.status {
+        HealthStatus::Ok => StatusCode::OK,
+        HealthStatus::Failing => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(response))
+}
+
+// Everything recorded through the `metrics` crate, in the Prometheus text format.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    responses(
+        (status = 200, description = "The metrics in the Prometheus text exposition format", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn render_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
+// Failures are only logged, since the endpoint is public.
+async fn run_check(health_check: HealthCheckType) -> (&'static str, DependencyHealth) {
+    let name = health_check.name();
+    let started = Instant::now();
+    let outcome = tokio::time::timeout(prod::health::CHECK_TIMEOUT, health_check.check()).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let status = match outcome {
+        Ok(Ok(())) => HealthStatus::Ok,
+        Ok(Err(e)) => {
+            tracing::warn!(dependency = name, "health check failed: {:?}", e);
+            HealthStatus::Failing
+        }
+        Err(_) => {
+            tracing::warn!(dependency = name, "health check timed out");
+            HealthStatus::Failing
+        }
+    };
+
+    (name, DependencyHealth { status, latency_ms })
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: HealthStatus,
+    // By dependency, e.g. `postgres`. Empty for the liveness check.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<String, DependencyHealth>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Failing,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DependencyHealth {
+    pub status: HealthStatus,
+    #[serde(rename = "latencyMs")]
+    pub latency_ms: u64,
+}
@@ -2,36 +2,58 @@ use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
 use secrecy::Secret;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, LoginAttemptId, Password, TwoFACode},
-    utils::auth::generate_auth_cookie,
+    domain::{
+        validate_fields, AuthAPIError, Email, FieldError, LoginAttemptId, Password, TwoFAChannel,
+        TwoFACode, User,
+    },
+    services::{
+        email_outbox::send_email,
+        email_templates::EmailTemplate,
+        sms::{send_sms, SmsTemplate},
+    },
+    utils::{audit::AuditUser, auth::start_session, extract::ApiJson, problem::ProblemDetails},
 };
 
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Logged in. The auth cookie is set."),
+        (status = 206, description = "The user has 2FA turned on. A code was sent and has to be passed to `/verify-2fa` along with `loginAttemptId`.", body = TwoFactorAuthResponse),
+        (status = 400, description = "Invalid email or password", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Incorrect credentials", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "The 2FA code could not be sent", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 #[tracing::instrument(name = "login", skip_all)]
 pub async fn login(
     State(state): State<AppState>,
     jar: CookieJar,
-    Json(request): Json<LoginRequest>,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<LoginRequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
-    //) -> Result<impl IntoResponse, AuthAPIError> {
-    //    let email =
-    //        Email::parse(request.email.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
-    //    let password =
-    //        Password::parse(request.password.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
-
-    let password = match Password::parse(request.password) {
-        Ok(password) => password,
-        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    let (email, password) = match validate_fields(
+        Email::parse(request.email).map_err(|_| FieldError::invalid_email("email")),
+        Password::parse(request.password).map_err(|_| FieldError::invalid_password("password")),
+    ) {
+        Ok(credentials) => credentials,
+        Err(e) => return (jar, Err(e)),
     };
 
-    let email = match Email::parse(request.email) {
-        Ok(email) => email,
-        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
-    };
+    let user_store = &state.user_store;
 
-    let user_store = &state.user_store.read().await;
+    let user = match user_store.get_user(&email).await {
+        Ok(user) => user,
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    };
+    audit_user.set(&user.id);
 
     //user_store
     //    .validate_user(&email, &password)
@@ -41,38 +63,26 @@ pub async fn login(
         return (jar, Err(AuthAPIError::IncorrectCredentials));
     }
 
-    let user = match user_store.get_user(&email).await {
-        Ok(user) => user,
-        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
-    };
-
-    // Call the generate_auth_cookie function defined in the auth module.
-    // If the function call fails return AuthAPIError::UnexpectedError.
-    let auth_cookie = match generate_auth_cookie(&email) {
-        Ok(x) => x,
-        //Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
-        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))), // Updated!
-    };
-
-    let jar = jar.add(auth_cookie);
-
+    // The auth cookie is only issued once the user's 2FA configuration is satisfied,
+    // either right away or by `verify_2fa`.
     // Handle request based on user's 2FA configuration
-    println!("REQUIRES 2fa: {:?}", user.requires_2fa);
     match user.requires_2fa {
-        true => handle_2fa(&user.email, &state, jar).await,
-        false => handle_no_2fa(&user.email, jar).await,
+        true => handle_2fa(&user, &state, jar).await,
+        false => handle_no_2fa(&user, &state, jar).await,
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct LoginRequest {
+    #[schema(value_type = String)]
     pub email: Secret<String>,
+    #[schema(value_type = String)]
     pub password: Secret<String>,
 }
 
 #[tracing::instrument(name = "handle_2fa", skip_all)]
-async fn handle_2fa(
-    email: &Email,
+pub(super) async fn handle_2fa(
+    user: &User,
     state: &AppState,
     jar: CookieJar,
 ) -> (
@@ -88,39 +98,39 @@ async fn handle_2fa(
     let login_attempt_id = LoginAttemptId::default();
     let two_fa_code = TwoFACode::default();
 
+    if let Err(e) = state
+        .two_fa_code_store
+        .add_code(user.id, login_attempt_id.clone(), two_fa_code.clone())
+        .await
     {
-        let two_fa_code_store = &mut state.two_fa_code_store.write().await;
-
-        if let Err(e) = two_fa_code_store
-            .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
-            .await
-        {
-            //            return (jar, Err(AuthAPIError::UnexpectedError));
-            return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
-        }
+        //            return (jar, Err(AuthAPIError::UnexpectedError));
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
     }
 
-    {
-        let email_client = &mut state.email_client.read().await;
-
-        //if let Err(e) = email_client
-        //    .send_email(
-        //        email,
-        //        "Here is your 2FA code",
-        //        &two_fa_code.clone().as_ref(),
-        //    )
-        //    .await
-        //{
-        //    //return (jar, Err(AuthAPIError::UnexpectedError));
-        //    return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
-        //}
-        if let Err(e) = email_client
-            .send_email(email, "2FA Code", two_fa_code.as_ref())
-            .await
-        {
-            return (jar, Err(AuthAPIError::UnexpectedError(e)));
+    // The code is only useful while it is fresh, so by default the login fails rather than
+    // leaving the user waiting for a message that may never come. See `EmailDeliveryPolicy`.
+    let (channel, sent) = match (user.two_fa_channel, &user.phone_number) {
+        (TwoFAChannel::Sms, Some(phone_number)) => {
+            let template = SmsTemplate::TwoFACode {
+                code: two_fa_code.as_ref(),
+            };
+            let sent = send_sms(state, phone_number, user.locale, template).await;
+            (TwoFAChannel::Sms, sent)
+        }
+        _ => {
+            let template = EmailTemplate::TwoFACode {
+                code: two_fa_code.as_ref(),
+            };
+            let sent = send_email(state, &user.id, &user.email, user.locale, template)
+                .await
+                .map_err(AuthAPIError::from);
+            (TwoFAChannel::Email, sent)
         }
+    };
+    if let Err(e) = sent {
+        return (jar, Err(e));
     }
+    metrics::counter!("two_fa_codes_issued_total", "channel" => channel.as_str()).increment(1);
 
     let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
         message: "2FA required".to_owned(),
@@ -133,17 +143,19 @@ async fn handle_2fa(
 }
 
 #[tracing::instrument(name = "handle_no_2fa", skip_all)]
-async fn handle_no_2fa(
-    email: &Email,
+pub(super) async fn handle_no_2fa(
+    user: &User,
+    state: &AppState,
     jar: CookieJar,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
-    let auth_cookie = match generate_auth_cookie(email) {
-        Ok(cookie) => cookie,
-        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))), // Updated!
-    };
+    let auth_cookie =
+        match start_session(&user.id, state.session_store.clone(), &state.settings.jwt).await {
+            Ok(cookie) => cookie,
+            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))), // Updated!
+        };
 
     let updated_jar = jar.add(auth_cookie);
 
@@ -172,7 +184,7 @@ pub enum LoginResponse {
 }
 
 // If a user requires 2FA, this JSON body should be returned!
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct TwoFactorAuthResponse {
     pub message: String,
     #[serde(rename = "loginAttemptId")]
@@ -3,14 +3,30 @@ use axum::{extract::State, http::StatusCode, response::IntoResponse};
 use axum_extra::extract::{cookie, CookieJar};
 
 use crate::{
-    app_state::AppState, domain::AuthAPIError, utils::auth::validate_token,
+    app_state::AppState,
+    domain::{AuthAPIError, UserId},
+    utils::audit::AuditUser,
+    utils::auth::validate_token,
     utils::constants::JWT_COOKIE_NAME,
+    utils::problem::ProblemDetails,
 };
 
+#[utoipa::path(
+    post,
+    path = "/logout",
+    tag = "auth",
+    security(("session" = [])),
+    responses(
+        (status = 200, description = "Logged out. The auth cookie is removed."),
+        (status = 400, description = "Missing auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 #[tracing::instrument(name = "logout", skip_all)]
 pub async fn logout(
     State(state): State<AppState>,
     jar: CookieJar,
+    audit_user: AuditUser,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
     let cookie = match jar.get(JWT_COOKIE_NAME) {
         Some(cookie) => cookie,
@@ -19,22 +35,30 @@ pub async fn logout(
 
     // Validate token
     let token = cookie.value().to_owned();
-    let _ = match validate_token(&token, state.banned_token_store.clone()).await {
+    let claims = match validate_token(
+        &token,
+        state.banned_token_store.clone(),
+        &state.settings.jwt,
+    )
+    .await
+    {
         Ok(claims) => claims,
         Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
     };
 
     // Add token to banned list
-    if let Err(e) = state
-        .banned_token_store
-        .write()
-        .await
-        .add_token(token.to_owned())
-        .await
-    {
+    if let Err(e) = state.banned_token_store.add_token(token.to_owned()).await {
         return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
     }
 
+    // The token is banned, so it no longer counts as one of the user's sessions
+    if let Ok(user_id) = UserId::parse(&claims.sub) {
+        audit_user.set(&user_id);
+        if let Err(e) = state.session_store.remove_session(&user_id, &token).await {
+            return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+        }
+    }
+
     // Remove jwt cookie
     let jar = jar.remove(cookie::Cookie::from(JWT_COOKIE_NAME));
 
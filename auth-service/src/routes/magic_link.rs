@@ -0,0 +1,154 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, FieldError, UserId},
+    services::{email_outbox::send_email, email_templates::EmailTemplate},
+    utils::{
+        audit::AuditUser,
+        auth::{
+            consume_token, generate_magic_link_token, validate_magic_link_token,
+            MAGIC_LINK_TTL_SECONDS,
+        },
+        extract::ApiJson,
+        problem::ProblemDetails,
+    },
+};
+
+use super::login::{handle_2fa, handle_no_2fa, TwoFactorAuthResponse};
+
+#[utoipa::path(
+    post,
+    path = "/login/magic-link",
+    tag = "auth",
+    request_body = MagicLinkRequest,
+    responses(
+        (status = 200, description = "A login link was sent if the account exists", body = MagicLinkResponse),
+        (status = 400, description = "Invalid email", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "The login link could not be sent", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "request_magic_link", skip_all)]
+pub async fn request_magic_link(
+    State(state): State<AppState>,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<MagicLinkRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| FieldError::invalid_email("email"))?;
+
+    // Always answer the same way so the endpoint can't be used to find out who has an account.
+    let response = Json(MagicLinkResponse {
+        message: "If an account exists for this email, a login link has been sent.".to_owned(),
+    });
+
+    let user = match state.user_store.get_user(&email).await {
+        Ok(user) => user,
+        Err(_) => return Ok((StatusCode::OK, response)),
+    };
+    audit_user.set(&user.id);
+
+    let token = generate_magic_link_token(&user.id, &state.settings.jwt)
+        .map_err(AuthAPIError::UnexpectedError)?;
+    let link = format!(
+        "{}/login/magic-link/callback?token={}",
+        state.settings.application.base_url.as_str(),
+        token
+    );
+    send_email(
+        &state,
+        &user.id,
+        &user.email,
+        user.locale,
+        EmailTemplate::MagicLink {
+            link: &link,
+            ttl_minutes: MAGIC_LINK_TTL_SECONDS / 60,
+        },
+    )
+    .await?;
+
+    Ok((StatusCode::OK, response))
+}
+
+// The link in the email points here. Email scanners and link previewers follow links
+// automatically, so a GET never consumes the token. It only serves a page that asks the
+// user to confirm, which then POSTs the token to `magic_link_callback`.
+#[tracing::instrument(name = "magic_link_callback_page", skip_all)]
+pub async fn magic_link_callback_page() -> Html<&'static str> {
+    Html(include_str!("../../assets/magic_link.html"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/login/magic-link/callback",
+    tag = "auth",
+    request_body = MagicLinkCallbackRequest,
+    responses(
+        (status = 200, description = "Logged in. The auth cookie is set."),
+        (status = 206, description = "The user has 2FA turned on. A code was sent and has to be passed to `/verify-2fa` along with `loginAttemptId`.", body = TwoFactorAuthResponse),
+        (status = 401, description = "Invalid or used login link", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "The 2FA code could not be sent", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "magic_link_callback", skip_all)]
+pub async fn magic_link_callback(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<MagicLinkCallbackRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let claims = match validate_magic_link_token(&request.token, &state.settings.jwt) {
+        Ok(claims) => claims,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    // A link can only be exchanged once.
+    match consume_token(&request.token, state.banned_token_store.clone()).await {
+        Ok(true) => {}
+        Ok(false) => return (jar, Err(AuthAPIError::InvalidToken)),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+    }
+
+    let user_id = match UserId::parse(&claims.sub) {
+        Ok(user_id) => user_id,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+    audit_user.set(&user_id);
+
+    let user = match state.user_store.get_user_by_id(&user_id).await {
+        Ok(user) => user,
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    };
+
+    // The link proves access to the inbox, but users with 2FA still go through the usual second step.
+    match user.requires_2fa {
+        true => handle_2fa(&user, &state, jar).await,
+        false => handle_no_2fa(&user, &state, jar).await,
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MagicLinkRequest {
+    #[schema(value_type = String)]
+    pub email: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct MagicLinkResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MagicLinkCallbackRequest {
+    pub token: String,
+}
@@ -1,11 +1,33 @@
+mod account;
+mod admin;
+mod delete_account;
+mod docs;
+mod email_events;
+mod export_account;
+mod health;
 mod login;
 mod logout;
+mod magic_link;
+mod passkey;
+mod phone;
 mod signup;
 mod verify_2fa;
 mod verify_token;
+mod webhooks;
 
+pub use account::*;
+pub use admin::*;
+pub use delete_account::*;
+pub use docs::*;
+pub use email_events::*;
+pub use export_account::*;
+pub use health::*;
 pub use login::*;
 pub use logout::*;
+pub use magic_link::*;
+pub use passkey::*;
+pub use phone::*;
 pub use signup::*;
 pub use verify_2fa::*;
 pub use verify_token::*;
+pub use webhooks::*;
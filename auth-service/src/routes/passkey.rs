@@ -0,0 +1,434 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, PasskeyAuthentication, PasskeyRegistration, PublicKeyCredential,
+    RegisterPublicKeyCredential, RequestChallengeResponse,
+};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, FieldError, LoginAttemptId, PasskeyStoreError, TwoFACodeStoreError,
+        User, UserId, UserStoreError,
+    },
+    utils::{
+        audit::AuditUser,
+        auth::{consume_token, start_session},
+        extract::ApiJson,
+        problem::ProblemDetails,
+        webauthn::{
+            generate_ceremony_token, validate_ceremony_token, AUTHENTICATION_AUDIENCE,
+            REGISTRATION_AUDIENCE,
+        },
+    },
+};
+
+use super::{account::authenticate_session, verify_2fa::publish_first_verification};
+
+// Registration is only open to a logged in user, who adds a passkey to their own account.
+#[utoipa::path(
+    post,
+    path = "/passkeys/register/start",
+    tag = "passkeys",
+    security(("session" = [])),
+    responses(
+        (status = 200, description = "Registration challenge for the authenticator", body = StartPasskeyRegistrationResponse),
+        (status = 400, description = "Missing auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "start_passkey_registration", skip_all)]
+pub async fn start_passkey_registration(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user, _) = authenticate_session(&state, &jar, &audit_user).await?;
+
+    // Existing credentials are excluded so the same authenticator isn't registered twice.
+    let exclude_credentials = state
+        .passkey_store
+        .get_passkeys(&user.id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .iter()
+        .map(|stored| stored.passkey.cred_id().clone())
+        .collect();
+
+    let username = user.email.as_ref().expose_secret();
+    let (challenge, registration) = state
+        .webauthn
+        .start_passkey_registration(
+            *user.id.as_ref(),
+            username,
+            username,
+            Some(exclude_credentials),
+        )
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let state = generate_ceremony_token(
+        &user.id,
+        registration,
+        REGISTRATION_AUDIENCE,
+        &state.settings.jwt,
+    )
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(StartPasskeyRegistrationResponse { challenge, state }),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/passkeys/register/finish",
+    tag = "passkeys",
+    request_body = FinishPasskeyRegistrationRequest,
+    security(("session" = [])),
+    responses(
+        (status = 201, description = "Passkey registered"),
+        (status = 400, description = "Missing auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid auth cookie, state or credential", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Passkey already registered", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "finish_passkey_registration", skip_all)]
+pub async fn finish_passkey_registration(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<FinishPasskeyRegistrationRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user, _) = authenticate_session(&state, &jar, &audit_user).await?;
+
+    let registration: PasskeyRegistration = validate_ceremony_token(
+        &request.state,
+        &user.id,
+        REGISTRATION_AUDIENCE,
+        &state.settings.jwt,
+    )
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    if !consume_token(&request.state, state.banned_token_store.clone())
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?
+    {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    let passkey = state
+        .webauthn
+        .finish_passkey_registration(&request.credential, &registration)
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    match state.passkey_store.add_passkey(&user.id, passkey).await {
+        Ok(()) => Ok(StatusCode::CREATED),
+        Err(PasskeyStoreError::PasskeyAlreadyExists) => Err(AuthAPIError::PasskeyAlreadyExists),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// Starts an assertion for either `login_with_passkey` or `verify_2fa_with_passkey`.
+#[utoipa::path(
+    post,
+    path = "/passkeys/authenticate/start",
+    tag = "passkeys",
+    request_body = StartPasskeyAuthenticationRequest,
+    responses(
+        (status = 200, description = "Authentication challenge for the authenticator. It allows no credential when the user is unknown or has no passkeys.", body = StartPasskeyAuthenticationResponse),
+        (status = 400, description = "Invalid email", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "start_passkey_authentication", skip_all)]
+pub async fn start_passkey_authentication(
+    State(state): State<AppState>,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<StartPasskeyAuthenticationRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user = find_user(&state, request.email, &audit_user).await?;
+
+    let passkeys: Vec<_> = match &user {
+        Some(user) => state
+            .passkey_store
+            .get_passkeys(&user.id)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+            .into_iter()
+            .map(|stored| stored.passkey)
+            .collect(),
+        None => Vec::new(),
+    };
+    // An unknown address gets a ceremony for an id no user has, which no login can complete.
+    let user_id = user.map(|user| user.id).unwrap_or_default();
+
+    // Without passkeys the challenge allows no credential, so no assertion can satisfy it, but
+    // the response doesn't tell callers whether the account exists or has passkeys.
+    let (challenge, state) = if passkeys.is_empty() {
+        let (mut challenge, authentication) = state
+            .webauthn
+            .start_discoverable_authentication()
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        challenge.mediation = None;
+        challenge.public_key.extensions = None;
+
+        let state = generate_ceremony_token(
+            &user_id,
+            authentication,
+            AUTHENTICATION_AUDIENCE,
+            &state.settings.jwt,
+        )
+        .map_err(AuthAPIError::UnexpectedError)?;
+        (challenge, state)
+    } else {
+        let (challenge, authentication) = state
+            .webauthn
+            .start_passkey_authentication(&passkeys)
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+        let state = generate_ceremony_token(
+            &user_id,
+            authentication,
+            AUTHENTICATION_AUDIENCE,
+            &state.settings.jwt,
+        )
+        .map_err(AuthAPIError::UnexpectedError)?;
+        (challenge, state)
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(StartPasskeyAuthenticationResponse { challenge, state }),
+    ))
+}
+
+// A passkey ceremony requires user verification on the authenticator, so it counts as
+// both factors and the email code step is skipped.
+#[utoipa::path(
+    post,
+    path = "/login/passkey",
+    tag = "passkeys",
+    request_body = PasskeyLoginRequest,
+    responses(
+        (status = 200, description = "Logged in. The auth cookie is set."),
+        (status = 400, description = "Invalid email", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid state or credential", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "login_with_passkey", skip_all)]
+pub async fn login_with_passkey(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<PasskeyLoginRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    // No assertion satisfies the ceremony of an unknown address, just like that of a user
+    // without passkeys.
+    let user_id = find_user(&state, request.email, &audit_user)
+        .await?
+        .ok_or(AuthAPIError::IncorrectCredentials)?
+        .id;
+
+    verify_assertion(&state, &user_id, &request.state, &request.credential).await?;
+
+    let auth_cookie = start_session(&user_id, state.session_store.clone(), &state.settings.jwt)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok((jar.add(auth_cookie), StatusCode::OK))
+}
+
+// Completes a pending 2FA login with a passkey instead of the emailed code.
+#[utoipa::path(
+    post,
+    path = "/verify-2fa/passkey",
+    tag = "passkeys",
+    request_body = PasskeyVerify2FARequest,
+    responses(
+        (status = 200, description = "Logged in. The auth cookie is set."),
+        (status = 400, description = "Invalid email or login attempt id", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Incorrect login attempt id, state or credential", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "verify_2fa_with_passkey", skip_all)]
+pub async fn verify_2fa_with_passkey(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<PasskeyVerify2FARequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user = find_user(&state, request.email, &audit_user)
+        .await?
+        .ok_or(AuthAPIError::IncorrectCredentials)?;
+    let user_id = user.id;
+
+    let login_attempt_id = LoginAttemptId::parse(request.login_attempt_id)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let (stored_login_attempt_id, _) = match state.two_fa_code_store.get_code(&user_id).await {
+        Ok(pending) => pending,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    if stored_login_attempt_id != login_attempt_id {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    verify_assertion(&state, &user_id, &request.state, &request.credential).await?;
+
+    // The pending login attempt is finished, so its emailed code can't be used any more.
+    match state.two_fa_code_store.remove_code(&user_id).await {
+        Ok(()) => {}
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+    metrics::counter!("two_fa_verified_total", "method" => "passkey").increment(1);
+
+    let auth_cookie = start_session(&user_id, state.session_store.clone(), &state.settings.jwt)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    publish_first_verification(&state, &user_id, &user.email).await?;
+
+    Ok((jar.add(auth_cookie), StatusCode::OK))
+}
+
+// Passkey ceremonies are started by email. Callers answer an unknown address the same way as
+// a user without passkeys, so the endpoints don't reveal which accounts exist.
+async fn find_user(
+    state: &AppState,
+    email: Secret<String>,
+    audit_user: &AuditUser,
+) -> Result<Option<User>, AuthAPIError> {
+    let email = Email::parse(email).map_err(|_| FieldError::invalid_email("email"))?;
+
+    match state.user_store.get_user(&email).await {
+        Ok(user) => {
+            audit_user.set(&user.id);
+            Ok(Some(user))
+        }
+        Err(UserStoreError::UserNotFound) => Ok(None),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// Checks an assertion against the user's stored passkeys and records the new sign count.
+async fn verify_assertion(
+    state: &AppState,
+    user_id: &UserId,
+    ceremony_token: &str,
+    credential: &PublicKeyCredential,
+) -> Result<(), AuthAPIError> {
+    let authentication: PasskeyAuthentication = validate_ceremony_token(
+        ceremony_token,
+        user_id,
+        AUTHENTICATION_AUDIENCE,
+        &state.settings.jwt,
+    )
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    if !consume_token(ceremony_token, state.banned_token_store.clone())
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?
+    {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    let result = state
+        .webauthn
+        .finish_passkey_authentication(credential, &authentication)
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    let mut stored = state
+        .passkey_store
+        .get_passkeys(user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .into_iter()
+        .find(|stored| stored.passkey.cred_id() == result.cred_id())
+        .ok_or(AuthAPIError::IncorrectCredentials)?;
+
+    stored.passkey.update_credential(&result);
+
+    // Authenticators that support counters increase them on every assertion. A counter that
+    // didn't move forward means the credential may have been cloned. Authenticators without
+    // counters always report 0, which is allowed as long as nothing higher was ever seen. The
+    // store checks this as it saves the counter, so concurrent assertions can't both pass.
+    let counter = result.counter();
+    match state
+        .passkey_store
+        .update_passkey(user_id, &stored.passkey, counter)
+        .await
+    {
+        Ok(()) => Ok(()),
+        Err(PasskeyStoreError::SignCountRegressed) => {
+            tracing::warn!(
+                stored_sign_count = stored.sign_count,
+                sign_count = counter,
+                "passkey sign count regressed, possible cloned authenticator"
+            );
+            Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(PasskeyStoreError::PasskeyNotFound) => Err(AuthAPIError::IncorrectCredentials),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct StartPasskeyRegistrationResponse {
+    #[schema(value_type = Object)]
+    pub challenge: CreationChallengeResponse,
+    pub state: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct FinishPasskeyRegistrationRequest {
+    pub state: String,
+    #[schema(value_type = Object)]
+    pub credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct StartPasskeyAuthenticationRequest {
+    #[schema(value_type = String)]
+    pub email: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct StartPasskeyAuthenticationResponse {
+    #[schema(value_type = Object)]
+    pub challenge: RequestChallengeResponse,
+    pub state: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PasskeyLoginRequest {
+    #[schema(value_type = String)]
+    pub email: Secret<String>,
+    pub state: String,
+    #[schema(value_type = Object)]
+    pub credential: PublicKeyCredential,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PasskeyVerify2FARequest {
+    #[schema(value_type = String)]
+    pub email: Secret<String>,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: String,
+    pub state: String,
+    #[schema(value_type = Object)]
+    pub credential: PublicKeyCredential,
+}
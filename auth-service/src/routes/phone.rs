@@ -0,0 +1,188 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::CookieJar;
+use secrecy::Secret;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, FieldError, PhoneNumber, PhoneVerificationStoreError, TwoFAChannel,
+        TwoFACode, UserStoreError,
+    },
+    services::sms::{send_sms, SmsTemplate},
+    utils::{audit::AuditUser, extract::ApiJson, problem::ProblemDetails},
+};
+
+use super::account::authenticate_session;
+
+// Sends a code to the number. The number is only saved on the account once the code comes
+// back through `verify_phone_number`.
+#[utoipa::path(
+    post,
+    path = "/account/phone",
+    tag = "account",
+    request_body = PhoneNumberRequest,
+    security(("session" = [])),
+    responses(
+        (status = 202, description = "A verification code was sent to the phone number"),
+        (status = 400, description = "Missing auth cookie or invalid phone number", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "A code was sent too recently, or too many were sent today", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "The verification code could not be sent", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "request_phone_verification", skip_all)]
+pub async fn request_phone_verification(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<PhoneNumberRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user, _) = authenticate_session(&state, &jar, &audit_user).await?;
+
+    let phone_number = PhoneNumber::parse(request.phone_number)
+        .map_err(|_| FieldError::invalid_phone_number("phoneNumber"))?;
+
+    match state.phone_verification_store.record_send(&user.id).await {
+        Ok(()) => {}
+        Err(PhoneVerificationStoreError::TooManyCodes) => {
+            return Err(AuthAPIError::TooManyPhoneVerificationCodes)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let code = TwoFACode::default();
+    state
+        .phone_verification_store
+        .add_code(user.id, phone_number.clone(), code.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    send_sms(
+        &state,
+        &phone_number,
+        user.locale,
+        SmsTemplate::PhoneVerification {
+            code: code.as_ref(),
+        },
+    )
+    .await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/account/phone/verify",
+    tag = "account",
+    request_body = VerifyPhoneNumberRequest,
+    security(("session" = [])),
+    responses(
+        (status = 200, description = "Phone number verified"),
+        (status = 400, description = "Missing auth cookie or invalid code", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid auth cookie or incorrect code", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "verify_phone_number", skip_all)]
+pub async fn verify_phone_number(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<VerifyPhoneNumberRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user, _) = authenticate_session(&state, &jar, &audit_user).await?;
+
+    let code = TwoFACode::parse(request.code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let (phone_number, expected_code) =
+        match state.phone_verification_store.get_code(&user.id).await {
+            Ok(pending) => pending,
+            Err(PhoneVerificationStoreError::VerificationNotFound) => {
+                return Err(AuthAPIError::IncorrectCredentials)
+            }
+            Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+        };
+
+    if code != expected_code {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    // A code can only be used once, even by two requests racing with it.
+    match state.phone_verification_store.remove_code(&user.id).await {
+        Ok(()) => {}
+        Err(PhoneVerificationStoreError::VerificationNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    state
+        .user_store
+        .update_phone_number(&user.id, &phone_number)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(StatusCode::OK)
+}
+
+// Picks where login codes are sent. SMS needs a verified phone number.
+#[utoipa::path(
+    post,
+    path = "/account/2fa-channel",
+    tag = "account",
+    request_body = TwoFAChannelRequest,
+    security(("session" = [])),
+    responses(
+        (status = 200, description = "2FA channel changed"),
+        (status = 400, description = "Missing auth cookie or unknown channel", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid auth cookie", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "SMS needs a verified phone number", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "update_two_fa_channel", skip_all)]
+pub async fn update_two_fa_channel(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<TwoFAChannelRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user, _) = authenticate_session(&state, &jar, &audit_user).await?;
+
+    let channel =
+        TwoFAChannel::parse(&request.channel).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    if channel == TwoFAChannel::Sms && user.phone_number.is_none() {
+        return Err(AuthAPIError::PhoneNumberNotVerified);
+    }
+
+    match state
+        .user_store
+        .update_two_fa_channel(&user.id, channel)
+        .await
+    {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(UserStoreError::UserNotFound) => Err(AuthAPIError::InvalidToken),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PhoneNumberRequest {
+    #[serde(rename = "phoneNumber")]
+    #[schema(value_type = String)]
+    pub phone_number: Secret<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct VerifyPhoneNumberRequest {
+    pub code: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TwoFAChannelRequest {
+    pub channel: String,
+}
@@ -1,34 +1,98 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    extract::State,
+    http::{header::ACCEPT_LANGUAGE, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
 use secrecy::Secret;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use crate::domain::{Email, Password};
-use crate::{app_state::AppState, domain::User, AuthAPIError};
+use crate::domain::{
+    validate_fields, Email, FieldError, Locale, Password, WebhookEvent, WebhookEventType,
+};
+use crate::services::{
+    account_purger::purge_deleted_accounts_with_email, webhooks::publish_webhook_event,
+};
+use crate::{
+    app_state::AppState,
+    domain::{User, UserStoreError},
+    utils::{audit::AuditUser, extract::ApiJson, problem::ProblemDetails},
+    AuthAPIError,
+};
 
+#[utoipa::path(
+    post,
+    path = "/signup",
+    tag = "auth",
+    request_body = SignupRequest,
+    responses(
+        (status = 201, description = "User created", body = SignupResponse),
+        (status = 400, description = "Invalid email or password", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Email already exists", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 #[tracing::instrument(name = "Signup", skip_all)]
 pub async fn signup(
     State(state): State<AppState>,
-    Json(request): Json<SignupRequest>,
+    audit_user: AuditUser,
+    headers: HeaderMap,
+    ApiJson(request): ApiJson<SignupRequest>,
 ) -> Result<impl IntoResponse, AuthAPIError> {
-    let email =
-        Email::parse(request.email.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
-    let password =
-        Password::parse(request.password.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let (email, password) = validate_fields(
+        Email::parse(request.email).map_err(|_| FieldError::invalid_email("email")),
+        Password::parse(request.password).map_err(|_| FieldError::invalid_password("password")),
+    )?;
 
-    let user = User::new(email, password, request.requires_2fa);
+    // An explicit choice wins over the language the client sent the request in. Languages we
+    // have no translation for fall back to English.
+    let locale = request
+        .locale
+        .as_deref()
+        .and_then(|locale| Locale::parse(locale).ok())
+        .or_else(|| {
+            headers
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(Locale::from_accept_language)
+        })
+        .unwrap_or_default();
 
-    {
-        let mut user_store = state.user_store.write().await;
+    let user = User::new(email.clone(), password, request.requires_2fa).with_locale(locale);
+    let user_id = user.id;
 
-        if let Ok(_) = user_store.get_user(&user.email).await {
-            return Err(AuthAPIError::UserAlreadyExists);
-        }
+    if state.user_store.get_user(&user.email).await.is_ok() {
+        return Err(AuthAPIError::UserAlreadyExists);
+    }
+
+    // Signing up again gives up on a deleted account with this email, which is purged now
+    // instead of at the end of its grace period. The signup doesn't reveal that it existed.
+    purge_deleted_accounts_with_email(
+        &state.user_store,
+        &state.audit_log_store,
+        &state.email_outbox_store,
+        &state.webhook_store,
+        &email,
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
 
-        if let Err(e) = user_store.add_user(user).await {
-            //return Err(AuthAPIError::UnexpectedError);
-            return Err(AuthAPIError::UnexpectedError(e.into()));
-        }
+    // A concurrent signup with the same email can still get in between, and the store
+    // rejects the second one.
+    match state.user_store.add_user(user).await {
+        Ok(()) => {}
+        Err(UserStoreError::UserAlreadyExists) => return Err(AuthAPIError::UserAlreadyExists),
+        //return Err(AuthAPIError::UnexpectedError);
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
     }
+    audit_user.set(&user_id);
+
+    publish_webhook_event(
+        &state.webhook_store,
+        WebhookEvent::new(WebhookEventType::UserSignedUp, &user_id, &email),
+    )
+    .await;
 
     let response = Json(SignupResponse {
         message: "User created successfully!".to_string(),
@@ -37,15 +101,19 @@ pub async fn signup(
     Ok((StatusCode::CREATED, response))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct SignupRequest {
+    #[schema(value_type = String)]
     pub email: Secret<String>,
+    #[schema(value_type = String)]
     pub password: Secret<String>,
     #[serde(rename = "requires2FA")]
     pub requires_2fa: bool,
+    // The language emails are written in, e.g. `de`.
+    pub locale: Option<String>,
 }
 
-#[derive(Deserialize, Debug, PartialEq, Serialize)]
+#[derive(Deserialize, Debug, PartialEq, Serialize, ToSchema)]
 pub struct SignupResponse {
     pub message: String,
 }
@@ -1,20 +1,41 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::CookieJar;
 use secrecy::Secret;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, LoginAttemptId, TwoFACode},
+    domain::{
+        AuthAPIError, Email, FieldError, LoginAttemptId, TwoFACode, TwoFACodeStoreError, UserId,
+        WebhookEvent, WebhookEventType,
+    },
+    services::webhooks::publish_webhook_event,
+    utils::{audit::AuditUser, auth::start_session, extract::ApiJson, problem::ProblemDetails},
 };
 
+#[utoipa::path(
+    post,
+    path = "/verify-2fa",
+    tag = "auth",
+    request_body = Verify2FARequest,
+    responses(
+        (status = 200, description = "Logged in. The auth cookie is set."),
+        (status = 400, description = "Invalid email, login attempt id or code", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Incorrect login attempt id or code", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 #[tracing::instrument(name = "verify_2fa", skip_all)]
 #[axum::debug_handler]
 pub async fn verify_2fa(
     State(state): State<AppState>,
-    Json(request): Json<Verify2FARequest>,
+    jar: CookieJar,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<Verify2FARequest>,
 ) -> Result<impl IntoResponse, AuthAPIError> {
     let email = Email::parse(Secret::new(request.email.clone()))
-        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+        .map_err(|_| FieldError::invalid_email("email"))?;
 
     let login_attempt_id = LoginAttemptId::parse(request.login_attempt_id.clone())
         .map_err(|_| AuthAPIError::InvalidCredentials)?;
@@ -22,13 +43,22 @@ pub async fn verify_2fa(
     let two_fa_code = TwoFACode::parse(request.two_fa_code.clone())
         .map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-    let two_fa_code_store = state.two_fa_code_store.write().await;
+    let user_id = match state.user_store.get_user(&email).await {
+        Ok(user) => user.id,
+        Err(_) => return Err(AuthAPIError::IncorrectCredentials),
+    };
+    audit_user.set(&user_id);
+
+    let two_fa_code_store = &state.two_fa_code_store;
 
-    // Call `two_fa_code_store.get_code`. If the call fails
+    // Call `two_fa_code_store.get_code`. If there is no code
     // return a `AuthAPIError::IncorrectCredentials`.
-    let code_tuple = match two_fa_code_store.get_code(&email).await {
+    let code_tuple = match two_fa_code_store.get_code(&user_id).await {
         Ok(x) => (x.0, x.1),
-        Err(_) => return Err(AuthAPIError::InvalidCredentials),
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
     };
 
     // TODO: Validate that the `login_attempt_id` and `two_fa_code`
@@ -38,10 +68,48 @@ pub async fn verify_2fa(
         return Err(AuthAPIError::IncorrectCredentials);
     }
 
-    Ok(StatusCode::OK.into_response())
+    // A 2FA code can only be used once. Another request may have used it since `get_code`.
+    match two_fa_code_store.remove_code(&user_id).await {
+        Ok(()) => {}
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+    metrics::counter!("two_fa_verified_total", "method" => "code").increment(1);
+
+    let auth_cookie = start_session(&user_id, state.session_store.clone(), &state.settings.jwt)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    publish_first_verification(&state, &user_id, &email).await?;
+
+    Ok((jar.add(auth_cookie), StatusCode::OK))
+}
+
+// Subscribers hear about a user's first completed second factor, not about every 2FA login.
+pub(super) async fn publish_first_verification(
+    state: &AppState,
+    user_id: &UserId,
+    email: &Email,
+) -> Result<(), AuthAPIError> {
+    let first = state
+        .user_store
+        .mark_verified(user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    if first {
+        publish_webhook_event(
+            &state.webhook_store,
+            WebhookEvent::new(WebhookEventType::UserVerified, user_id, email),
+        )
+        .await;
+    }
+    Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct Verify2FARequest {
     email: String,
     #[serde(rename = "loginAttemptId")]
@@ -1,20 +1,48 @@
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{extract::State, http::StatusCode};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use crate::{domain::AuthAPIError, utils::auth::validate_token, AppState};
+use crate::{
+    domain::{AuthAPIError, UserId},
+    utils::{audit::AuditUser, auth::validate_token, extract::ApiJson, problem::ProblemDetails},
+    AppState,
+};
 
+#[utoipa::path(
+    post,
+    path = "/verify-token",
+    tag = "auth",
+    request_body = VerifyTokenRequest,
+    responses(
+        (status = 200, description = "The token is valid"),
+        (status = 401, description = "Invalid token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 #[tracing::instrument(name = "verify_token", skip_all)]
 pub async fn verify_token(
     State(state): State<AppState>,
-    Json(request): Json<VerifyTokenRequest>,
+    audit_user: AuditUser,
+    ApiJson(request): ApiJson<VerifyTokenRequest>,
 ) -> Result<StatusCode, AuthAPIError> {
-    match validate_token(&request.token, state.banned_token_store.clone()).await {
-        Ok(_) => Ok(StatusCode::OK),
+    match validate_token(
+        &request.token,
+        state.banned_token_store.clone(),
+        &state.settings.jwt,
+    )
+    .await
+    {
+        Ok(claims) => {
+            if let Ok(user_id) = UserId::parse(&claims.sub) {
+                audit_user.set(&user_id);
+            }
+            Ok(StatusCode::OK)
+        }
         Err(_) => Err(AuthAPIError::InvalidToken),
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq, Serialize)]
+#[derive(Deserialize, Debug, PartialEq, Serialize, ToSchema)]
 pub struct VerifyTokenRequest {
     pub token: String,
 }
@@ -0,0 +1,329 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        data_stores::WebhookStoreError, AuthAPIError, WebhookDelivery, WebhookDeliveryStatus,
+        WebhookEventType, WebhookSubscription,
+    },
+    utils::{extract::ApiJson, problem::ProblemDetails},
+};
+
+use super::admin::authorize_admin;
+
+const DEFAULT_WEBHOOK_DELIVERY_LIMIT: i64 = 100;
+const MAX_WEBHOOK_DELIVERY_LIMIT: i64 = 1000;
+
+// The signing secret is only ever returned here. Subscribers that lose it create a new
+// subscription.
+#[utoipa::path(
+    post,
+    path = "/admin/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookSubscriptionRequest,
+    security(("admin_token" = [])),
+    responses(
+        (status = 201, description = "Subscription created. The signing secret is only returned here.", body = CreateWebhookSubscriptionResponse),
+        (status = 400, description = "Missing admin token, invalid URL or unknown event type", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid admin token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "create_webhook_subscription", skip_all)]
+pub async fn create_webhook_subscription(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ApiJson(request): ApiJson<CreateWebhookSubscriptionRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+
+    let url = Url::parse(&request.url).map_err(|_| AuthAPIError::InvalidWebhookSubscription)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AuthAPIError::InvalidWebhookSubscription);
+    }
+
+    let event_types = request
+        .event_types
+        .iter()
+        .map(|event_type| WebhookEventType::parse(event_type))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| AuthAPIError::InvalidWebhookSubscription)?;
+    if event_types.is_empty() {
+        return Err(AuthAPIError::InvalidWebhookSubscription);
+    }
+
+    let subscription = WebhookSubscription::new(url.to_string(), event_types);
+    let secret = subscription.secret.expose_secret().to_owned();
+
+    state
+        .webhook_store
+        .add_subscription(subscription.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateWebhookSubscriptionResponse {
+            subscription: subscription.into(),
+            secret,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks",
+    tag = "webhooks",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "All subscriptions", body = WebhookSubscriptionsResponse),
+        (status = 400, description = "Missing admin token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid admin token", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "list_webhook_subscriptions", skip_all)]
+pub async fn list_webhook_subscriptions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+
+    let subscriptions = state
+        .webhook_store
+        .get_subscriptions()
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(WebhookSubscriptionResponse::from)
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(WebhookSubscriptionsResponse { subscriptions }),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = Uuid, Path, description = "Subscription id")),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Subscription deleted"),
+        (status = 400, description = "Missing admin token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid admin token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown subscription", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "delete_webhook_subscription", skip_all)]
+pub async fn delete_webhook_subscription(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+
+    match state.webhook_store.delete_subscription(&id).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(WebhookStoreError::SubscriptionNotFound) => Err(AuthAPIError::NotFound),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// Lists deliveries, newest first. Dead-lettered deliveries are found with `status=dead`.
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks/deliveries",
+    tag = "webhooks",
+    params(WebhookDeliveryQuery),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Deliveries, newest first", body = WebhookDeliveriesResponse),
+        (status = 400, description = "Missing admin token or invalid query", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid admin token", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "list_webhook_deliveries", skip_all)]
+pub async fn list_webhook_deliveries(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<WebhookDeliveryQuery>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+
+    let status = query
+        .status
+        .map(|status| WebhookDeliveryStatus::parse(&status))
+        .transpose()
+        .map_err(|_| AuthAPIError::InvalidQuery)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_WEBHOOK_DELIVERY_LIMIT);
+    if !(1..=MAX_WEBHOOK_DELIVERY_LIMIT).contains(&limit) {
+        return Err(AuthAPIError::InvalidQuery);
+    }
+
+    let deliveries = state
+        .webhook_store
+        .get_deliveries(status, limit)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(WebhookDeliveryResponse::from)
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(WebhookDeliveriesResponse { deliveries }),
+    ))
+}
+
+// Queues the delivery to be sent again right away with a fresh set of attempts.
+#[utoipa::path(
+    post,
+    path = "/admin/webhooks/deliveries/{id}/replay",
+    tag = "webhooks",
+    params(("id" = Uuid, Path, description = "Delivery id")),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "The delivery is queued again", body = WebhookDeliveryResponse),
+        (status = 400, description = "Missing admin token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid admin token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown delivery", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "replay_webhook_delivery", skip_all)]
+pub async fn replay_webhook_delivery(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+
+    let webhook_store = &state.webhook_store;
+
+    let mut delivery = match webhook_store.get_delivery(&id).await {
+        Ok(delivery) => delivery,
+        Err(WebhookStoreError::DeliveryNotFound) => return Err(AuthAPIError::NotFound),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    delivery.replay(Utc::now());
+
+    webhook_store
+        .update_delivery(&delivery)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(WebhookDeliveryResponse::from(delivery)),
+    ))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateWebhookSubscriptionRequest {
+    pub url: String,
+    #[serde(rename = "eventTypes")]
+    pub event_types: Vec<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WebhookDeliveryQuery {
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateWebhookSubscriptionResponse {
+    #[serde(flatten)]
+    pub subscription: WebhookSubscriptionResponse,
+    pub secret: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookSubscriptionsResponse {
+    pub subscriptions: Vec<WebhookSubscriptionResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookSubscriptionResponse {
+    pub id: Uuid,
+    pub url: String,
+    #[serde(rename = "eventTypes")]
+    pub event_types: Vec<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<WebhookSubscription> for WebhookSubscriptionResponse {
+    fn from(subscription: WebhookSubscription) -> Self {
+        Self {
+            id: subscription.id,
+            url: subscription.url,
+            event_types: subscription
+                .event_types
+                .iter()
+                .map(|event_type| event_type.as_str().to_owned())
+                .collect(),
+            created_at: subscription.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookDeliveriesResponse {
+    pub deliveries: Vec<WebhookDeliveryResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookDeliveryResponse {
+    pub id: Uuid,
+    #[serde(rename = "subscriptionId")]
+    pub subscription_id: Uuid,
+    #[serde(rename = "eventId")]
+    pub event_id: Uuid,
+    #[serde(rename = "eventType")]
+    pub event_type: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    #[serde(rename = "nextAttemptAt")]
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "deliveredAt")]
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryResponse {
+    fn from(delivery: WebhookDelivery) -> Self {
+        Self {
+            id: delivery.id,
+            subscription_id: delivery.subscription_id,
+            event_id: delivery.event_id,
+            event_type: delivery.event_type.as_str().to_owned(),
+            payload: delivery.payload,
+            status: delivery.status.as_str().to_owned(),
+            attempts: delivery.attempts,
+            next_attempt_at: delivery.next_attempt_at,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at,
+            delivered_at: delivery.delivered_at,
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Result};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{
+    app_state::{AuditLogStoreType, EmailOutboxStoreType, UserStoreType, WebhookStoreType},
+    domain::{Email, UserId},
+    utils::{constants::ACCOUNT_DELETION_GRACE_PERIOD_DAYS, shutdown::Shutdown},
+};
+
+// Removes the accounts whose grace period was over at `now` together with their emails, and
+// strips their personal data from the audit log and the webhook payloads.
+#[tracing::instrument(name = "purge_deleted_accounts", skip_all)]
+pub async fn purge_deleted_accounts(
+    user_store: &UserStoreType,
+    audit_log_store: &AuditLogStoreType,
+    email_outbox_store: &EmailOutboxStoreType,
+    webhook_store: &WebhookStoreType,
+    now: DateTime<Utc>,
+) -> Result<Vec<UserId>> {
+    let grace_period = chrono::Duration::try_days(ACCOUNT_DELETION_GRACE_PERIOD_DAYS)
+        .ok_or(eyre!("failed to create grace period time delta"))?;
+
+    let purged = user_store.purge_deleted_users(now - grace_period).await?;
+    erase_purged_accounts(audit_log_store, email_outbox_store, webhook_store, &purged).await?;
+
+    Ok(purged)
+}
+
+// Removes the deleted accounts with this email right away, like `purge_deleted_accounts` does
+// once their grace period is over. Used when someone signs up with the email again.
+#[tracing::instrument(name = "purge_deleted_accounts_with_email", skip_all)]
+pub async fn purge_deleted_accounts_with_email(
+    user_store: &UserStoreType,
+    audit_log_store: &AuditLogStoreType,
+    email_outbox_store: &EmailOutboxStoreType,
+    webhook_store: &WebhookStoreType,
+    email: &Email,
+) -> Result<Vec<UserId>> {
+    let purged = user_store.purge_deleted_users_with_email(email).await?;
+    erase_purged_accounts(audit_log_store, email_outbox_store, webhook_store, &purged).await?;
+
+    Ok(purged)
+}
+
+async fn erase_purged_accounts(
+    audit_log_store: &AuditLogStoreType,
+    email_outbox_store: &EmailOutboxStoreType,
+    webhook_store: &WebhookStoreType,
+    purged: &[UserId],
+) -> Result<()> {
+    for user_id in purged {
+        audit_log_store.anonymize_user(user_id).await?;
+        email_outbox_store.delete_user_emails(user_id).await?;
+        webhook_store.redact_user_deliveries(user_id).await?;
+    }
+
+    if !purged.is_empty() {
+        tracing::info!(count = purged.len(), "purged deleted accounts");
+    }
+
+    Ok(())
+}
+
+// Runs `purge_deleted_accounts` every `interval` until the service shuts down.
+pub fn spawn_account_purger(
+    user_store: UserStoreType,
+    audit_log_store: AuditLogStoreType,
+    email_outbox_store: EmailOutboxStoreType,
+    webhook_store: WebhookStoreType,
+    interval: Duration,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            if let Err(e) = purge_deleted_accounts(
+                &user_store,
+                &audit_log_store,
+                &email_outbox_store,
+                &webhook_store,
+                Utc::now(),
+            )
+            .await
+            {
+                tracing::error!("failed to purge deleted accounts: {:?}", e);
+            }
+        }
+    })
+}
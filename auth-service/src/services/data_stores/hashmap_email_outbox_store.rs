@@ -0,0 +1,178 @@
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::domain::{
+    data_stores::{EmailOutboxStore, EmailOutboxStoreError},
+    OutboxEmail, OutboxEmailStatus, UserId,
+};
+
+#[derive(Default)]
+pub struct HashmapEmailOutboxStore {
+    emails: DashMap<Uuid, OutboxEmail>,
+}
+
+fn is_due(email: &OutboxEmail, now: DateTime<Utc>) -> bool {
+    email.status == OutboxEmailStatus::Pending && email.next_attempt_at <= now
+}
+
+#[async_trait::async_trait]
+impl EmailOutboxStore for HashmapEmailOutboxStore {
+    async fn add_email(&self, email: &OutboxEmail) -> Result<(), EmailOutboxStoreError> {
+        self.emails.insert(email.id, email.clone());
+        Ok(())
+    }
+
+    async fn claim_due_emails(
+        &self,
+        now: DateTime<Utc>,
+        claimed_until: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        let mut due: Vec<_> = self
+            .emails
+            .iter()
+            .filter(|email| is_due(email, now))
+            .map(|email| (email.next_attempt_at, email.id))
+            .collect();
+        due.sort();
+
+        // Another worker may have claimed an email since it was listed, so each one is checked
+        // again under its entry lock.
+        Ok(due
+            .into_iter()
+            .filter_map(|(_, id)| {
+                let mut email = self.emails.get_mut(&id)?;
+                if !is_due(&email, now) {
+                    return None;
+                }
+                email.next_attempt_at = claimed_until;
+                Some(email.clone())
+            })
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn get_email(&self, id: &Uuid) -> Result<OutboxEmail, EmailOutboxStoreError> {
+        self.emails
+            .get(id)
+            .map(|email| email.clone())
+            .ok_or(EmailOutboxStoreError::EmailNotFound)
+    }
+
+    async fn get_emails(
+        &self,
+        status: Option<OutboxEmailStatus>,
+        limit: i64,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        let mut emails: Vec<_> = self
+            .emails
+            .iter()
+            .filter(|email| status.is_none_or(|status| email.status == status))
+            .map(|email| email.clone())
+            .collect();
+        emails.sort_by_key(|email| std::cmp::Reverse(email.created_at));
+        emails.truncate(limit as usize);
+        Ok(emails)
+    }
+
+    async fn get_user_emails(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        let mut emails: Vec<_> = self
+            .emails
+            .iter()
+            .filter(|email| email.user_id == *user_id)
+            .map(|email| email.clone())
+            .collect();
+        emails.sort_by_key(|email| std::cmp::Reverse(email.created_at));
+        Ok(emails)
+    }
+
+    async fn update_email(&self, email: &OutboxEmail) -> Result<(), EmailOutboxStoreError> {
+        let mut stored = self
+            .emails
+            .get_mut(&email.id)
+            .ok_or(EmailOutboxStoreError::EmailNotFound)?;
+        *stored = email.clone();
+        Ok(())
+    }
+
+    async fn delete_user_emails(&self, user_id: &UserId) -> Result<u64, EmailOutboxStoreError> {
+        let before = self.emails.len();
+        self.emails.retain(|_, email| email.user_id != *user_id);
+        Ok((before - self.emails.len()) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Email, EmailKind, EmailMessage};
+    use secrecy::Secret;
+
+    fn email() -> OutboxEmail {
+        OutboxEmail::new(
+            EmailKind::AccountDeleted,
+            &UserId::default(),
+            &Email::parse(Secret::new("user@example.com".to_owned())).unwrap(),
+            EmailMessage::new(
+                "Your account was deleted".to_owned(),
+                "<p>content</p>".to_owned(),
+                "content".to_owned(),
+            ),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_claim_due_emails_claims_each_email_once() {
+        let store = HashmapEmailOutboxStore::default();
+        store.add_email(&email()).await.unwrap();
+
+        let now = Utc::now();
+        let claimed_until = now + chrono::Duration::try_minutes(1).unwrap();
+
+        let claimed = store
+            .claim_due_emails(now, claimed_until, 10)
+            .await
+            .unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].next_attempt_at, claimed_until);
+
+        let claimed = store
+            .claim_due_emails(now, claimed_until, 10)
+            .await
+            .unwrap();
+        assert!(claimed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_claim_due_emails_skips_emails_that_are_not_pending() {
+        let store = HashmapEmailOutboxStore::default();
+        let mut sent = email();
+        store.add_email(&sent).await.unwrap();
+        sent.record_sent(Utc::now());
+        store.update_email(&sent).await.unwrap();
+
+        let now = Utc::now();
+        let claimed = store.claim_due_emails(now, now, 10).await.unwrap();
+        assert!(claimed.is_empty());
+
+        let emails = store
+            .get_emails(Some(OutboxEmailStatus::Sent), 10)
+            .await
+            .unwrap();
+        assert_eq!(emails.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_email_errors_when_email_does_not_exist() {
+        let store = HashmapEmailOutboxStore::default();
+
+        assert_eq!(
+            store.update_email(&email()).await,
+            Err(EmailOutboxStoreError::EmailNotFound)
+        );
+    }
+}
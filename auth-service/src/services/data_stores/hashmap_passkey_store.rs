@@ -0,0 +1,166 @@
+use dashmap::{mapref::entry::Entry, DashMap};
+use webauthn_rs::prelude::{CredentialID, Passkey};
+
+use crate::domain::{
+    data_stores::{PasskeyStore, PasskeyStoreError, StoredPasskey},
+    UserId,
+};
+
+// Keyed by credential id, which is unique across all users, so adding a passkey can check for
+// a duplicate and insert it under one entry lock.
+#[derive(Default)]
+pub struct HashmapPasskeyStore {
+    passkeys: DashMap<CredentialID, (UserId, StoredPasskey)>,
+}
+
+#[async_trait::async_trait]
+impl PasskeyStore for HashmapPasskeyStore {
+    async fn add_passkey(
+        &self,
+        user_id: &UserId,
+        passkey: Passkey,
+    ) -> Result<(), PasskeyStoreError> {
+        match self.passkeys.entry(passkey.cred_id().clone()) {
+            Entry::Occupied(_) => Err(PasskeyStoreError::PasskeyAlreadyExists),
+            Entry::Vacant(entry) => {
+                entry.insert((
+                    *user_id,
+                    StoredPasskey {
+                        passkey,
+                        sign_count: 0,
+                    },
+                ));
+                Ok(())
+            }
+        }
+    }
+
+    async fn get_passkeys(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<StoredPasskey>, PasskeyStoreError> {
+        Ok(self
+            .passkeys
+            .iter()
+            .filter(|entry| entry.0 == *user_id)
+            .map(|entry| entry.1.clone())
+            .collect())
+    }
+
+    async fn update_passkey(
+        &self,
+        user_id: &UserId,
+        passkey: &Passkey,
+        sign_count: u32,
+    ) -> Result<(), PasskeyStoreError> {
+        match self.passkeys.get_mut(passkey.cred_id()) {
+            Some(mut entry) if entry.0 == *user_id => {
+                let stored_sign_count = entry.1.sign_count;
+                if sign_count <= stored_sign_count && !(sign_count == 0 && stored_sign_count == 0) {
+                    return Err(PasskeyStoreError::SignCountRegressed);
+                }
+                entry.1 = StoredPasskey {
+                    passkey: passkey.clone(),
+                    sign_count,
+                };
+                Ok(())
+            }
+            _ => Err(PasskeyStoreError::PasskeyNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::webauthn::build_webauthn;
+    use webauthn_authenticator_rs::{softpasskey::SoftPasskey, WebauthnAuthenticator};
+
+    // Registers a fresh software authenticator to get a real credential.
+    fn new_passkey(user_id: &UserId) -> Passkey {
+        let webauthn = build_webauthn("http://localhost:3000");
+        let (challenge, registration) = webauthn
+            .start_passkey_registration(*user_id.as_ref(), "user", "user", None)
+            .unwrap();
+        let origin = webauthn.get_allowed_origins()[0].clone();
+        let credential = WebauthnAuthenticator::new(SoftPasskey::new(true))
+            .do_registration(origin, challenge)
+            .unwrap();
+
+        webauthn
+            .finish_passkey_registration(&credential, &registration)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_add_passkey_succeeds() {
+        let expected = Ok(());
+        let store = HashmapPasskeyStore::default();
+        let user_id = UserId::default();
+
+        let actual = store.add_passkey(&user_id, new_passkey(&user_id)).await;
+
+        assert_eq!(actual, expected);
+        assert_eq!(store.get_passkeys(&user_id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_add_passkey_errors_when_credential_exists() {
+        let store = HashmapPasskeyStore::default();
+        let user_id = UserId::default();
+        let passkey = new_passkey(&user_id);
+
+        store.add_passkey(&user_id, passkey.clone()).await.unwrap();
+        let actual = store.add_passkey(&user_id, passkey).await;
+
+        assert_eq!(actual, Err(PasskeyStoreError::PasskeyAlreadyExists));
+    }
+
+    #[tokio::test]
+    async fn test_get_passkeys_is_empty_for_unknown_user() {
+        let store = HashmapPasskeyStore::default();
+        let user_id = UserId::default();
+
+        assert!(store.get_passkeys(&user_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update_passkey_stores_sign_count() {
+        let store = HashmapPasskeyStore::default();
+        let user_id = UserId::default();
+        let passkey = new_passkey(&user_id);
+
+        store.add_passkey(&user_id, passkey.clone()).await.unwrap();
+        store.update_passkey(&user_id, &passkey, 7).await.unwrap();
+
+        let stored = store.get_passkeys(&user_id).await.unwrap();
+        assert_eq!(stored[0].sign_count, 7);
+    }
+
+    #[tokio::test]
+    async fn test_update_passkey_errors_when_sign_count_regresses() {
+        let store = HashmapPasskeyStore::default();
+        let user_id = UserId::default();
+        let passkey = new_passkey(&user_id);
+
+        store.add_passkey(&user_id, passkey.clone()).await.unwrap();
+        store.update_passkey(&user_id, &passkey, 7).await.unwrap();
+        let actual = store.update_passkey(&user_id, &passkey, 7).await;
+
+        assert_eq!(actual, Err(PasskeyStoreError::SignCountRegressed));
+        let stored = store.get_passkeys(&user_id).await.unwrap();
+        assert_eq!(stored[0].sign_count, 7);
+    }
+
+    #[tokio::test]
+    async fn test_update_passkey_errors_when_passkey_does_not_exist() {
+        let store = HashmapPasskeyStore::default();
+        let user_id = UserId::default();
+
+        let actual = store
+            .update_passkey(&user_id, &new_passkey(&user_id), 1)
+            .await;
+
+        assert_eq!(actual, Err(PasskeyStoreError::PasskeyNotFound));
+    }
+}
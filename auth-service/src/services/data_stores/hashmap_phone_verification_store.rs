@@ -0,0 +1,171 @@
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+
+use crate::domain::{
+    data_stores::{
+        PhoneVerificationStore, PhoneVerificationStoreError, TwoFACode,
+        PHONE_VERIFICATION_MAX_CODES_PER_DAY, PHONE_VERIFICATION_RESEND_COOLDOWN_SECONDS,
+    },
+    PhoneNumber, UserId,
+};
+
+#[derive(Default)]
+pub struct HashmapPhoneVerificationStore {
+    codes: DashMap<UserId, (PhoneNumber, TwoFACode)>,
+    sends: DashMap<UserId, Sends>,
+}
+
+// The codes sent to one user since `day_started_at`.
+struct Sends {
+    last_sent_at: DateTime<Utc>,
+    day_started_at: DateTime<Utc>,
+    count: u64,
+}
+
+#[async_trait::async_trait]
+impl PhoneVerificationStore for HashmapPhoneVerificationStore {
+    async fn record_send(&self, user_id: &UserId) -> Result<(), PhoneVerificationStoreError> {
+        let now = Utc::now();
+        let mut sends = self.sends.entry(*user_id).or_insert(Sends {
+            last_sent_at: DateTime::<Utc>::MIN_UTC,
+            day_started_at: now,
+            count: 0,
+        });
+
+        if now - sends.last_sent_at
+            < Duration::seconds(PHONE_VERIFICATION_RESEND_COOLDOWN_SECONDS as i64)
+        {
+            return Err(PhoneVerificationStoreError::TooManyCodes);
+        }
+        if now - sends.day_started_at >= Duration::days(1) {
+            sends.day_started_at = now;
+            sends.count = 0;
+        }
+        if sends.count >= PHONE_VERIFICATION_MAX_CODES_PER_DAY {
+            return Err(PhoneVerificationStoreError::TooManyCodes);
+        }
+
+        sends.last_sent_at = now;
+        sends.count += 1;
+        Ok(())
+    }
+
+    async fn add_code(
+        &self,
+        user_id: UserId,
+        phone_number: PhoneNumber,
+        code: TwoFACode,
+    ) -> Result<(), PhoneVerificationStoreError> {
+        self.codes.insert(user_id, (phone_number, code));
+        Ok(())
+    }
+
+    async fn remove_code(&self, user_id: &UserId) -> Result<(), PhoneVerificationStoreError> {
+        match self.codes.remove(user_id) {
+            Some(_) => Ok(()),
+            None => Err(PhoneVerificationStoreError::VerificationNotFound),
+        }
+    }
+
+    async fn get_code(
+        &self,
+        user_id: &UserId,
+    ) -> Result<(PhoneNumber, TwoFACode), PhoneVerificationStoreError> {
+        self.codes
+            .get(user_id)
+            .map(|pending| pending.value().clone())
+            .ok_or(PhoneVerificationStoreError::VerificationNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::*;
+
+    fn phone_number(number: &str) -> PhoneNumber {
+        PhoneNumber::parse(Secret::new(number.to_owned())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_add_code_replaces_the_pending_number() {
+        let store = HashmapPhoneVerificationStore::default();
+        let user_id = UserId::default();
+        let code = TwoFACode::default();
+
+        let _ = store
+            .add_code(
+                user_id,
+                phone_number("+4915123456789"),
+                TwoFACode::default(),
+            )
+            .await;
+        let actual = store
+            .add_code(user_id, phone_number("+14155552671"), code.clone())
+            .await;
+        assert_eq!(actual, Ok(()));
+
+        let (stored_number, stored_code) = store.get_code(&user_id).await.unwrap();
+        assert_eq!(stored_number, phone_number("+14155552671"));
+        assert_eq!(stored_code, code);
+    }
+
+    #[tokio::test]
+    async fn test_remove_code_errors_when_nothing_is_pending() {
+        let store = HashmapPhoneVerificationStore::default();
+        let user_id = UserId::default();
+
+        let _ = store
+            .add_code(
+                user_id,
+                phone_number("+4915123456789"),
+                TwoFACode::default(),
+            )
+            .await;
+        assert_eq!(store.remove_code(&user_id).await, Ok(()));
+
+        assert_eq!(
+            store.remove_code(&user_id).await,
+            Err(PhoneVerificationStoreError::VerificationNotFound)
+        );
+        assert_eq!(
+            store.get_code(&user_id).await.unwrap_err(),
+            PhoneVerificationStoreError::VerificationNotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_send_enforces_the_cooldown() {
+        let store = HashmapPhoneVerificationStore::default();
+        let user_id = UserId::default();
+
+        assert_eq!(store.record_send(&user_id).await, Ok(()));
+        assert_eq!(
+            store.record_send(&user_id).await,
+            Err(PhoneVerificationStoreError::TooManyCodes)
+        );
+        // Other users have a cooldown of their own.
+        assert_eq!(store.record_send(&UserId::default()).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_record_send_enforces_the_daily_limit() {
+        let store = HashmapPhoneVerificationStore::default();
+        let user_id = UserId::default();
+
+        for _ in 0..PHONE_VERIFICATION_MAX_CODES_PER_DAY {
+            assert_eq!(store.record_send(&user_id).await, Ok(()));
+            // Skip the cooldown.
+            store.sends.get_mut(&user_id).unwrap().last_sent_at = DateTime::<Utc>::MIN_UTC;
+        }
+        assert_eq!(
+            store.record_send(&user_id).await,
+            Err(PhoneVerificationStoreError::TooManyCodes)
+        );
+
+        // A new day starts over.
+        store.sends.get_mut(&user_id).unwrap().day_started_at = Utc::now() - Duration::days(1);
+        assert_eq!(store.record_send(&user_id).await, Ok(()));
+    }
+}
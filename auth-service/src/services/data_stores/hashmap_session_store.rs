@@ -0,0 +1,78 @@
+use dashmap::DashMap;
+use std::collections::HashSet;
+
+use crate::domain::{
+    data_stores::{SessionStore, SessionStoreError},
+    UserId,
+};
+
+#[derive(Default)]
+pub struct HashmapSessionStore {
+    sessions: DashMap<UserId, HashSet<String>>,
+}
+
+#[async_trait::async_trait]
+impl SessionStore for HashmapSessionStore {
+    async fn add_session(&self, user_id: &UserId, token: String) -> Result<(), SessionStoreError> {
+        self.sessions.entry(*user_id).or_default().insert(token);
+        Ok(())
+    }
+
+    async fn remove_session(&self, user_id: &UserId, token: &str) -> Result<(), SessionStoreError> {
+        if let Some(mut tokens) = self.sessions.get_mut(user_id) {
+            tokens.remove(token);
+        }
+        Ok(())
+    }
+
+    async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<String>, SessionStoreError> {
+        Ok(self
+            .sessions
+            .get(user_id)
+            .map(|tokens| tokens.value().iter().cloned().collect())
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_session() {
+        let store = HashmapSessionStore::default();
+        let user_id = UserId::default();
+
+        let result = store.add_session(&user_id, "token".to_owned()).await;
+
+        assert!(result.is_ok());
+        assert_eq!(store.get_sessions(&user_id).await.unwrap(), vec!["token"]);
+    }
+
+    #[tokio::test]
+    async fn test_remove_session() {
+        let store = HashmapSessionStore::default();
+        let user_id = UserId::default();
+        store
+            .add_session(&user_id, "first".to_owned())
+            .await
+            .unwrap();
+        store
+            .add_session(&user_id, "second".to_owned())
+            .await
+            .unwrap();
+
+        let result = store.remove_session(&user_id, "first").await;
+
+        assert!(result.is_ok());
+        assert_eq!(store.get_sessions(&user_id).await.unwrap(), vec!["second"]);
+    }
+
+    #[tokio::test]
+    async fn test_get_sessions_is_empty_for_unknown_user() {
+        let store = HashmapSessionStore::default();
+        let user_id = UserId::default();
+
+        assert!(store.get_sessions(&user_id).await.unwrap().is_empty());
+    }
+}
@@ -1,30 +1,29 @@
-use secrecy::Secret;
-use std::collections::HashMap;
+use dashmap::DashMap;
 
 use crate::domain::{
     data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
-    email::Email,
+    UserId,
 };
 
 #[derive(Default)]
 pub struct HashmapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    codes: DashMap<UserId, (LoginAttemptId, TwoFACode)>,
 }
 
 #[async_trait::async_trait]
 impl TwoFACodeStore for HashmapTwoFACodeStore {
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
+        user_id: UserId,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        self.codes.insert(email, (login_attempt_id, code));
+        self.codes.insert(user_id, (login_attempt_id, code));
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        match self.codes.remove(email) {
+    async fn remove_code(&self, user_id: &UserId) -> Result<(), TwoFACodeStoreError> {
+        match self.codes.remove(user_id) {
             Some(_) => Ok(()),
             None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
@@ -32,10 +31,10 @@ impl TwoFACodeStore for HashmapTwoFACodeStore {
 
     async fn get_code(
         &self,
-        email: &Email,
+        user_id: &UserId,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        match self.codes.get(email) {
-            Some(x) => Ok(x.clone()),
+        match self.codes.get(user_id) {
+            Some(x) => Ok(x.value().clone()),
             None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
     }
@@ -48,34 +47,32 @@ mod tests {
     #[tokio::test]
     async fn test_add_code_succeeds() {
         let expected = Ok(());
-        let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("user@example.com".to_string())).unwrap();
+        let store = HashmapTwoFACodeStore::default();
+        let user_id = UserId::default();
         let login_attempt_id = LoginAttemptId::default();
         let two_fa_code = TwoFACode::default();
 
-        let actual = store.add_code(email, login_attempt_id, two_fa_code).await;
+        let actual = store.add_code(user_id, login_attempt_id, two_fa_code).await;
 
         assert_eq!(actual, expected);
     }
 
     #[tokio::test]
     async fn test_remove_code_successful_when_code_exists() {
-        let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("user@example.com".to_string())).unwrap();
+        let store = HashmapTwoFACodeStore::default();
+        let user_id = UserId::default();
         let login_attempt_id = LoginAttemptId::default();
         let two_fa_code = TwoFACode::default();
 
         {
             let expected = Ok(());
-            let actual = store
-                .add_code(email.clone(), login_attempt_id, two_fa_code)
-                .await;
+            let actual = store.add_code(user_id, login_attempt_id, two_fa_code).await;
             assert_eq!(actual, expected);
         }
 
         {
             let expected = Ok(());
-            let actual = store.remove_code(&email).await;
+            let actual = store.remove_code(&user_id).await;
 
             assert_eq!(actual, expected);
         }
@@ -83,14 +80,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_remove_code_errors_when_code_does_not_exist() {
-        let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("user@example.com".to_string())).unwrap();
-        let login_attempt_id = LoginAttemptId::default();
-        let two_fa_code = TwoFACode::default();
+        let store = HashmapTwoFACodeStore::default();
+        let user_id = UserId::default();
 
         {
             let expected = true;
-            let actual = store.remove_code(&email).await;
+            let actual = store.remove_code(&user_id).await;
 
             assert_eq!(actual.is_err(), expected);
         }
@@ -98,15 +93,15 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_code_succeeds() {
-        let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("user@example.com".to_string())).unwrap();
+        let store = HashmapTwoFACodeStore::default();
+        let user_id = UserId::default();
         let login_attempt_id = LoginAttemptId::default();
         let two_fa_code = TwoFACode::default();
 
         {
             let expected = Ok(());
             let actual = store
-                .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
+                .add_code(user_id, login_attempt_id.clone(), two_fa_code.clone())
                 .await;
 
             assert_eq!(actual, expected);
@@ -114,7 +109,7 @@ mod tests {
 
         {
             let expected = true;
-            let actual = store.get_code(&email.clone()).await;
+            let actual = store.get_code(&user_id).await;
 
             assert_eq!(actual.is_ok(), expected);
             //            assert_eq!(actual.unwrap().0, login_attempt_id);
@@ -123,14 +118,12 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_get_code_fails_when_email_does_not_exist() {
+    async fn test_get_code_fails_when_user_id_does_not_exist() {
         let store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("user@example.com".to_string())).unwrap();
-        let login_attempt_id = LoginAttemptId::default();
-        let two_fa_code = TwoFACode::default();
+        let user_id = UserId::default();
 
         let expected = true;
-        let actual = store.get_code(&email.clone()).await;
+        let actual = store.get_code(&user_id).await;
 
         assert_eq!(actual.is_err(), expected);
     }
@@ -1,46 +1,240 @@
-use secrecy::{ExposeSecret, Secret};
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use dashmap::{mapref::entry::Entry, DashMap};
+use secrecy::ExposeSecret;
 
-use crate::domain::{Email, Password};
+use crate::domain::{Email, EmailUndeliverable, Password, PhoneNumber, TwoFAChannel, UserId};
 use crate::domain::{User, UserStore, UserStoreError};
 
+// Sharded by email, so requests for different users don't wait on each other. Deleted users
+// are kept in their entry, and no method holds one entry while locking another.
 #[derive(Default)]
 pub struct HashmapUserStore {
-    users: HashMap<Email, User>,
+    users: DashMap<Email, StoredUser>,
+}
+
+struct StoredUser {
+    user: User,
+    deleted_at: Option<DateTime<Utc>>,
+    verified_at: Option<DateTime<Utc>>,
+}
+
+impl HashmapUserStore {
+    fn email_of(&self, id: &UserId) -> Result<Email, UserStoreError> {
+        self.users
+            .iter()
+            .find(|entry| entry.user.id == *id && entry.deleted_at.is_none())
+            .map(|entry| entry.key().clone())
+            .ok_or(UserStoreError::UserNotFound)
+    }
+
+    // Runs `update` on the user with this id, unless it was deleted.
+    fn update_user(
+        &self,
+        id: &UserId,
+        update: impl FnOnce(&mut User),
+    ) -> Result<(), UserStoreError> {
+        let email = self.email_of(id)?;
+        match self.users.get_mut(&email) {
+            Some(mut entry) if entry.deleted_at.is_none() => {
+                update(&mut entry.user);
+                Ok(())
+            }
+            _ => Err(UserStoreError::UserNotFound),
+        }
+    }
 }
 
 #[async_trait::async_trait]
 impl UserStore for HashmapUserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        if self.users.contains_key(&user.email) {
-            Err(UserStoreError::UserAlreadyExists)
-        } else {
-            self.users.insert(user.email.clone(), user);
-            Ok(())
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        match self.users.entry(user.email.clone()) {
+            Entry::Occupied(_) => Err(UserStoreError::UserAlreadyExists),
+            Entry::Vacant(entry) => {
+                entry.insert(StoredUser {
+                    user,
+                    deleted_at: None,
+                    verified_at: None,
+                });
+                Ok(())
+            }
         }
     }
 
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
         match self.users.get(email) {
-            Some(u) => Ok(u.clone()),
-            None => Err(UserStoreError::UserNotFound),
+            Some(entry) if entry.deleted_at.is_none() => Ok(entry.user.clone()),
+            _ => Err(UserStoreError::UserNotFound),
         }
     }
 
+    async fn get_user_by_id(&self, id: &UserId) -> Result<User, UserStoreError> {
+        self.users
+            .iter()
+            .find(|entry| entry.user.id == *id && entry.deleted_at.is_none())
+            .map(|entry| entry.user.clone())
+            .ok_or(UserStoreError::UserNotFound)
+    }
+
     async fn validate_user(
         &self,
         email: &Email,
         password: &Password,
     ) -> Result<(), UserStoreError> {
-        match self.users.get(email) {
-            Some(user) => {
-                if user.password.as_ref().expose_secret() == password.as_ref().expose_secret() {
-                    Ok(())
-                } else {
-                    Err(UserStoreError::InvalidCredentials)
-                }
+        let user = self.get_user(email).await?;
+        if user.password.as_ref().expose_secret() == password.as_ref().expose_secret() {
+            Ok(())
+        } else {
+            Err(UserStoreError::InvalidCredentials)
+        }
+    }
+
+    async fn update_password(&self, id: &UserId, password: Password) -> Result<(), UserStoreError> {
+        self.update_user(id, |user| user.password = password)
+    }
+
+    async fn update_email(&self, id: &UserId, new_email: &Email) -> Result<(), UserStoreError> {
+        if self.users.contains_key(new_email) {
+            return Err(UserStoreError::UserAlreadyExists);
+        }
+
+        let email = self.email_of(id)?;
+        let (_, mut stored) = self
+            .users
+            .remove_if(&email, |_, stored| stored.deleted_at.is_none())
+            .ok_or(UserStoreError::UserNotFound)?;
+        match self.users.entry(new_email.clone()) {
+            Entry::Vacant(entry) => {
+                stored.user.email = new_email.clone();
+                stored.user.email_undeliverable = None;
+                entry.insert(stored);
+                Ok(())
+            }
+            // Taken since the check above. The user keeps the old address.
+            Entry::Occupied(entry) => {
+                drop(entry);
+                self.users.insert(email, stored);
+                Err(UserStoreError::UserAlreadyExists)
+            }
+        }
+    }
+
+    async fn delete_user(&self, id: &UserId) -> Result<(), UserStoreError> {
+        let email = self.email_of(id)?;
+        match self.users.get_mut(&email) {
+            Some(mut entry) if entry.deleted_at.is_none() => {
+                entry.deleted_at = Some(Utc::now());
+                Ok(())
+            }
+            _ => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn restore_user(
+        &self,
+        email: &Email,
+        password: &Password,
+    ) -> Result<UserId, UserStoreError> {
+        let mut entry = match self.users.get_mut(email) {
+            Some(entry) if entry.deleted_at.is_some() => entry,
+            _ => return Err(UserStoreError::UserNotFound),
+        };
+
+        if entry.user.password.as_ref().expose_secret() != password.as_ref().expose_secret() {
+            return Err(UserStoreError::InvalidCredentials);
+        }
+
+        entry.deleted_at = None;
+        Ok(entry.user.id)
+    }
+
+    async fn purge_deleted_users(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> Result<Vec<UserId>, UserStoreError> {
+        let mut purged = vec![];
+        self.users.retain(|_, stored| match stored.deleted_at {
+            Some(deleted_at) if deleted_at < deleted_before => {
+                purged.push(stored.user.id);
+                false
+            }
+            _ => true,
+        });
+
+        Ok(purged)
+    }
+
+    async fn purge_deleted_users_with_email(
+        &self,
+        email: &Email,
+    ) -> Result<Vec<UserId>, UserStoreError> {
+        Ok(self
+            .users
+            .remove_if(email, |_, stored| stored.deleted_at.is_some())
+            .map(|(_, stored)| stored.user.id)
+            .into_iter()
+            .collect())
+    }
+
+    async fn mark_email_undeliverable(
+        &self,
+        email: &Email,
+        undeliverable: EmailUndeliverable,
+    ) -> Result<UserId, UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(mut entry) if entry.deleted_at.is_none() => {
+                entry.user.email_undeliverable.get_or_insert(undeliverable);
+                Ok(entry.user.id)
+            }
+            _ => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn clear_email_undeliverable(&self, id: &UserId) -> Result<(), UserStoreError> {
+        self.update_user(id, |user| user.email_undeliverable = None)
+    }
+
+    async fn get_users_with_undeliverable_email(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<User>, UserStoreError> {
+        let mut users: Vec<User> = self
+            .users
+            .iter()
+            .filter(|entry| entry.user.email_undeliverable.is_some() && entry.deleted_at.is_none())
+            .map(|entry| entry.user.clone())
+            .collect();
+        users.sort_by_key(|user| {
+            std::cmp::Reverse(user.email_undeliverable.as_ref().map(|state| state.since))
+        });
+        users.truncate(limit.max(0) as usize);
+        Ok(users)
+    }
+
+    async fn update_phone_number(
+        &self,
+        id: &UserId,
+        phone_number: &PhoneNumber,
+    ) -> Result<(), UserStoreError> {
+        self.update_user(id, |user| user.phone_number = Some(phone_number.clone()))
+    }
+
+    async fn update_two_fa_channel(
+        &self,
+        id: &UserId,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError> {
+        self.update_user(id, |user| user.two_fa_channel = channel)
+    }
+
+    async fn mark_verified(&self, id: &UserId) -> Result<bool, UserStoreError> {
+        let email = self.email_of(id)?;
+        match self.users.get_mut(&email) {
+            Some(mut entry) if entry.deleted_at.is_none() => {
+                let first = entry.verified_at.is_none();
+                entry.verified_at.get_or_insert_with(Utc::now);
+                Ok(first)
             }
-            None => Err(UserStoreError::UserNotFound),
+            _ => Err(UserStoreError::UserNotFound),
         }
     }
 }
@@ -48,11 +242,12 @@ impl UserStore for HashmapUserStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use secrecy::Secret;
 
     #[tokio::test]
     async fn test_add_user_succeeds_when_user_not_already_added() {
         let expected = Ok(());
-        let mut store = HashmapUserStore::default();
+        let store = HashmapUserStore::default();
         let user = User::new(
             Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
             Password::parse(Secret::new("password123".to_string())).unwrap(),
@@ -67,7 +262,7 @@ mod tests {
     #[tokio::test]
     async fn test_add_user_fails_when_user_already_added() {
         let expected = Err(UserStoreError::UserAlreadyExists);
-        let mut store = HashmapUserStore::default();
+        let store = HashmapUserStore::default();
         let user = User::new(
             Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
             Password::parse(Secret::new("password123".to_string())).unwrap(),
@@ -80,6 +275,32 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[tokio::test]
+    async fn test_add_user_concurrently_adds_the_user_once() {
+        let store = std::sync::Arc::new(HashmapUserStore::default());
+        let user = User::new(
+            Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                let user = user.clone();
+                tokio::spawn(async move { store.add_user(user).await })
+            })
+            .collect();
+        let mut added = 0;
+        for task in tasks {
+            if task.await.unwrap().is_ok() {
+                added += 1;
+            }
+        }
+
+        assert_eq!(added, 1);
+    }
+
     #[tokio::test]
     async fn test_get_user_succeeds_when_user_exists() {
         let expected = User::new(
@@ -87,7 +308,7 @@ mod tests {
             Password::parse(Secret::new("password123".to_string())).unwrap(),
             true,
         );
-        let mut store = HashmapUserStore::default();
+        let store = HashmapUserStore::default();
 
         let _ = store.add_user(expected.clone()).await;
         let actual = store.get_user(&expected.email).await.unwrap();
@@ -118,7 +339,7 @@ mod tests {
             Password::parse(Secret::new("password123".to_string())).unwrap(),
             true,
         );
-        let mut store = HashmapUserStore::default();
+        let store = HashmapUserStore::default();
 
         let _ = store.add_user(user.clone()).await;
         let actual = store.validate_user(&user.email, &user.password).await;
@@ -149,7 +370,7 @@ mod tests {
             Password::parse(Secret::new("password123".to_string())).unwrap(),
             true,
         );
-        let mut store = HashmapUserStore::default();
+        let store = HashmapUserStore::default();
 
         let _ = store.add_user(user.clone()).await;
         let actual = store
@@ -161,4 +382,324 @@ mod tests {
 
         assert_eq!(actual, expected);
     }
+
+    #[tokio::test]
+    async fn test_update_password_replaces_password() {
+        let user = User::new(
+            Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let new_password = Password::parse(Secret::new("new_password123".to_string())).unwrap();
+        let store = HashmapUserStore::default();
+
+        let _ = store.add_user(user.clone()).await;
+        let actual = store.update_password(&user.id, new_password.clone()).await;
+
+        assert_eq!(actual, Ok(()));
+        assert_eq!(
+            store.validate_user(&user.email, &user.password).await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+        assert_eq!(
+            store.validate_user(&user.email, &new_password).await,
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_password_when_user_does_not_exist() {
+        let expected = Err(UserStoreError::UserNotFound);
+        let store = HashmapUserStore::default();
+
+        let actual = store
+            .update_password(
+                &UserId::default(),
+                Password::parse(Secret::new("password123".to_string())).unwrap(),
+            )
+            .await;
+
+        assert_eq!(actual, expected);
+    }
+
+    #[tokio::test]
+    async fn test_update_email_moves_user() {
+        let user = User::new(
+            Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let new_email = Email::parse(Secret::new("new@example.com".to_string())).unwrap();
+        let store = HashmapUserStore::default();
+
+        let _ = store.add_user(user.clone()).await;
+        let actual = store.update_email(&user.id, &new_email).await;
+
+        assert_eq!(actual, Ok(()));
+        assert_eq!(
+            store.get_user(&user.email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(store.get_user(&new_email).await.unwrap().email, new_email);
+    }
+
+    #[tokio::test]
+    async fn test_update_email_fails_when_new_email_is_taken() {
+        let expected = Err(UserStoreError::UserAlreadyExists);
+        let user = User::new(
+            Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let other = User::new(
+            Email::parse(Secret::new("other@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let store = HashmapUserStore::default();
+
+        let _ = store.add_user(user.clone()).await;
+        let _ = store.add_user(other.clone()).await;
+        let actual = store.update_email(&user.id, &other.email).await;
+
+        assert_eq!(actual, expected);
+    }
+
+    #[tokio::test]
+    async fn test_deleted_user_is_hidden_until_restored() {
+        let user = User::new(
+            Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let store = HashmapUserStore::default();
+
+        let _ = store.add_user(user.clone()).await;
+        assert_eq!(store.delete_user(&user.id).await, Ok(()));
+
+        assert_eq!(
+            store.get_user(&user.email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            store.validate_user(&user.email, &user.password).await,
+            Err(UserStoreError::UserNotFound)
+        );
+
+        assert_eq!(
+            store.restore_user(&user.email, &user.password).await,
+            Ok(user.id)
+        );
+        assert_eq!(store.get_user(&user.email).await, Ok(user));
+    }
+
+    #[tokio::test]
+    async fn test_restore_user_requires_password() {
+        let user = User::new(
+            Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let store = HashmapUserStore::default();
+
+        let _ = store.add_user(user.clone()).await;
+        let _ = store.delete_user(&user.id).await;
+        let actual = store
+            .restore_user(
+                &user.email,
+                &Password::parse(Secret::new("non_matching_password".to_string())).unwrap(),
+            )
+            .await;
+
+        assert_eq!(actual, Err(UserStoreError::InvalidCredentials));
+    }
+
+    #[tokio::test]
+    async fn test_purge_deleted_users_only_removes_expired_deletions() {
+        let deleted = User::new(
+            Email::parse(Secret::new("deleted@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let active = User::new(
+            Email::parse(Secret::new("active@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let store = HashmapUserStore::default();
+
+        let _ = store.add_user(deleted.clone()).await;
+        let _ = store.add_user(active.clone()).await;
+        let _ = store.delete_user(&deleted.id).await;
+
+        let before_deletion = Utc::now() - chrono::Duration::try_hours(1).unwrap();
+        assert_eq!(store.purge_deleted_users(before_deletion).await, Ok(vec![]));
+
+        let purged = store.purge_deleted_users(Utc::now()).await;
+        assert_eq!(purged, Ok(vec![deleted.id]));
+        assert_eq!(
+            store.restore_user(&deleted.email, &deleted.password).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(store.get_user(&active.email).await, Ok(active));
+    }
+
+    #[tokio::test]
+    async fn test_purge_deleted_users_with_email_keeps_live_users() {
+        let user = User::new(
+            Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let store = HashmapUserStore::default();
+        let _ = store.add_user(user.clone()).await;
+
+        assert_eq!(
+            store.purge_deleted_users_with_email(&user.email).await,
+            Ok(vec![])
+        );
+
+        let _ = store.delete_user(&user.id).await;
+        assert_eq!(
+            store.purge_deleted_users_with_email(&user.email).await,
+            Ok(vec![user.id])
+        );
+        assert_eq!(
+            store.restore_user(&user.email, &user.password).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_user_by_id() {
+        let user = User::new(
+            Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let store = HashmapUserStore::default();
+
+        let _ = store.add_user(user.clone()).await;
+
+        assert_eq!(store.get_user_by_id(&user.id).await, Ok(user));
+        assert_eq!(
+            store.get_user_by_id(&UserId::default()).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_undeliverable_email_is_flagged_until_the_address_changes() {
+        use crate::domain::UndeliverableReason;
+
+        let user = User::new(
+            Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let new_email = Email::parse(Secret::new("new@example.com".to_string())).unwrap();
+        let store = HashmapUserStore::default();
+        let _ = store.add_user(user.clone()).await;
+
+        let first = EmailUndeliverable {
+            reason: UndeliverableReason::HardBounce,
+            since: Utc::now(),
+        };
+        let second = EmailUndeliverable {
+            reason: UndeliverableReason::SpamComplaint,
+            since: Utc::now(),
+        };
+        assert_eq!(
+            store
+                .mark_email_undeliverable(&user.email, first.clone())
+                .await,
+            Ok(user.id)
+        );
+        let _ = store.mark_email_undeliverable(&user.email, second).await;
+
+        // The first report is kept.
+        assert_eq!(
+            store
+                .get_user(&user.email)
+                .await
+                .unwrap()
+                .email_undeliverable,
+            Some(first)
+        );
+        assert_eq!(
+            store
+                .get_users_with_undeliverable_email(10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let _ = store.update_email(&user.id, &new_email).await;
+        assert_eq!(
+            store
+                .get_user(&new_email)
+                .await
+                .unwrap()
+                .email_undeliverable,
+            None
+        );
+        assert!(store
+            .get_users_with_undeliverable_email(10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update_phone_number_and_two_fa_channel() {
+        let user = User::new(
+            Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let phone_number = PhoneNumber::parse(Secret::new("+4915123456789".to_string())).unwrap();
+        let store = HashmapUserStore::default();
+        let _ = store.add_user(user.clone()).await;
+
+        assert_eq!(
+            store.update_phone_number(&user.id, &phone_number).await,
+            Ok(())
+        );
+        assert_eq!(
+            store
+                .update_two_fa_channel(&user.id, TwoFAChannel::Sms)
+                .await,
+            Ok(())
+        );
+
+        let stored = store.get_user_by_id(&user.id).await.unwrap();
+        assert_eq!(stored.phone_number, Some(phone_number.clone()));
+        assert_eq!(stored.two_fa_channel, TwoFAChannel::Sms);
+
+        assert_eq!(
+            store
+                .update_phone_number(&UserId::default(), &phone_number)
+                .await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mark_verified_is_only_first_once() {
+        let user = User::new(
+            Email::parse(Secret::new("user@example.com".to_string())).unwrap(),
+            Password::parse(Secret::new("password123".to_string())).unwrap(),
+            true,
+        );
+        let store = HashmapUserStore::default();
+        let _ = store.add_user(user.clone()).await;
+
+        assert_eq!(store.mark_verified(&user.id).await, Ok(true));
+        assert_eq!(store.mark_verified(&user.id).await, Ok(false));
+        assert_eq!(
+            store.mark_verified(&UserId::default()).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
 }
@@ -0,0 +1,253 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Context;
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::domain::{
+    data_stores::{WebhookStore, WebhookStoreError},
+    UserId, WebhookDelivery, WebhookDeliveryStatus, WebhookEvent, WebhookSubscription,
+};
+
+#[derive(Default)]
+pub struct HashmapWebhookStore {
+    subscriptions: DashMap<Uuid, WebhookSubscription>,
+    deliveries: DashMap<Uuid, WebhookDelivery>,
+}
+
+fn is_due(delivery: &WebhookDelivery, now: DateTime<Utc>) -> bool {
+    delivery.status == WebhookDeliveryStatus::Pending && delivery.next_attempt_at <= now
+}
+
+#[async_trait::async_trait]
+impl WebhookStore for HashmapWebhookStore {
+    async fn add_subscription(
+        &self,
+        subscription: WebhookSubscription,
+    ) -> Result<(), WebhookStoreError> {
+        self.subscriptions.insert(subscription.id, subscription);
+        Ok(())
+    }
+
+    async fn get_subscription(&self, id: &Uuid) -> Result<WebhookSubscription, WebhookStoreError> {
+        self.subscriptions
+            .get(id)
+            .map(|subscription| subscription.clone())
+            .ok_or(WebhookStoreError::SubscriptionNotFound)
+    }
+
+    async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscription>, WebhookStoreError> {
+        let mut subscriptions: Vec<_> = self
+            .subscriptions
+            .iter()
+            .map(|subscription| subscription.clone())
+            .collect();
+        subscriptions.sort_by_key(|subscription| subscription.created_at);
+        Ok(subscriptions)
+    }
+
+    async fn delete_subscription(&self, id: &Uuid) -> Result<(), WebhookStoreError> {
+        self.subscriptions
+            .remove(id)
+            .ok_or(WebhookStoreError::SubscriptionNotFound)?;
+        self.deliveries
+            .retain(|_, delivery| delivery.subscription_id != *id);
+        Ok(())
+    }
+
+    async fn enqueue_event(&self, event: &WebhookEvent) -> Result<(), WebhookStoreError> {
+        let payload = event
+            .payload()
+            .wrap_err("failed to render webhook payload")
+            .map_err(WebhookStoreError::UnexpectedError)?;
+
+        for subscription in self.subscriptions.iter() {
+            if subscription.subscribes_to(event.event_type) {
+                let delivery = WebhookDelivery::new(subscription.id, event, payload.clone());
+                self.deliveries.insert(delivery.id, delivery);
+            }
+        }
+        Ok(())
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        claimed_until: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let mut due: Vec<_> = self
+            .deliveries
+            .iter()
+            .filter(|delivery| is_due(delivery, now))
+            .map(|delivery| (delivery.next_attempt_at, delivery.id))
+            .collect();
+        due.sort();
+
+        // Another worker may have claimed a delivery since it was listed, so each one is
+        // checked again under its entry lock.
+        Ok(due
+            .into_iter()
+            .filter_map(|(_, id)| {
+                let mut delivery = self.deliveries.get_mut(&id)?;
+                if !is_due(&delivery, now) {
+                    return None;
+                }
+                delivery.next_attempt_at = claimed_until;
+                Some(delivery.clone())
+            })
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn get_delivery(&self, id: &Uuid) -> Result<WebhookDelivery, WebhookStoreError> {
+        self.deliveries
+            .get(id)
+            .map(|delivery| delivery.clone())
+            .ok_or(WebhookStoreError::DeliveryNotFound)
+    }
+
+    async fn get_deliveries(
+        &self,
+        status: Option<WebhookDeliveryStatus>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let mut deliveries: Vec<_> = self
+            .deliveries
+            .iter()
+            .filter(|delivery| status.is_none_or(|status| delivery.status == status))
+            .map(|delivery| delivery.clone())
+            .collect();
+        deliveries.sort_by_key(|delivery| std::cmp::Reverse(delivery.created_at));
+        deliveries.truncate(limit as usize);
+        Ok(deliveries)
+    }
+
+    async fn get_user_deliveries(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let mut deliveries: Vec<_> = self
+            .deliveries
+            .iter()
+            .filter(|delivery| delivery.user_id == *user_id)
+            .map(|delivery| delivery.clone())
+            .collect();
+        deliveries.sort_by_key(|delivery| std::cmp::Reverse(delivery.created_at));
+        Ok(deliveries)
+    }
+
+    async fn update_delivery(&self, delivery: &WebhookDelivery) -> Result<(), WebhookStoreError> {
+        let mut stored = self
+            .deliveries
+            .get_mut(&delivery.id)
+            .ok_or(WebhookStoreError::DeliveryNotFound)?;
+        *stored = delivery.clone();
+        Ok(())
+    }
+
+    async fn redact_user_deliveries(&self, user_id: &UserId) -> Result<u64, WebhookStoreError> {
+        let mut redacted = 0;
+        for mut delivery in self.deliveries.iter_mut() {
+            if delivery.user_id == *user_id {
+                delivery
+                    .redact()
+                    .wrap_err("failed to redact webhook payload")
+                    .map_err(WebhookStoreError::UnexpectedError)?;
+                redacted += 1;
+            }
+        }
+        Ok(redacted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Email, UserId, WebhookEventType};
+    use secrecy::Secret;
+
+    fn event(event_type: WebhookEventType) -> WebhookEvent {
+        WebhookEvent::new(
+            event_type,
+            &UserId::default(),
+            &Email::parse(Secret::new("user@example.com".to_owned())).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_event_only_reaches_subscribers_of_its_type() {
+        let store = HashmapWebhookStore::default();
+        let signups = WebhookSubscription::new(
+            "https://crm.example.com/hooks".to_owned(),
+            vec![WebhookEventType::UserSignedUp],
+        );
+        let deletions = WebhookSubscription::new(
+            "https://billing.example.com/hooks".to_owned(),
+            vec![WebhookEventType::AccountDeleted],
+        );
+        store.add_subscription(signups.clone()).await.unwrap();
+        store.add_subscription(deletions).await.unwrap();
+
+        store
+            .enqueue_event(&event(WebhookEventType::UserSignedUp))
+            .await
+            .unwrap();
+
+        let deliveries = store.get_deliveries(None, 10).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].subscription_id, signups.id);
+        assert_eq!(deliveries[0].status, WebhookDeliveryStatus::Pending);
+    }
+
+    #[tokio::test]
+    async fn test_claim_due_deliveries_claims_each_delivery_once() {
+        let store = HashmapWebhookStore::default();
+        let subscription = WebhookSubscription::new(
+            "https://crm.example.com/hooks".to_owned(),
+            vec![WebhookEventType::UserSignedUp],
+        );
+        store.add_subscription(subscription).await.unwrap();
+        store
+            .enqueue_event(&event(WebhookEventType::UserSignedUp))
+            .await
+            .unwrap();
+
+        let now = Utc::now();
+        let claimed_until = now + chrono::Duration::try_minutes(1).unwrap();
+
+        let claimed = store
+            .claim_due_deliveries(now, claimed_until, 10)
+            .await
+            .unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].next_attempt_at, claimed_until);
+
+        let claimed = store
+            .claim_due_deliveries(now, claimed_until, 10)
+            .await
+            .unwrap();
+        assert!(claimed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_subscription_drops_its_deliveries() {
+        let store = HashmapWebhookStore::default();
+        let subscription = WebhookSubscription::new(
+            "https://crm.example.com/hooks".to_owned(),
+            vec![WebhookEventType::UserSignedUp],
+        );
+        store.add_subscription(subscription.clone()).await.unwrap();
+        store
+            .enqueue_event(&event(WebhookEventType::UserSignedUp))
+            .await
+            .unwrap();
+
+        store.delete_subscription(&subscription.id).await.unwrap();
+
+        assert!(store.get_deliveries(None, 10).await.unwrap().is_empty());
+        assert_eq!(
+            store.delete_subscription(&subscription.id).await,
+            Err(WebhookStoreError::SubscriptionNotFound)
+        );
+    }
+}
@@ -1,17 +1,16 @@
-use std::collections::HashSet;
+use dashmap::DashSet;
 
 use crate::domain::data_stores::{BannedTokenStore, BannedTokenStoreError};
 
 #[derive(Default)]
 pub struct HashsetBannedTokenStore {
-    tokens: HashSet<String>,
+    tokens: DashSet<String>,
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for HashsetBannedTokenStore {
-    async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError> {
-        self.tokens.insert(token);
-        Ok(())
+    async fn add_token(&self, token: String) -> Result<bool, BannedTokenStoreError> {
+        Ok(self.tokens.insert(token))
     }
 
     async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
@@ -24,7 +23,7 @@ mod tests {
     use super::*;
     #[tokio::test]
     async fn test_add_token() {
-        let mut store = HashsetBannedTokenStore::default();
+        let store = HashsetBannedTokenStore::default();
         let token = "test_token".to_owned();
 
         let result = store.add_token(token.clone()).await;
@@ -35,7 +34,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_contains_token() {
-        let mut store = HashsetBannedTokenStore::default();
+        let store = HashsetBannedTokenStore::default();
         let token = "test_token".to_owned();
         store.tokens.insert(token.clone());
 
@@ -43,4 +42,13 @@ mod tests {
 
         assert!(result.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_add_token_reports_an_already_banned_token() {
+        let store = HashsetBannedTokenStore::default();
+        let token = "test_token".to_owned();
+
+        assert!(store.add_token(token.clone()).await.unwrap());
+        assert!(!store.add_token(token).await.unwrap());
+    }
 }
@@ -1,13 +1,54 @@
+pub mod hashmap_email_outbox_store;
+pub mod hashmap_passkey_store;
+pub mod hashmap_phone_verification_store;
+pub mod hashmap_session_store;
 pub mod hashmap_two_fa_code_store;
 pub mod hashmap_user_store;
+pub mod hashmap_webhook_store;
 pub mod hashset_banned_token_store;
+pub mod postgres_audit_log_store;
+pub mod postgres_email_outbox_store;
+pub mod postgres_passkey_store;
 pub mod postgres_user_store;
+pub mod postgres_webhook_store;
 pub mod redis_banned_token_store;
+pub mod redis_phone_verification_store;
+pub mod redis_session_store;
 pub mod redis_two_fa_code_store;
+pub mod vec_audit_log_store;
 
+pub use hashmap_email_outbox_store::*;
+pub use hashmap_passkey_store::*;
+pub use hashmap_phone_verification_store::*;
+pub use hashmap_session_store::*;
 pub use hashmap_two_fa_code_store::*;
 pub use hashmap_user_store::*;
+pub use hashmap_webhook_store::*;
 pub use hashset_banned_token_store::*;
+pub use postgres_audit_log_store::*;
+pub use postgres_email_outbox_store::*;
+pub use postgres_passkey_store::*;
 pub use postgres_user_store::*;
+pub use postgres_webhook_store::*;
 pub use redis_banned_token_store::*;
+pub use redis_phone_verification_store::*;
+pub use redis_session_store::*;
 pub use redis_two_fa_code_store::*;
+pub use vec_audit_log_store::*;
+
+// Runs one command, or one pipeline, on the Redis connection and records how long it took.
+pub(crate) async fn time_redis_command<T>(
+    store: &'static str,
+    command: &'static str,
+    run: impl std::future::Future<Output = T>,
+) -> T {
+    let started = std::time::Instant::now();
+    let result = run.await;
+    metrics::histogram!(
+        "redis_command_duration_seconds",
+        "store" => store,
+        "command" => command
+    )
+    .record(started.elapsed());
+    result
+}
@@ -0,0 +1,200 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Report, Result};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{
+    data_stores::{AuditLogStore, AuditLogStoreError},
+    AuditEvent, AuditEventFilter, AuditEventType, AuditOutcome, NewAuditEvent, UserId,
+};
+
+pub struct PostgresAuditLogStore {
+    pool: PgPool,
+}
+
+impl PostgresAuditLogStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+struct AuditEventRow {
+    id: i64,
+    created_at: DateTime<Utc>,
+    event_type: String,
+    user_id: Option<Uuid>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+    outcome: String,
+    reason: Option<String>,
+    pii_salt: Option<String>,
+    pii_digest: String,
+    prev_hash: String,
+    hash: String,
+}
+
+impl TryFrom<AuditEventRow> for AuditEvent {
+    type Error = Report;
+
+    fn try_from(row: AuditEventRow) -> Result<Self> {
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at,
+            event_type: AuditEventType::parse(&row.event_type)?,
+            user_id: row.user_id.map(UserId::from),
+            ip_address: row.ip_address,
+            user_agent: row.user_agent,
+            request_id: row.request_id,
+            outcome: AuditOutcome::parse(&row.outcome)?,
+            reason: row.reason,
+            pii_salt: row.pii_salt,
+            pii_digest: row.pii_digest,
+            prev_hash: row.prev_hash,
+            hash: row.hash,
+        })
+    }
+}
+
+fn into_events(rows: Vec<AuditEventRow>) -> Result<Vec<AuditEvent>, AuditLogStoreError> {
+    rows.into_iter()
+        .map(|row| AuditEvent::try_from(row).map_err(AuditLogStoreError::UnexpectedError))
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl AuditLogStore for PostgresAuditLogStore {
+    #[tracing::instrument(name = "Appending audit event to PostgreSQL", skip_all)]
+    async fn append(&self, event: NewAuditEvent) -> Result<AuditEvent, AuditLogStoreError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| AuditLogStoreError::UnexpectedError(e.into()))?;
+
+        // Appends from every instance of the service wait on the chain head, so each new event
+        // links to the one that really is the last. Nothing else on audit_events has to wait.
+        let head = sqlx::query!("SELECT last_id, last_hash FROM audit_chain_head FOR UPDATE")
+            .fetch_one(&mut *transaction)
+            .await
+            .map_err(|e| AuditLogStoreError::UnexpectedError(e.into()))?;
+
+        let event = event.seal(head.last_id + 1, head.last_hash);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_events (id, created_at, event_type, user_id, ip_address, user_agent,
+                request_id, outcome, reason, pii_salt, pii_digest, prev_hash, hash)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            "#,
+            event.id,
+            event.created_at,
+            event.event_type.as_str(),
+            event.user_id.as_ref().map(|id| *id.as_ref()),
+            event.ip_address,
+            event.user_agent,
+            event.request_id,
+            event.outcome.as_str(),
+            event.reason,
+            event.pii_salt,
+            event.pii_digest,
+            event.prev_hash,
+            event.hash,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| AuditLogStoreError::UnexpectedError(e.into()))?;
+
+        sqlx::query!(
+            "UPDATE audit_chain_head SET last_id = $1, last_hash = $2",
+            event.id,
+            event.hash,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| AuditLogStoreError::UnexpectedError(e.into()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| AuditLogStoreError::UnexpectedError(e.into()))?;
+
+        Ok(event)
+    }
+
+    #[tracing::instrument(name = "Retrieving audit events from PostgreSQL", skip_all)]
+    async fn get_events(
+        &self,
+        filter: &AuditEventFilter,
+    ) -> Result<Vec<AuditEvent>, AuditLogStoreError> {
+        let rows = sqlx::query_as!(
+            AuditEventRow,
+            r#"
+            SELECT id, created_at, event_type, user_id, ip_address, user_agent, request_id,
+                outcome, reason, pii_salt, pii_digest, prev_hash, hash
+            FROM audit_events
+            WHERE ($1::TEXT IS NULL OR event_type = $1)
+                AND ($2::UUID IS NULL OR user_id = $2)
+                AND ($3::TEXT IS NULL OR outcome = $3)
+                AND ($4::TEXT IS NULL OR ip_address = $4)
+                AND ($5::TIMESTAMPTZ IS NULL OR created_at >= $5)
+                AND ($6::TIMESTAMPTZ IS NULL OR created_at < $6)
+                AND ($7::BIGINT IS NULL OR id < $7)
+            ORDER BY id DESC
+            LIMIT $8
+            "#,
+            filter.event_type.map(|event_type| event_type.as_str()),
+            filter.user_id.as_ref().map(|id| *id.as_ref()),
+            filter.outcome.map(|outcome| outcome.as_str()),
+            filter.ip_address,
+            filter.since,
+            filter.until,
+            filter.before_id,
+            filter.limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AuditLogStoreError::UnexpectedError(e.into()))?;
+
+        into_events(rows)
+    }
+
+    #[tracing::instrument(name = "Retrieving audit chain from PostgreSQL", skip_all)]
+    async fn get_events_after(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<AuditEvent>, AuditLogStoreError> {
+        let rows = sqlx::query_as!(
+            AuditEventRow,
+            r#"
+            SELECT id, created_at, event_type, user_id, ip_address, user_agent, request_id,
+                outcome, reason, pii_salt, pii_digest, prev_hash, hash
+            FROM audit_events
+            WHERE id > $1
+            ORDER BY id
+            LIMIT $2
+            "#,
+            after_id,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AuditLogStoreError::UnexpectedError(e.into()))?;
+
+        into_events(rows)
+    }
+
+    #[tracing::instrument(name = "Anonymizing audit events in PostgreSQL", skip_all)]
+    async fn anonymize_user(&self, user_id: &UserId) -> Result<u64, AuditLogStoreError> {
+        let result = sqlx::query!(
+            "UPDATE audit_events SET user_id = NULL, ip_address = NULL, user_agent = NULL, pii_salt = NULL WHERE user_id = $1",
+            user_id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AuditLogStoreError::UnexpectedError(e.into()))?;
+
+        Ok(result.rows_affected())
+    }
+}
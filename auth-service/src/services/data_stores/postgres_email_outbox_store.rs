@@ -0,0 +1,243 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Report, Result};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{
+    data_stores::{EmailOutboxStore, EmailOutboxStoreError},
+    Email, EmailKind, EmailMessage, OutboxEmail, OutboxEmailStatus, UserId,
+};
+
+pub struct PostgresEmailOutboxStore {
+    pool: PgPool,
+}
+
+impl PostgresEmailOutboxStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+struct OutboxEmailRow {
+    id: Uuid,
+    kind: String,
+    user_id: Uuid,
+    recipient: String,
+    subject: String,
+    html_body: String,
+    text_body: String,
+    status: String,
+    attempts: i32,
+    next_attempt_at: DateTime<Utc>,
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+    sent_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<OutboxEmailRow> for OutboxEmail {
+    type Error = Report;
+
+    fn try_from(row: OutboxEmailRow) -> Result<Self> {
+        Ok(Self {
+            id: row.id,
+            kind: EmailKind::parse(&row.kind)?,
+            user_id: UserId::from(row.user_id),
+            recipient: Email::parse(Secret::new(row.recipient))?,
+            message: EmailMessage {
+                subject: row.subject,
+                html_body: Secret::new(row.html_body),
+                text_body: Secret::new(row.text_body),
+            },
+            status: OutboxEmailStatus::parse(&row.status)?,
+            attempts: row.attempts,
+            next_attempt_at: row.next_attempt_at,
+            last_error: row.last_error,
+            created_at: row.created_at,
+            sent_at: row.sent_at,
+        })
+    }
+}
+
+fn into_emails(rows: Vec<OutboxEmailRow>) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+    rows.into_iter()
+        .map(|row| OutboxEmail::try_from(row).map_err(EmailOutboxStoreError::UnexpectedError))
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl EmailOutboxStore for PostgresEmailOutboxStore {
+    #[tracing::instrument(name = "Adding email to the outbox in PostgreSQL", skip_all)]
+    async fn add_email(&self, email: &OutboxEmail) -> Result<(), EmailOutboxStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO email_outbox (id, kind, user_id, recipient, subject, html_body, text_body,
+                status, attempts, next_attempt_at, last_error, created_at, sent_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            "#,
+            email.id,
+            email.kind.as_str(),
+            email.user_id.as_ref(),
+            email.recipient.as_ref().expose_secret(),
+            email.message.subject,
+            email.message.html_body.expose_secret(),
+            email.message.text_body.expose_secret(),
+            email.status.as_str(),
+            email.attempts,
+            email.next_attempt_at,
+            email.last_error,
+            email.created_at,
+            email.sent_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    // SKIP LOCKED lets several instances of the service claim emails at the same time without
+    // waiting on each other or sending anything twice.
+    #[tracing::instrument(name = "Claiming outbox emails in PostgreSQL", skip_all)]
+    async fn claim_due_emails(
+        &self,
+        now: DateTime<Utc>,
+        claimed_until: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        let rows = sqlx::query_as!(
+            OutboxEmailRow,
+            r#"
+            UPDATE email_outbox SET next_attempt_at = $2
+            WHERE id IN (
+                SELECT id FROM email_outbox
+                WHERE status = 'pending' AND next_attempt_at <= $1
+                ORDER BY next_attempt_at
+                LIMIT $3
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, kind, user_id, recipient, subject, html_body, text_body, status, attempts,
+                next_attempt_at, last_error, created_at, sent_at
+            "#,
+            now,
+            claimed_until,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxStoreError::UnexpectedError(e.into()))?;
+
+        into_emails(rows)
+    }
+
+    #[tracing::instrument(name = "Retrieving outbox email from PostgreSQL", skip_all)]
+    async fn get_email(&self, id: &Uuid) -> Result<OutboxEmail, EmailOutboxStoreError> {
+        let row = sqlx::query_as!(
+            OutboxEmailRow,
+            r#"
+            SELECT id, kind, user_id, recipient, subject, html_body, text_body, status, attempts,
+                next_attempt_at, last_error, created_at, sent_at
+            FROM email_outbox
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxStoreError::UnexpectedError(e.into()))?
+        .ok_or(EmailOutboxStoreError::EmailNotFound)?;
+
+        OutboxEmail::try_from(row).map_err(EmailOutboxStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Retrieving outbox emails from PostgreSQL", skip_all)]
+    async fn get_emails(
+        &self,
+        status: Option<OutboxEmailStatus>,
+        limit: i64,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        let rows = sqlx::query_as!(
+            OutboxEmailRow,
+            r#"
+            SELECT id, kind, user_id, recipient, subject, html_body, text_body, status, attempts,
+                next_attempt_at, last_error, created_at, sent_at
+            FROM email_outbox
+            WHERE ($1::TEXT IS NULL OR status = $1)
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+            status.map(|status| status.as_str()),
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxStoreError::UnexpectedError(e.into()))?;
+
+        into_emails(rows)
+    }
+
+    #[tracing::instrument(name = "Retrieving user outbox emails from PostgreSQL", skip_all)]
+    async fn get_user_emails(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        let rows = sqlx::query_as!(
+            OutboxEmailRow,
+            r#"
+            SELECT id, kind, user_id, recipient, subject, html_body, text_body, status, attempts,
+                next_attempt_at, last_error, created_at, sent_at
+            FROM email_outbox
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+            user_id.as_ref(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxStoreError::UnexpectedError(e.into()))?;
+
+        into_emails(rows)
+    }
+
+    #[tracing::instrument(name = "Updating outbox email in PostgreSQL", skip_all)]
+    async fn update_email(&self, email: &OutboxEmail) -> Result<(), EmailOutboxStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE email_outbox
+            SET html_body = $2, text_body = $3, status = $4, attempts = $5, next_attempt_at = $6,
+                last_error = $7, sent_at = $8
+            WHERE id = $1
+            "#,
+            email.id,
+            email.message.html_body.expose_secret(),
+            email.message.text_body.expose_secret(),
+            email.status.as_str(),
+            email.attempts,
+            email.next_attempt_at,
+            email.last_error,
+            email.sent_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(EmailOutboxStoreError::EmailNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Deleting user outbox emails from PostgreSQL", skip_all)]
+    async fn delete_user_emails(&self, user_id: &UserId) -> Result<u64, EmailOutboxStoreError> {
+        let result = sqlx::query!(
+            "DELETE FROM email_outbox WHERE user_id = $1",
+            user_id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxStoreError::UnexpectedError(e.into()))?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,130 @@
+use color_eyre::eyre::Context;
+use sqlx::PgPool;
+use webauthn_rs::prelude::Passkey;
+
+use crate::domain::{
+    data_stores::{PasskeyStore, PasskeyStoreError, StoredPasskey},
+    UserId,
+};
+
+pub struct PostgresPasskeyStore {
+    pool: PgPool,
+}
+
+impl PostgresPasskeyStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasskeyStore for PostgresPasskeyStore {
+    #[tracing::instrument(name = "Adding passkey to PostgreSQL", skip_all)]
+    async fn add_passkey(
+        &self,
+        user_id: &UserId,
+        passkey: Passkey,
+    ) -> Result<(), PasskeyStoreError> {
+        let public_key = serde_json::to_value(passkey.get_public_key())
+            .wrap_err("failed to serialize passkey public key")
+            .map_err(PasskeyStoreError::UnexpectedError)?;
+        let passkey_json = serde_json::to_value(&passkey)
+            .wrap_err("failed to serialize passkey")
+            .map_err(PasskeyStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            "INSERT INTO passkeys (credential_id, user_id, public_key, sign_count, passkey) VALUES ($1, $2, $3, 0, $4) ON CONFLICT (credential_id) DO NOTHING",
+            passkey.cred_id().as_ref(),
+            user_id.as_ref(),
+            public_key,
+            passkey_json,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(PasskeyStoreError::PasskeyAlreadyExists);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving passkeys from PostgreSQL", skip_all)]
+    async fn get_passkeys(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<StoredPasskey>, PasskeyStoreError> {
+        let rows = sqlx::query!(
+            "SELECT passkey, sign_count FROM passkeys WHERE user_id = $1 ORDER BY created_at",
+            user_id.as_ref(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?;
+
+        rows.into_iter()
+            .map(|row| {
+                let passkey: Passkey = serde_json::from_value(row.passkey)
+                    .wrap_err("failed to deserialize passkey")
+                    .map_err(PasskeyStoreError::UnexpectedError)?;
+                let sign_count: u32 = row
+                    .sign_count
+                    .try_into()
+                    .wrap_err("failed to cast sign_count to u32")
+                    .map_err(PasskeyStoreError::UnexpectedError)?;
+
+                Ok(StoredPasskey {
+                    passkey,
+                    sign_count,
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(name = "Updating passkey in PostgreSQL", skip_all)]
+    async fn update_passkey(
+        &self,
+        user_id: &UserId,
+        passkey: &Passkey,
+        sign_count: u32,
+    ) -> Result<(), PasskeyStoreError> {
+        let passkey_json = serde_json::to_value(passkey)
+            .wrap_err("failed to serialize passkey")
+            .map_err(PasskeyStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE passkeys SET passkey = $1, sign_count = $2
+            WHERE credential_id = $3 AND user_id = $4
+                AND ($2 > sign_count OR ($2 = 0 AND sign_count = 0))
+            "#,
+            passkey_json,
+            i64::from(sign_count),
+            passkey.cred_id().as_ref(),
+            user_id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() > 0 {
+            return Ok(());
+        }
+
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM passkeys WHERE credential_id = $1 AND user_id = $2) AS "exists!""#,
+            passkey.cred_id().as_ref(),
+            user_id.as_ref(),
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?;
+
+        Err(if exists {
+            PasskeyStoreError::SignCountRegressed
+        } else {
+            PasskeyStoreError::PasskeyNotFound
+        })
+    }
+}
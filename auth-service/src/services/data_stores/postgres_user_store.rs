@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::{Context, Result};
 use secrecy::{ExposeSecret, Secret};
 
@@ -7,19 +8,26 @@ use argon2::{
 };
 
 use sqlx::PgPool;
+use std::time::Instant;
 
 use crate::domain::{
     data_stores::{UserStore, UserStoreError},
-    Email, Password, User,
+    Email, EmailUndeliverable, Locale, Password, PhoneNumber, TwoFAChannel, UndeliverableReason,
+    User, UserId,
 };
 
 pub struct PostgresUserStore {
     pool: PgPool,
+    // Used for new hashes. Existing hashes carry the parameters they were made with.
+    hashing_params: Params,
 }
 
 impl PostgresUserStore {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, hashing_params: Params) -> Self {
+        Self {
+            pool,
+            hashing_params,
+        }
     }
 }
 
@@ -27,23 +35,33 @@ impl PostgresUserStore {
 impl UserStore for PostgresUserStore {
     // TODO: Implement all required methods. Note that you will need to make SQL queries against our PostgreSQL instance inside these methods.
     #[tracing::instrument(name = "Adding user to PostgreSQL", skip_all)]
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        let password_hash = compute_password_hash(user.password.as_ref().to_owned())
-            .await
-            //.map_err(UserStoreError::UnexpectedError)?;
-            .map_err(UserStoreError::UnexpectedError)?;
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        let password_hash = compute_password_hash(
+            user.password.as_ref().to_owned(),
+            self.hashing_params.clone(),
+        )
+        .await
+        //.map_err(UserStoreError::UnexpectedError)?;
+        .map_err(UserStoreError::UnexpectedError)?;
 
         //match sqlx::query!(
         sqlx::query!(
-            "INSERT INTO users (email, password_hash, requires_2fa) VALUES ($1, $2, $3) RETURNING email",
+            "INSERT INTO users (id, email, password_hash, requires_2fa, locale) VALUES ($1, $2, $3, $4, $5) RETURNING email",
+            user.id.as_ref(),
             user.email.as_ref().expose_secret(),
             &password_hash.expose_secret(),
             //compute_password_hash(user.password.as_ref()).await.unwrap(),
-            user.requires_2fa
+            user.requires_2fa,
+            user.locale.as_str(),
         )
         .fetch_one(&self.pool)
         .await
-        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
         //{
         //    Ok(_) => (),
         //    Err(_) => return Err(UserStoreError::UserAlreadyExists),
@@ -61,7 +79,7 @@ impl UserStore for PostgresUserStore {
     #[tracing::instrument(name = "Retrieving user from PostgreSQL", skip_all)]
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
         let rec = match sqlx::query!(
-            "select email, password_hash, requires_2fa from users where email = $1",
+            "select id, email, password_hash, requires_2fa, locale, email_undeliverable_reason, email_undeliverable_at, phone_number, two_fa_channel from users where email = $1 and deleted_at is null",
             email.as_ref().expose_secret(),
         )
         .fetch_one(&self.pool)
@@ -72,11 +90,19 @@ impl UserStore for PostgresUserStore {
         };
 
         Ok(User {
+            id: rec.id.into(),
             email: Email::parse(Secret::new(rec.email)).unwrap(),
             //            password: Password::parse(rec.password_hash).unwrap(),
             password: Password::parse(Secret::new(rec.password_hash))
                 .map_err(UserStoreError::UnexpectedError)?,
             requires_2fa: rec.requires_2fa,
+            locale: Locale::parse(&rec.locale).unwrap_or_default(),
+            email_undeliverable: email_undeliverable(
+                rec.email_undeliverable_reason,
+                rec.email_undeliverable_at,
+            ),
+            phone_number: phone_number(rec.phone_number),
+            two_fa_channel: TwoFAChannel::parse(&rec.two_fa_channel).unwrap_or_default(),
         })
         //        match self.users.get(email) {
         //            Some(u) => Ok(u.clone()),
@@ -84,6 +110,33 @@ impl UserStore for PostgresUserStore {
         //        }
     }
 
+    #[tracing::instrument(name = "Retrieving user by id from PostgreSQL", skip_all)]
+    async fn get_user_by_id(&self, id: &UserId) -> Result<User, UserStoreError> {
+        let rec = sqlx::query!(
+            "select id, email, password_hash, requires_2fa, locale, email_undeliverable_reason, email_undeliverable_at, phone_number, two_fa_channel from users where id = $1 and deleted_at is null",
+            id.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        Ok(User {
+            id: rec.id.into(),
+            email: Email::parse(Secret::new(rec.email)).map_err(UserStoreError::UnexpectedError)?,
+            password: Password::parse(Secret::new(rec.password_hash))
+                .map_err(UserStoreError::UnexpectedError)?,
+            requires_2fa: rec.requires_2fa,
+            locale: Locale::parse(&rec.locale).unwrap_or_default(),
+            email_undeliverable: email_undeliverable(
+                rec.email_undeliverable_reason,
+                rec.email_undeliverable_at,
+            ),
+            phone_number: phone_number(rec.phone_number),
+            two_fa_channel: TwoFAChannel::parse(&rec.two_fa_channel).unwrap_or_default(),
+        })
+    }
+
     #[tracing::instrument(name = "Validating user credentials in PostgreSQL", skip_all)]
     async fn validate_user(
         &self,
@@ -91,7 +144,7 @@ impl UserStore for PostgresUserStore {
         password: &Password,
     ) -> Result<(), UserStoreError> {
         let rec = match sqlx::query!(
-            "select email, password_hash, requires_2fa from users where email = $1",
+            "select email, password_hash, requires_2fa from users where email = $1 and deleted_at is null",
             email.as_ref().expose_secret(),
         )
         .fetch_one(&self.pool)
@@ -124,6 +177,302 @@ impl UserStore for PostgresUserStore {
         //            None => Err(UserStoreError::UserNotFound),
         //        }
     }
+
+    #[tracing::instrument(name = "Updating user password in PostgreSQL", skip_all)]
+    async fn update_password(&self, id: &UserId, password: Password) -> Result<(), UserStoreError> {
+        let password_hash =
+            compute_password_hash(password.as_ref().to_owned(), self.hashing_params.clone())
+                .await
+                .map_err(UserStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            "UPDATE users SET password_hash = $1 WHERE id = $2 AND deleted_at IS NULL",
+            password_hash.expose_secret(),
+            id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Updating user email in PostgreSQL", skip_all)]
+    async fn update_email(&self, id: &UserId, new_email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "UPDATE users SET email = $1, email_undeliverable_reason = NULL, email_undeliverable_at = NULL WHERE id = $2 AND deleted_at IS NULL",
+            new_email.as_ref().expose_secret(),
+            id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Soft deleting user in PostgreSQL", skip_all)]
+    async fn delete_user(&self, id: &UserId) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "UPDATE users SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL",
+            id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Restoring user in PostgreSQL", skip_all)]
+    async fn restore_user(
+        &self,
+        email: &Email,
+        password: &Password,
+    ) -> Result<UserId, UserStoreError> {
+        let rec = sqlx::query!(
+            "select id, password_hash from users where email = $1 and deleted_at is not null",
+            email.as_ref().expose_secret(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        verify_password_hash(Secret::new(rec.password_hash), password.as_ref().to_owned())
+            .await
+            .map_err(|_| UserStoreError::InvalidCredentials)?;
+
+        // Another account may have taken the email while this one was deleted.
+        sqlx::query!("UPDATE users SET deleted_at = NULL WHERE id = $1", rec.id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                    UserStoreError::UserAlreadyExists
+                }
+                e => UserStoreError::UnexpectedError(e.into()),
+            })?;
+
+        Ok(rec.id.into())
+    }
+
+    // Passkeys go with the user through the foreign key cascade.
+    #[tracing::instrument(name = "Purging deleted users from PostgreSQL", skip_all)]
+    async fn purge_deleted_users(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> Result<Vec<UserId>, UserStoreError> {
+        let rows = sqlx::query!(
+            "DELETE FROM users WHERE deleted_at IS NOT NULL AND deleted_at < $1 RETURNING id",
+            deleted_before,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        Ok(rows.into_iter().map(|row| row.id.into()).collect())
+    }
+
+    #[tracing::instrument(name = "Purging deleted users by email from PostgreSQL", skip_all)]
+    async fn purge_deleted_users_with_email(
+        &self,
+        email: &Email,
+    ) -> Result<Vec<UserId>, UserStoreError> {
+        let rows = sqlx::query!(
+            "DELETE FROM users WHERE email = $1 AND deleted_at IS NOT NULL RETURNING id",
+            email.as_ref().expose_secret(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        Ok(rows.into_iter().map(|row| row.id.into()).collect())
+    }
+
+    // Keeps the first report, so `since` is when the address started failing.
+    #[tracing::instrument(name = "Marking user email undeliverable in PostgreSQL", skip_all)]
+    async fn mark_email_undeliverable(
+        &self,
+        email: &Email,
+        undeliverable: EmailUndeliverable,
+    ) -> Result<UserId, UserStoreError> {
+        let rec = sqlx::query!(
+            r#"
+            UPDATE users
+            SET email_undeliverable_reason = COALESCE(email_undeliverable_reason, $1),
+                email_undeliverable_at = COALESCE(email_undeliverable_at, $2)
+            WHERE email = $3 AND deleted_at IS NULL
+            RETURNING id
+            "#,
+            undeliverable.reason.as_str(),
+            undeliverable.since,
+            email.as_ref().expose_secret(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        Ok(rec.id.into())
+    }
+
+    #[tracing::instrument(name = "Clearing user email undeliverable in PostgreSQL", skip_all)]
+    async fn clear_email_undeliverable(&self, id: &UserId) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "UPDATE users SET email_undeliverable_reason = NULL, email_undeliverable_at = NULL WHERE id = $1 AND deleted_at IS NULL",
+            id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "Retrieving users with undeliverable email from PostgreSQL",
+        skip_all
+    )]
+    async fn get_users_with_undeliverable_email(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<User>, UserStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, email, password_hash, requires_2fa, locale, email_undeliverable_reason, email_undeliverable_at, phone_number, two_fa_channel
+            FROM users
+            WHERE email_undeliverable_at IS NOT NULL AND deleted_at IS NULL
+            ORDER BY email_undeliverable_at DESC
+            LIMIT $1
+            "#,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        rows.into_iter()
+            .map(|rec| {
+                Ok(User {
+                    id: rec.id.into(),
+                    email: Email::parse(Secret::new(rec.email))
+                        .map_err(UserStoreError::UnexpectedError)?,
+                    password: Password::parse(Secret::new(rec.password_hash))
+                        .map_err(UserStoreError::UnexpectedError)?,
+                    requires_2fa: rec.requires_2fa,
+                    locale: Locale::parse(&rec.locale).unwrap_or_default(),
+                    email_undeliverable: email_undeliverable(
+                        rec.email_undeliverable_reason,
+                        rec.email_undeliverable_at,
+                    ),
+                    phone_number: phone_number(rec.phone_number),
+                    two_fa_channel: TwoFAChannel::parse(&rec.two_fa_channel).unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(name = "Updating user phone number in PostgreSQL", skip_all)]
+    async fn update_phone_number(
+        &self,
+        id: &UserId,
+        phone_number: &PhoneNumber,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "UPDATE users SET phone_number = $1 WHERE id = $2 AND deleted_at IS NULL",
+            phone_number.as_ref().expose_secret(),
+            id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Updating user 2FA channel in PostgreSQL", skip_all)]
+    async fn update_two_fa_channel(
+        &self,
+        id: &UserId,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "UPDATE users SET two_fa_channel = $1 WHERE id = $2 AND deleted_at IS NULL",
+            channel.as_str(),
+            id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Marking user as verified in PostgreSQL", skip_all)]
+    async fn mark_verified(&self, id: &UserId) -> Result<bool, UserStoreError> {
+        // The row lock makes concurrent logins of the same user agree on which one was first.
+        let first = sqlx::query_scalar!(
+            r#"
+            WITH previous AS (
+                SELECT verified_at FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE
+            )
+            UPDATE users SET verified_at = COALESCE(users.verified_at, NOW())
+            FROM previous
+            WHERE users.id = $1 AND users.deleted_at IS NULL
+            RETURNING previous.verified_at IS NULL AS "first!"
+            "#,
+            id.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        first.ok_or(UserStoreError::UserNotFound)
+    }
+}
+
+fn phone_number(phone_number: Option<String>) -> Option<PhoneNumber> {
+    PhoneNumber::parse(Secret::new(phone_number?)).ok()
+}
+
+fn email_undeliverable(
+    reason: Option<String>,
+    since: Option<DateTime<Utc>>,
+) -> Option<EmailUndeliverable> {
+    Some(EmailUndeliverable {
+        reason: UndeliverableReason::parse(&reason?).ok()?,
+        since: since?,
+    })
 }
 
 // Helper function to verify if a given password matches an expected hash
@@ -139,7 +488,8 @@ async fn verify_password_hash(
 ) -> Result<()> {
     let current_span: tracing::Span = tracing::Span::current();
     let result = tokio::task::spawn_blocking(move || {
-        current_span.in_scope(|| {
+        let started = Instant::now();
+        let verified = current_span.in_scope(|| {
             let expected_password_hash: PasswordHash<'_> =
                 PasswordHash::new(expected_password_hash.expose_secret())?;
 
@@ -149,7 +499,10 @@ async fn verify_password_hash(
                     &expected_password_hash,
                 )
                 .wrap_err("failed to verify password hash")
-        })
+        });
+        metrics::histogram!("password_hash_duration_seconds", "operation" => "verify")
+            .record(started.elapsed());
+        verified
     })
     .await;
 
@@ -163,22 +516,22 @@ async fn verify_password_hash(
 // will need to update the input parameters to be String types instead of &str
 
 #[tracing::instrument(name = "Computing password hash", skip_all)]
-async fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>> {
+async fn compute_password_hash(password: Secret<String>, params: Params) -> Result<Secret<String>> {
     let current_span: tracing::Span = tracing::Span::current();
 
     let result = tokio::task::spawn_blocking(move || {
-        current_span.in_scope(|| {
+        let started = Instant::now();
+        let hashed = current_span.in_scope(|| {
             let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
-            let password_hash = Argon2::new(
-                Algorithm::Argon2id,
-                Version::V0x13,
-                Params::new(15000, 2, 1, None)?,
-            )
-            .hash_password(password.expose_secret().as_bytes(), &salt)? // Updated!
-            .to_string();
+            let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password(password.expose_secret().as_bytes(), &salt)? // Updated!
+                .to_string();
 
             Ok(Secret::new(password_hash))
-        })
+        });
+        metrics::histogram!("password_hash_duration_seconds", "operation" => "hash")
+            .record(started.elapsed());
+        hashed
     })
     .await;
 
@@ -0,0 +1,339 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Context, Report, Result};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{
+    data_stores::{WebhookStore, WebhookStoreError},
+    UserId, WebhookDelivery, WebhookDeliveryStatus, WebhookEvent, WebhookEventType,
+    WebhookSubscription,
+};
+
+pub struct PostgresWebhookStore {
+    pool: PgPool,
+}
+
+impl PostgresWebhookStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+struct WebhookSubscriptionRow {
+    id: Uuid,
+    url: String,
+    secret: String,
+    event_types: Vec<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<WebhookSubscriptionRow> for WebhookSubscription {
+    type Error = Report;
+
+    fn try_from(row: WebhookSubscriptionRow) -> Result<Self> {
+        Ok(Self {
+            id: row.id,
+            url: row.url,
+            secret: Secret::new(row.secret),
+            event_types: row
+                .event_types
+                .iter()
+                .map(|event_type| WebhookEventType::parse(event_type))
+                .collect::<Result<_>>()?,
+            created_at: row.created_at,
+        })
+    }
+}
+
+struct WebhookDeliveryRow {
+    id: Uuid,
+    subscription_id: Uuid,
+    event_id: Uuid,
+    event_type: String,
+    user_id: Uuid,
+    payload: String,
+    status: String,
+    attempts: i32,
+    next_attempt_at: DateTime<Utc>,
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<WebhookDeliveryRow> for WebhookDelivery {
+    type Error = Report;
+
+    fn try_from(row: WebhookDeliveryRow) -> Result<Self> {
+        Ok(Self {
+            id: row.id,
+            subscription_id: row.subscription_id,
+            event_id: row.event_id,
+            event_type: WebhookEventType::parse(&row.event_type)?,
+            user_id: UserId::from(row.user_id),
+            payload: row.payload,
+            status: WebhookDeliveryStatus::parse(&row.status)?,
+            attempts: row.attempts,
+            next_attempt_at: row.next_attempt_at,
+            last_error: row.last_error,
+            created_at: row.created_at,
+            delivered_at: row.delivered_at,
+        })
+    }
+}
+
+fn into_deliveries(
+    rows: Vec<WebhookDeliveryRow>,
+) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+    rows.into_iter()
+        .map(|row| WebhookDelivery::try_from(row).map_err(WebhookStoreError::UnexpectedError))
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl WebhookStore for PostgresWebhookStore {
+    #[tracing::instrument(name = "Adding webhook subscription to PostgreSQL", skip_all)]
+    async fn add_subscription(
+        &self,
+        subscription: WebhookSubscription,
+    ) -> Result<(), WebhookStoreError> {
+        let event_types: Vec<String> = subscription
+            .event_types
+            .iter()
+            .map(|event_type| event_type.as_str().to_owned())
+            .collect();
+
+        sqlx::query!(
+            "INSERT INTO webhook_subscriptions (id, url, secret, event_types, created_at) VALUES ($1, $2, $3, $4, $5)",
+            subscription.id,
+            subscription.url,
+            subscription.secret.expose_secret(),
+            &event_types,
+            subscription.created_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving webhook subscription from PostgreSQL", skip_all)]
+    async fn get_subscription(&self, id: &Uuid) -> Result<WebhookSubscription, WebhookStoreError> {
+        let row = sqlx::query_as!(
+            WebhookSubscriptionRow,
+            "SELECT id, url, secret, event_types, created_at FROM webhook_subscriptions WHERE id = $1",
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?
+        .ok_or(WebhookStoreError::SubscriptionNotFound)?;
+
+        WebhookSubscription::try_from(row).map_err(WebhookStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Retrieving webhook subscriptions from PostgreSQL", skip_all)]
+    async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscription>, WebhookStoreError> {
+        let rows = sqlx::query_as!(
+            WebhookSubscriptionRow,
+            "SELECT id, url, secret, event_types, created_at FROM webhook_subscriptions ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?;
+
+        rows.into_iter()
+            .map(|row| {
+                WebhookSubscription::try_from(row).map_err(WebhookStoreError::UnexpectedError)
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(name = "Deleting webhook subscription from PostgreSQL", skip_all)]
+    async fn delete_subscription(&self, id: &Uuid) -> Result<(), WebhookStoreError> {
+        let result = sqlx::query!("DELETE FROM webhook_subscriptions WHERE id = $1", id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(WebhookStoreError::SubscriptionNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Enqueueing webhook event in PostgreSQL", skip_all)]
+    async fn enqueue_event(&self, event: &WebhookEvent) -> Result<(), WebhookStoreError> {
+        let payload = event
+            .payload()
+            .wrap_err("failed to render webhook payload")
+            .map_err(WebhookStoreError::UnexpectedError)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO webhook_deliveries (id, subscription_id, event_id, event_type, user_id,
+                payload, status, attempts, next_attempt_at, created_at)
+            SELECT gen_random_uuid(), id, $1, $2, $3, $4, 'pending', 0, NOW(), NOW()
+            FROM webhook_subscriptions
+            WHERE $2 = ANY(event_types)
+            "#,
+            event.id,
+            event.event_type.as_str(),
+            event.user_id.as_ref(),
+            payload,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    // SKIP LOCKED lets several instances of the service claim deliveries at the same time
+    // without waiting on each other or sending anything twice.
+    #[tracing::instrument(name = "Claiming webhook deliveries in PostgreSQL", skip_all)]
+    async fn claim_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        claimed_until: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let rows = sqlx::query_as!(
+            WebhookDeliveryRow,
+            r#"
+            UPDATE webhook_deliveries SET next_attempt_at = $2
+            WHERE id IN (
+                SELECT id FROM webhook_deliveries
+                WHERE status = 'pending' AND next_attempt_at <= $1
+                ORDER BY next_attempt_at
+                LIMIT $3
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, subscription_id, event_id, event_type, user_id, payload, status, attempts,
+                next_attempt_at, last_error, created_at, delivered_at
+            "#,
+            now,
+            claimed_until,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?;
+
+        into_deliveries(rows)
+    }
+
+    #[tracing::instrument(name = "Retrieving webhook delivery from PostgreSQL", skip_all)]
+    async fn get_delivery(&self, id: &Uuid) -> Result<WebhookDelivery, WebhookStoreError> {
+        let row = sqlx::query_as!(
+            WebhookDeliveryRow,
+            r#"
+            SELECT id, subscription_id, event_id, event_type, user_id, payload, status, attempts,
+                next_attempt_at, last_error, created_at, delivered_at
+            FROM webhook_deliveries
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?
+        .ok_or(WebhookStoreError::DeliveryNotFound)?;
+
+        WebhookDelivery::try_from(row).map_err(WebhookStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Retrieving webhook deliveries from PostgreSQL", skip_all)]
+    async fn get_deliveries(
+        &self,
+        status: Option<WebhookDeliveryStatus>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let rows = sqlx::query_as!(
+            WebhookDeliveryRow,
+            r#"
+            SELECT id, subscription_id, event_id, event_type, user_id, payload, status, attempts,
+                next_attempt_at, last_error, created_at, delivered_at
+            FROM webhook_deliveries
+            WHERE ($1::TEXT IS NULL OR status = $1)
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+            status.map(|status| status.as_str()),
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?;
+
+        into_deliveries(rows)
+    }
+
+    #[tracing::instrument(name = "Retrieving user webhook deliveries from PostgreSQL", skip_all)]
+    async fn get_user_deliveries(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let rows = sqlx::query_as!(
+            WebhookDeliveryRow,
+            r#"
+            SELECT id, subscription_id, event_id, event_type, user_id, payload, status, attempts,
+                next_attempt_at, last_error, created_at, delivered_at
+            FROM webhook_deliveries
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+            user_id.as_ref(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?;
+
+        into_deliveries(rows)
+    }
+
+    #[tracing::instrument(name = "Updating webhook delivery in PostgreSQL", skip_all)]
+    async fn update_delivery(&self, delivery: &WebhookDelivery) -> Result<(), WebhookStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $2, attempts = $3, next_attempt_at = $4, last_error = $5, delivered_at = $6
+            WHERE id = $1
+            "#,
+            delivery.id,
+            delivery.status.as_str(),
+            delivery.attempts,
+            delivery.next_attempt_at,
+            delivery.last_error,
+            delivery.delivered_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(WebhookStoreError::DeliveryNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Redacting user webhook deliveries in PostgreSQL", skip_all)]
+    async fn redact_user_deliveries(&self, user_id: &UserId) -> Result<u64, WebhookStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET payload = jsonb_set(payload::jsonb, '{data,email}', 'null')::text
+            WHERE user_id = $1
+            "#,
+            user_id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?;
+
+        Ok(result.rows_affected())
+    }
+}
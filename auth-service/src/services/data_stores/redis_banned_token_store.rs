@@ -1,28 +1,28 @@
 use color_eyre::eyre::{Context, Result};
-use std::sync::Arc;
 
-use redis::{Commands, Connection};
-use tokio::sync::RwLock;
+use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
 
 use crate::{
     domain::data_stores::{BannedTokenStore, BannedTokenStoreError},
-    utils::auth::TOKEN_TTL_SECONDS,
+    services::data_stores::time_redis_command,
 };
 
 pub struct RedisBannedTokenStore {
-    conn: Arc<RwLock<Connection>>,
+    conn: ConnectionManager,
+    // A banned token only needs to be remembered until it would have expired anyway.
+    ttl_seconds: i64,
 }
 
 impl RedisBannedTokenStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(conn: ConnectionManager, ttl_seconds: i64) -> Self {
+        Self { conn, ttl_seconds }
     }
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for RedisBannedTokenStore {
     #[tracing::instrument(name = "add_token", skip_all)]
-    async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError> {
+    async fn add_token(&self, token: String) -> Result<bool, BannedTokenStoreError> {
         // TODO:
         // 1. Create a new key using the get_key helper function.
         let key = get_key(&token);
@@ -30,26 +30,33 @@ impl BannedTokenStore for RedisBannedTokenStore {
         // 2. Call the set_ex command on the Redis connection to set a new key/value pair with an expiration time (TTL).
         // The value should simply be a `true` (boolean value).
 
-        let ttl: u64 = TOKEN_TTL_SECONDS
+        let ttl: usize = self
+            .ttl_seconds
             .try_into()
-            .wrap_err("failed to cast TOKEN_TTL_SECONDS to u64")
+            .wrap_err("failed to cast ttl_seconds to usize")
             //.map_err(|_| BannedTokenStoreError::UnexpectedError)?;
             .map_err(BannedTokenStoreError::UnexpectedError)?;
 
-        // The expiration time should be set to TOKEN_TTL_SECONDS.
-        // NOTE: The TTL is expected to be a u64 so you will have to cast TOKEN_TTL_SECONDS to a u64.
+        // The expiration time should be set to the token TTL.
+        // NOTE: The TTL is expected to be a u64 so you will have to cast ttl_seconds to a u64.
         // Return BannedTokenStoreError::UnexpectedError if casting fails or the call to set_ex fails.
 
-        let _: () = self
-            .conn
-            .write()
-            .await
-            .set_ex(&key, true, ttl)
-            .wrap_err("failed to set banned token in Redis")
-            //.map_err(|_| BannedTokenStoreError::UnexpectedError)?;
-            .map_err(BannedTokenStoreError::UnexpectedError)?;
+        // NX leaves a token that is already banned alone and answers nil for it.
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::EX(ttl));
+        let mut conn = self.conn.clone();
+        let set: Option<String> = time_redis_command(
+            "banned_tokens",
+            "set_nx_ex",
+            conn.set_options(&key, true, options),
+        )
+        .await
+        .wrap_err("failed to set banned token in Redis")
+        //.map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+        .map_err(BannedTokenStoreError::UnexpectedError)?;
 
-        Ok(())
+        Ok(set.is_some())
 
         // why doesn't this compile? needs type annotation.
         // let con = self.conn.write().await;
@@ -65,13 +72,12 @@ impl BannedTokenStore for RedisBannedTokenStore {
 
         let token_key = get_key(token);
 
-        let is_banned: bool = self
-            .conn
-            .write()
-            .await
-            .exists(&token_key)
-            .wrap_err("failed to check if token exists in Redis") // New!
-            .map_err(BannedTokenStoreError::UnexpectedError)?;
+        let mut conn = self.conn.clone();
+        let is_banned: bool =
+            time_redis_command("banned_tokens", "exists", conn.exists(&token_key))
+                .await
+                .wrap_err("failed to check if token exists in Redis") // New!
+                .map_err(BannedTokenStoreError::UnexpectedError)?;
         //.map_err(|_| BannedTokenStoreError::UnexpectedError)?;
 
         Ok(is_banned)
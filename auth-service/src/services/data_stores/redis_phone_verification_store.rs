@@ -0,0 +1,164 @@
+use color_eyre::eyre::Context;
+use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    domain::{
+        data_stores::{
+            PhoneVerificationStore, PhoneVerificationStoreError, TwoFACode,
+            PHONE_VERIFICATION_MAX_CODES_PER_DAY, PHONE_VERIFICATION_RESEND_COOLDOWN_SECONDS,
+        },
+        PhoneNumber, UserId,
+    },
+    services::data_stores::time_redis_command,
+};
+
+pub struct RedisPhoneVerificationStore {
+    conn: ConnectionManager,
+}
+
+impl RedisPhoneVerificationStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl PhoneVerificationStore for RedisPhoneVerificationStore {
+    // The cooldown is a key that expires with it, and the daily count a counter that expires a
+    // day after the first code.
+    #[tracing::instrument(name = "record_phone_verification_send", skip_all)]
+    async fn record_send(&self, user_id: &UserId) -> Result<(), PhoneVerificationStoreError> {
+        // NX answers nil while the last cooldown is still running.
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::EX(
+                PHONE_VERIFICATION_RESEND_COOLDOWN_SECONDS as usize,
+            ));
+        let mut conn = self.conn.clone();
+        let cooldown: Option<String> = time_redis_command(
+            "phone_verifications",
+            "set_nx_ex",
+            conn.set_options(get_cooldown_key(user_id), true, options),
+        )
+        .await
+        .wrap_err("failed to start phone verification cooldown in Redis")
+        .map_err(PhoneVerificationStoreError::UnexpectedError)?;
+        if cooldown.is_none() {
+            return Err(PhoneVerificationStoreError::TooManyCodes);
+        }
+
+        let count_key = get_count_key(user_id);
+        let count: u64 =
+            time_redis_command("phone_verifications", "incr", conn.incr(&count_key, 1))
+                .await
+                .wrap_err("failed to count phone verification codes in Redis")
+                .map_err(PhoneVerificationStoreError::UnexpectedError)?;
+        if count == 1 {
+            let _: () = time_redis_command(
+                "phone_verifications",
+                "expire",
+                conn.expire(&count_key, ONE_DAY_IN_SECONDS),
+            )
+            .await
+            .wrap_err("failed to expire phone verification count in Redis")
+            .map_err(PhoneVerificationStoreError::UnexpectedError)?;
+        }
+        if count > PHONE_VERIFICATION_MAX_CODES_PER_DAY {
+            return Err(PhoneVerificationStoreError::TooManyCodes);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "add_phone_verification_code", skip_all)]
+    async fn add_code(
+        &self,
+        user_id: UserId,
+        phone_number: PhoneNumber,
+        code: TwoFACode,
+    ) -> Result<(), PhoneVerificationStoreError> {
+        let verification = PendingVerification(
+            phone_number.as_ref().expose_secret().to_owned(),
+            code.as_ref().to_owned(),
+        );
+        let verification_json = serde_json::to_string(&verification)
+            .wrap_err("failed to serialize phone verification")
+            .map_err(PhoneVerificationStoreError::UnexpectedError)?;
+
+        let mut conn = self.conn.clone();
+        let _: () = time_redis_command(
+            "phone_verifications",
+            "set_ex",
+            conn.set_ex(get_key(&user_id), verification_json, TEN_MINUTES_IN_SECONDS),
+        )
+        .await
+        .wrap_err("failed to set phone verification code in Redis")
+        .map_err(PhoneVerificationStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "remove_phone_verification_code", skip_all)]
+    async fn remove_code(&self, user_id: &UserId) -> Result<(), PhoneVerificationStoreError> {
+        let mut conn = self.conn.clone();
+        let deleted: u64 =
+            time_redis_command("phone_verifications", "del", conn.del(get_key(user_id)))
+                .await
+                .wrap_err("failed to delete phone verification code from Redis")
+                .map_err(PhoneVerificationStoreError::UnexpectedError)?;
+        if deleted == 0 {
+            return Err(PhoneVerificationStoreError::VerificationNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "get_phone_verification_code", skip_all)]
+    async fn get_code(
+        &self,
+        user_id: &UserId,
+    ) -> Result<(PhoneNumber, TwoFACode), PhoneVerificationStoreError> {
+        let mut conn = self.conn.clone();
+        let value = time_redis_command(
+            "phone_verifications",
+            "get",
+            conn.get::<_, Option<String>>(get_key(user_id)),
+        )
+        .await
+        .wrap_err("failed to get phone verification from Redis")
+        .map_err(PhoneVerificationStoreError::UnexpectedError)?
+        .ok_or(PhoneVerificationStoreError::VerificationNotFound)?;
+
+        let PendingVerification(phone_number, code) = serde_json::from_str(&value)
+            .wrap_err("failed to deserialize phone verification")
+            .map_err(PhoneVerificationStoreError::UnexpectedError)?;
+        let phone_number = PhoneNumber::parse(Secret::new(phone_number))
+            .map_err(PhoneVerificationStoreError::UnexpectedError)?;
+        let code = TwoFACode::parse(code).map_err(PhoneVerificationStoreError::UnexpectedError)?;
+
+        Ok((phone_number, code))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingVerification(pub String, pub String);
+
+const TEN_MINUTES_IN_SECONDS: u64 = 600;
+const ONE_DAY_IN_SECONDS: i64 = 24 * 60 * 60;
+const PHONE_VERIFICATION_PREFIX: &str = "phone_verification:";
+const PHONE_VERIFICATION_COOLDOWN_PREFIX: &str = "phone_verification_cooldown:";
+const PHONE_VERIFICATION_COUNT_PREFIX: &str = "phone_verification_count:";
+
+fn get_key(user_id: &UserId) -> String {
+    format!("{}{}", PHONE_VERIFICATION_PREFIX, user_id)
+}
+
+fn get_cooldown_key(user_id: &UserId) -> String {
+    format!("{}{}", PHONE_VERIFICATION_COOLDOWN_PREFIX, user_id)
+}
+
+fn get_count_key(user_id: &UserId) -> String {
+    format!("{}{}", PHONE_VERIFICATION_COUNT_PREFIX, user_id)
+}
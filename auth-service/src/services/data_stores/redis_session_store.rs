@@ -0,0 +1,81 @@
+use color_eyre::eyre::Context;
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use crate::{
+    domain::{
+        data_stores::{SessionStore, SessionStoreError},
+        UserId,
+    },
+    services::data_stores::time_redis_command,
+};
+
+pub struct RedisSessionStore {
+    conn: ConnectionManager,
+    // How long an auth token is valid for.
+    ttl_seconds: i64,
+}
+
+impl RedisSessionStore {
+    pub fn new(conn: ConnectionManager, ttl_seconds: i64) -> Self {
+        Self { conn, ttl_seconds }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for RedisSessionStore {
+    #[tracing::instrument(name = "add_session", skip_all)]
+    async fn add_session(&self, user_id: &UserId, token: String) -> Result<(), SessionStoreError> {
+        let key = get_key(user_id);
+
+        let mut conn = self.conn.clone();
+
+        // Every token in the set has expired once the newest one has, so the whole set can go then.
+        // Both go in one round trip, and a set is never left without an expiry.
+        let _: () = time_redis_command(
+            "sessions",
+            "sadd_expire",
+            redis::pipe()
+                .atomic()
+                .sadd(&key, token)
+                .ignore()
+                .expire(&key, self.ttl_seconds)
+                .ignore()
+                .query_async(&mut conn),
+        )
+        .await
+        .wrap_err("failed to add session to Redis")
+        .map_err(SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "remove_session", skip_all)]
+    async fn remove_session(&self, user_id: &UserId, token: &str) -> Result<(), SessionStoreError> {
+        let key = get_key(user_id);
+
+        let mut conn = self.conn.clone();
+        let _: () = time_redis_command("sessions", "srem", conn.srem(&key, token))
+            .await
+            .wrap_err("failed to remove session from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "get_sessions", skip_all)]
+    async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<String>, SessionStoreError> {
+        let key = get_key(user_id);
+
+        let mut conn = self.conn.clone();
+        time_redis_command("sessions", "smembers", conn.smembers(&key))
+            .await
+            .wrap_err("failed to get sessions from Redis")
+            .map_err(SessionStoreError::UnexpectedError)
+    }
+}
+
+const SESSIONS_KEY_PREFIX: &str = "sessions:";
+
+fn get_key(user_id: &UserId) -> String {
+    format!("{}{}", SESSIONS_KEY_PREFIX, user_id)
+}
@@ -1,21 +1,21 @@
 use color_eyre::eyre::Context;
-use redis::{Commands, Connection};
-use secrecy::ExposeSecret;
+use redis::{aio::ConnectionManager, AsyncCommands};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::sync::RwLock;
 
-use crate::domain::{
-    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
-    Email,
+use crate::{
+    domain::{
+        data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+        UserId,
+    },
+    services::data_stores::time_redis_command,
 };
 
 pub struct RedisTwoFACodeStore {
-    conn: Arc<RwLock<Connection>>,
+    conn: ConnectionManager,
 }
 
 impl RedisTwoFACodeStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+    pub fn new(conn: ConnectionManager) -> Self {
         Self { conn }
     }
 }
@@ -24,14 +24,14 @@ impl RedisTwoFACodeStore {
 impl TwoFACodeStore for RedisTwoFACodeStore {
     #[tracing::instrument(name = "add_code", skip_all)]
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
+        user_id: UserId,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
         // TODO:
         // 1. Create a new key using the get_key helper function.
-        let key = get_key(&email);
+        let key = get_key(&user_id);
 
         // 2. Create a TwoFATuple instance.
         let two_fa = TwoFATuple(
@@ -65,22 +65,24 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         //            .await
         //            .set_ex(&key, two_fa_json, ttl)
         //            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
-        let _: () = self
-            .conn
-            .write()
-            .await
-            .set_ex(&key, two_fa_json, TEN_MINUTES_IN_SECONDS)
-            .wrap_err("failed to set 2FA code in Redis")
-            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let mut conn = self.conn.clone();
+        let _: () = time_redis_command(
+            "two_fa_codes",
+            "set_ex",
+            conn.set_ex(&key, two_fa_json, TEN_MINUTES_IN_SECONDS),
+        )
+        .await
+        .wrap_err("failed to set 2FA code in Redis")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
         Ok(())
     }
 
     #[tracing::instrument(name = "remove_code", skip_all)]
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
+    async fn remove_code(&self, user_id: &UserId) -> Result<(), TwoFACodeStoreError> {
         // TODO:
         // 1. Create a new key using the get_key helper function.
-        let key = get_key(&email);
+        let key = get_key(user_id);
 
         // 2. Call the del command on the Redis connection to delete the 2FA code entry.
         // Return TwoFACodeStoreError::UnexpectedError if the operation fails.
@@ -91,13 +93,14 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         //            .del(&key)
         //            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
 
-        let _: () = self
-            .conn
-            .write()
+        let mut conn = self.conn.clone();
+        let deleted: u64 = time_redis_command("two_fa_codes", "del", conn.del(&key))
             .await
-            .del(&key)
             .wrap_err("failed to delete 2FA code from Redis") // New!
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        if deleted == 0 {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        }
 
         Ok(())
     }
@@ -105,31 +108,33 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
     #[tracing::instrument(name = "get_code", skip_all)]
     async fn get_code(
         &self,
-        email: &Email,
+        user_id: &UserId,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
         // TODO:
         // 1. Create a new key using the get_key helper function.
-        let key = get_key(&email);
+        let key = get_key(user_id);
 
         // 2. Call the get command on the Redis connection to get the value stored for the key.
-        // Return TwoFACodeStoreError::LoginAttemptIdNotFound if the operation fails.
+        // Return TwoFACodeStoreError::LoginAttemptIdNotFound if there is no value, and
+        // TwoFACodeStoreError::UnexpectedError if the operation fails.
 
-        match self.conn.write().await.get::<_, String>(&key) {
-            Ok(value) => {
-                let data: TwoFATuple = serde_json::from_str(&value)
-                    .wrap_err("failed to deserialize 2FA tuple") // New!
-                    .map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
+        let mut conn = self.conn.clone();
+        let value = time_redis_command("two_fa_codes", "get", conn.get::<_, Option<String>>(&key))
+            .await
+            .wrap_err("failed to get 2FA code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
 
-                let login_attempt_id =
-                    LoginAttemptId::parse(data.0).map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
+        let data: TwoFATuple = serde_json::from_str(&value)
+            .wrap_err("failed to deserialize 2FA tuple") // New!
+            .map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
 
-                let email_code =
-                    TwoFACode::parse(data.1).map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
+        let login_attempt_id =
+            LoginAttemptId::parse(data.0).map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
 
-                Ok((login_attempt_id, email_code))
-            }
-            Err(_) => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
-        }
+        let email_code = TwoFACode::parse(data.1).map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
+
+        Ok((login_attempt_id, email_code))
         //let val = self
         //    .conn
         //    .write()
@@ -163,6 +168,65 @@ const TEN_MINUTES_IN_SECONDS: u64 = 600;
 const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
 
 #[tracing::instrument(name = "get_key", skip_all)]
-fn get_key(email: &Email) -> String {
-    format!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref().expose_secret())
+fn get_key(user_id: &UserId) -> String {
+    format!("{}{}", TWO_FA_CODE_PREFIX, user_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use redis::Client;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    // A Redis server that answers every command with `reply`.
+    async fn fake_redis(reply: &'static str) -> ConnectionManager {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    // Each command is an array header followed by a length and a value line
+                    // for every argument.
+                    while let Ok(Some(header)) = lines.next_line().await {
+                        let arguments: usize = header.trim_start_matches('*').parse().unwrap();
+                        for _ in 0..arguments * 2 {
+                            lines.next_line().await.unwrap();
+                        }
+                        writer.write_all(reply.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        let client = Client::open(format!("redis://127.0.0.1:{port}")).unwrap();
+        ConnectionManager::new(client).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_code_errors_with_not_found_when_there_is_no_code() {
+        let store = RedisTwoFACodeStore::new(fake_redis("$-1\r\n").await);
+
+        let actual = store.get_code(&UserId::default()).await;
+
+        assert_eq!(actual, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_get_code_errors_with_unexpected_error_when_redis_fails() {
+        let store = RedisTwoFACodeStore::new(fake_redis("-ERR unavailable\r\n").await);
+
+        let actual = store.get_code(&UserId::default()).await;
+
+        assert!(matches!(
+            actual,
+            Err(TwoFACodeStoreError::UnexpectedError(_))
+        ));
+    }
 }
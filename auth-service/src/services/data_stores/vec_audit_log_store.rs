@@ -0,0 +1,202 @@
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::domain::{
+    data_stores::{AuditLogStore, AuditLogStoreError},
+    AuditEvent, AuditEventFilter, NewAuditEvent, UserId, GENESIS_HASH,
+};
+
+// The events sit behind a lock so each append links to the event that really is the last.
+#[derive(Default)]
+pub struct VecAuditLogStore {
+    events: RwLock<Vec<AuditEvent>>,
+}
+
+// A panic can't leave the events half-written, so a poisoned lock is still safe to use.
+impl VecAuditLogStore {
+    fn read(&self) -> RwLockReadGuard<'_, Vec<AuditEvent>> {
+        self.events.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Vec<AuditEvent>> {
+        self.events.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditLogStore for VecAuditLogStore {
+    async fn append(&self, event: NewAuditEvent) -> Result<AuditEvent, AuditLogStoreError> {
+        let mut events = self.write();
+        let event = match events.last() {
+            Some(last) => event.seal(last.id + 1, last.hash.clone()),
+            None => event.seal(1, GENESIS_HASH.to_owned()),
+        };
+        events.push(event.clone());
+        Ok(event)
+    }
+
+    async fn get_events(
+        &self,
+        filter: &AuditEventFilter,
+    ) -> Result<Vec<AuditEvent>, AuditLogStoreError> {
+        let limit = filter.limit.map_or(usize::MAX, |limit| limit as usize);
+        Ok(self
+            .read()
+            .iter()
+            .rev()
+            .filter(|event| filter.matches(event))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_events_after(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<AuditEvent>, AuditLogStoreError> {
+        Ok(self
+            .read()
+            .iter()
+            .filter(|event| event.id > after_id)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn anonymize_user(&self, user_id: &UserId) -> Result<u64, AuditLogStoreError> {
+        let mut anonymized = 0;
+        for event in self
+            .write()
+            .iter_mut()
+            .filter(|event| event.user_id == Some(*user_id))
+        {
+            event.anonymize();
+            anonymized += 1;
+        }
+        Ok(anonymized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{AuditChainError, AuditEventType, AuditOutcome};
+
+    fn login_event(user_id: UserId, outcome: AuditOutcome) -> NewAuditEvent {
+        let mut event = NewAuditEvent::new(AuditEventType::Login, outcome);
+        event.user_id = Some(user_id);
+        event.ip_address = Some("127.0.0.1".to_owned());
+        event
+    }
+
+    #[tokio::test]
+    async fn test_append_links_events() {
+        let store = VecAuditLogStore::default();
+        let user_id = UserId::default();
+
+        let first = store
+            .append(login_event(user_id, AuditOutcome::Failure))
+            .await
+            .unwrap();
+        let second = store
+            .append(login_event(user_id, AuditOutcome::Success))
+            .await
+            .unwrap();
+
+        assert_eq!(first.id, 1);
+        assert_eq!(first.prev_hash, GENESIS_HASH);
+        assert_eq!(second.id, 2);
+        assert_eq!(second.prev_hash, first.hash);
+
+        let verifier = store.verify_chain().await.unwrap();
+        assert_eq!(verifier.verified_events(), 2);
+        assert_eq!(verifier.head_hash(), second.hash);
+    }
+
+    #[tokio::test]
+    async fn test_get_events_filters_newest_first() {
+        let store = VecAuditLogStore::default();
+        let user_id = UserId::default();
+        let other_user_id = UserId::default();
+
+        let _ = store
+            .append(login_event(user_id, AuditOutcome::Failure))
+            .await;
+        let _ = store
+            .append(login_event(other_user_id, AuditOutcome::Failure))
+            .await;
+        let _ = store
+            .append(login_event(user_id, AuditOutcome::Success))
+            .await;
+
+        let filter = AuditEventFilter {
+            user_id: Some(user_id),
+            ..Default::default()
+        };
+        let ids: Vec<i64> = store
+            .get_events(&filter)
+            .await
+            .unwrap()
+            .iter()
+            .map(|event| event.id)
+            .collect();
+        assert_eq!(ids, vec![3, 1]);
+
+        let filter = AuditEventFilter {
+            outcome: Some(AuditOutcome::Failure),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let ids: Vec<i64> = store
+            .get_events(&filter)
+            .await
+            .unwrap()
+            .iter()
+            .map(|event| event.id)
+            .collect();
+        assert_eq!(ids, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_anonymize_user_keeps_chain_intact() {
+        let store = VecAuditLogStore::default();
+        let user_id = UserId::default();
+        let other_user_id = UserId::default();
+
+        let _ = store
+            .append(login_event(user_id, AuditOutcome::Failure))
+            .await;
+        let _ = store
+            .append(login_event(other_user_id, AuditOutcome::Success))
+            .await;
+
+        assert_eq!(store.anonymize_user(&user_id).await.unwrap(), 1);
+
+        let events = store.get_events_after(0, 10).await.unwrap();
+        assert_eq!(events[0].user_id, None);
+        assert_eq!(events[0].ip_address, None);
+        assert_eq!(events[1].user_id, Some(other_user_id));
+        assert!(store.verify_chain().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_verify_chain_detects_tampering() {
+        let store = VecAuditLogStore::default();
+        let user_id = UserId::default();
+
+        let _ = store
+            .append(login_event(user_id, AuditOutcome::Failure))
+            .await;
+        let _ = store
+            .append(login_event(user_id, AuditOutcome::Failure))
+            .await;
+        store.events.write().unwrap()[0].outcome = AuditOutcome::Success;
+
+        assert!(matches!(
+            store.verify_chain().await,
+            Err(AuditLogStoreError::ChainBroken(
+                AuditChainError::HashMismatch(1)
+            ))
+        ));
+    }
+}
@@ -0,0 +1,50 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Result};
+use std::{future::Future, time::Duration};
+use tokio::task::JoinHandle;
+
+use crate::utils::shutdown::Shutdown;
+
+pub(crate) const DISPATCH_BATCH_SIZE: i64 = 100;
+// A dispatcher claims a batch by pushing each item's next attempt to the end of the claim
+// window, so other workers skip it in the meantime. The window is long enough for a whole batch
+// to time out, so a claimed item is never picked up again while it is still being sent. If the
+// worker dies, its items are due again once the window is over.
+const CLAIM_WINDOW_SECONDS: i64 = 30 * 60;
+
+// When a batch claimed at `now` may be claimed again.
+pub(crate) fn claimed_until(now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    Ok(now
+        + chrono::Duration::try_seconds(CLAIM_WINDOW_SECONDS)
+            .ok_or(eyre!("failed to create claim time delta"))?)
+}
+
+// Runs `dispatch` every `interval` until the service shuts down. `dispatch` claims and sends
+// whatever is due at the time it is given, and `what` names it in the error log.
+pub(crate) fn spawn_dispatcher<F, Fut>(
+    what: &'static str,
+    interval: Duration,
+    shutdown: Shutdown,
+    dispatch: F,
+) -> JoinHandle<()>
+where
+    F: Fn(DateTime<Utc>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<usize>> + Send,
+{
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            // On shutdown, one last pass sends what the drained requests queued up.
+            let stopping = tokio::select! {
+                _ = ticker.tick() => false,
+                _ = shutdown.triggered() => true,
+            };
+            if let Err(e) = dispatch(Utc::now()).await {
+                tracing::error!("failed to deliver {}: {:?}", what, e);
+            }
+            if stopping {
+                break;
+            }
+        }
+    })
+}
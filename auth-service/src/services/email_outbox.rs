@@ -0,0 +1,148 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Report, Result};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+use crate::{
+    app_state::{AppState, EmailClientType, EmailOutboxStoreType},
+    domain::{
+        data_stores::EmailOutboxStoreError, AuthAPIError, Email, EmailDelivery, EmailSuppressed,
+        Locale, OutboxEmail, UserId,
+    },
+    services::{
+        dispatcher::{claimed_until, spawn_dispatcher, DISPATCH_BATCH_SIZE},
+        email_templates::EmailTemplate,
+    },
+    utils::shutdown::Shutdown,
+};
+
+#[derive(Debug, Error)]
+pub enum EmailDeliveryError {
+    #[error("Failed to send email")]
+    SendFailed(#[source] Report),
+    #[error("Email address is undeliverable")]
+    Suppressed,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<EmailDeliveryError> for AuthAPIError {
+    fn from(error: EmailDeliveryError) -> Self {
+        match error {
+            EmailDeliveryError::SendFailed(_) => AuthAPIError::EmailDeliveryFailed,
+            EmailDeliveryError::Suppressed => AuthAPIError::EmailUndeliverable,
+            EmailDeliveryError::UnexpectedError(e) => AuthAPIError::UnexpectedError(e),
+        }
+    }
+}
+
+// Renders the template in the recipient's language and writes the email to the outbox.
+// Best-effort emails are left to the dispatcher. Synchronous ones are sent before this
+// returns, and an error is returned if that fails.
+#[tracing::instrument(name = "send_email", skip_all)]
+pub async fn send_email(
+    state: &AppState,
+    user_id: &UserId,
+    recipient: &Email,
+    locale: Locale,
+    template: EmailTemplate<'_>,
+) -> Result<(), EmailDeliveryError> {
+    let message = template
+        .render(locale, &state.settings.email.brand)
+        .map_err(EmailDeliveryError::UnexpectedError)?;
+    let mut email = OutboxEmail::new(template.kind(), user_id, recipient, message);
+    let delivery = state
+        .settings
+        .email
+        .delivery_policy
+        .delivery_for(email.kind);
+
+    if delivery == EmailDelivery::Synchronous {
+        // Claimed by this request from the start, so the dispatcher leaves it alone.
+        email.next_attempt_at =
+            claimed_until(Utc::now()).map_err(EmailDeliveryError::UnexpectedError)?;
+    }
+
+    state
+        .email_outbox_store
+        .add_email(&email)
+        .await
+        .map_err(|e| EmailDeliveryError::UnexpectedError(e.into()))?;
+
+    if delivery == EmailDelivery::BestEffort {
+        return Ok(());
+    }
+
+    let result = state
+        .email_client
+        .send_email(&email.recipient, &email.message)
+        .await;
+
+    match &result {
+        Ok(()) => email.record_sent(Utc::now()),
+        Err(e) => email.record_permanent_failure(e.to_string()),
+    }
+
+    state
+        .email_outbox_store
+        .update_email(&email)
+        .await
+        .map_err(|e| EmailDeliveryError::UnexpectedError(e.into()))?;
+
+    result.map_err(|e| match e.downcast_ref::<EmailSuppressed>() {
+        Some(_) => EmailDeliveryError::Suppressed,
+        None => EmailDeliveryError::SendFailed(e),
+    })
+}
+
+// Sends the outbox emails that are due at `now` and records how each attempt went. Returns the
+// number of emails attempted.
+#[tracing::instrument(name = "deliver_due_emails", skip_all)]
+pub async fn deliver_due_emails(
+    outbox_store: &EmailOutboxStoreType,
+    email_client: &EmailClientType,
+    now: DateTime<Utc>,
+) -> Result<usize> {
+    let emails = outbox_store
+        .claim_due_emails(now, claimed_until(now)?, DISPATCH_BATCH_SIZE)
+        .await?;
+
+    for mut email in emails.iter().cloned() {
+        let result = email_client
+            .send_email(&email.recipient, &email.message)
+            .await;
+
+        match result {
+            Ok(()) => email.record_sent(now),
+            Err(e) if e.downcast_ref::<EmailSuppressed>().is_some() => {
+                email.record_permanent_failure(e.to_string());
+            }
+            Err(e) => {
+                tracing::warn!(email_id = %email.id, "email delivery failed: {}", e);
+                email.record_failure(e.to_string(), now);
+            }
+        }
+
+        match outbox_store.update_email(&email).await {
+            Ok(()) | Err(EmailOutboxStoreError::EmailNotFound) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(emails.len())
+}
+
+// Runs `deliver_due_emails` every `interval` until the service shuts down.
+pub fn spawn_email_dispatcher(
+    outbox_store: EmailOutboxStoreType,
+    email_client: EmailClientType,
+    interval: Duration,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    spawn_dispatcher("emails", interval, shutdown, move |now| {
+        let outbox_store = outbox_store.clone();
+        let email_client = email_client.clone();
+        async move { deliver_due_emails(&outbox_store, &email_client, now).await }
+    })
+}
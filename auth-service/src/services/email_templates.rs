@@ -0,0 +1,326 @@
+use askama::Template;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+
+use crate::domain::{EmailKind, EmailMessage, Locale};
+
+// Branding shared by every email. Set through the `email.brand` settings.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Brand {
+    pub name: String,
+    // Defaults to the sender address.
+    #[serde(default)]
+    pub support_email: String,
+    // Accent color of the HTML emails, as a CSS color.
+    pub color: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum SecurityNotice<'a> {
+    PasswordChanged,
+    // Sent to the old address, which gets a link to cancel the change.
+    EmailChangeRequested {
+        new_email: &'a str,
+        cancel_link: &'a str,
+    },
+    AccountDeleted {
+        grace_period_days: i64,
+    },
+}
+
+// Every email the service sends. Each one has an HTML and a plain-text template under
+// `templates/emails`, with a translation for every `Locale`.
+#[derive(Debug, Clone)]
+pub enum EmailTemplate<'a> {
+    TwoFACode { code: &'a str },
+    MagicLink { link: &'a str, ttl_minutes: i64 },
+    // Confirms a new email address.
+    Verification { link: &'a str, ttl_minutes: i64 },
+    SecurityNotice(SecurityNotice<'a>),
+}
+
+impl EmailTemplate<'_> {
+    pub fn kind(&self) -> EmailKind {
+        match self {
+            Self::TwoFACode { .. } => EmailKind::TwoFACode,
+            Self::MagicLink { .. } => EmailKind::MagicLink,
+            Self::Verification { .. } => EmailKind::EmailChangeConfirmation,
+            Self::SecurityNotice(SecurityNotice::PasswordChanged) => EmailKind::PasswordChanged,
+            Self::SecurityNotice(SecurityNotice::EmailChangeRequested { .. }) => {
+                EmailKind::EmailChangeNotice
+            }
+            Self::SecurityNotice(SecurityNotice::AccountDeleted { .. }) => {
+                EmailKind::AccountDeleted
+            }
+        }
+    }
+
+    pub fn render(&self, locale: Locale, brand: &Brand) -> Result<EmailMessage> {
+        let subject = self.subject(locale);
+        let (html_body, text_body) = match self {
+            Self::TwoFACode { code } => (
+                TwoFACodeHtml {
+                    locale,
+                    brand,
+                    code,
+                }
+                .render()?,
+                TwoFACodeText {
+                    locale,
+                    brand,
+                    code,
+                }
+                .render()?,
+            ),
+            Self::MagicLink { link, ttl_minutes } => (
+                MagicLinkHtml {
+                    locale,
+                    brand,
+                    link,
+                    ttl_minutes: *ttl_minutes,
+                }
+                .render()?,
+                MagicLinkText {
+                    locale,
+                    brand,
+                    link,
+                    ttl_minutes: *ttl_minutes,
+                }
+                .render()?,
+            ),
+            Self::Verification { link, ttl_minutes } => (
+                VerificationHtml {
+                    locale,
+                    brand,
+                    link,
+                    ttl_minutes: *ttl_minutes,
+                }
+                .render()?,
+                VerificationText {
+                    locale,
+                    brand,
+                    link,
+                    ttl_minutes: *ttl_minutes,
+                }
+                .render()?,
+            ),
+            Self::SecurityNotice(notice) => (
+                SecurityNoticeHtml {
+                    locale,
+                    brand,
+                    notice,
+                }
+                .render()?,
+                SecurityNoticeText {
+                    locale,
+                    brand,
+                    notice,
+                }
+                .render()?,
+            ),
+        };
+
+        Ok(EmailMessage::new(subject.to_owned(), html_body, text_body))
+    }
+
+    fn subject(&self, locale: Locale) -> &'static str {
+        match (self, locale) {
+            (Self::TwoFACode { .. }, Locale::En) => "Your login code",
+            (Self::TwoFACode { .. }, Locale::De) => "Ihr Anmeldecode",
+            (Self::TwoFACode { .. }, Locale::Fr) => "Votre code de connexion",
+            (Self::MagicLink { .. }, Locale::En) => "Your login link",
+            (Self::MagicLink { .. }, Locale::De) => "Ihr Anmeldelink",
+            (Self::MagicLink { .. }, Locale::Fr) => "Votre lien de connexion",
+            (Self::Verification { .. }, Locale::En) => "Confirm your new email address",
+            (Self::Verification { .. }, Locale::De) => "Bestätigen Sie Ihre neue E-Mail-Adresse",
+            (Self::Verification { .. }, Locale::Fr) => "Confirmez votre nouvelle adresse e-mail",
+            (Self::SecurityNotice(notice), locale) => match (notice, locale) {
+                (SecurityNotice::PasswordChanged, Locale::En) => "Your password was changed",
+                (SecurityNotice::PasswordChanged, Locale::De) => "Ihr Passwort wurde geändert",
+                (SecurityNotice::PasswordChanged, Locale::Fr) => "Votre mot de passe a été modifié",
+                (SecurityNotice::EmailChangeRequested { .. }, Locale::En) => {
+                    "Your email address is being changed"
+                }
+                (SecurityNotice::EmailChangeRequested { .. }, Locale::De) => {
+                    "Ihre E-Mail-Adresse wird geändert"
+                }
+                (SecurityNotice::EmailChangeRequested { .. }, Locale::Fr) => {
+                    "Votre adresse e-mail est en cours de modification"
+                }
+                (SecurityNotice::AccountDeleted { .. }, Locale::En) => "Your account was deleted",
+                (SecurityNotice::AccountDeleted { .. }, Locale::De) => "Ihr Konto wurde gelöscht",
+                (SecurityNotice::AccountDeleted { .. }, Locale::Fr) => {
+                    "Votre compte a été supprimé"
+                }
+            },
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "emails/two_fa_code.html")]
+struct TwoFACodeHtml<'a> {
+    locale: Locale,
+    brand: &'a Brand,
+    code: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/two_fa_code.txt")]
+struct TwoFACodeText<'a> {
+    locale: Locale,
+    brand: &'a Brand,
+    code: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/magic_link.html")]
+struct MagicLinkHtml<'a> {
+    locale: Locale,
+    brand: &'a Brand,
+    link: &'a str,
+    ttl_minutes: i64,
+}
+
+#[derive(Template)]
+#[template(path = "emails/magic_link.txt")]
+struct MagicLinkText<'a> {
+    locale: Locale,
+    brand: &'a Brand,
+    link: &'a str,
+    ttl_minutes: i64,
+}
+
+#[derive(Template)]
+#[template(path = "emails/verification.html")]
+struct VerificationHtml<'a> {
+    locale: Locale,
+    brand: &'a Brand,
+    link: &'a str,
+    ttl_minutes: i64,
+}
+
+#[derive(Template)]
+#[template(path = "emails/verification.txt")]
+struct VerificationText<'a> {
+    locale: Locale,
+    brand: &'a Brand,
+    link: &'a str,
+    ttl_minutes: i64,
+}
+
+#[derive(Template)]
+#[template(path = "emails/security_notice.html")]
+struct SecurityNoticeHtml<'a> {
+    locale: Locale,
+    brand: &'a Brand,
+    notice: &'a SecurityNotice<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "emails/security_notice.txt")]
+struct SecurityNoticeText<'a> {
+    locale: Locale,
+    brand: &'a Brand,
+    notice: &'a SecurityNotice<'a>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+
+    fn brand() -> Brand {
+        Brand {
+            name: "Acme".to_owned(),
+            support_email: "support@acme.test".to_owned(),
+            color: "#ff0000".to_owned(),
+        }
+    }
+
+    fn templates() -> Vec<EmailTemplate<'static>> {
+        vec![
+            EmailTemplate::TwoFACode { code: "123456" },
+            EmailTemplate::MagicLink {
+                link: "https://acme.test/login?token=abc",
+                ttl_minutes: 15,
+            },
+            EmailTemplate::Verification {
+                link: "https://acme.test/confirm?token=abc",
+                ttl_minutes: 30,
+            },
+            EmailTemplate::SecurityNotice(SecurityNotice::PasswordChanged),
+            EmailTemplate::SecurityNotice(SecurityNotice::EmailChangeRequested {
+                new_email: "new@acme.test",
+                cancel_link: "https://acme.test/cancel?token=abc",
+            }),
+            EmailTemplate::SecurityNotice(SecurityNotice::AccountDeleted {
+                grace_period_days: 30,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_every_template_renders_in_every_locale() {
+        for template in templates() {
+            for locale in Locale::ALL {
+                let message = template.render(locale, &brand()).unwrap();
+                let html = message.html_body.expose_secret();
+                let text = message.text_body.expose_secret();
+
+                assert!(!message.subject.is_empty());
+                assert!(html.contains(&format!("<html lang=\"{}\">", locale.as_str())));
+                assert!(html.contains("Acme") && html.contains("#ff0000"));
+                assert!(text.starts_with("Acme\n"));
+                assert!(text.contains("support@acme.test"));
+                assert!(!text.contains('<'));
+            }
+        }
+    }
+
+    #[test]
+    fn test_variables_end_up_in_both_bodies() {
+        let message = EmailTemplate::MagicLink {
+            link: "https://acme.test/login?token=abc",
+            ttl_minutes: 15,
+        }
+        .render(Locale::En, &brand())
+        .unwrap();
+
+        assert!(message
+            .text_body
+            .expose_secret()
+            .contains("\nhttps://acme.test/login?token=abc\n"));
+        assert!(message.text_body.expose_secret().contains("15 minutes"));
+        assert!(message.html_body.expose_secret().contains("token=abc"));
+    }
+
+    #[test]
+    fn test_html_variables_are_escaped() {
+        let message = EmailTemplate::SecurityNotice(SecurityNotice::EmailChangeRequested {
+            new_email: "<script>@acme.test",
+            cancel_link: "https://acme.test/cancel",
+        })
+        .render(Locale::En, &brand())
+        .unwrap();
+
+        assert!(!message.html_body.expose_secret().contains("<script>"));
+        assert!(message.text_body.expose_secret().contains("<script>"));
+    }
+
+    #[test]
+    fn test_locale_selects_the_translation() {
+        let template = EmailTemplate::TwoFACode { code: "123456" };
+
+        let message = template.render(Locale::De, &brand()).unwrap();
+        assert_eq!(message.subject, "Ihr Anmeldecode");
+        assert!(message
+            .text_body
+            .expose_secret()
+            .contains("Anmeldung abzuschließen: 123456"));
+
+        let message = template.render(Locale::Fr, &brand()).unwrap();
+        assert_eq!(message.subject, "Votre code de connexion");
+        assert!(message.html_body.expose_secret().contains("123456"));
+    }
+}
@@ -0,0 +1,384 @@
+use color_eyre::eyre::{eyre, Result};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::domain::{
+    CircuitBreaker, CircuitBreakerConfig, CircuitState, Email, EmailClient, EmailMessage,
+};
+
+struct Provider {
+    name: String,
+    client: Box<dyn EmailClient + Send + Sync>,
+    breaker: Mutex<CircuitBreaker>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderHealth {
+    pub name: String,
+    pub state: CircuitState,
+}
+
+// Sends through the first provider whose circuit is closed, and falls over to the next one in
+// order when a send fails. A provider that keeps failing is skipped until its circuit lets a
+// trial send through again.
+pub struct FailoverEmailClient {
+    providers: Vec<Provider>,
+    config: CircuitBreakerConfig,
+}
+
+impl FailoverEmailClient {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            providers: Vec::new(),
+            config,
+        }
+    }
+
+    pub fn with_provider(
+        mut self,
+        name: &str,
+        client: impl EmailClient + Send + Sync + 'static,
+    ) -> Self {
+        record_state(name, CircuitState::Closed);
+        self.providers.push(Provider {
+            name: name.to_owned(),
+            client: Box::new(client),
+            breaker: Mutex::new(CircuitBreaker::new(self.config)),
+        });
+        self
+    }
+
+    pub fn health(&self) -> Vec<ProviderHealth> {
+        self.providers
+            .iter()
+            .map(|provider| ProviderHealth {
+                name: provider.name.clone(),
+                state: provider.breaker().state(),
+            })
+            .collect()
+    }
+}
+
+impl Provider {
+    fn breaker(&self) -> std::sync::MutexGuard<'_, CircuitBreaker> {
+        // The breaker is only ever updated in place, so a poisoned lock still holds valid state.
+        self.breaker
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Applies `update` to the breaker and reports the change if the circuit moved.
+    fn update_breaker<T>(&self, update: impl FnOnce(&mut CircuitBreaker) -> T) -> T {
+        let mut breaker = self.breaker();
+        let before = breaker.state();
+        let result = update(&mut breaker);
+        let after = breaker.state();
+        drop(breaker);
+
+        if before != after {
+            match after {
+                CircuitState::Open => {
+                    tracing::warn!(provider = %self.name, "email provider circuit opened")
+                }
+                CircuitState::HalfOpen => {
+                    tracing::info!(provider = %self.name, "email provider circuit half-open")
+                }
+                CircuitState::Closed => {
+                    tracing::info!(provider = %self.name, "email provider circuit closed")
+                }
+            }
+            record_state(&self.name, after);
+        }
+        result
+    }
+}
+
+// A send the breaker allowed, until it reports how it went. If the send is dropped before that,
+// like when the request that made it is cancelled, the call is given back to the breaker.
+// Otherwise a trial send that never finished would keep the circuit half-open for good.
+struct AllowedSend<'a> {
+    provider: &'a Provider,
+    finished: bool,
+}
+
+impl<'a> AllowedSend<'a> {
+    fn new(provider: &'a Provider) -> Self {
+        Self {
+            provider,
+            finished: false,
+        }
+    }
+
+    fn succeeded(mut self) {
+        self.finished = true;
+        self.provider
+            .update_breaker(|breaker| breaker.record_success());
+    }
+
+    fn failed(mut self) {
+        self.finished = true;
+        self.provider
+            .update_breaker(|breaker| breaker.record_failure(Instant::now()));
+    }
+}
+
+impl Drop for AllowedSend<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.provider.update_breaker(|breaker| breaker.abandon());
+        }
+    }
+}
+
+fn record_state(provider: &str, state: CircuitState) {
+    for candidate in [
+        CircuitState::Closed,
+        CircuitState::Open,
+        CircuitState::HalfOpen,
+    ] {
+        metrics::gauge!(
+            "email_provider_circuit_state",
+            "provider" => provider.to_owned(),
+            "state" => candidate.as_str()
+        )
+        .set(if candidate == state { 1.0 } else { 0.0 });
+    }
+}
+
+fn record_outcome(provider: &str, outcome: &'static str) {
+    metrics::counter!(
+        "email_provider_sends_total",
+        "provider" => provider.to_owned(),
+        "outcome" => outcome
+    )
+    .increment(1);
+}
+
+#[async_trait::async_trait]
+impl EmailClient for FailoverEmailClient {
+    #[tracing::instrument(name = "Sending email with failover", skip_all)]
+    async fn send_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()> {
+        let mut last_error = None;
+
+        for provider in &self.providers {
+            if !provider.update_breaker(|breaker| breaker.allow(Instant::now())) {
+                record_outcome(&provider.name, "skipped");
+                continue;
+            }
+
+            let send = AllowedSend::new(provider);
+            match provider.client.send_email(recipient, message).await {
+                Ok(()) => {
+                    send.succeeded();
+                    record_outcome(&provider.name, "success");
+                    return Ok(());
+                }
+                Err(e) => {
+                    tracing::warn!(provider = %provider.name, "email provider failed: {}", e);
+                    send.failed();
+                    record_outcome(&provider.name, "failure");
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(match last_error {
+            Some(e) => e.wrap_err("Every email provider failed"),
+            None => eyre!("Every email provider is unavailable"),
+        })
+    }
+
+    // Healthy as long as one provider's circuit isn't open.
+    async fn check_health(&self) -> Result<()> {
+        if self
+            .health()
+            .iter()
+            .any(|provider| provider.state != CircuitState::Open)
+        {
+            Ok(())
+        } else {
+            Err(eyre!("Every email provider circuit is open"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use reqwest::Client;
+    use secrecy::Secret;
+    use wiremock::matchers::any;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::services::{postmark_email_client::PostmarkEmailClient, MockEmailClient};
+    use crate::utils::constants::test;
+
+    // Fails every send and counts how often it was called.
+    #[derive(Clone, Default)]
+    struct FailingEmailClient {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl EmailClient for FailingEmailClient {
+        async fn send_email(&self, _: &Email, _: &EmailMessage) -> Result<()> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Err(eyre!("provider is down"))
+        }
+    }
+
+    // Fails the first `failures` sends, never finishes the next one, and succeeds after that.
+    #[derive(Clone)]
+    struct StallingEmailClient {
+        failures: usize,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl EmailClient for StallingEmailClient {
+        async fn send_email(&self, _: &Email, _: &EmailMessage) -> Result<()> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call < self.failures {
+                return Err(eyre!("provider is down"));
+            }
+            if call == self.failures {
+                std::future::pending::<()>().await;
+            }
+            Ok(())
+        }
+    }
+
+    fn config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            window: 10,
+            min_calls: 3,
+            failure_rate_threshold: 0.5,
+            open_duration: Duration::from_secs(60),
+        }
+    }
+
+    fn email() -> Email {
+        Email::parse(Secret::new("user@example.com".to_owned())).unwrap()
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage::new(
+            "Subject".to_owned(),
+            "<p>content</p>".to_owned(),
+            "content".to_owned(),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_falls_over_to_the_next_provider() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let postmark = PostmarkEmailClient::new(
+            mock_server.uri(),
+            Email::parse(Secret::new(test::email_client::SENDER.to_owned())).unwrap(),
+            Secret::new("token".to_owned()).into(),
+            Client::builder()
+                .timeout(test::email_client::TIMEOUT)
+                .build()
+                .unwrap(),
+        );
+        let email_client = FailoverEmailClient::new(config())
+            .with_provider("postmark", postmark)
+            .with_provider("mock", MockEmailClient);
+
+        let outcome = email_client.send_email(&email(), &message()).await;
+
+        assert!(outcome.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_email_skips_a_provider_whose_circuit_is_open() {
+        let failing = FailingEmailClient::default();
+        let email_client = FailoverEmailClient::new(config())
+            .with_provider("failing", failing.clone())
+            .with_provider("mock", MockEmailClient);
+
+        for _ in 0..5 {
+            assert!(email_client.send_email(&email(), &message()).await.is_ok());
+        }
+
+        // The circuit opened after `min_calls` failures and the rest went straight to the mock.
+        assert_eq!(failing.calls.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            email_client.health(),
+            vec![
+                ProviderHealth {
+                    name: "failing".to_owned(),
+                    state: CircuitState::Open,
+                },
+                ProviderHealth {
+                    name: "mock".to_owned(),
+                    state: CircuitState::Closed,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_when_every_provider_fails() {
+        let failing = FailingEmailClient::default();
+        let email_client = FailoverEmailClient::new(config())
+            .with_provider("first", failing.clone())
+            .with_provider("second", failing.clone());
+
+        let outcome = email_client.send_email(&email(), &message()).await;
+
+        assert!(outcome.is_err());
+        assert_eq!(failing.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn send_email_tries_again_after_a_trial_send_is_dropped() {
+        let stalling = StallingEmailClient {
+            failures: 3,
+            calls: Arc::default(),
+        };
+        let email_client = FailoverEmailClient::new(CircuitBreakerConfig {
+            open_duration: Duration::ZERO,
+            ..config()
+        })
+        .with_provider("stalling", stalling.clone());
+
+        for _ in 0..3 {
+            assert!(email_client.send_email(&email(), &message()).await.is_err());
+        }
+
+        // The trial send never finishes, and is dropped when the caller gives up on it.
+        let trial = tokio::time::timeout(
+            Duration::from_millis(50),
+            email_client.send_email(&email(), &message()),
+        )
+        .await;
+        assert!(trial.is_err());
+
+        assert!(email_client.send_email(&email(), &message()).await.is_ok());
+        assert_eq!(stalling.calls.load(Ordering::SeqCst), 5);
+        assert_eq!(email_client.health()[0].state, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn check_health_fails_once_every_circuit_is_open() {
+        let failing = FailingEmailClient::default();
+        let email_client = FailoverEmailClient::new(config()).with_provider("failing", failing);
+
+        assert!(email_client.check_health().await.is_ok());
+        for _ in 0..3 {
+            let _ = email_client.send_email(&email(), &message()).await;
+        }
+
+        assert!(email_client.check_health().await.is_err());
+    }
+}
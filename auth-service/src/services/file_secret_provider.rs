@@ -0,0 +1,68 @@
+use color_eyre::eyre::{Context, Result};
+use secrecy::Secret;
+use std::{io::ErrorKind, path::PathBuf};
+
+use crate::domain::SecretProvider;
+
+// Reads every secret from a file named after it, e.g. `/run/secrets/jwt_secret`. That's how
+// Docker and Kubernetes mount secrets.
+pub struct FileSecretProvider {
+    directory: PathBuf,
+}
+
+impl FileSecretProvider {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SecretProvider for FileSecretProvider {
+    #[tracing::instrument(name = "Reading secret file", skip(self))]
+    async fn get_secret(&self, name: &str) -> Result<Option<Secret<String>>> {
+        let path = self.directory.join(name);
+        match tokio::fs::read_to_string(&path).await {
+            // Editors and `echo` leave a trailing newline, which is never part of the secret.
+            Ok(secret) => Ok(Some(Secret::new(secret.trim_end().to_owned()))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).wrap_err(format!("failed to read {}", path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn secrets_directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir(&directory).unwrap();
+        directory
+    }
+
+    #[tokio::test]
+    async fn test_get_secret_reads_the_file_named_after_it() {
+        let directory = secrets_directory();
+        std::fs::write(directory.join("jwt_secret"), "secret\n").unwrap();
+        let provider = FileSecretProvider::new(&directory);
+
+        let secret = provider.get_secret("jwt_secret").await.unwrap().unwrap();
+
+        assert_eq!(secret.expose_secret(), "secret");
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_secret_returns_none_without_a_file() {
+        let directory = secrets_directory();
+        let provider = FileSecretProvider::new(&directory);
+
+        assert!(provider.get_secret("jwt_secret").await.unwrap().is_none());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
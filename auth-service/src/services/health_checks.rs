@@ -0,0 +1,77 @@
+use color_eyre::eyre::{Context, Result};
+use redis::aio::ConnectionManager;
+use sqlx::PgPool;
+
+use crate::{app_state::EmailClientType, domain::HealthCheck};
+
+pub struct PostgresHealthCheck {
+    pool: PgPool,
+}
+
+impl PostgresHealthCheck {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl HealthCheck for PostgresHealthCheck {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn check(&self) -> Result<()> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to query Postgres")?;
+        Ok(())
+    }
+}
+
+// Pings over the connection the stores use, so one that can't reconnect shows up here.
+pub struct RedisHealthCheck {
+    conn: ConnectionManager,
+}
+
+impl RedisHealthCheck {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl HealthCheck for RedisHealthCheck {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn check(&self) -> Result<()> {
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut self.conn.clone())
+            .await
+            .wrap_err("failed to ping Redis")?;
+        Ok(())
+    }
+}
+
+pub struct EmailHealthCheck {
+    email_client: EmailClientType,
+}
+
+impl EmailHealthCheck {
+    pub fn new(email_client: EmailClientType) -> Self {
+        Self { email_client }
+    }
+}
+
+#[async_trait::async_trait]
+impl HealthCheck for EmailHealthCheck {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    async fn check(&self) -> Result<()> {
+        self.email_client.check_health().await
+    }
+}
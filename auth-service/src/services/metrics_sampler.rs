@@ -0,0 +1,38 @@
+use metrics_exporter_prometheus::PrometheusHandle;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::utils::shutdown::Shutdown;
+
+// Records how close the Postgres pool is to running out of connections. Requests wait for a
+// connection once `in_use` reaches the maximum.
+pub fn sample_pool_metrics(pg_pool: &PgPool) {
+    let open = pg_pool.size();
+    let idle = pg_pool.num_idle() as u32;
+
+    metrics::gauge!("db_pool_connections", "state" => "idle").set(idle);
+    metrics::gauge!("db_pool_connections", "state" => "in_use").set(open.saturating_sub(idle));
+    metrics::gauge!("db_pool_max_connections").set(pg_pool.options().get_max_connections());
+}
+
+// Samples the gauges that nothing else updates every `interval`, and keeps the recorder's
+// histograms from growing without bound, until the service shuts down.
+pub fn spawn_metrics_sampler(
+    metrics: PrometheusHandle,
+    pg_pool: PgPool,
+    interval: Duration,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            sample_pool_metrics(&pg_pool);
+            metrics.run_upkeep();
+        }
+    })
+}
@@ -0,0 +1,16 @@
+use crate::domain::{PhoneNumber, SmsClient};
+use color_eyre::eyre::Result;
+use secrecy::Secret;
+
+pub struct MockSmsClient;
+
+#[async_trait::async_trait]
+impl SmsClient for MockSmsClient {
+    async fn send_sms(&self, _recipient: &PhoneNumber, _body: &Secret<String>) -> Result<()> {
+        // Like the mock email client, it only logs that a message would have been sent. The
+        // number and the code stay out of the logs.
+        tracing::debug!("Sending SMS with the mock client");
+
+        Ok(())
+    }
+}
@@ -1,5 +1,22 @@
+pub mod account_purger;
 pub mod data_stores;
+mod dispatcher;
+pub mod email_outbox;
+pub mod email_templates;
+pub mod failover_email_client;
+pub mod file_secret_provider;
+pub mod health_checks;
+pub mod metrics_sampler;
 pub mod mock_email_client;
+pub mod mock_sms_client;
 pub mod postmark_email_client;
+pub mod secret_reloader;
+pub mod sms;
+pub mod smtp_email_client;
+pub mod suppressing_email_client;
+pub mod twilio_sms_client;
+pub mod vault_secret_provider;
+pub mod webhooks;
 
 pub use mock_email_client::*;
+pub use mock_sms_client::*;
@@ -1,15 +1,16 @@
 use color_eyre::eyre::Result; // For improved error handling and reporting
 use reqwest::{Client, Url}; // For making HTTP requests
-use secrecy::{ExposeSecret, Secret}; // For securely handling sensitive data
+use secrecy::ExposeSecret; // For securely handling sensitive data
 
-use crate::domain::{Email, EmailClient}; // Import domain-specific modules
+use crate::domain::{Email, EmailClient, EmailMessage, ReloadableSecret}; // Import domain-specific modules
+use crate::utils::tracing::trace_context_headers;
 
 // Define the PostmarkEmailClient struct
 pub struct PostmarkEmailClient {
-    http_client: Client,                 // HTTP client for making requests
-    base_url: String,                    // Base URL for the email service
-    sender: Email,                       // Email address of the sender
-    authorization_token: Secret<String>, // Authorization token for the email service, wrapped in Secret for security
+    http_client: Client,                   // HTTP client for making requests
+    base_url: String,                      // Base URL for the email service
+    sender: Email,                         // Email address of the sender
+    authorization_token: ReloadableSecret, // Authorization token for the email service, replaced when secrets are reloaded
 }
 
 impl PostmarkEmailClient {
@@ -17,7 +18,7 @@ impl PostmarkEmailClient {
     pub fn new(
         base_url: String,
         sender: Email,
-        authorization_token: Secret<String>,
+        authorization_token: ReloadableSecret,
         http_client: Client,
     ) -> Self {
         Self {
@@ -32,7 +33,7 @@ impl PostmarkEmailClient {
 #[async_trait::async_trait]
 impl EmailClient for PostmarkEmailClient {
     #[tracing::instrument(name = "Sending email", skip_all)] // Trace this function, skipping logging its parameters
-    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()> {
+    async fn send_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()> {
         // Parse the base URL and join it with the email endpoint
         let base = Url::parse(&self.base_url)?;
         let url = base.join("/email")?;
@@ -41,9 +42,9 @@ impl EmailClient for PostmarkEmailClient {
         let request_body = SendEmailRequest {
             from: self.sender.as_ref().expose_secret(),
             to: recipient.as_ref().expose_secret(),
-            subject,
-            html_body: content,
-            text_body: content,
+            subject: &message.subject,
+            html_body: message.html_body.expose_secret(),
+            text_body: message.text_body.expose_secret(),
             message_stream: MESSAGE_STREAM,
         };
 
@@ -53,9 +54,15 @@ impl EmailClient for PostmarkEmailClient {
             .post(url)
             .header(
                 POSTMARK_AUTH_HEADER,
-                self.authorization_token.expose_secret(), // Securely expose the authorization token
+                self.authorization_token.load().expose_secret(), // Securely expose the authorization token
             )
             .json(&request_body);
+        // Lets Postmark's side of the call be found from our trace.
+        let request = trace_context_headers()
+            .into_iter()
+            .fold(request, |request, (name, value)| {
+                request.header(name, value)
+            });
 
         // Send the request and handle the response
         request.send().await?.error_for_status()?;
@@ -89,6 +96,7 @@ mod tests {
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
+    use secrecy::Secret;
     use wiremock::matchers::{any, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
@@ -104,6 +112,11 @@ mod tests {
         Paragraph(1..10).fake()
     }
 
+    // Helper function to generate a test message
+    fn message() -> EmailMessage {
+        EmailMessage::new(subject(), content(), content())
+    }
+
     // Helper function to generate a test email
     fn email() -> Email {
         Email::parse(Secret::new(SafeEmail().fake())).unwrap()
@@ -115,7 +128,12 @@ mod tests {
             .timeout(test::email_client::TIMEOUT)
             .build()
             .unwrap();
-        PostmarkEmailClient::new(base_url, email(), Secret::new(Faker.fake()), http_client)
+        PostmarkEmailClient::new(
+            base_url,
+            email(),
+            ReloadableSecret::new(Secret::new(Faker.fake())),
+            http_client,
+        )
     }
 
     // Custom matcher to validate the email request body
@@ -155,9 +173,7 @@ mod tests {
             .await;
 
         // Execute the send_email function and check the outcome
-        let outcome = email_client
-            .send_email(&email(), &subject(), &content())
-            .await;
+        let outcome = email_client.send_email(&email(), &message()).await;
 
         assert!(outcome.is_ok());
     }
@@ -176,9 +192,7 @@ mod tests {
             .await;
 
         // Execute the send_email function and check the outcome
-        let outcome = email_client
-            .send_email(&email(), &subject(), &content())
-            .await;
+        let outcome = email_client.send_email(&email(), &message()).await;
 
         assert!(outcome.is_err());
     }
@@ -198,9 +212,7 @@ mod tests {
             .await;
 
         // Execute the send_email function and check the outcome
-        let outcome = email_client
-            .send_email(&email(), &subject(), &content())
-            .await;
+        let outcome = email_client.send_email(&email(), &message()).await;
 
         assert!(outcome.is_err());
     }
@@ -0,0 +1,36 @@
+use color_eyre::eyre::Result;
+use sqlx::PgPool;
+use std::sync::Arc;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task::JoinHandle,
+};
+
+use crate::settings::Settings;
+
+// Loads the settings again and swaps their secrets into the running service. New database
+// connections use the new password, while open ones are kept until the pool retires them.
+#[tracing::instrument(name = "reload_secrets", skip_all)]
+pub async fn reload_secrets(settings: &Settings, pg_pool: &PgPool) -> Result<()> {
+    let fresh = Settings::load().await?;
+
+    settings.reload_secrets(&fresh);
+    pg_pool.set_connect_options(fresh.database.connect_options()?);
+
+    tracing::info!("reloaded secrets");
+
+    Ok(())
+}
+
+// Runs `reload_secrets` whenever the process receives SIGHUP, for as long as the service is up.
+pub fn spawn_secret_reloader(settings: Arc<Settings>, pg_pool: PgPool) -> Result<JoinHandle<()>> {
+    let mut hangups = signal(SignalKind::hangup())?;
+
+    Ok(tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            if let Err(e) = reload_secrets(&settings, &pg_pool).await {
+                tracing::error!("failed to reload secrets: {:?}", e);
+            }
+        }
+    }))
+}
@@ -0,0 +1,96 @@
+use secrecy::Secret;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Locale, PhoneNumber},
+    services::email_templates::Brand,
+};
+
+// Every SMS the service sends. They are short enough to fit in a single message, so unlike
+// emails they are written inline rather than as templates.
+#[derive(Debug, Clone)]
+pub enum SmsTemplate<'a> {
+    TwoFACode { code: &'a str },
+    // Confirms a phone number the user wants to add.
+    PhoneVerification { code: &'a str },
+}
+
+impl SmsTemplate<'_> {
+    pub fn render(&self, locale: Locale, brand: &Brand) -> Secret<String> {
+        let name = &brand.name;
+        let body = match (self, locale) {
+            (Self::TwoFACode { code }, Locale::En) => {
+                format!("{code} is your {name} login code.")
+            }
+            (Self::TwoFACode { code }, Locale::De) => {
+                format!("{code} ist Ihr Anmeldecode für {name}.")
+            }
+            (Self::TwoFACode { code }, Locale::Fr) => {
+                format!("{code} est votre code de connexion {name}.")
+            }
+            (Self::PhoneVerification { code }, Locale::En) => {
+                format!("{code} is your {name} code to confirm this phone number.")
+            }
+            (Self::PhoneVerification { code }, Locale::De) => {
+                format!("{code} ist Ihr {name}-Code zur Bestätigung dieser Telefonnummer.")
+            }
+            (Self::PhoneVerification { code }, Locale::Fr) => {
+                format!("{code} est votre code {name} pour confirmer ce numéro de téléphone.")
+            }
+        };
+        Secret::new(body)
+    }
+}
+
+// SMS are only ever one-time codes the user is waiting for, so they are sent right away
+// instead of going through an outbox, and a failure is returned to the caller.
+#[tracing::instrument(name = "send_sms", skip_all)]
+pub async fn send_sms(
+    state: &AppState,
+    recipient: &PhoneNumber,
+    locale: Locale,
+    template: SmsTemplate<'_>,
+) -> Result<(), AuthAPIError> {
+    let body = template.render(locale, &state.settings.email.brand);
+
+    state
+        .sms_client
+        .send_sms(recipient, &body)
+        .await
+        .map_err(|e| {
+            tracing::warn!("failed to send SMS: {:?}", e);
+            AuthAPIError::SmsDeliveryFailed
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+
+    fn brand() -> Brand {
+        Brand {
+            name: "Acme".to_owned(),
+            support_email: "support@acme.test".to_owned(),
+            color: "#ff0000".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_every_template_renders_in_every_locale() {
+        let templates = [
+            SmsTemplate::TwoFACode { code: "123456" },
+            SmsTemplate::PhoneVerification { code: "123456" },
+        ];
+        for template in templates {
+            for locale in Locale::ALL {
+                let body = template.render(locale, &brand());
+                let body = body.expose_secret();
+                assert!(body.contains("123456"), "{:?} in {:?}", template, locale);
+                assert!(body.contains("Acme"), "{:?} in {:?}", template, locale);
+                // Longer messages are split and billed as several.
+                assert!(body.chars().count() <= 70, "{:?} in {:?}", template, locale);
+            }
+        }
+    }
+}
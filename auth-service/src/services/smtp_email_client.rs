@@ -0,0 +1,386 @@
+use color_eyre::eyre::{eyre, Result};
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::{
+        authentication::Credentials,
+        client::{Tls, TlsParameters},
+        PoolConfig,
+    },
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+use crate::domain::{Email, EmailClient, EmailMessage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    // Plain text. Only meant for a relay on the same host or network.
+    None,
+    // Connects in plain text and upgrades with STARTTLS. Nothing is sent if the server can't
+    // upgrade.
+    StartTls,
+    // TLS from the first byte, usually on port 465.
+    Implicit,
+}
+
+impl SmtpTls {
+    pub fn parse(tls: &str) -> Result<Self> {
+        match tls {
+            "none" => Ok(Self::None),
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Implicit),
+            _ => Err(eyre!("Invalid SMTP TLS mode")),
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Self::None => 25,
+            Self::StartTls => 587,
+            Self::Implicit => 465,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpCredentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub credentials: Option<SmtpCredentials>,
+    // Applies to connecting and to every command sent over the connection.
+    pub timeout: Duration,
+    pub max_connections: u32,
+    // Pooled connections that sit unused for this long are closed.
+    pub idle_timeout: Duration,
+}
+
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Email,
+    timeout: Duration,
+}
+
+impl SmtpEmailClient {
+    pub fn new(settings: &SmtpSettings, sender: Email) -> Result<Self> {
+        let tls = match settings.tls {
+            SmtpTls::None => Tls::None,
+            SmtpTls::StartTls => Tls::Required(TlsParameters::new(settings.host.clone())?),
+            SmtpTls::Implicit => Tls::Wrapper(TlsParameters::new(settings.host.clone())?),
+        };
+        let pool = PoolConfig::new()
+            .max_size(settings.max_connections)
+            .idle_timeout(settings.idle_timeout);
+
+        let mut builder =
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(settings.host.as_str())
+                .port(settings.port)
+                .tls(tls)
+                .timeout(Some(settings.timeout))
+                .pool_config(pool);
+        if let Some(credentials) = &settings.credentials {
+            builder = builder.credentials(Credentials::new(
+                credentials.username.clone(),
+                credentials.password.expose_secret().clone(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+            timeout: settings.timeout,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for SmtpEmailClient {
+    #[tracing::instrument(name = "Sending email over SMTP", skip_all)]
+    async fn send_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()> {
+        let from: Mailbox = self.sender.as_ref().expose_secret().parse()?;
+        let to: Mailbox = recipient.as_ref().expose_secret().parse()?;
+
+        let email = Message::builder()
+            .from(from)
+            .to(to)
+            .subject(&message.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                message.text_body.expose_secret().clone(),
+                message.html_body.expose_secret().clone(),
+            ))?;
+
+        // lettre only times out the TCP connect, so the whole conversation is bounded here.
+        tokio::time::timeout(self.timeout, self.transport.send(email))
+            .await
+            .map_err(|_| eyre!("SMTP server timed out"))??;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+    use crate::utils::constants::test;
+
+    const USERNAME: &str = "smtp-user";
+    const PASSWORD: &str = "smtp-password";
+    // base64 of "\0smtp-user\0smtp-password", the AUTH PLAIN response for the above.
+    const AUTH_PLAIN_RESPONSE: &str = "AHNtdHAtdXNlcgBzbXRwLXBhc3N3b3Jk";
+
+    #[derive(Default)]
+    struct Received {
+        connections: usize,
+        authenticated: bool,
+        recipients: Vec<String>,
+        messages: Vec<String>,
+    }
+
+    // A bare-bones SMTP server that accepts everything except wrong credentials and
+    // recipients on `rejected.test`, and records what it was sent.
+    struct FakeSmtpServer {
+        port: u16,
+        received: Arc<Mutex<Received>>,
+    }
+
+    impl FakeSmtpServer {
+        async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let received = Arc::new(Mutex::new(Received::default()));
+
+            let state = received.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    state.lock().unwrap().connections += 1;
+                    tokio::spawn(handle_connection(stream, state.clone()));
+                }
+            });
+
+            Self { port, received }
+        }
+    }
+
+    async fn handle_connection(stream: TcpStream, received: Arc<Mutex<Received>>) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        writer
+            .write_all(reply("220 fake.test ESMTP").as_bytes())
+            .await
+            .unwrap();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let command = line.to_ascii_uppercase();
+            let response = if command.starts_with("EHLO") {
+                reply("250-fake.test\r\n250-AUTH PLAIN\r\n250 8BITMIME")
+            } else if let Some(auth) = line.strip_prefix("AUTH PLAIN ") {
+                if auth == AUTH_PLAIN_RESPONSE {
+                    received.lock().unwrap().authenticated = true;
+                    reply("235 Authentication successful")
+                } else {
+                    reply("535 Authentication failed")
+                }
+            } else if command.starts_with("RCPT TO") {
+                if command.contains("@REJECTED.TEST") {
+                    reply("550 No such user")
+                } else {
+                    received.lock().unwrap().recipients.push(line.clone());
+                    reply("250 OK")
+                }
+            } else if command == "DATA" {
+                writer
+                    .write_all(reply("354 Go ahead").as_bytes())
+                    .await
+                    .unwrap();
+                let mut data = String::new();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                received.lock().unwrap().messages.push(data);
+                reply("250 Queued")
+            } else if command == "QUIT" {
+                let _ = writer.write_all(reply("221 Bye").as_bytes()).await;
+                return;
+            } else {
+                reply("250 OK")
+            };
+
+            if writer.write_all(response.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    fn reply(line: &str) -> String {
+        line.to_owned() + "\r\n"
+    }
+
+    fn settings(port: u16) -> SmtpSettings {
+        SmtpSettings {
+            host: "127.0.0.1".to_owned(),
+            port,
+            tls: SmtpTls::None,
+            credentials: Some(SmtpCredentials {
+                username: USERNAME.to_owned(),
+                password: Secret::new(PASSWORD.to_owned()),
+            }),
+            timeout: test::email_client::TIMEOUT,
+            max_connections: 2,
+            idle_timeout: Duration::from_secs(60),
+        }
+    }
+
+    fn email(address: &str) -> Email {
+        Email::parse(Secret::new(address.to_owned())).unwrap()
+    }
+
+    fn email_client(settings: &SmtpSettings) -> SmtpEmailClient {
+        SmtpEmailClient::new(settings, email(test::email_client::SENDER)).unwrap()
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage::new(
+            "Your login code".to_owned(),
+            "<p>Your code is 123456</p>".to_owned(),
+            "Your code is 123456".to_owned(),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_both_bodies() {
+        let server = FakeSmtpServer::start().await;
+        let email_client = email_client(&settings(server.port));
+
+        let outcome = email_client
+            .send_email(&email("user@example.com"), &message())
+            .await;
+        assert!(outcome.is_ok());
+
+        let received = server.received.lock().unwrap();
+        assert!(received.authenticated);
+        assert_eq!(received.recipients, ["RCPT TO:<user@example.com>"]);
+        let data = &received.messages[0];
+        assert!(data.contains("Subject: Your login code"));
+        assert!(data.contains("From: test@email.com"));
+        assert!(data.contains("multipart/alternative"));
+        assert!(data.contains("Your code is 123456"));
+        assert!(data.contains("<p>Your code is 123456</p>"));
+    }
+
+    #[tokio::test]
+    async fn send_email_reuses_pooled_connections() {
+        let server = FakeSmtpServer::start().await;
+        let email_client = email_client(&settings(server.port));
+
+        for _ in 0..3 {
+            email_client
+                .send_email(&email("user@example.com"), &message())
+                .await
+                .unwrap();
+            // lettre returns the connection to the pool from a background task.
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        let received = server.received.lock().unwrap();
+        assert_eq!(received.messages.len(), 3);
+        assert_eq!(received.connections, 1);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_credentials_are_wrong() {
+        let server = FakeSmtpServer::start().await;
+        let mut settings = settings(server.port);
+        settings.credentials = Some(SmtpCredentials {
+            username: USERNAME.to_owned(),
+            password: Secret::new("wrong-password".to_owned()),
+        });
+        let email_client = email_client(&settings);
+
+        let outcome = email_client
+            .send_email(&email("user@example.com"), &message())
+            .await;
+
+        assert!(outcome.is_err());
+        assert!(server.received.lock().unwrap().messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_recipient_is_rejected() {
+        let server = FakeSmtpServer::start().await;
+        let email_client = email_client(&settings(server.port));
+
+        let outcome = email_client
+            .send_email(&email("user@rejected.test"), &message())
+            .await;
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_email_refuses_to_send_in_plain_text_when_starttls_is_required() {
+        let server = FakeSmtpServer::start().await;
+        let mut settings = settings(server.port);
+        settings.tls = SmtpTls::StartTls;
+        let email_client = email_client(&settings);
+
+        let outcome = email_client
+            .send_email(&email("user@example.com"), &message())
+            .await;
+
+        assert!(outcome.is_err());
+        let received = server.received.lock().unwrap();
+        assert!(!received.authenticated);
+        assert!(received.messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Accepts connections but never greets the client.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+        let email_client = email_client(&settings(port));
+
+        let outcome = tokio::time::timeout(
+            Duration::from_secs(5),
+            email_client.send_email(&email("user@example.com"), &message()),
+        )
+        .await
+        .expect("The SMTP timeout did not apply");
+
+        assert!(outcome.is_err());
+    }
+
+    #[test]
+    fn tls_mode_parses() {
+        assert_eq!(SmtpTls::parse("none").unwrap(), SmtpTls::None);
+        assert_eq!(SmtpTls::parse("starttls").unwrap(), SmtpTls::StartTls);
+        assert_eq!(SmtpTls::parse("tls").unwrap(), SmtpTls::Implicit);
+        assert!(SmtpTls::parse("ssl").is_err());
+        assert_eq!(SmtpTls::Implicit.default_port(), 465);
+    }
+}
@@ -0,0 +1,42 @@
+use color_eyre::eyre::Result;
+
+use crate::{
+    app_state::UserStoreType,
+    domain::{Email, EmailClient, EmailMessage, EmailSuppressed, UserStoreError},
+};
+
+// Refuses to send to addresses the email provider reported as undeliverable, so a bouncing
+// address doesn't keep getting mail and hurting the sender reputation. Addresses that don't
+// belong to a user, like a new address that is being confirmed, are always sent to.
+pub struct SuppressingEmailClient<C> {
+    inner: C,
+    user_store: UserStoreType,
+}
+
+impl<C> SuppressingEmailClient<C> {
+    pub fn new(inner: C, user_store: UserStoreType) -> Self {
+        Self { inner, user_store }
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: EmailClient + Send + Sync> EmailClient for SuppressingEmailClient<C> {
+    async fn send_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()> {
+        match self.user_store.get_user(recipient).await {
+            Ok(user) if user.email_undeliverable.is_some() => {
+                tracing::info!(user_id = %user.id, "suppressed email to undeliverable address");
+                metrics::counter!("emails_suppressed_total").increment(1);
+                return Err(EmailSuppressed.into());
+            }
+            Ok(_) | Err(UserStoreError::UserNotFound) => {}
+            // Better to send a mail that may bounce than to hold back a login code.
+            Err(e) => tracing::warn!("failed to check email suppression: {:?}", e),
+        }
+
+        self.inner.send_email(recipient, message).await
+    }
+
+    async fn check_health(&self) -> Result<()> {
+        self.inner.check_health().await
+    }
+}
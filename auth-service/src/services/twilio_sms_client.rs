@@ -0,0 +1,185 @@
+use color_eyre::eyre::Result;
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+
+use crate::domain::{PhoneNumber, ReloadableSecret, SmsClient};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TwilioSettings {
+    pub account_sid: String,
+    pub auth_token: ReloadableSecret,
+    // The Twilio number messages are sent from.
+    #[serde(
+        rename = "from_number",
+        deserialize_with = "crate::settings::deserialize_phone_number"
+    )]
+    pub sender: PhoneNumber,
+}
+
+// Sends through Twilio's Messages API, or anything that speaks the same protocol.
+pub struct TwilioSmsClient {
+    http_client: Client,
+    base_url: String,
+    account_sid: String,
+    auth_token: ReloadableSecret,
+    sender: PhoneNumber,
+}
+
+impl TwilioSmsClient {
+    pub fn new(
+        base_url: String,
+        account_sid: String,
+        auth_token: ReloadableSecret,
+        sender: PhoneNumber,
+        http_client: Client,
+    ) -> Self {
+        Self {
+            http_client,
+            base_url,
+            account_sid,
+            auth_token,
+            sender,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SmsClient for TwilioSmsClient {
+    #[tracing::instrument(name = "Sending SMS", skip_all)]
+    async fn send_sms(&self, recipient: &PhoneNumber, body: &Secret<String>) -> Result<()> {
+        let base = Url::parse(&self.base_url)?;
+        let url = base.join(&format!(
+            "/2010-04-01/Accounts/{}/Messages.json",
+            self.account_sid
+        ))?;
+
+        let request_body = SendSmsRequest {
+            to: recipient.as_ref().expose_secret(),
+            from: self.sender.as_ref().expose_secret(),
+            body: body.expose_secret(),
+        };
+
+        self.http_client
+            .post(url)
+            .basic_auth(
+                &self.account_sid,
+                Some(self.auth_token.load().expose_secret()),
+            )
+            .form(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+// For more information about the request structure, see the API docs:
+// https://www.twilio.com/docs/messaging/api/message-resource#create-a-message-resource
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct SendSmsRequest<'a> {
+    to: &'a str,
+    from: &'a str,
+    body: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{any, body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::utils::constants::test;
+
+    fn phone_number(number: &str) -> PhoneNumber {
+        PhoneNumber::parse(Secret::new(number.to_owned())).unwrap()
+    }
+
+    fn sms_client(base_url: String) -> TwilioSmsClient {
+        let http_client = Client::builder()
+            .timeout(test::sms_client::TIMEOUT)
+            .build()
+            .unwrap();
+        TwilioSmsClient::new(
+            base_url,
+            test::sms_client::ACCOUNT_SID.to_owned(),
+            ReloadableSecret::new(Secret::new("auth-token".to_owned())),
+            phone_number(test::sms_client::SENDER),
+            http_client,
+        )
+    }
+
+    fn body() -> Secret<String> {
+        Secret::new("Your code is 123456".to_owned())
+    }
+
+    #[tokio::test]
+    async fn send_sms_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(path(format!(
+            "/2010-04-01/Accounts/{}/Messages.json",
+            test::sms_client::ACCOUNT_SID
+        )))
+        .and(method("POST"))
+        // base64 of "AC0123456789:auth-token"
+        .and(header(
+            "Authorization",
+            "Basic QUMwMTIzNDU2Nzg5OmF1dGgtdG9rZW4=",
+        ))
+        .and(header("Content-Type", "application/x-www-form-urlencoded"))
+        .and(body_string_contains("To=%2B4915123456789"))
+        .and(body_string_contains("From=%2B15005550006"))
+        .and(body_string_contains("Body=Your+code+is+123456"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        let outcome = sms_client
+            .send_sms(&phone_number("+4915123456789"), &body())
+            .await;
+
+        assert!(outcome.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_sms_fails_if_the_server_returns_400() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client
+            .send_sms(&phone_number("+4915123456789"), &body())
+            .await;
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_sms_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(201).set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client
+            .send_sms(&phone_number("+4915123456789"), &body())
+            .await;
+
+        assert!(outcome.is_err());
+    }
+}
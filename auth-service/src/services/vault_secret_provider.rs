@@ -0,0 +1,151 @@
+use color_eyre::eyre::{Context, Result};
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::domain::SecretProvider;
+
+const VAULT_TOKEN_HEADER: &str = "X-Vault-Token";
+
+// Reads secrets from the keys of one HashiCorp Vault KV version 2 secret, e.g. the `jwt_secret`
+// key of `secret/auth-service`. The latest version is read every time.
+pub struct VaultSecretProvider {
+    http_client: Client,
+    address: String,
+    // Where the KV engine is mounted, usually `secret`.
+    mount: String,
+    path: String,
+    token: Secret<String>,
+}
+
+impl VaultSecretProvider {
+    pub fn new(
+        address: String,
+        mount: String,
+        path: String,
+        token: Secret<String>,
+        http_client: Client,
+    ) -> Self {
+        Self {
+            http_client,
+            address,
+            mount,
+            path,
+            token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SecretProvider for VaultSecretProvider {
+    #[tracing::instrument(name = "Reading secret from Vault", skip(self))]
+    async fn get_secret(&self, name: &str) -> Result<Option<Secret<String>>> {
+        let url =
+            Url::parse(&self.address)?.join(&format!("/v1/{}/data/{}", self.mount, self.path))?;
+
+        let response: ReadSecretResponse = self
+            .http_client
+            .get(url)
+            .header(VAULT_TOKEN_HEADER, self.token.expose_secret())
+            .send()
+            .await?
+            .error_for_status()
+            .wrap_err("failed to read secret from Vault")?
+            .json()
+            .await?;
+
+        Ok(response.data.data.get(name).cloned().map(Secret::new))
+    }
+}
+
+// For more information about the response, see the API docs:
+// https://developer.hashicorp.com/vault/api-docs/secret/kv/kv-v2#read-secret-version
+#[derive(Deserialize)]
+struct ReadSecretResponse {
+    data: SecretVersion,
+}
+
+#[derive(Deserialize)]
+struct SecretVersion {
+    data: HashMap<String, String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    const TOKEN: &str = "vault-token";
+
+    fn provider(address: String) -> VaultSecretProvider {
+        VaultSecretProvider::new(
+            address,
+            "secret".to_owned(),
+            "auth-service".to_owned(),
+            Secret::new(TOKEN.to_owned()),
+            Client::new(),
+        )
+    }
+
+    fn secret_response() -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "data": {
+                "data": { "jwt_secret": "secret" },
+                "metadata": { "version": 3 }
+            }
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_get_secret_reads_the_key_with_the_token() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/secret/data/auth-service"))
+            .and(header(VAULT_TOKEN_HEADER, TOKEN))
+            .respond_with(secret_response())
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let secret = provider(mock_server.uri())
+            .get_secret("jwt_secret")
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(secret.expose_secret(), "secret");
+    }
+
+    #[tokio::test]
+    async fn test_get_secret_returns_none_for_a_missing_key() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(secret_response())
+            .mount(&mock_server)
+            .await;
+
+        let secret = provider(mock_server.uri())
+            .get_secret("admin_api_token")
+            .await
+            .unwrap();
+
+        assert!(secret.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_secret_fails_if_vault_denies_access() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&mock_server)
+            .await;
+
+        let result = provider(mock_server.uri()).get_secret("jwt_secret").await;
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use reqwest::Client;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{
+    app_state::WebhookStoreType,
+    domain::{
+        data_stores::WebhookStoreError, sign_webhook_payload, WebhookDelivery, WebhookEvent,
+        WebhookSubscription,
+    },
+    services::dispatcher::{claimed_until, spawn_dispatcher, DISPATCH_BATCH_SIZE},
+    utils::shutdown::Shutdown,
+};
+
+pub const WEBHOOK_EVENT_HEADER: &str = "X-Webhook-Event";
+pub const WEBHOOK_ID_HEADER: &str = "X-Webhook-Id";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+// Queues the event for every subscription to it. Delivery happens in the background, so a
+// failure here is logged and doesn't fail the request that caused the event.
+#[tracing::instrument(name = "publish_webhook_event", skip_all)]
+pub async fn publish_webhook_event(webhook_store: &WebhookStoreType, event: WebhookEvent) {
+    if let Err(e) = webhook_store.enqueue_event(&event).await {
+        tracing::error!("failed to enqueue webhook event: {:?}", e);
+    }
+}
+
+// Sends the deliveries that are due at `now` and records how each attempt went. Returns the
+// number of deliveries attempted.
+#[tracing::instrument(name = "deliver_due_webhooks", skip_all)]
+pub async fn deliver_due_webhooks(
+    webhook_store: &WebhookStoreType,
+    http_client: &Client,
+    now: DateTime<Utc>,
+) -> Result<usize> {
+    let deliveries = webhook_store
+        .claim_due_deliveries(now, claimed_until(now)?, DISPATCH_BATCH_SIZE)
+        .await?;
+
+    for mut delivery in deliveries.iter().cloned() {
+        let subscription = match webhook_store
+            .get_subscription(&delivery.subscription_id)
+            .await
+        {
+            Ok(subscription) => subscription,
+            // Deleted since the delivery was claimed, which took the delivery with it.
+            Err(WebhookStoreError::SubscriptionNotFound) => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        match send_webhook(http_client, &subscription, &delivery).await {
+            Ok(()) => delivery.record_success(now),
+            Err(e) => {
+                tracing::warn!(delivery_id = %delivery.id, "webhook delivery failed: {}", e);
+                delivery.record_failure(e.to_string(), now);
+            }
+        }
+
+        match webhook_store.update_delivery(&delivery).await {
+            Ok(()) | Err(WebhookStoreError::DeliveryNotFound) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(deliveries.len())
+}
+
+async fn send_webhook(
+    http_client: &Client,
+    subscription: &WebhookSubscription,
+    delivery: &WebhookDelivery,
+) -> Result<()> {
+    let timestamp = Utc::now().timestamp();
+    let signature = sign_webhook_payload(&subscription.secret, timestamp, &delivery.payload);
+
+    http_client
+        .post(&subscription.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(WEBHOOK_EVENT_HEADER, delivery.event_type.as_str())
+        .header(WEBHOOK_ID_HEADER, delivery.event_id.to_string())
+        .header(WEBHOOK_TIMESTAMP_HEADER, timestamp.to_string())
+        .header(WEBHOOK_SIGNATURE_HEADER, format!("sha256={}", signature))
+        .body(delivery.payload.clone())
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+// Runs `deliver_due_webhooks` every `interval` until the service shuts down.
+pub fn spawn_webhook_dispatcher(
+    webhook_store: WebhookStoreType,
+    http_client: Client,
+    interval: Duration,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    spawn_dispatcher("webhooks", interval, shutdown, move |now| {
+        let webhook_store = webhook_store.clone();
+        let http_client = http_client.clone();
+        async move { deliver_due_webhooks(&webhook_store, &http_client, now).await }
+    })
+}
@@ -0,0 +1,1054 @@
+use arc_swap::ArcSwapOption;
+use argon2::Params;
+use axum::http::HeaderValue;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use config::{
+    builder::DefaultState, Config, ConfigBuilder, ConfigError, Environment, File, FileFormat,
+};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde::{de, Deserialize, Deserializer};
+use sqlx::postgres::PgConnectOptions;
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+use thiserror::Error;
+use webauthn_rs::prelude::Url;
+
+use crate::{
+    domain::{
+        Email, EmailDeliveryPolicy, EmailKind, EmailProvider, PhoneNumber, ReloadableSecret,
+        SecretBackend, SecretProvider, SmsProvider,
+    },
+    services::{
+        email_templates::Brand,
+        file_secret_provider::FileSecretProvider,
+        smtp_email_client::{SmtpCredentials, SmtpSettings, SmtpTls},
+        twilio_sms_client::TwilioSettings,
+        vault_secret_provider::VaultSecretProvider,
+    },
+    utils::{
+        auth::MAGIC_LINK_TTL_SECONDS,
+        constants::{env, prod},
+        tracing::LogFormat,
+        webauthn::CEREMONY_TTL_SECONDS,
+    },
+};
+
+// Compiled in, so the service starts without any file on disk.
+const DEFAULTS: &str = include_str!("../config/default.toml");
+
+// Names a TOML or YAML file that is layered over the defaults.
+const CONFIG_FILE_ENV_VAR: &str = "AUTH_CONFIG_FILE";
+
+// Any setting can be overridden with e.g. `AUTH__JWT__TOKEN_TTL_SECONDS=300`.
+const ENV_PREFIX: &str = "AUTH";
+const ENV_SEPARATOR: &str = "__";
+
+// The variables the service was configured with before it had settings files. They still work
+// and take precedence over the settings file and `AUTH__` variables. Empty values are ignored.
+// Each can also be read from the file named by the variable with a `_FILE` suffix, e.g.
+// `JWT_SECRET_FILE`, so that secrets don't show up in the environment.
+const LEGACY_ENV_VARS: [(&str, &str); 24] = [
+    (env::JWT_SECRET_ENV_VAR, "jwt.secret"),
+    (env::DATABASE_URL_ENV_VAR, "database.url"),
+    (env::DATABASE_PASSWORD_ENV_VAR, "database.password"),
+    (env::REDIS_HOST_NAME_ENV_VAR, "redis.host_name"),
+    (
+        env::POSTMARK_AUTH_TOKEN_ENV_VAR,
+        "email.postmark.auth_token",
+    ),
+    (env::AUTH_SERVICE_BASE_URL_ENV_VAR, "application.base_url"),
+    (env::ADMIN_API_TOKEN_ENV_VAR, "admin.api_token"),
+    (
+        env::POSTMARK_WEBHOOK_SECRET_ENV_VAR,
+        "email.postmark.webhook_secret",
+    ),
+    (env::EMAIL_BRAND_NAME_ENV_VAR, "email.brand.name"),
+    (
+        env::EMAIL_BRAND_SUPPORT_EMAIL_ENV_VAR,
+        "email.brand.support_email",
+    ),
+    (env::EMAIL_BRAND_COLOR_ENV_VAR, "email.brand.color"),
+    (env::SMTP_HOST_ENV_VAR, "email.smtp.host"),
+    (env::SMTP_PORT_ENV_VAR, "email.smtp.port"),
+    (env::SMTP_TLS_ENV_VAR, "email.smtp.tls"),
+    (env::SMTP_USERNAME_ENV_VAR, "email.smtp.username"),
+    (env::SMTP_PASSWORD_ENV_VAR, "email.smtp.password"),
+    (env::SMS_PROVIDER_ENV_VAR, "sms.provider"),
+    (env::TWILIO_ACCOUNT_SID_ENV_VAR, "sms.twilio.account_sid"),
+    (env::TWILIO_AUTH_TOKEN_ENV_VAR, "sms.twilio.auth_token"),
+    (env::TWILIO_FROM_NUMBER_ENV_VAR, "sms.twilio.from_number"),
+    (env::VAULT_ADDR_ENV_VAR, "secrets.vault.address"),
+    (env::LOG_FORMAT_ENV_VAR, "logging.format"),
+    (
+        env::OTEL_EXPORTER_OTLP_ENDPOINT_ENV_VAR,
+        "telemetry.otlp_endpoint",
+    ),
+    (env::VAULT_TOKEN_ENV_VAR, "secrets.vault.token"),
+];
+
+// Legacy variables holding comma-separated lists.
+const LEGACY_LIST_ENV_VARS: [(&str, &str); 2] = [
+    (env::EMAIL_PROVIDERS_ENV_VAR, "email.providers"),
+    (
+        env::SYNCHRONOUS_EMAIL_KINDS_ENV_VAR,
+        "email.synchronous_kinds",
+    ),
+];
+
+// The names secret providers know secrets by, and the settings they are for. A secret found by
+// the provider wins over every other source.
+const PROVIDED_SECRETS: [(&str, &str); 7] = [
+    ("jwt_secret", "jwt.secret"),
+    ("database_password", "database.password"),
+    ("admin_api_token", "admin.api_token"),
+    ("postmark_auth_token", "email.postmark.auth_token"),
+    ("postmark_webhook_secret", "email.postmark.webhook_secret"),
+    ("smtp_password", "email.smtp.password"),
+    ("twilio_auth_token", "sms.twilio.auth_token"),
+];
+
+// Settings whose `AUTH__` variables are comma-separated lists.
+const LIST_KEYS: [&str; 3] = [
+    "application.allowed_origins",
+    "email.providers",
+    "email.synchronous_kinds",
+];
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("Failed to load settings: {0}")]
+    Load(#[from] ConfigError),
+    #[error("Invalid setting `{key}`: {reason}")]
+    Invalid { key: &'static str, reason: String },
+    #[error("Failed to read `{var}` from {path}: {error}")]
+    EnvFile {
+        var: String,
+        path: String,
+        error: std::io::Error,
+    },
+    #[error("Failed to read secret `{name}`: {error:#}")]
+    Secret {
+        name: &'static str,
+        error: color_eyre::Report,
+    },
+}
+
+impl SettingsError {
+    fn invalid(key: &'static str, reason: impl Into<String>) -> Self {
+        Self::Invalid {
+            key,
+            reason: reason.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Settings {
+    pub application: ApplicationSettings,
+    pub database: DatabaseSettings,
+    pub redis: RedisSettings,
+    pub jwt: JwtSettings,
+    pub admin: AdminSettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub email: EmailSettings,
+    pub sms: SmsSettings,
+    pub health: HealthSettings,
+    pub logging: LoggingSettings,
+    pub telemetry: TelemetrySettings,
+    pub secrets: SecretsSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApplicationSettings {
+    pub address: String,
+    // Public address of this service, used to build links that are sent out by email.
+    pub base_url: String,
+    // Origins allowed to call the service from a browser.
+    pub allowed_origins: Vec<String>,
+    // How long the service keeps accepting connections after SIGTERM while readiness fails.
+    pub shutdown_drain_delay_seconds: u64,
+    // How long in-flight requests and background work get to finish after that.
+    pub shutdown_timeout_seconds: u64,
+}
+
+impl ApplicationSettings {
+    pub fn shutdown_drain_delay(&self) -> Duration {
+        Duration::from_secs(self.shutdown_drain_delay_seconds)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_seconds)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DatabaseSettings {
+    pub url: Secret<String>,
+    // Replaces the password in the url, so the url itself doesn't have to be kept secret.
+    pub password: Option<Secret<String>>,
+}
+
+impl DatabaseSettings {
+    pub fn connect_options(&self) -> Result<PgConnectOptions, sqlx::Error> {
+        let options = PgConnectOptions::from_str(self.url.expose_secret())?;
+        Ok(match &self.password {
+            Some(password) => options.password(password.expose_secret()),
+            None => options,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RedisSettings {
+    pub host_name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtSettings {
+    pub secret: ReloadableSecret,
+    pub token_ttl_seconds: i64,
+    // The secret the last reload replaced, and until when tokens signed with it can be valid.
+    #[serde(skip)]
+    previous_secret: Arc<ArcSwapOption<(Secret<String>, DateTime<Utc>)>>,
+}
+
+impl JwtSettings {
+    pub fn new(secret: Secret<String>, token_ttl_seconds: i64) -> Self {
+        Self {
+            secret: secret.into(),
+            token_ttl_seconds,
+            previous_secret: Arc::default(),
+        }
+    }
+
+    // Replaces the signing secret. Tokens signed with the old one are still accepted until the
+    // longest-lived of them would have expired, so a rotation doesn't log everyone out.
+    pub fn rotate_secret(&self, secret: Arc<Secret<String>>, now: DateTime<Utc>) {
+        let current = self.secret.load();
+        if current.expose_secret() == secret.expose_secret() {
+            return;
+        }
+
+        let ttl = self
+            .token_ttl_seconds
+            .max(MAGIC_LINK_TTL_SECONDS)
+            .max(CEREMONY_TTL_SECONDS);
+        let valid_until = now + chrono::Duration::seconds(ttl);
+        self.previous_secret
+            .store(Some(Arc::new(((*current).clone(), valid_until))));
+        self.secret.store(secret);
+    }
+
+    // The secret replaced by the last rotation, while tokens signed with it can still be valid.
+    pub fn previous_secret(&self, now: DateTime<Utc>) -> Option<Secret<String>> {
+        self.previous_secret
+            .load()
+            .as_ref()
+            .filter(|previous| now < previous.1)
+            .map(|previous| previous.0.clone())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdminSettings {
+    // Bearer token for the admin endpoints, which are disabled without one.
+    pub api_token: Option<ReloadableSecret>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordHashingSettings {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl PasswordHashingSettings {
+    // The parameters are checked when the settings are loaded.
+    pub fn params(&self) -> Params {
+        Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .expect("Invalid password hashing parameters")
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmailSettings {
+    #[serde(deserialize_with = "deserialize_email_providers")]
+    pub providers: Vec<EmailProvider>,
+    #[serde(deserialize_with = "deserialize_sender")]
+    pub sender: Email,
+    #[serde(
+        rename = "synchronous_kinds",
+        deserialize_with = "deserialize_delivery_policy"
+    )]
+    pub delivery_policy: EmailDeliveryPolicy,
+    pub brand: Brand,
+    pub postmark: PostmarkSettings,
+    pub smtp: SmtpServerSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PostmarkSettings {
+    pub auth_token: Option<ReloadableSecret>,
+    // Shared secret Postmark sends with its webhooks, which are rejected without one.
+    pub webhook_secret: Option<ReloadableSecret>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SmtpServerSettings {
+    pub host: Option<String>,
+    // Defaults to the usual port for the TLS mode.
+    pub port: Option<u16>,
+    #[serde(deserialize_with = "deserialize_smtp_tls")]
+    pub tls: SmtpTls,
+    // Authentication is skipped when no username is set.
+    pub username: Option<String>,
+    // The connection pool logs in once per connection, so a new password needs a restart.
+    pub password: Option<Secret<String>>,
+}
+
+impl SmtpServerSettings {
+    // None unless a host is set, which is checked when smtp is one of the providers.
+    pub fn smtp_settings(&self) -> Option<SmtpSettings> {
+        let host = self.host.clone()?;
+        let credentials = self.username.clone().map(|username| SmtpCredentials {
+            username,
+            password: self.password.clone().unwrap_or(Secret::new(String::new())),
+        });
+
+        Some(SmtpSettings {
+            host,
+            port: self.port.unwrap_or(self.tls.default_port()),
+            tls: self.tls,
+            credentials,
+            timeout: prod::smtp::TIMEOUT,
+            max_connections: prod::smtp::MAX_CONNECTIONS,
+            idle_timeout: prod::smtp::IDLE_TIMEOUT,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SmsSettings {
+    #[serde(deserialize_with = "deserialize_sms_provider")]
+    pub provider: SmsProvider,
+    // Only read when the provider is twilio.
+    pub twilio: Option<TwilioSettings>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HealthSettings {
+    // Whether readiness also depends on an email provider being available.
+    pub check_email_provider: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoggingSettings {
+    #[serde(deserialize_with = "deserialize_log_format")]
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelemetrySettings {
+    // Base address of an OTLP/HTTP collector, e.g. `http://otel-collector:4318`. Spans aren't
+    // exported without one.
+    pub otlp_endpoint: Option<String>,
+}
+
+impl TelemetrySettings {
+    pub fn otlp_traces_endpoint(&self) -> Option<String> {
+        self.otlp_endpoint
+            .as_ref()
+            .map(|endpoint| format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SecretsSettings {
+    #[serde(deserialize_with = "deserialize_secret_backend")]
+    pub backend: SecretBackend,
+    // Read by the file backend, which expects one file per secret.
+    pub directory: String,
+    pub vault: VaultSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VaultSettings {
+    pub address: Option<String>,
+    // Where the KV version 2 engine is mounted.
+    pub mount: String,
+    // The secret whose keys are the secret names, e.g. `jwt_secret`.
+    pub path: String,
+    pub token: Option<Secret<String>>,
+}
+
+impl SecretsSettings {
+    // None for the env backend, which has nothing to add to what the settings already hold.
+    pub fn provider(&self) -> Result<Option<Box<dyn SecretProvider + Send + Sync>>, SettingsError> {
+        match self.backend {
+            SecretBackend::Env => Ok(None),
+            SecretBackend::File => Ok(Some(Box::new(FileSecretProvider::new(&self.directory)))),
+            SecretBackend::Vault => {
+                let vault = &self.vault;
+                let address = vault.address.clone().ok_or(SettingsError::invalid(
+                    "secrets.vault.address",
+                    "must be set when the secret backend is vault",
+                ))?;
+                let token = vault.token.clone().ok_or(SettingsError::invalid(
+                    "secrets.vault.token",
+                    "must be set when the secret backend is vault",
+                ))?;
+                let http_client = Client::builder()
+                    .timeout(prod::vault::TIMEOUT)
+                    .build()
+                    .map_err(|e| SettingsError::Secret {
+                        name: "secrets.vault",
+                        error: e.into(),
+                    })?;
+
+                Ok(Some(Box::new(VaultSecretProvider::new(
+                    address,
+                    vault.mount.clone(),
+                    vault.path.clone(),
+                    token,
+                    http_client,
+                ))))
+            }
+        }
+    }
+}
+
+impl Settings {
+    // Reads the settings from the defaults, the optional settings file, the environment,
+    // including a `.env` file, and the secret backend.
+    pub async fn load() -> Result<Self, SettingsError> {
+        dotenvy::dotenv().ok();
+        Self::from_env(std::env::vars().collect()).await
+    }
+
+    // Like `load`, but with the given variables in place of the process environment.
+    pub async fn from_env(vars: HashMap<String, String>) -> Result<Self, SettingsError> {
+        let mut builder = Self::builder(&vars)?;
+
+        // The secret backend is configured like everything else, so it can only be asked once
+        // the other sources have been read.
+        let secrets: SecretsSettings = builder.build_cloned()?.get("secrets")?;
+        if let Some(provider) = secrets.provider()? {
+            for (name, key) in PROVIDED_SECRETS {
+                let secret = provider
+                    .get_secret(name)
+                    .await
+                    .map_err(|error| SettingsError::Secret { name, error })?;
+                if let Some(secret) = secret {
+                    builder = builder.set_override(key, secret.expose_secret().to_owned())?;
+                }
+            }
+        }
+
+        let mut settings: Settings = builder.build()?.try_deserialize()?;
+        if settings.email.brand.support_email.is_empty() {
+            settings.email.brand.support_email =
+                settings.email.sender.as_ref().expose_secret().to_owned();
+        }
+        // An empty endpoint, e.g. from a compose default, turns exporting off.
+        if settings.telemetry.otlp_endpoint.as_deref() == Some("") {
+            settings.telemetry.otlp_endpoint = None;
+        }
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    fn builder(
+        vars: &HashMap<String, String>,
+    ) -> Result<ConfigBuilder<DefaultState>, SettingsError> {
+        let mut builder = Config::builder().add_source(File::from_str(DEFAULTS, FileFormat::Toml));
+
+        if let Some(path) = non_empty(vars, CONFIG_FILE_ENV_VAR) {
+            builder = builder.add_source(File::with_name(&path).required(true));
+        }
+
+        builder = builder.add_source(
+            Environment::with_prefix(ENV_PREFIX)
+                .separator(ENV_SEPARATOR)
+                .source(Some(vars.clone())),
+        );
+
+        // The config crate only splits lists when it also parses every other variable into
+        // numbers and booleans, which would mangle secrets and phone numbers.
+        for key in LIST_KEYS {
+            let var = format!(
+                "{ENV_PREFIX}{ENV_SEPARATOR}{}",
+                key.replace('.', ENV_SEPARATOR).to_uppercase()
+            );
+            builder = builder.set_override_option(key, non_empty(vars, &var).map(split_list))?;
+        }
+
+        for (var, key) in LEGACY_ENV_VARS {
+            builder = builder.set_override_option(key, env_value(vars, var, key)?)?;
+        }
+        for (var, key) in LEGACY_LIST_ENV_VARS {
+            builder = builder.set_override_option(key, non_empty(vars, var).map(split_list))?;
+        }
+
+        Ok(builder)
+    }
+
+    // Copies the secrets of freshly loaded settings into these ones. Clones of these settings
+    // share the secrets, so the running service picks them up. A secret that was added or
+    // removed only takes effect after a restart, as do the other settings. So does the SMTP
+    // password, since pooled connections have already logged in with the old one.
+    pub fn reload_secrets(&self, fresh: &Settings) {
+        self.jwt.rotate_secret(fresh.jwt.secret.load(), Utc::now());
+        reload_secret(
+            "admin.api_token",
+            &self.admin.api_token,
+            &fresh.admin.api_token,
+        );
+        reload_secret(
+            "email.postmark.auth_token",
+            &self.email.postmark.auth_token,
+            &fresh.email.postmark.auth_token,
+        );
+        reload_secret(
+            "email.postmark.webhook_secret",
+            &self.email.postmark.webhook_secret,
+            &fresh.email.postmark.webhook_secret,
+        );
+        reload_secret(
+            "sms.twilio.auth_token",
+            &self
+                .sms
+                .twilio
+                .as_ref()
+                .map(|twilio| twilio.auth_token.clone()),
+            &fresh
+                .sms
+                .twilio
+                .as_ref()
+                .map(|twilio| twilio.auth_token.clone()),
+        );
+        if self
+            .email
+            .smtp
+            .password
+            .as_ref()
+            .map(ExposeSecret::expose_secret)
+            != fresh
+                .email
+                .smtp
+                .password
+                .as_ref()
+                .map(ExposeSecret::expose_secret)
+        {
+            tracing::warn!("`email.smtp.password` changed, restart to apply the change");
+        }
+    }
+
+    // Checks what deserializing can't: required values, and settings that depend on others.
+    fn validate(&self) -> Result<(), SettingsError> {
+        if self.jwt.secret.load().expose_secret().is_empty() {
+            return Err(SettingsError::invalid("jwt.secret", "must be set"));
+        }
+        if self.jwt.token_ttl_seconds <= 0 {
+            return Err(SettingsError::invalid(
+                "jwt.token_ttl_seconds",
+                "must be positive",
+            ));
+        }
+        if self.database.url.expose_secret().is_empty() {
+            return Err(SettingsError::invalid("database.url", "must be set"));
+        }
+        self.database
+            .connect_options()
+            .map_err(|e| SettingsError::invalid("database.url", e.to_string()))?;
+
+        let base_url = Url::parse(&self.application.base_url)
+            .map_err(|e| SettingsError::invalid("application.base_url", e.to_string()))?;
+        if base_url.host_str().is_none() {
+            return Err(SettingsError::invalid(
+                "application.base_url",
+                "must have a host",
+            ));
+        }
+        for origin in &self.application.allowed_origins {
+            origin.parse::<HeaderValue>().map_err(|_| {
+                SettingsError::invalid(
+                    "application.allowed_origins",
+                    format!("`{origin}` is not a valid origin"),
+                )
+            })?;
+        }
+
+        let hashing = &self.password_hashing;
+        Params::new(
+            hashing.memory_kib,
+            hashing.iterations,
+            hashing.parallelism,
+            None,
+        )
+        .map_err(|e| SettingsError::invalid("password_hashing", e.to_string()))?;
+
+        let email = &self.email;
+        if email.providers.is_empty() {
+            return Err(SettingsError::invalid(
+                "email.providers",
+                "must name at least one provider",
+            ));
+        }
+        if email.providers.contains(&EmailProvider::Postmark) && email.postmark.auth_token.is_none()
+        {
+            return Err(SettingsError::invalid(
+                "email.postmark.auth_token",
+                "must be set when postmark is an email provider",
+            ));
+        }
+        if email.providers.contains(&EmailProvider::Smtp) {
+            if email.smtp.host.is_none() {
+                return Err(SettingsError::invalid(
+                    "email.smtp.host",
+                    "must be set when smtp is an email provider",
+                ));
+            }
+            if email.smtp.username.is_some() && email.smtp.password.is_none() {
+                return Err(SettingsError::invalid(
+                    "email.smtp.password",
+                    "must be set when email.smtp.username is",
+                ));
+            }
+        }
+
+        if self.sms.provider == SmsProvider::Twilio && self.sms.twilio.is_none() {
+            return Err(SettingsError::invalid(
+                "sms.twilio",
+                "must be set when the SMS provider is twilio",
+            ));
+        }
+
+        if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+            let endpoint = Url::parse(endpoint)
+                .map_err(|e| SettingsError::invalid("telemetry.otlp_endpoint", e.to_string()))?;
+            if !matches!(endpoint.scheme(), "http" | "https") {
+                return Err(SettingsError::invalid(
+                    "telemetry.otlp_endpoint",
+                    "must be an http or https address",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn non_empty(vars: &HashMap<String, String>, name: &str) -> Option<String> {
+    vars.get(name).filter(|value| !value.is_empty()).cloned()
+}
+
+// The value of `var`, or the contents of the file named by `var` with a `_FILE` suffix.
+fn env_value(
+    vars: &HashMap<String, String>,
+    var: &str,
+    key: &'static str,
+) -> Result<Option<String>, SettingsError> {
+    let file_var = format!("{var}_FILE");
+    match (non_empty(vars, var), non_empty(vars, &file_var)) {
+        (Some(_), Some(_)) => Err(SettingsError::invalid(
+            key,
+            format!("only one of {var} and {file_var} can be set"),
+        )),
+        (value, None) => Ok(value),
+        (None, Some(path)) => std::fs::read_to_string(&path)
+            // Editors and `echo` leave a trailing newline, which is never part of the value.
+            .map(|value| Some(value.trim_end().to_owned()))
+            .map_err(|error| SettingsError::EnvFile {
+                var: file_var,
+                path,
+                error,
+            }),
+    }
+}
+
+fn reload_secret(key: &str, current: &Option<ReloadableSecret>, fresh: &Option<ReloadableSecret>) {
+    match (current, fresh) {
+        (Some(current), Some(fresh)) => current.store(fresh.load()),
+        (None, None) => {}
+        _ => tracing::warn!(
+            "`{}` was added or removed, restart to apply the change",
+            key
+        ),
+    }
+}
+
+fn split_list(value: String) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+// The config crate doesn't say which key a custom error is for, so every message names it.
+fn parse_with<'de, D, T>(
+    deserializer: D,
+    parse: impl Fn(&str) -> Result<T>,
+    message: &str,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse(value.trim()).map_err(|_| de::Error::custom(format!("{message}, got `{value}`")))
+}
+
+fn parse_list_with<'de, D, T>(
+    deserializer: D,
+    parse: impl Fn(&str) -> Result<T>,
+    message: &str,
+) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|value| {
+            parse(value.trim()).map_err(|_| de::Error::custom(format!("{message}, got `{value}`")))
+        })
+        .collect()
+}
+
+fn deserialize_email_providers<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<EmailProvider>, D::Error> {
+    parse_list_with(
+        deserializer,
+        EmailProvider::parse,
+        "email.providers must be a list of postmark, smtp or mock",
+    )
+}
+
+fn deserialize_sender<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Email, D::Error> {
+    parse_with(
+        deserializer,
+        |sender| Email::parse(Secret::new(sender.to_owned())),
+        "email.sender must be an email address",
+    )
+}
+
+fn deserialize_delivery_policy<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<EmailDeliveryPolicy, D::Error> {
+    parse_list_with(
+        deserializer,
+        EmailKind::parse,
+        "email.synchronous_kinds must be a list of email kinds",
+    )
+    .map(EmailDeliveryPolicy::new)
+}
+
+fn deserialize_smtp_tls<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SmtpTls, D::Error> {
+    parse_with(
+        deserializer,
+        SmtpTls::parse,
+        "email.smtp.tls must be one of none, starttls or tls",
+    )
+}
+
+fn deserialize_secret_backend<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<SecretBackend, D::Error> {
+    parse_with(
+        deserializer,
+        SecretBackend::parse,
+        "secrets.backend must be one of env, file or vault",
+    )
+}
+
+fn deserialize_log_format<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<LogFormat, D::Error> {
+    parse_with(
+        deserializer,
+        LogFormat::parse,
+        "logging.format must be compact or json",
+    )
+}
+
+fn deserialize_sms_provider<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<SmsProvider, D::Error> {
+    parse_with(
+        deserializer,
+        SmsProvider::parse,
+        "sms.provider must be twilio or mock",
+    )
+}
+
+pub(crate) fn deserialize_phone_number<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PhoneNumber, D::Error> {
+    parse_with(
+        deserializer,
+        |number| PhoneNumber::parse(Secret::new(number.to_owned())),
+        "sms.twilio.from_number must be a phone number in E.164 format",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn required() -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("JWT_SECRET", "secret"),
+            ("DATABASE_URL", "postgres://localhost"),
+            ("POSTMARK_AUTH_TOKEN", "token"),
+        ]
+    }
+
+    fn secrets_directory() -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir(&directory).unwrap();
+        directory
+    }
+
+    #[tokio::test]
+    async fn test_defaults() {
+        let settings = Settings::from_env(vars(&required())).await.unwrap();
+        assert_eq!(settings.application.address, "0.0.0.0:3000");
+        assert_eq!(settings.jwt.token_ttl_seconds, 600);
+        assert_eq!(settings.email.providers, vec![EmailProvider::Postmark]);
+        assert_eq!(
+            settings.email.delivery_policy,
+            EmailDeliveryPolicy::default()
+        );
+        assert_eq!(settings.email.brand.support_email, "bogdan@codeiron.io");
+        assert_eq!(settings.sms.provider, SmsProvider::Mock);
+        assert!(settings.admin.api_token.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_prefixed_env_vars_override_defaults() {
+        let mut pairs = required();
+        pairs.extend([
+            ("AUTH__JWT__TOKEN_TTL_SECONDS", "300"),
+            ("AUTH__EMAIL__PROVIDERS", "mock,postmark"),
+            (
+                "AUTH__APPLICATION__ALLOWED_ORIGINS",
+                "http://a:8000,http://b:8000",
+            ),
+        ]);
+        let settings = Settings::from_env(vars(&pairs)).await.unwrap();
+        assert_eq!(settings.jwt.token_ttl_seconds, 300);
+        assert_eq!(
+            settings.email.providers,
+            vec![EmailProvider::Mock, EmailProvider::Postmark]
+        );
+        assert_eq!(
+            settings.application.allowed_origins,
+            vec!["http://a:8000", "http://b:8000"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_legacy_env_vars_win_and_ignore_empty_values() {
+        let mut pairs = required();
+        pairs.extend([
+            ("AUTH__REDIS__HOST_NAME", "prefixed"),
+            ("REDIS_HOST_NAME", "legacy"),
+            ("ADMIN_API_TOKEN", ""),
+            ("SYNCHRONOUS_EMAIL_KINDS", "two_fa_code, magic_link"),
+        ]);
+        let settings = Settings::from_env(vars(&pairs)).await.unwrap();
+        assert_eq!(settings.redis.host_name, "legacy");
+        assert!(settings.admin.api_token.is_none());
+        assert_eq!(
+            settings.email.delivery_policy,
+            EmailDeliveryPolicy::new(vec![EmailKind::TwoFACode, EmailKind::MagicLink])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_logging_and_telemetry_come_from_the_settings() {
+        let settings = Settings::from_env(vars(&required())).await.unwrap();
+        assert_eq!(settings.logging.format, LogFormat::Compact);
+        assert_eq!(settings.telemetry.otlp_traces_endpoint(), None);
+
+        let mut pairs = required();
+        pairs.extend([
+            ("LOG_FORMAT", "json"),
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://otel-collector:4318/"),
+        ]);
+        let settings = Settings::from_env(vars(&pairs)).await.unwrap();
+        assert_eq!(settings.logging.format, LogFormat::Json);
+        assert_eq!(
+            settings.telemetry.otlp_traces_endpoint().as_deref(),
+            Some("http://otel-collector:4318/v1/traces")
+        );
+
+        let mut pairs = required();
+        pairs.push(("AUTH__TELEMETRY__OTLP_ENDPOINT", ""));
+        let settings = Settings::from_env(vars(&pairs)).await.unwrap();
+        assert_eq!(settings.telemetry.otlp_endpoint, None);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_logging_and_telemetry_are_rejected() {
+        let mut pairs = required();
+        pairs.push(("LOG_FORMAT", "pretty"));
+        let error = Settings::from_env(vars(&pairs)).await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("logging.format must be compact or json, got `pretty`"));
+
+        let mut pairs = required();
+        pairs.push(("AUTH__TELEMETRY__OTLP_ENDPOINT", "otel-collector:4318"));
+        let error = Settings::from_env(vars(&pairs)).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid setting `telemetry.otlp_endpoint`: must be an http or https address"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_jwt_secret_is_rejected() {
+        let pairs: Vec<_> = required()
+            .into_iter()
+            .filter(|(name, _)| *name != "JWT_SECRET")
+            .collect();
+        let error = Settings::from_env(vars(&pairs)).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid setting `jwt.secret`: must be set"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_smtp_provider_requires_a_host() {
+        let mut pairs = required();
+        pairs.push(("EMAIL_PROVIDERS", "smtp"));
+        let error = Settings::from_env(vars(&pairs)).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid setting `email.smtp.host`: must be set when smtp is an email provider"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_provider_names_the_setting() {
+        let mut pairs = required();
+        pairs.push(("EMAIL_PROVIDERS", "carrier-pigeon"));
+        let error = Settings::from_env(vars(&pairs)).await.unwrap_err();
+        assert!(error.to_string().contains("email.providers"));
+    }
+
+    #[tokio::test]
+    async fn test_file_variants_are_read_and_exclusive() {
+        let directory = secrets_directory();
+        let path = directory.join("jwt_secret");
+        std::fs::write(&path, "from-file\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let mut pairs: Vec<_> = required()
+            .into_iter()
+            .filter(|(name, _)| *name != "JWT_SECRET")
+            .collect();
+        pairs.push(("JWT_SECRET_FILE", path));
+        let settings = Settings::from_env(vars(&pairs)).await.unwrap();
+        assert_eq!(settings.jwt.secret.load().expose_secret(), "from-file");
+
+        let mut pairs = required();
+        pairs.push(("JWT_SECRET_FILE", path));
+        let error = Settings::from_env(vars(&pairs)).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid setting `jwt.secret`: only one of JWT_SECRET and JWT_SECRET_FILE can be set"
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_secrets_win_over_the_environment() {
+        let directory = secrets_directory();
+        std::fs::write(directory.join("jwt_secret"), "provided").unwrap();
+        std::fs::write(directory.join("database_password"), "password").unwrap();
+
+        let mut pairs = required();
+        pairs.extend([
+            ("AUTH__SECRETS__BACKEND", "file"),
+            ("AUTH__SECRETS__DIRECTORY", directory.to_str().unwrap()),
+        ]);
+        let settings = Settings::from_env(vars(&pairs)).await.unwrap();
+        assert_eq!(settings.jwt.secret.load().expose_secret(), "provided");
+        assert_eq!(
+            settings.database.password.unwrap().expose_secret(),
+            "password"
+        );
+        assert_eq!(
+            settings
+                .email
+                .postmark
+                .auth_token
+                .unwrap()
+                .load()
+                .expose_secret(),
+            "token"
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_vault_backend_requires_an_address() {
+        let mut pairs = required();
+        pairs.push(("AUTH__SECRETS__BACKEND", "vault"));
+        let error = Settings::from_env(vars(&pairs)).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid setting `secrets.vault.address`: must be set when the secret backend is vault"
+        );
+    }
+
+    #[test]
+    fn test_rotated_jwt_secret_is_kept_until_its_tokens_expire() {
+        let jwt = JwtSettings::new(Secret::new("old".to_owned()), 600);
+        let now = Utc::now();
+
+        jwt.rotate_secret(Arc::new(Secret::new("new".to_owned())), now);
+        assert_eq!(jwt.secret.load().expose_secret(), "new");
+        assert_eq!(
+            jwt.previous_secret(now + chrono::Duration::seconds(599))
+                .unwrap()
+                .expose_secret(),
+            "old"
+        );
+        assert!(jwt
+            .previous_secret(now + chrono::Duration::seconds(600))
+            .is_none());
+
+        // Reloading an unchanged secret keeps the secret it replaced.
+        jwt.rotate_secret(Arc::new(Secret::new("new".to_owned())), now);
+        assert_eq!(jwt.previous_secret(now).unwrap().expose_secret(), "old");
+    }
+
+    #[tokio::test]
+    async fn test_reload_secrets_is_seen_by_clones() {
+        let settings = Settings::from_env(vars(&required())).await.unwrap();
+        let clone = settings.clone();
+
+        let mut pairs = required();
+        pairs[0] = ("JWT_SECRET", "rotated");
+        let fresh = Settings::from_env(vars(&pairs)).await.unwrap();
+        settings.reload_secrets(&fresh);
+
+        assert_eq!(clone.jwt.secret.load().expose_secret(), "rotated");
+    }
+}
@@ -0,0 +1,139 @@
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{header, request::Parts, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuditEventType, AuditOutcome, NewAuditEvent, UserId},
+    utils::problem::ProblemDetails,
+};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Handlers report the user a request acted on through this slot, once they know who it is.
+// Requests that never get that far, like a login with an unknown email, are recorded without
+// a user.
+#[derive(Clone, Default)]
+pub struct AuditUser(Arc<Mutex<Option<UserId>>>);
+
+impl AuditUser {
+    pub fn set(&self, user_id: &UserId) {
+        if let Ok(mut slot) = self.0.lock() {
+            *slot = Some(*user_id);
+        }
+    }
+
+    fn get(&self) -> Option<UserId> {
+        self.0.lock().ok().and_then(|slot| *slot)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuditUser {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_default())
+    }
+}
+
+// Why a request failed, attached to error responses so it ends up in the audit log.
+#[derive(Clone, Copy)]
+pub struct AuditReason(pub &'static str);
+
+// Records an audit event of the given type for every request to the route it's layered on.
+// `Application::build` gives every route in `routes/` its own type; pages and static assets
+// aren't recorded. A failure to write the event is logged but doesn't fail the request.
+pub async fn record_audit_event(
+    State((state, event_type)): State<(AppState, AuditEventType)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let audit_user = AuditUser::default();
+    request.extensions_mut().insert(audit_user.clone());
+
+    let ip_address = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip().to_string());
+    let user_agent = header_value(&request, header::USER_AGENT.as_str());
+    let request_id = header_value(&request, REQUEST_ID_HEADER);
+
+    let response = next.run(request).await;
+
+    let status = response.status();
+    let outcome = match status {
+        StatusCode::PARTIAL_CONTENT => AuditOutcome::Pending,
+        status if status.is_success() => AuditOutcome::Success,
+        _ => AuditOutcome::Failure,
+    };
+    record_login_metric(event_type, outcome, &response);
+
+    let reason = match response.extensions().get::<AuditReason>() {
+        Some(AuditReason(reason)) => Some(reason.to_string()),
+        None if !status.is_success() => status.canonical_reason().map(str::to_owned),
+        None => None,
+    };
+
+    let event = NewAuditEvent {
+        event_type,
+        user_id: audit_user.get(),
+        ip_address,
+        user_agent,
+        request_id,
+        outcome,
+        reason,
+    };
+
+    if let Err(e) = state.audit_log_store.append(event).await {
+        tracing::error!("failed to record audit event: {:?}", e);
+    }
+
+    response
+}
+
+// Counts the outcome of every way to log in, labeled with the problem `code` of failures. A
+// login that still needs its second factor is `pending`.
+fn record_login_metric(event_type: AuditEventType, outcome: AuditOutcome, response: &Response) {
+    let is_login = matches!(
+        event_type,
+        AuditEventType::Login
+            | AuditEventType::Verify2FA
+            | AuditEventType::MagicLinkLogin
+            | AuditEventType::PasskeyLogin
+            | AuditEventType::PasskeyVerify2FA
+    );
+    if !is_login {
+        return;
+    }
+
+    let reason = response
+        .extensions()
+        .get::<ProblemDetails>()
+        .map(|problem| problem.code.clone())
+        .unwrap_or_default();
+    metrics::counter!(
+        "logins_total",
+        "method" => event_type.as_str(),
+        "outcome" => outcome.as_str(),
+        "reason" => reason
+    )
+    .increment(1);
+}
+
+fn header_value(request: &Request, name: &str) -> Option<String> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
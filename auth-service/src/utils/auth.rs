@@ -1,18 +1,21 @@
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use chrono::Utc;
 use color_eyre::eyre::{eyre, Context, ContextCompat, Result};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
-use secrecy::ExposeSecret;
-use secrecy::Secret;
-use serde::{Deserialize, Serialize};
+use jsonwebtoken::{decode, encode, errors::ErrorKind, DecodingKey, EncodingKey, Validation};
+use secrecy::{ExposeSecret, Secret};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{app_state::BannedTokenStoreType, domain::email::Email};
+use crate::{
+    app_state::{BannedTokenStoreType, SessionStoreType},
+    domain::{email::Email, UserId},
+    settings::JwtSettings,
+};
 
-use super::constants::{JWT_COOKIE_NAME, JWT_SECRET};
+use super::constants::JWT_COOKIE_NAME;
 
 #[tracing::instrument(name = "generate_auth_cookie", skip_all)]
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email)?;
+pub fn generate_auth_cookie(user_id: &UserId, jwt: &JwtSettings) -> Result<Cookie<'static>> {
+    let token = generate_auth_token(user_id, jwt)?;
     Ok(create_auth_cookie(token))
 }
 
@@ -33,16 +36,14 @@ pub enum GenerateTokenError {
     UnexpectedError,
 }
 
-pub const TOKEN_TTL_SECONDS: i64 = 600;
-
 #[tracing::instrument(name = "generate_auth_token", skip_all)]
-fn generate_auth_token(email: &Email) -> Result<String> {
-    let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
-        .wrap_err("failed to create 10 minute time delta")?;
+fn generate_auth_token(user_id: &UserId, jwt: &JwtSettings) -> Result<String> {
+    let delta = chrono::Duration::try_seconds(jwt.token_ttl_seconds)
+        .wrap_err("failed to create token time delta")?;
 
     let exp = Utc::now()
         .checked_add_signed(delta)
-        .ok_or(eyre!("failed to add 10 minutes to current time"))?
+        .ok_or(eyre!("failed to add token ttl to current time"))?
         .timestamp();
     //    let exp = Utc::now()
     //        .checked_add_signed(delta)
@@ -57,11 +58,16 @@ fn generate_auth_token(email: &Email) -> Result<String> {
         exp
     ))?;
 
-    let sub = email.as_ref().expose_secret().to_owned();
+    // The subject is the user id rather than the email, so tokens carry no personal data.
+    let sub = user_id.to_string();
+
+    // A unique id keeps two tokens issued to the same user in the same second apart,
+    // so one session can be revoked without the other.
+    let jti = uuid::Uuid::new_v4().to_string();
 
-    let claims = Claims { sub, exp };
+    let claims = Claims { sub, exp, jti };
 
-    create_token(&claims)
+    create_token(&claims, jwt)
     //    create_token(&claims).map_err(GenerateTokenError::TokenError)
 }
 
@@ -69,8 +75,9 @@ fn generate_auth_token(email: &Email) -> Result<String> {
 pub async fn validate_token(
     token: &str,
     banned_token_store: BannedTokenStoreType,
+    jwt: &JwtSettings,
 ) -> Result<Claims> {
-    match banned_token_store.read().await.contains_token(token).await {
+    match banned_token_store.contains_token(token).await {
         Ok(value) => {
             if value {
                 return Err(eyre!("token is banned"));
@@ -87,47 +94,291 @@ pub async fn validate_token(
         //        }
     }
 
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
-        &Validation::default(),
+    decode_token::<Claims>(token, jwt, &Validation::default()).wrap_err("failed to decode token")
+}
+
+// Issues an auth cookie and records its token as one of the user's sessions.
+#[tracing::instrument(name = "start_session", skip_all)]
+pub async fn start_session(
+    user_id: &UserId,
+    session_store: SessionStoreType,
+    jwt: &JwtSettings,
+) -> Result<Cookie<'static>> {
+    let cookie = generate_auth_cookie(user_id, jwt)?;
+
+    session_store
+        .add_session(user_id, cookie.value().to_owned())
+        .await?;
+
+    Ok(cookie)
+}
+
+// Bans every token issued to the user except `current_token`, which stays the only session.
+#[tracing::instrument(name = "revoke_other_sessions", skip_all)]
+pub async fn revoke_other_sessions(
+    user_id: &UserId,
+    current_token: &str,
+    session_store: SessionStoreType,
+    banned_token_store: BannedTokenStoreType,
+) -> Result<()> {
+    revoke_sessions(
+        user_id,
+        Some(current_token),
+        session_store,
+        banned_token_store,
     )
-    .map(|data| data.claims)
-    .wrap_err("failed to decode token")
+    .await
+}
+
+// Bans every token issued to the user.
+#[tracing::instrument(name = "revoke_all_sessions", skip_all)]
+pub async fn revoke_all_sessions(
+    user_id: &UserId,
+    session_store: SessionStoreType,
+    banned_token_store: BannedTokenStoreType,
+) -> Result<()> {
+    revoke_sessions(user_id, None, session_store, banned_token_store).await
+}
+
+async fn revoke_sessions(
+    user_id: &UserId,
+    keep_token: Option<&str>,
+    session_store: SessionStoreType,
+    banned_token_store: BannedTokenStoreType,
+) -> Result<()> {
+    for token in session_store.get_sessions(user_id).await? {
+        if Some(token.as_str()) == keep_token {
+            continue;
+        }
+
+        banned_token_store.add_token(token.clone()).await?;
+        session_store.remove_session(user_id, &token).await?;
+    }
+
+    Ok(())
+}
+
+// Bans a single-use token, returning false if it had already been used. The store checks and
+// bans in one step, so two concurrent requests can't both use the same token.
+#[tracing::instrument(name = "consume_token", skip_all)]
+pub async fn consume_token(token: &str, banned_token_store: BannedTokenStoreType) -> Result<bool> {
+    Ok(banned_token_store.add_token(token.to_owned()).await?)
 }
 
 #[tracing::instrument(name = "create_token", skip_all)]
-fn create_token(claims: &Claims) -> Result<String> {
+pub(super) fn create_token<T: Serialize>(claims: &T, jwt: &JwtSettings) -> Result<String> {
     encode(
         &jsonwebtoken::Header::default(),
         &claims,
-        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &EncodingKey::from_secret(jwt.secret.load().expose_secret().as_bytes()),
     )
     .wrap_err("failed to create token")
 }
 
+// Falls back to the secret a reload replaced, for tokens that were signed before the reload.
+pub(super) fn decode_token<T: DeserializeOwned>(
+    token: &str,
+    jwt: &JwtSettings,
+    validation: &Validation,
+) -> jsonwebtoken::errors::Result<T> {
+    let decode_with = |secret: &Secret<String>| {
+        decode::<T>(
+            token,
+            &DecodingKey::from_secret(secret.expose_secret().as_bytes()),
+            validation,
+        )
+        .map(|data| data.claims)
+    };
+
+    match decode_with(&jwt.secret.load()) {
+        Err(e) if *e.kind() == ErrorKind::InvalidSignature => {
+            match jwt.previous_secret(Utc::now()) {
+                Some(previous) => decode_with(&previous),
+                None => Err(e),
+            }
+        }
+        result => result,
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    pub jti: String,
+}
+
+// Magic links are short-lived so that a leaked email is only useful for a few minutes.
+pub const MAGIC_LINK_TTL_SECONDS: i64 = 300;
+
+// Magic link tokens carry an audience so they can never be used as an auth token,
+// and auth tokens (which have no audience) can never be used as a magic link.
+const MAGIC_LINK_AUDIENCE: &str = "magic-link";
+
+#[tracing::instrument(name = "generate_magic_link_token", skip_all)]
+pub fn generate_magic_link_token(user_id: &UserId, jwt: &JwtSettings) -> Result<String> {
+    let delta = chrono::Duration::try_seconds(MAGIC_LINK_TTL_SECONDS)
+        .wrap_err("failed to create magic link time delta")?;
+
+    let exp = Utc::now()
+        .checked_add_signed(delta)
+        .ok_or(eyre!("failed to add magic link ttl to current time"))?
+        .timestamp();
+
+    let exp: usize = exp.try_into().wrap_err(format!(
+        "failed to cast exp time to usize. exp time: {}",
+        exp
+    ))?;
+
+    let claims = MagicLinkClaims {
+        sub: user_id.to_string(),
+        exp,
+        aud: MAGIC_LINK_AUDIENCE.to_owned(),
+    };
+
+    create_token(&claims, jwt)
+}
+
+// Only checks the signature, expiry and audience of a magic link token.
+// Single-use is enforced by the caller, which bans the token once it has been exchanged.
+#[tracing::instrument(name = "validate_magic_link_token", skip_all)]
+pub fn validate_magic_link_token(token: &str, jwt: &JwtSettings) -> Result<MagicLinkClaims> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[MAGIC_LINK_AUDIENCE]);
+    validation.set_required_spec_claims(&["exp", "aud", "sub"]);
+
+    decode_token::<MagicLinkClaims>(token, jwt, &validation)
+        .wrap_err("failed to decode magic link token")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MagicLinkClaims {
+    pub sub: String,
+    pub exp: usize,
+    pub aud: String,
+}
+
+// The link sent to the new address applies the change, the one sent to the old address cancels it.
+pub const EMAIL_CHANGE_CONFIRM_AUDIENCE: &str = "email-change-confirm";
+pub const EMAIL_CHANGE_CANCEL_AUDIENCE: &str = "email-change-cancel";
+
+#[tracing::instrument(name = "generate_email_change_confirm_token", skip_all)]
+pub fn generate_email_change_confirm_token(
+    user_id: &UserId,
+    new_email: &Email,
+    change_id: &str,
+    jwt: &JwtSettings,
+) -> Result<String> {
+    create_email_change_token(
+        EmailChangeClaims {
+            sub: user_id.to_string(),
+            new_email: new_email.as_ref().expose_secret().to_owned(),
+            change_id: change_id.to_owned(),
+            cancel_id: None,
+            old_email: None,
+            exp: 0,
+            aud: EMAIL_CHANGE_CONFIRM_AUDIENCE.to_owned(),
+        },
+        jwt,
+    )
+}
+
+// The cancel link has an id of its own, so it still works once the change is confirmed, and
+// carries the old address to change back to.
+#[tracing::instrument(name = "generate_email_change_cancel_token", skip_all)]
+pub fn generate_email_change_cancel_token(
+    user_id: &UserId,
+    old_email: &Email,
+    new_email: &Email,
+    change_id: &str,
+    cancel_id: &str,
+    jwt: &JwtSettings,
+) -> Result<String> {
+    create_email_change_token(
+        EmailChangeClaims {
+            sub: user_id.to_string(),
+            new_email: new_email.as_ref().expose_secret().to_owned(),
+            change_id: change_id.to_owned(),
+            cancel_id: Some(cancel_id.to_owned()),
+            old_email: Some(old_email.as_ref().expose_secret().to_owned()),
+            exp: 0,
+            aud: EMAIL_CHANGE_CANCEL_AUDIENCE.to_owned(),
+        },
+        jwt,
+    )
+}
+
+fn create_email_change_token(mut claims: EmailChangeClaims, jwt: &JwtSettings) -> Result<String> {
+    // Email change links are valid for as long as an auth token. Their ids are banned once
+    // they are used, and that ban must outlive both links.
+    let delta = chrono::Duration::try_seconds(jwt.token_ttl_seconds)
+        .wrap_err("failed to create email change time delta")?;
+
+    let exp = Utc::now()
+        .checked_add_signed(delta)
+        .ok_or(eyre!("failed to add email change ttl to current time"))?
+        .timestamp();
+
+    claims.exp = exp.try_into().wrap_err(format!(
+        "failed to cast exp time to usize. exp time: {}",
+        exp
+    ))?;
+
+    create_token(&claims, jwt)
+}
+
+// Only checks the signature, expiry and audience. The caller consumes the link's id so that
+// each link is used at most once.
+#[tracing::instrument(name = "validate_email_change_token", skip_all)]
+pub fn validate_email_change_token(
+    token: &str,
+    audience: &str,
+    jwt: &JwtSettings,
+) -> Result<EmailChangeClaims> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[audience]);
+    validation.set_required_spec_claims(&["exp", "aud", "sub"]);
+
+    decode_token::<EmailChangeClaims>(token, jwt, &validation)
+        .wrap_err("failed to decode email change token")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailChangeClaims {
+    pub sub: String,
+    pub new_email: String,
+    // The id of the confirm link. Cancelling a change bans it too.
+    pub change_id: String,
+    // Only set on the cancel link.
+    pub cancel_id: Option<String>,
+    pub old_email: Option<String>,
+    pub exp: usize,
+    pub aud: String,
 }
 
 #[cfg(test)]
 mod tests {
+    use secrecy::Secret;
     use std::sync::Arc;
-    use tokio::sync::RwLock;
 
     use crate::{
-        domain::BannedTokenStore,
-        services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore,
+        domain::{BannedTokenStore, SessionStore},
+        services::data_stores::{
+            hashmap_session_store::HashmapSessionStore,
+            hashset_banned_token_store::HashsetBannedTokenStore,
+        },
     };
 
     use super::*;
 
+    fn jwt() -> JwtSettings {
+        JwtSettings::new(Secret::new("secret".to_owned()), 600)
+    }
+
     #[tokio::test]
     async fn test_generate_auth_cookie() {
-        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let user_id = UserId::default();
+        let cookie = generate_auth_cookie(&user_id, &jwt()).unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -148,18 +399,20 @@ mod tests {
 
     #[tokio::test]
     async fn test_generate_auth_token() {
-        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let user_id = UserId::default();
+        let result = generate_auth_token(&user_id, &jwt()).unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
-        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
-        let token = generate_auth_token(&email).unwrap();
-        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let result = validate_token(&token, banned_token_store).await.unwrap();
-        assert_eq!(result.sub, "test@example.com");
+        let user_id = UserId::default();
+        let token = generate_auth_token(&user_id, &jwt()).unwrap();
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let result = validate_token(&token, banned_token_store, &jwt())
+            .await
+            .unwrap();
+        assert_eq!(result.sub, user_id.to_string());
 
         let exp = Utc::now()
             .checked_add_signed(chrono::Duration::try_minutes(9).expect("valid duration"))
@@ -169,22 +422,114 @@ mod tests {
         assert!(result.exp > exp as usize);
     }
 
+    #[tokio::test]
+    async fn test_revoke_other_sessions_keeps_current_session() {
+        let user_id = UserId::default();
+        let session_store = Arc::new(HashmapSessionStore::default());
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let other = start_session(&user_id, session_store.clone(), &jwt())
+            .await
+            .unwrap();
+        let current = start_session(&user_id, session_store.clone(), &jwt())
+            .await
+            .unwrap();
+
+        revoke_other_sessions(
+            &user_id,
+            current.value(),
+            session_store.clone(),
+            banned_token_store.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert!(
+            validate_token(other.value(), banned_token_store.clone(), &jwt())
+                .await
+                .is_err()
+        );
+        assert!(validate_token(current.value(), banned_token_store, &jwt())
+            .await
+            .is_ok());
+        assert_eq!(
+            session_store.get_sessions(&user_id).await.unwrap(),
+            vec![current.value().to_owned()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_accepts_token_signed_before_rotation() {
+        let user_id = UserId::default();
+        let jwt = jwt();
+        let old_token = generate_auth_token(&user_id, &jwt).unwrap();
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+
+        jwt.rotate_secret(Arc::new(Secret::new("rotated".to_owned())), Utc::now());
+        let new_token = generate_auth_token(&user_id, &jwt).unwrap();
+
+        assert!(validate_token(&old_token, banned_token_store.clone(), &jwt)
+            .await
+            .is_ok());
+        assert!(validate_token(&new_token, banned_token_store, &jwt)
+            .await
+            .is_ok());
+    }
+
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
         let token = "invalid_token".to_owned();
-        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let result = validate_token(&token, banned_token_store).await;
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let result = validate_token(&token, banned_token_store, &jwt()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_magic_link_token_with_valid_token() {
+        let user_id = UserId::default();
+        let token = generate_magic_link_token(&user_id, &jwt()).unwrap();
+        let result = validate_magic_link_token(&token, &jwt()).unwrap();
+        assert_eq!(result.sub, user_id.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_email_change_token_is_bound_to_its_audience() {
+        let user_id = UserId::default();
+        let new_email = Email::parse(Secret::new("new@example.com".to_string())).unwrap();
+        let token =
+            generate_email_change_confirm_token(&user_id, &new_email, "id", &jwt()).unwrap();
+
+        let claims =
+            validate_email_change_token(&token, EMAIL_CHANGE_CONFIRM_AUDIENCE, &jwt()).unwrap();
+        assert_eq!(claims.sub, user_id.to_string());
+        assert_eq!(claims.new_email, "new@example.com");
+        assert!(validate_email_change_token(&token, EMAIL_CHANGE_CANCEL_AUDIENCE, &jwt()).is_err());
+        assert!(validate_magic_link_token(&token, &jwt()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_magic_link_token_rejects_auth_token() {
+        let user_id = UserId::default();
+        let token = generate_auth_token(&user_id, &jwt()).unwrap();
+        assert!(validate_magic_link_token(&token, &jwt()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_rejects_magic_link_token() {
+        let user_id = UserId::default();
+        let token = generate_magic_link_token(&user_id, &jwt()).unwrap();
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let result = validate_token(&token, banned_token_store, &jwt()).await;
         assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_validate_token_with_banned_token() {
-        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
-        let token = generate_auth_token(&email).unwrap();
-        let mut hs = HashsetBannedTokenStore::default();
+        let user_id = UserId::default();
+        let token = generate_auth_token(&user_id, &jwt()).unwrap();
+        let hs = HashsetBannedTokenStore::default();
         hs.add_token(token.clone()).await.unwrap();
-        let banned_token_store = Arc::new(RwLock::new(hs));
-        let result = validate_token(&token, banned_token_store).await;
+        let banned_token_store = Arc::new(hs);
+        let result = validate_token(&token, banned_token_store, &jwt()).await;
         assert!(result.is_err());
     }
 }
@@ -1,70 +1,129 @@
-use dotenvy::dotenv;
-use lazy_static::lazy_static;
-use secrecy::Secret;
-use std::env as std_env;
-
-// Define a lazily evaluated static. lazy_static is needed because std_env::var is not a const function.
-lazy_static! {
-    pub static ref JWT_SECRET: String = set_token();
-    pub static ref DATABASE_URL: String = set_postgres_url();
-    pub static ref REDIS_HOST_NAME: String = set_redis_host();
-    pub static ref POSTMARK_AUTH_TOKEN: Secret<String> = set_postmark_auth_token();
-}
-
-fn set_token() -> String {
-    dotenv().ok(); // Load environment variables
-    let secret = std_env::var(env::JWT_SECRET_ENV_VAR).expect("JWT_SECRET must be set.");
-    if secret.is_empty() {
-        panic!("JWT_SECRET must not be empty.");
-    }
-    secret
-}
-
-fn set_postgres_url() -> String {
-    dotenv().ok();
-    std_env::var(env::DATABASE_URL_ENV_VAR).expect("DATABASE_URL must be set.")
-}
-
-fn set_redis_host() -> String {
-    dotenv().ok();
-    std_env::var(env::REDIS_HOST_NAME_ENV_VAR).unwrap_or(DEFAULT_REDIS_HOSTNAME.to_owned())
-}
-
-fn set_postmark_auth_token() -> Secret<String> {
-    dotenv().ok();
-    Secret::new(
-        std_env::var(env::POSTMARK_AUTH_TOKEN_ENV_VAR).expect("POSTMARK_AUTH_TOKEN must be set."),
-    )
-}
-
+// Environment variables that set a single setting each, see `settings.rs`.
 pub mod env {
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
     pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
+    pub const DATABASE_PASSWORD_ENV_VAR: &str = "DATABASE_PASSWORD";
     pub const REDIS_HOST_NAME_ENV_VAR: &str = "REDIS_HOST_NAME";
     pub const POSTMARK_AUTH_TOKEN_ENV_VAR: &str = "POSTMARK_AUTH_TOKEN";
+    pub const AUTH_SERVICE_BASE_URL_ENV_VAR: &str = "AUTH_SERVICE_BASE_URL";
+    pub const ADMIN_API_TOKEN_ENV_VAR: &str = "ADMIN_API_TOKEN";
+    pub const POSTMARK_WEBHOOK_SECRET_ENV_VAR: &str = "POSTMARK_WEBHOOK_SECRET";
+    pub const SYNCHRONOUS_EMAIL_KINDS_ENV_VAR: &str = "SYNCHRONOUS_EMAIL_KINDS";
+    pub const EMAIL_BRAND_NAME_ENV_VAR: &str = "EMAIL_BRAND_NAME";
+    pub const EMAIL_BRAND_SUPPORT_EMAIL_ENV_VAR: &str = "EMAIL_BRAND_SUPPORT_EMAIL";
+    pub const EMAIL_BRAND_COLOR_ENV_VAR: &str = "EMAIL_BRAND_COLOR";
+    pub const EMAIL_PROVIDERS_ENV_VAR: &str = "EMAIL_PROVIDERS";
+    pub const SMTP_HOST_ENV_VAR: &str = "SMTP_HOST";
+    pub const SMTP_PORT_ENV_VAR: &str = "SMTP_PORT";
+    pub const SMTP_TLS_ENV_VAR: &str = "SMTP_TLS";
+    pub const SMTP_USERNAME_ENV_VAR: &str = "SMTP_USERNAME";
+    pub const SMTP_PASSWORD_ENV_VAR: &str = "SMTP_PASSWORD";
+    pub const SMS_PROVIDER_ENV_VAR: &str = "SMS_PROVIDER";
+    pub const TWILIO_ACCOUNT_SID_ENV_VAR: &str = "TWILIO_ACCOUNT_SID";
+    pub const TWILIO_AUTH_TOKEN_ENV_VAR: &str = "TWILIO_AUTH_TOKEN";
+    pub const TWILIO_FROM_NUMBER_ENV_VAR: &str = "TWILIO_FROM_NUMBER";
+    pub const VAULT_ADDR_ENV_VAR: &str = "VAULT_ADDR";
+    pub const VAULT_TOKEN_ENV_VAR: &str = "VAULT_TOKEN";
+    pub const OTEL_EXPORTER_OTLP_ENDPOINT_ENV_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+    pub const LOG_FORMAT_ENV_VAR: &str = "LOG_FORMAT";
 }
 
 pub const JWT_COOKIE_NAME: &str = "jwt";
-pub const DEFAULT_REDIS_HOSTNAME: &str = "127.0.0.1";
+// A deleted account can be restored for this long before it is purged.
+pub const ACCOUNT_DELETION_GRACE_PERIOD_DAYS: i64 = 30;
 
 pub mod prod {
-    pub const APP_ADDRESS: &str = "0.0.0.0:3000";
+    use std::time::Duration;
+
+    pub const ACCOUNT_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+    // Often enough that every scrape sees a fresh pool gauge.
+    pub const METRICS_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+    pub mod webhooks {
+        use std::time::Duration;
+
+        pub const DISPATCH_INTERVAL: Duration = Duration::from_secs(5);
+        pub const TIMEOUT: Duration = Duration::from_secs(10);
+    }
+    pub mod email_outbox {
+        use std::time::Duration;
+
+        pub const DISPATCH_INTERVAL: Duration = Duration::from_secs(5);
+    }
     pub mod email_client {
         use std::time::Duration;
 
         pub const BASE_URL: &str = "https://api.postmarkapp.com/email";
-        // If you created your own Postmark account, make sure to use your email address!
-        pub const SENDER: &str = "bogdan@codeiron.io";
         pub const TIMEOUT: Duration = std::time::Duration::from_secs(10);
     }
+    pub mod email_failover {
+        use std::time::Duration;
+
+        use crate::domain::CircuitBreakerConfig;
+
+        pub const CIRCUIT_BREAKER: CircuitBreakerConfig = CircuitBreakerConfig {
+            window: 20,
+            min_calls: 5,
+            failure_rate_threshold: 0.5,
+            open_duration: Duration::from_secs(30),
+        };
+    }
+    pub mod sms_client {
+        use std::time::Duration;
+
+        pub const BASE_URL: &str = "https://api.twilio.com";
+        pub const TIMEOUT: Duration = Duration::from_secs(10);
+    }
+    pub mod smtp {
+        use std::time::Duration;
+
+        pub const TIMEOUT: Duration = Duration::from_secs(10);
+        pub const MAX_CONNECTIONS: u32 = 4;
+        pub const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+    }
+    pub mod health {
+        use std::time::Duration;
+
+        // A dependency that takes longer than this to answer counts as failing.
+        pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+    }
+    pub mod vault {
+        use std::time::Duration;
+
+        pub const TIMEOUT: Duration = Duration::from_secs(10);
+    }
+    pub mod redis {
+        use std::time::Duration;
+
+        pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+        pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(2);
+        // Reconnects wait `RETRY_FACTOR` ms times `RETRY_EXPONENT_BASE` to the power of the attempt.
+        pub const RETRY_EXPONENT_BASE: u64 = 2;
+        pub const RETRY_FACTOR: u64 = 100;
+        pub const RETRIES: usize = 5;
+    }
 }
 
 pub mod test {
     pub const APP_ADDRESS: &str = "127.0.0.1:0";
+    pub const ADMIN_API_TOKEN: &str = "test-admin-token";
+    pub const POSTMARK_WEBHOOK_SECRET: &str = "test-postmark-secret";
+    pub mod webhooks {
+        use std::time::Duration;
+
+        pub const TIMEOUT: Duration = Duration::from_millis(200);
+    }
     pub mod email_client {
         use std::time::Duration;
 
         pub const SENDER: &str = "test@email.com";
         pub const TIMEOUT: Duration = std::time::Duration::from_millis(200);
     }
+    pub mod sms_client {
+        use std::time::Duration;
+
+        pub const ACCOUNT_SID: &str = "AC0123456789";
+        // One of Twilio's magic test numbers.
+        pub const SENDER: &str = "+15005550006";
+        pub const TIMEOUT: Duration = Duration::from_millis(200);
+    }
 }
@@ -0,0 +1,9 @@
+use axum::extract::FromRequest;
+
+use crate::domain::AuthAPIError;
+
+// `axum::Json`, except that a body that can't be read comes back as a problem response
+// like every other error.
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(AuthAPIError))]
+pub struct ApiJson<T>(pub T);
@@ -0,0 +1,25 @@
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use std::sync::OnceLock;
+
+// Every histogram measures a duration in seconds. The buckets go from a fast Redis command up
+// to a request that waited on an email provider.
+const DURATION_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0,
+];
+
+// Installs the Prometheus recorder the first time it is called, and returns a handle to render
+// what it collected. Metrics recorded before that are lost.
+pub fn prometheus_handle() -> PrometheusHandle {
+    // There can only be one recorder per process, which the tests share between apps.
+    static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+    HANDLE
+        .get_or_init(|| {
+            PrometheusBuilder::new()
+                .set_buckets(&DURATION_BUCKETS)
+                .expect("Histogram buckets must not be empty")
+                .install_recorder()
+                .expect("Failed to install the metrics recorder")
+        })
+        .clone()
+}
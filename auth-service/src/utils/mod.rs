@@ -1,6 +1,13 @@
+pub mod audit;
 pub mod auth;
 pub mod constants;
+pub mod extract;
+pub mod metrics;
+pub mod problem;
+pub mod redaction;
+pub mod shutdown;
 pub mod tracing;
+pub mod webauthn;
 
 pub use auth::*;
 pub use constants::*;
@@ -0,0 +1,91 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderValue, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::domain::FieldError;
+
+use super::audit::REQUEST_ID_HEADER;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+// An RFC 7807 error body. `code` is what clients should match on; `detail` is for people
+// and may change wording.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    #[schema(example = "auth.invalid_credentials")]
+    pub code: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+impl ProblemDetails {
+    pub fn new(status: StatusCode, code: &str, detail: String) -> Self {
+        Self {
+            problem_type: "about:blank".to_owned(),
+            title: status.canonical_reason().unwrap_or_default().to_owned(),
+            status: status.as_u16(),
+            detail,
+            code: code.to_owned(),
+            request_id: None,
+            errors: Vec::new(),
+        }
+    }
+
+    fn to_body(&self) -> Body {
+        Body::from(serde_json::to_vec(self).unwrap_or_default())
+    }
+}
+
+// The body is written without the request id, which handlers don't know about. A copy of the
+// problem travels in the response extensions so `attach_request_id` can fill it in.
+impl IntoResponse for ProblemDetails {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (status, self.to_body()).into_response();
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+// Must run inside the layer that assigns request ids.
+pub async fn attach_request_id(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+
+    let response = next.run(request).await;
+
+    let (mut parts, body) = response.into_parts();
+    match parts.extensions.remove::<ProblemDetails>() {
+        Some(problem) => {
+            let problem = ProblemDetails {
+                request_id,
+                ..problem
+            };
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, problem.to_body())
+        }
+        None => Response::from_parts(parts, body),
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+
+// Tells the server and the background tasks that the service is shutting down. Clones share
+// the state, so triggering one is seen by every other.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            sender: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    // Completes once `trigger` was called, right away if it already was.
+    pub async fn triggered(&self) {
+        let mut receiver = self.sender.subscribe();
+        // The sender lives as long as `self`, so waiting can't fail.
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Completes when the process receives SIGTERM, which is how orchestrators stop containers, or
+// SIGINT from Ctrl+C.
+pub async fn wait_for_shutdown_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_clones_see_the_trigger() {
+        let shutdown = Shutdown::new();
+        let clone = shutdown.clone();
+        assert!(!clone.is_triggered());
+
+        let waiting = tokio::spawn(async move { clone.triggered().await });
+        shutdown.trigger();
+
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("the clone didn't see the trigger")
+            .unwrap();
+        assert!(shutdown.is_triggered());
+    }
+}
@@ -1,44 +1,199 @@
-use axum::{body::Body, extract::Request, response::Response};
-use color_eyre::eyre::Result;
-use std::time::Duration;
-use tracing::{Level, Span};
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request},
+    http::{HeaderMap, HeaderValue, Method},
+    middleware::Next,
+    response::Response,
+};
+use color_eyre::eyre::{eyre, Result};
+use opentelemetry::{
+    propagation::TextMapPropagator,
+    trace::{TraceContextExt, TracerProvider as _},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{BatchSpanProcessor, Tracer, TracerProvider},
+    Resource,
+};
+use std::{collections::HashMap, time::Duration};
+use tower_http::request_id::{MakeRequestId, RequestId};
+use tracing::{Level, Span, Subscriber};
 use tracing_error::ErrorLayer;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
 use tracing_subscriber::prelude::*;
-use tracing_subscriber::{fmt, EnvFilter};
+use tracing_subscriber::{
+    fmt::{self, MakeWriter},
+    registry::LookupSpan,
+    EnvFilter, Layer,
+};
+use uuid::Uuid;
 
+use crate::settings::{LoggingSettings, TelemetrySettings};
+
+use super::{
+    audit::REQUEST_ID_HEADER,
+    redaction::{RedactingMakeWriter, RedactingSpanProcessor},
+};
+
+const SERVICE_NAME: &str = "auth-service";
+
+// How log lines are written, picked with `logging.format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    // One line per event for people reading a terminal.
+    #[default]
+    Compact,
+    // One JSON object per event for log collectors.
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(format: &str) -> Result<Self> {
+        match format {
+            "compact" => Ok(Self::Compact),
+            "json" => Ok(Self::Json),
+            _ => Err(eyre!("Invalid log format")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Compact => "compact",
+            Self::Json => "json",
+        }
+    }
+}
+
+// Formats events in `format` and writes them to `make_writer`, with emails, tokens, codes and
+// passwords masked.
+pub fn fmt_layer<S, W>(format: LogFormat, make_writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let make_writer = RedactingMakeWriter::new(make_writer);
+    match format {
+        LogFormat::Compact => fmt::layer().compact().with_writer(make_writer).boxed(),
+        LogFormat::Json => fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_writer(make_writer)
+            .boxed(),
+    }
+}
+
+// Also exports spans over OTLP when `telemetry.otlp_endpoint` is set. Shut the returned
+// provider down before exiting, or the spans it still queues are lost.
 //pub fn init_tracing() {
-pub fn init_tracing() -> Result<()> {
-    // Create a formatting layer for tracing output with a compact format
-    let fmt_layer = fmt::layer().compact();
+pub fn init_tracing(
+    logging: &LoggingSettings,
+    telemetry: &TelemetrySettings,
+) -> Result<Option<TracerProvider>> {
+    // Create a formatting layer for tracing output in the configured format
+    let fmt_layer = fmt_layer(logging.format, std::io::stdout);
 
     // Create a filter layer to control the verbosity of logs
     // Try to get the filter configuration from the environment variables
     // If it fails, default to the "info" log level
     let filter_layer = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?;
 
+    let tracer_provider = telemetry
+        .otlp_traces_endpoint()
+        .map(|endpoint| otlp_tracer_provider(&endpoint))
+        .transpose()?;
+
     // Build the tracing subscriber registry with the formatting layer,
     // the filter layer, and the error layer for enhanced error reporting
     tracing_subscriber::registry()
         .with(filter_layer) // Add the filter layer to control log verbosity
-        .with(fmt_layer) // Add the formatting layer for compact log output
+        .with(fmt_layer) // Add the formatting layer for redacted log output
         .with(ErrorLayer::default()) // Add the error layer to capture error contexts
+        .with(tracer_provider.as_ref().map(otel_layer)) // Export spans, if configured
         .init(); // Initialize the tracing subscriber
 
-    Ok(())
+    Ok(tracer_provider)
+}
+
+// Batches spans and posts them as OTLP/JSON to `endpoint`, e.g.
+// `http://collector:4318/v1/traces`. The batches are sent from a thread of their own, and are
+// redacted like the logs.
+pub fn otlp_tracer_provider(endpoint: &str) -> Result<TracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_protocol(Protocol::HttpJson)
+        .with_endpoint(endpoint)
+        .build()?;
+
+    let processor = BatchSpanProcessor::builder(exporter, runtime::TokioCurrentThread).build();
+
+    Ok(TracerProvider::builder()
+        .with_span_processor(RedactingSpanProcessor::new(processor))
+        .with_resource(Resource::new([KeyValue::new("service.name", SERVICE_NAME)]))
+        .build())
 }
 
-// Creates a new tracing span with a unique request ID for each incoming request.
+// Turns the `tracing` spans into OpenTelemetry spans of `tracer_provider`.
+pub fn otel_layer<S>(tracer_provider: &TracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer(SERVICE_NAME))
+}
+
+// The W3C `traceparent` of a caller, or an empty context when it didn't send a valid one.
+fn extract_trace_context(headers: &HeaderMap) -> Context {
+    TraceContextPropagator::new().extract(&opentelemetry_http::HeaderExtractor(headers))
+}
+
+// The headers that continue the current trace in a service this one calls.
+pub fn trace_context_headers() -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    TraceContextPropagator::new().inject_context(&Span::current().context(), &mut headers);
+    headers
+}
+
+// Keeps the caller's trace ID as the request ID when it only sent a `traceparent`, so its
+// logs and ours can be searched for the same value. Callers that send an `X-Request-Id` keep
+// theirs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MakeRequestIdFromTrace;
+
+impl MakeRequestId for MakeRequestIdFromTrace {
+    fn make_request_id<B>(&mut self, request: &Request<B>) -> Option<RequestId> {
+        let context = extract_trace_context(request.headers());
+        let span_context = context.span().span_context().clone();
+        let request_id = if span_context.is_valid() {
+            span_context.trace_id().to_string()
+        } else {
+            Uuid::new_v4().to_string()
+        };
+        HeaderValue::from_str(&request_id).ok().map(RequestId::new)
+    }
+}
+
+// Creates a new tracing span with the ID of the incoming request.
 // This helps in tracking and correlating logs for individual requests.
+// The span continues the caller's trace, if it sent a `traceparent`.
 pub fn make_span_with_request_id(request: &Request<Body>) -> Span {
-    let request_id = uuid::Uuid::new_v4();
-    tracing::span!(
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let span = tracing::span!(
         Level::INFO,
         "[REQUEST]",
         method = tracing::field::display(request.method()),
         uri = tracing::field::display(request.uri()),
         version = tracing::field::debug(request.version()),
         request_id = tracing::field::display(request_id),
-    )
+    );
+    span.set_parent(extract_trace_context(request.headers()));
+    span
 }
 
 // Logs an event indicating the start of a request.
@@ -46,13 +201,46 @@ pub fn on_request(_request: &Request<Body>, _span: &Span) {
     tracing::event!(Level::INFO, "[REQUEST START]");
 }
 
+// The route that handled a request, like `/admin/webhooks/:id`. `record_route` copies it into
+// the response so `on_response` can label metrics with it.
+#[derive(Clone)]
+pub struct MatchedRoute {
+    method: Method,
+    path: MatchedPath,
+}
+
+// Must run as a route layer, which only sees requests that matched a route. Anything else is
+// counted as `unmatched`, so probing random paths doesn't create new series.
+pub async fn record_route(path: MatchedPath, request: Request, next: Next) -> Response {
+    let route = MatchedRoute {
+        method: request.method().clone(),
+        path,
+    };
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(route);
+    response
+}
+
 // Logs an event indicating the end of a request, including its latency and status code.
 // If the status code indicates an error (4xx or 5xx), it logs at the ERROR level.
+// It also counts the request and records its latency per route and status.
 pub fn on_response(response: &Response, latency: Duration, _span: &Span) {
     let status = response.status();
     let status_code = status.as_u16();
     let status_code_class = status_code / 100;
 
+    let (method, route) = match response.extensions().get::<MatchedRoute>() {
+        Some(route) => (route.method.to_string(), route.path.as_str().to_owned()),
+        None => ("unmatched".to_owned(), "unmatched".to_owned()),
+    };
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", status_code.to_string()),
+    ];
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!("http_request_duration_seconds", &labels).record(latency.as_secs_f64());
+
     match status_code_class {
         4..=5 => {
             tracing::event!(
@@ -0,0 +1,136 @@
+use chrono::Utc;
+use color_eyre::eyre::{eyre, Context, ContextCompat, Result};
+use jsonwebtoken::Validation;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use webauthn_rs::{prelude::Url, Webauthn, WebauthnBuilder};
+
+use crate::{domain::UserId, settings::JwtSettings};
+
+use super::auth::{create_token, decode_token};
+
+const RP_NAME: &str = "Auth Service";
+
+// The relying party is this service. Its id is the host of the public base url,
+// and only that exact origin may perform ceremonies.
+pub fn build_webauthn(base_url: &str) -> Webauthn {
+    let rp_origin = Url::parse(base_url).expect("application.base_url must be a valid URL.");
+    let rp_id = rp_origin
+        .host_str()
+        .expect("application.base_url must have a host.")
+        .to_owned();
+
+    WebauthnBuilder::new(&rp_id, &rp_origin)
+        .expect("Invalid WebAuthn configuration.")
+        .rp_name(RP_NAME)
+        .build()
+        .expect("Invalid WebAuthn configuration.")
+}
+
+// A ceremony has to be completed within this window.
+pub const CEREMONY_TTL_SECONDS: i64 = 300;
+
+pub const REGISTRATION_AUDIENCE: &str = "webauthn-registration";
+pub const AUTHENTICATION_AUDIENCE: &str = "webauthn-authentication";
+
+// The server-side state of a registration or authentication ceremony is signed and handed to
+// the client, which sends it back with the authenticator's response. The audience ties the
+// token to one kind of ceremony and the subject ties it to one user. Callers must consume the
+// token with `consume_token` so that a ceremony can't be replayed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CeremonyClaims<T> {
+    pub sub: String,
+    pub exp: usize,
+    pub aud: String,
+    pub state: T,
+}
+
+#[tracing::instrument(name = "generate_ceremony_token", skip_all)]
+pub fn generate_ceremony_token<T: Serialize>(
+    user_id: &UserId,
+    state: T,
+    audience: &str,
+    jwt: &JwtSettings,
+) -> Result<String> {
+    let delta = chrono::Duration::try_seconds(CEREMONY_TTL_SECONDS)
+        .wrap_err("failed to create ceremony time delta")?;
+
+    let exp = Utc::now()
+        .checked_add_signed(delta)
+        .ok_or(eyre!("failed to add ceremony ttl to current time"))?
+        .timestamp();
+
+    let exp: usize = exp.try_into().wrap_err(format!(
+        "failed to cast exp time to usize. exp time: {}",
+        exp
+    ))?;
+
+    let claims = CeremonyClaims {
+        sub: user_id.to_string(),
+        exp,
+        aud: audience.to_owned(),
+        state,
+    };
+
+    create_token(&claims, jwt)
+}
+
+#[tracing::instrument(name = "validate_ceremony_token", skip_all)]
+pub fn validate_ceremony_token<T: DeserializeOwned>(
+    token: &str,
+    user_id: &UserId,
+    audience: &str,
+    jwt: &JwtSettings,
+) -> Result<T> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[audience]);
+    validation.set_required_spec_claims(&["exp", "aud", "sub"]);
+
+    let claims = decode_token::<CeremonyClaims<T>>(token, jwt, &validation)
+        .wrap_err("failed to decode ceremony token")?;
+
+    (claims.sub == user_id.to_string())
+        .then_some(claims.state)
+        .wrap_err("ceremony token was issued for another user")
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::*;
+
+    fn jwt() -> JwtSettings {
+        JwtSettings::new(Secret::new("secret".to_owned()), 600)
+    }
+
+    #[test]
+    fn test_ceremony_token_round_trip() {
+        let user_id = UserId::default();
+        let token =
+            generate_ceremony_token(&user_id, "state", REGISTRATION_AUDIENCE, &jwt()).unwrap();
+        let state: String =
+            validate_ceremony_token(&token, &user_id, REGISTRATION_AUDIENCE, &jwt()).unwrap();
+        assert_eq!(state, "state");
+    }
+
+    #[test]
+    fn test_ceremony_token_rejects_other_audience() {
+        let user_id = UserId::default();
+        let token =
+            generate_ceremony_token(&user_id, "state", REGISTRATION_AUDIENCE, &jwt()).unwrap();
+        let result: Result<String> =
+            validate_ceremony_token(&token, &user_id, AUTHENTICATION_AUDIENCE, &jwt());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_ceremony_token_rejects_other_user() {
+        let user_id = UserId::default();
+        let other = UserId::default();
+        let token =
+            generate_ceremony_token(&user_id, "state", AUTHENTICATION_AUDIENCE, &jwt()).unwrap();
+        let result: Result<String> =
+            validate_ceremony_token(&token, &other, AUTHENTICATION_AUDIENCE, &jwt());
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use auth_service::{
+    domain::{data_stores::AuditLogStoreError, GENESIS_HASH},
+    routes::{AuditEventResponse, AuditEventsResponse},
+    services::account_purger::purge_deleted_accounts,
+    utils::constants::{test, ACCOUNT_DELETION_GRACE_PERIOD_DAYS},
+};
+use chrono::Utc;
+
+use crate::helpers::{get_random_email, login_body, TestApp};
+
+async fn get_events(app: &TestApp, query: &str) -> Vec<AuditEventResponse> {
+    let response = app
+        .get_audit_events(query, Some(test::ADMIN_API_TOKEN))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<AuditEventsResponse>()
+        .await
+        .expect("Could not deserialize response body to AuditEventsResponse")
+        .events
+}
+
+#[tokio::test]
+async fn should_record_login_attempts() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, false).await;
+    let user_id = app.get_user_id(&random_email).await;
+
+    let response = app
+        .post_login(&login_body(&random_email, "wrong_password"))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app
+        .post_login(&login_body(&random_email, "password123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let events = get_events(&app, &format!("eventType=login&userId={}", user_id)).await;
+    assert_eq!(events.len(), 2);
+
+    // Newest first.
+    assert_eq!(events[0].outcome, "success");
+    assert_eq!(events[0].reason, None);
+    assert_eq!(events[1].outcome, "failure");
+    assert_eq!(events[1].reason.as_deref(), Some("Incorrect credentials"));
+
+    for event in &events {
+        assert_eq!(event.user_id, Some(user_id.to_string()));
+        assert_eq!(event.ip_address.as_deref(), Some("127.0.0.1"));
+        assert!(event.request_id.is_some());
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_record_requests_for_unknown_users_without_a_user() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_login(&login_body(&get_random_email(), "password123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let events = get_events(&app, "eventType=login").await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].user_id, None);
+    assert_eq!(events[0].outcome, "failure");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_use_the_request_id_header_if_present() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/login", &app.address))
+        .header("x-request-id", "test-request-id")
+        .json(&serde_json::json!({
+            "email": get_random_email(),
+            "password": "password123",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(
+        response
+            .headers()
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok()),
+        Some("test-request-id")
+    );
+
+    let events = get_events(&app, "eventType=login").await;
+    assert_eq!(events[0].request_id.as_deref(), Some("test-request-id"));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_filter_and_page_events() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, false).await;
+    for _ in 0..3 {
+        let response = app
+            .post_login(&login_body(&random_email, "wrong_password"))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let events = get_events(&app, "outcome=failure&limit=2").await;
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().all(|event| event.outcome == "failure"));
+
+    let older = get_events(&app, &format!("outcome=failure&beforeId={}", events[1].id)).await;
+    assert_eq!(older.len(), 1);
+    assert!(older[0].id < events[1].id);
+
+    let events = get_events(&app, "eventType=signup").await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].outcome, "success");
+
+    let events = get_events(
+        &app,
+        "since=2000-01-01T00:00:00Z&until=2000-01-02T00:00:00Z",
+    )
+    .await;
+    assert!(events.is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_query_is_malformed() {
+    let mut app = TestApp::new().await;
+
+    let queries = [
+        "eventType=unknown",
+        "userId=not-a-uuid",
+        "outcome=maybe",
+        "since=yesterday",
+        "limit=0",
+        "limit=1001",
+    ];
+
+    for query in queries {
+        let response = app
+            .get_audit_events(query, Some(test::ADMIN_API_TOKEN))
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for query: {}",
+            query
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_the_admin_token() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_audit_events("", None).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.get_audit_events("", Some("wrong-token")).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Rejected queries are recorded too.
+    let events = get_events(&app, "eventType=audit_log_query&outcome=failure").await;
+    assert_eq!(events.len(), 2);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_a_verifiable_chain() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, false).await;
+    let response = app
+        .post_login(&login_body(&random_email, "password123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let verifier = app
+        .audit_log_store
+        .verify_chain()
+        .await
+        .expect("Audit chain is broken");
+    assert_eq!(verifier.verified_events(), 3);
+
+    let mut events = get_events(&app, "").await;
+    events.reverse();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0].prev_hash, GENESIS_HASH);
+    for pair in events.windows(2) {
+        assert_eq!(pair[1].prev_hash, pair[0].hash);
+    }
+    assert_eq!(verifier.head_hash(), events[2].hash);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_a_verifiable_chain_under_concurrent_requests() {
+    let mut app = TestApp::new().await;
+
+    let body = serde_json::json!({ "token": "a_bad_token" });
+    let _ = tokio::join!(
+        app.post_verify_token(&body),
+        app.post_verify_token(&body),
+        app.post_verify_token(&body),
+        app.post_verify_token(&body),
+        app.post_verify_token(&body),
+        app.post_verify_token(&body),
+    );
+
+    let verifier = app
+        .audit_log_store
+        .verify_chain()
+        .await
+        .expect("Audit chain is broken");
+    assert_eq!(verifier.verified_events(), 6);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_detect_tampering() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, false).await;
+    let response = app
+        .post_login(&login_body(&random_email, "wrong_password"))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Only the anonymizing update is allowed.
+    let result = sqlx::query("UPDATE audit_events SET outcome = 'success' WHERE id = 2")
+        .execute(&app.pg_pool)
+        .await;
+    assert!(result.is_err());
+    let result = sqlx::query("DELETE FROM audit_events WHERE id = 2")
+        .execute(&app.pg_pool)
+        .await;
+    assert!(result.is_err());
+
+    // Someone able to switch the trigger off is still caught by the chain.
+    sqlx::query("ALTER TABLE audit_events DISABLE TRIGGER USER")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE audit_events SET outcome = 'success' WHERE id = 2")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    let result = app.audit_log_store.verify_chain().await;
+    assert!(matches!(result, Err(AuditLogStoreError::ChainBroken(_))));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_anonymize_events_of_purged_accounts() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, false).await;
+    let user_id = app.get_user_id(&random_email).await;
+    let response = app
+        .post_login(&login_body(&random_email, "password123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let after_grace_period =
+        Utc::now() + chrono::Duration::try_days(ACCOUNT_DELETION_GRACE_PERIOD_DAYS + 1).unwrap();
+    let purged = purge_deleted_accounts(
+        &app.user_store,
+        &app.audit_log_store,
+        &app.email_outbox_store,
+        &app.webhook_store,
+        after_grace_period,
+    )
+    .await
+    .unwrap();
+    assert_eq!(purged, vec![user_id]);
+
+    let events = get_events(&app, &format!("userId={}", user_id)).await;
+    assert!(events.is_empty());
+
+    let events = get_events(&app, "").await;
+    assert_eq!(events.len(), 4);
+    for event in events
+        .iter()
+        .filter(|event| event.event_type != "audit_log_query")
+    {
+        assert_eq!(event.user_id, None);
+        assert_eq!(event.ip_address, None);
+    }
+
+    assert!(app.audit_log_store.verify_chain().await.is_ok());
+
+    app.clean_up().await;
+}
@@ -0,0 +1,353 @@
+use auth_service::{domain::Email, utils::constants::JWT_COOKIE_NAME};
+use chrono::Utc;
+use secrecy::Secret;
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Pulls the token out of the last email sent to `to` through the mock Postmark server.
+async fn get_token_from_email_to(app: &TestApp, to: &str) -> String {
+    app.deliver_emails(Utc::now()).await;
+    let requests = app
+        .email_server
+        .received_requests()
+        .await
+        .expect("Request recording is disabled");
+
+    let body = requests
+        .iter()
+        .rev()
+        .map(|request| serde_json::from_slice::<serde_json::Value>(&request.body).unwrap())
+        .find(|body| body["To"] == to)
+        .expect("No email was sent to this address");
+
+    body["TextBody"]
+        .as_str()
+        .unwrap()
+        .split("token=")
+        .nth(1)
+        .expect("No token in email")
+        .split_whitespace()
+        .next()
+        .unwrap()
+        .to_owned()
+}
+
+async fn login_status(app: &TestApp, email: &str) -> u16 {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+    }))
+    .await
+    .status()
+    .as_u16()
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "newEmail": get_random_email(),
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_is_incorrect() {
+    let mut app = TestApp::new().await;
+
+    app.signup_and_login(&get_random_email()).await;
+
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "newEmail": get_random_email(),
+            "password": "wrong_password",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_email_is_invalid() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+
+    for new_email in ["fooexample.com", random_email.as_str()] {
+        let response = app
+            .post_change_email(&serde_json::json!({
+                "newEmail": new_email,
+                "password": "password123",
+            }))
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            new_email
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_409_if_new_email_is_taken() {
+    let mut app = TestApp::new().await;
+
+    let taken_email = get_random_email();
+    app.signup_and_login(&taken_email).await;
+    app.signup_and_login(&get_random_email()).await;
+
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "newEmail": taken_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_change_email_once_confirmed() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+
+    let old_email = get_random_email();
+    let new_email = get_random_email();
+    let session = app.signup_and_login(&old_email).await;
+
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "newEmail": new_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // The old address is told about the change and can cancel it.
+    get_token_from_email_to(&app, &old_email).await;
+    let token = get_token_from_email_to(&app, &new_email).await;
+
+    assert_eq!(login_status(&app, &old_email).await, 200);
+    assert_eq!(login_status(&app, &new_email).await, 401);
+
+    let response = app
+        .post_confirm_email_change(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(login_status(&app, &old_email).await, 401);
+    assert_eq!(login_status(&app, &new_email).await, 200);
+
+    // Sessions of the old address are logged out.
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": session }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_confirm_email_change(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_change_email_if_cancelled() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+
+    let old_email = get_random_email();
+    let new_email = get_random_email();
+    app.signup_and_login(&old_email).await;
+
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "newEmail": new_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let cancel_token = get_token_from_email_to(&app, &old_email).await;
+    let confirm_token = get_token_from_email_to(&app, &new_email).await;
+
+    let response = app
+        .post_cancel_email_change(&serde_json::json!({ "token": cancel_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_confirm_email_change(&serde_json::json!({ "token": confirm_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert_eq!(login_status(&app, &old_email).await, 200);
+    assert_eq!(login_status(&app, &new_email).await, 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_undo_a_confirmed_change_if_cancelled() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+
+    let old_email = get_random_email();
+    let new_email = get_random_email();
+    app.signup_and_login(&old_email).await;
+
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "newEmail": new_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let cancel_token = get_token_from_email_to(&app, &old_email).await;
+    let confirm_token = get_token_from_email_to(&app, &new_email).await;
+
+    let response = app
+        .post_confirm_email_change(&serde_json::json!({ "token": confirm_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Whoever confirmed the change is logged out again.
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": new_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let session = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let response = app
+        .post_cancel_email_change(&serde_json::json!({ "token": cancel_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(login_status(&app, &old_email).await, 200);
+    assert_eq!(login_status(&app, &new_email).await, 401);
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": session }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Both links are used up.
+    let response = app
+        .post_cancel_email_change(&serde_json::json!({ "token": cancel_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app
+        .post_confirm_email_change(&serde_json::json!({ "token": confirm_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_the_confirm_link_if_the_change_fails() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+
+    let old_email = get_random_email();
+    let new_email = get_random_email();
+    app.signup_and_login(&old_email).await;
+
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "newEmail": new_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let confirm_token = get_token_from_email_to(&app, &new_email).await;
+
+    // Someone else takes the new address before the change is confirmed.
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": new_email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_confirm_email_change(&serde_json::json!({ "token": confirm_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    // Once the address is free again, the same link still works.
+    let squatter = app
+        .user_store
+        .get_user(&Email::parse(Secret::new(new_email.clone())).unwrap())
+        .await
+        .unwrap();
+    app.user_store
+        .update_email(
+            &squatter.id,
+            &Email::parse(Secret::new(get_random_email())).unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let response = app
+        .post_confirm_email_change(&serde_json::json!({ "token": confirm_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(login_status(&app, &new_email).await, 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_tokens_are_swapped() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+
+    let old_email = get_random_email();
+    let new_email = get_random_email();
+    app.signup_and_login(&old_email).await;
+
+    app.post_change_email(&serde_json::json!({
+        "newEmail": new_email,
+        "password": "password123",
+    }))
+    .await;
+
+    let cancel_token = get_token_from_email_to(&app, &old_email).await;
+    let confirm_token = get_token_from_email_to(&app, &new_email).await;
+
+    let response = app
+        .post_confirm_email_change(&serde_json::json!({ "token": cancel_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_cancel_email_change(&serde_json::json!({ "token": confirm_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,115 @@
+use chrono::Utc;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    app.signup_and_login(&get_random_email()).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({ "currentPassword": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "new_password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_password_is_invalid() {
+    let mut app = TestApp::new().await;
+
+    app.signup_and_login(&get_random_email()).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "short",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_current_password_is_incorrect() {
+    let mut app = TestApp::new().await;
+
+    app.signup_and_login(&get_random_email()).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "wrong_password",
+            "newPassword": "new_password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_change_password_and_revoke_other_sessions() {
+    let mut app = TestApp::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let random_email = get_random_email();
+    let other_session = app.signup_and_login(&random_email).await;
+    let current_session = app.login(&random_email, "password123").await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "new_password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.deliver_emails(Utc::now()).await, 1);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": other_session }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": current_session }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.login(&random_email, "new_password123").await;
+
+    app.clean_up().await;
+}
@@ -0,0 +1,225 @@
+use auth_service::{
+    domain::OutboxEmail, routes::DeleteAccountResponse,
+    services::account_purger::purge_deleted_accounts,
+    utils::constants::ACCOUNT_DELETION_GRACE_PERIOD_DAYS,
+};
+use chrono::Utc;
+
+use crate::helpers::{get_random_email, login_body, TestApp};
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_is_incorrect() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "wrong_password" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The account is untouched.
+    let response = app
+        .post_login(&login_body(&random_email, "password123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_delete_account_and_end_sessions() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+
+    let random_email = get_random_email();
+    let token = app.signup_and_login(&random_email).await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.json::<DeleteAccountResponse>().await.unwrap(),
+        DeleteAccountResponse {
+            grace_period_days: ACCOUNT_DELETION_GRACE_PERIOD_DAYS
+        }
+    );
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_login(&login_body(&random_email, "password123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_restore_account_within_grace_period() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_restore_account(&serde_json::json!({
+            "email": random_email,
+            "password": "wrong_password",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_restore_account(&login_body(&random_email, "password123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&login_body(&random_email, "password123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Only a deleted account can be restored.
+    let response = app
+        .post_restore_account(&login_body(&random_email, "password123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_purge_account_after_grace_period() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+    let user_id = app.get_user_id(&random_email).await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let user_emails = |emails: Vec<OutboxEmail>| {
+        emails
+            .into_iter()
+            .filter(|email| email.user_id == user_id)
+            .count()
+    };
+    let emails = app.email_outbox_store.get_emails(None, 100).await.unwrap();
+    assert!(user_emails(emails) > 0);
+
+    // Still within the grace period.
+    let purged = purge_deleted_accounts(
+        &app.user_store,
+        &app.audit_log_store,
+        &app.email_outbox_store,
+        &app.webhook_store,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    assert!(purged.is_empty());
+
+    let after_grace_period =
+        Utc::now() + chrono::Duration::try_days(ACCOUNT_DELETION_GRACE_PERIOD_DAYS + 1).unwrap();
+    let purged = purge_deleted_accounts(
+        &app.user_store,
+        &app.audit_log_store,
+        &app.email_outbox_store,
+        &app.webhook_store,
+        after_grace_period,
+    )
+    .await
+    .unwrap();
+    assert_eq!(purged, vec![user_id]);
+
+    // Nothing the outbox sent to the account is left.
+    let emails = app.email_outbox_store.get_emails(None, 100).await.unwrap();
+    assert_eq!(user_emails(emails), 0);
+
+    let response = app
+        .post_restore_account(&login_body(&random_email, "password123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The address is free again.
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_purge_deleted_account_when_its_email_signs_up_again() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+    let old_user_id = app.get_user_id(&random_email).await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The signup looks like any other, so it doesn't reveal the deleted account.
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": random_email,
+            "password": "new_password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    assert_ne!(app.get_user_id(&random_email).await, old_user_id);
+
+    // The deleted account is gone for good.
+    let response = app
+        .post_restore_account(&login_body(&random_email, "password123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "new_password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
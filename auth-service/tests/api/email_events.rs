@@ -0,0 +1,179 @@
+use auth_service::{
+    routes::{AccountResponse, UndeliverableEmailsResponse},
+    utils::constants::test,
+};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+fn hard_bounce(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "TypeCode": 1,
+        "Email": email,
+        "Description": "The server was unable to deliver your message",
+    })
+}
+
+async fn get_account(app: &TestApp) -> AccountResponse {
+    let response = app.get_account().await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn should_return_401_without_the_shared_secret() {
+    let mut app = TestApp::new().await;
+
+    let body = hard_bounce(&get_random_email());
+    for secret in [None, Some("wrong-secret")] {
+        let response = app.post_postmark_event(&body, secret).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_accept_the_secret_as_basic_auth_password() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .basic_auth("postmark", Some(test::POSTMARK_WEBHOOK_SECRET))
+        .json(&hard_bounce(&get_random_email()))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_flag_the_email_after_a_hard_bounce() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+    assert!(get_account(&app).await.email_undeliverable.is_none());
+
+    let response = app
+        .post_postmark_event(
+            &hard_bounce(&random_email),
+            Some(test::POSTMARK_WEBHOOK_SECRET),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let account = get_account(&app).await;
+    let undeliverable = account
+        .email_undeliverable
+        .expect("The email was not flagged");
+    assert_eq!(undeliverable.reason, "hard_bounce");
+
+    let response = app.get_undeliverable_emails().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let listed: UndeliverableEmailsResponse = response.json().await.unwrap();
+    assert_eq!(listed.users.len(), 1);
+    assert_eq!(listed.users[0].email, random_email);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_ignore_soft_bounces() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+
+    let response = app
+        .post_postmark_event(
+            &serde_json::json!({
+                "RecordType": "Bounce",
+                "Type": "SoftBounce",
+                "Email": random_email,
+            }),
+            Some(test::POSTMARK_WEBHOOK_SECRET),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert!(get_account(&app).await.email_undeliverable.is_none());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_send_2fa_codes_to_an_undeliverable_email() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_postmark_event(
+            &serde_json::json!({
+                "RecordType": "SpamComplaint",
+                "Email": random_email,
+            }),
+            Some(test::POSTMARK_WEBHOOK_SECRET),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_again_once_an_admin_clears_the_flag() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+    let user_id = app.get_user_id(&random_email).await.to_string();
+
+    let response = app
+        .post_postmark_event(
+            &hard_bounce(&random_email),
+            Some(test::POSTMARK_WEBHOOK_SECRET),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.delete_email_undeliverable(&user_id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    assert!(get_account(&app).await.email_undeliverable.is_none());
+    let listed: UndeliverableEmailsResponse =
+        app.get_undeliverable_emails().await.json().await.unwrap();
+    assert!(listed.users.is_empty());
+
+    app.clean_up().await;
+}
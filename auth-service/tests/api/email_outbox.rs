@@ -0,0 +1,106 @@
+use auth_service::domain::{OutboxEmailStatus, OUTBOX_EMAIL_MAX_ATTEMPTS};
+use chrono::{Duration, Utc};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Changing the password sends a best-effort notification, which is what these tests follow
+// through the outbox.
+async fn change_password(app: &TestApp) {
+    app.signup_and_login(&get_random_email()).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "new_password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn outbox_status(app: &TestApp) -> (OutboxEmailStatus, i32) {
+    let emails = app.email_outbox_store.get_emails(None, 10).await.unwrap();
+    assert_eq!(emails.len(), 1);
+    (emails[0].status, emails[0].attempts)
+}
+
+#[tokio::test]
+async fn should_not_fail_the_request_when_the_email_provider_is_down() {
+    let mut app = TestApp::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    change_password(&app).await;
+
+    assert_eq!(outbox_status(&app).await, (OutboxEmailStatus::Pending, 0));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_retry_until_the_email_is_sent() {
+    let mut app = TestApp::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    change_password(&app).await;
+
+    let now = Utc::now();
+    assert_eq!(app.deliver_emails(now).await, 1);
+    assert_eq!(outbox_status(&app).await, (OutboxEmailStatus::Pending, 1));
+
+    // Not due again until the backoff has passed.
+    assert_eq!(app.deliver_emails(now).await, 0);
+
+    let later = now + Duration::try_minutes(1).unwrap();
+    assert_eq!(app.deliver_emails(later).await, 1);
+    assert_eq!(outbox_status(&app).await, (OutboxEmailStatus::Sent, 2));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_give_up_after_the_last_attempt() {
+    let mut app = TestApp::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(OUTBOX_EMAIL_MAX_ATTEMPTS as u64)
+        .mount(&app.email_server)
+        .await;
+
+    change_password(&app).await;
+
+    let mut now = Utc::now();
+    for _ in 0..OUTBOX_EMAIL_MAX_ATTEMPTS {
+        assert_eq!(app.deliver_emails(now).await, 1);
+        now += Duration::try_days(1).unwrap();
+    }
+
+    assert_eq!(
+        outbox_status(&app).await,
+        (OutboxEmailStatus::Failed, OUTBOX_EMAIL_MAX_ATTEMPTS)
+    );
+    assert_eq!(app.deliver_emails(now).await, 0);
+
+    app.clean_up().await;
+}
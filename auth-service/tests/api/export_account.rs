@@ -0,0 +1,147 @@
+use auth_service::{
+    domain::{Email, EmailUndeliverable, UndeliverableReason},
+    routes::AccountExport,
+};
+use chrono::Utc;
+use secrecy::Secret;
+
+use crate::helpers::{get_random_email, TestApp};
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_account_export().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_stored_data_of_logged_in_user() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+            "requires2FA": false,
+            "locale": "fr"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_account_export().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"account-export.json\""
+    );
+
+    let body = response.text().await.unwrap();
+    assert!(!body.contains("password"));
+
+    let export: AccountExport = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        export.user.id,
+        app.get_user_id(&random_email).await.to_string()
+    );
+    assert_eq!(export.user.email, random_email);
+    assert!(!export.user.requires_2fa);
+    assert_eq!(export.user.locale, "fr");
+    assert!(export.passkeys.is_empty());
+    assert_eq!(export.active_sessions, 1);
+    assert!(export.user.email_undeliverable.is_none());
+    assert!(export.emails.is_empty());
+    assert!(export.webhook_deliveries.is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_include_audit_events_emails_and_webhook_deliveries() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+    let response = app
+        .post_webhook_subscription(&serde_json::json!({
+            "url": format!("{}/hooks", app.webhook_server.uri()),
+            "eventTypes": ["user.signed_up"],
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+    let user_id = app.get_user_id(&random_email).await;
+    let response = app
+        .post_magic_link(&serde_json::json!({ "email": random_email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.user_store
+        .mark_email_undeliverable(
+            &Email::parse(Secret::new(random_email.clone())).unwrap(),
+            EmailUndeliverable {
+                reason: UndeliverableReason::HardBounce,
+                since: Utc::now(),
+            },
+        )
+        .await
+        .unwrap();
+
+    // Someone else's data stays out of the export.
+    app.signup_and_login(&get_random_email()).await;
+    let response = app
+        .http_client
+        .post(format!("{}/login", &app.address))
+        .header("User-Agent", "export-test")
+        .json(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_account_export().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let export: AccountExport = response.json().await.unwrap();
+
+    let undeliverable = export.user.email_undeliverable.unwrap();
+    assert_eq!(undeliverable.reason, "hard_bounce");
+
+    // Newest first, so this is the login above.
+    let login = export
+        .audit_events
+        .iter()
+        .find(|event| event.event_type == "login")
+        .expect("No login event exported");
+    assert_eq!(login.ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(login.user_agent.as_deref(), Some("export-test"));
+    assert!(login.request_id.is_some());
+    assert!(export
+        .audit_events
+        .iter()
+        .all(|event| event.user_id == Some(user_id.to_string())));
+
+    assert_eq!(export.emails.len(), 1);
+    assert_eq!(export.emails[0].kind, "magic_link");
+    assert_eq!(export.emails[0].recipient, random_email);
+
+    assert_eq!(export.webhook_deliveries.len(), 1);
+    assert_eq!(export.webhook_deliveries[0].event_type, "user.signed_up");
+    let payload: serde_json::Value =
+        serde_json::from_str(&export.webhook_deliveries[0].payload).unwrap();
+    assert_eq!(payload["data"]["userId"], user_id.to_string());
+
+    app.clean_up().await;
+}
@@ -0,0 +1,89 @@
+use auth_service::routes::{HealthResponse, HealthStatus};
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn liveness_should_return_200() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_health("live").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<HealthResponse>()
+        .await
+        .expect("Could not deserialize response body to HealthResponse");
+    assert_eq!(body.status, HealthStatus::Ok);
+    assert!(body.checks.is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn readiness_should_report_every_dependency() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_health("ready").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<HealthResponse>()
+        .await
+        .expect("Could not deserialize response body to HealthResponse");
+    assert_eq!(body.status, HealthStatus::Ok);
+    assert_eq!(
+        body.checks.keys().collect::<Vec<_>>(),
+        vec!["email", "postgres", "redis"]
+    );
+    assert!(body
+        .checks
+        .values()
+        .all(|dependency| dependency.status == HealthStatus::Ok));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn readiness_should_return_503_when_postgres_is_unavailable() {
+    let mut app = TestApp::new().await;
+    app.pg_pool.close().await;
+
+    let response = app.get_health("ready").await;
+    assert_eq!(response.status().as_u16(), 503);
+
+    let body = response
+        .json::<HealthResponse>()
+        .await
+        .expect("Could not deserialize response body to HealthResponse");
+    assert_eq!(body.status, HealthStatus::Failing);
+    assert_eq!(body.checks["postgres"].status, HealthStatus::Failing);
+    assert_eq!(body.checks["redis"].status, HealthStatus::Ok);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn readiness_should_return_503_until_the_drain_delay_is_over() {
+    let mut app = TestApp::new().await;
+    app.shutdown.trigger();
+
+    let response = app.get_health("ready").await;
+    assert_eq!(response.status().as_u16(), 503);
+
+    let body = response
+        .json::<HealthResponse>()
+        .await
+        .expect("Could not deserialize response body to HealthResponse");
+    assert_eq!(body.status, HealthStatus::Failing);
+
+    // The server stops accepting connections once the delay is over.
+    tokio::time::sleep(app.settings.application.shutdown_drain_delay() * 2).await;
+    let result = app
+        .http_client
+        .get(format!("{}/health/ready", &app.address))
+        .send()
+        .await;
+    assert!(result.is_err());
+
+    app.clean_up().await;
+}
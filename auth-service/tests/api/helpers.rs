@@ -1,4 +1,6 @@
-use auth_service::get_redis_client;
+use auth_service::{get_redis_client, get_redis_connection};
+use chrono::{DateTime, Utc};
+use redis::aio::ConnectionManager;
 use reqwest::cookie::Jar;
 use reqwest::Client;
 use secrecy::Secret;
@@ -6,25 +8,43 @@ use sqlx::postgres::{PgConnectOptions, PgConnection, PgPoolOptions};
 use sqlx::Connection;
 use sqlx::Executor;
 use sqlx::PgPool;
-use std::str::FromStr;
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 use auth_service::{
-    app_state::{AppState, BannedTokenStoreType, EmailClientType, TwoFACodeStoreType},
-    domain::Email,
+    app_state::{
+        AppState, AuditLogStoreType, BannedTokenStoreType, EmailClientType, EmailOutboxStoreType,
+        PasskeyStoreType, PhoneVerificationStoreType, SessionStoreType, SmsClientType,
+        TwoFACodeStoreType, UserStoreType, WebhookStoreType,
+    },
+    domain::{Email, EmailDeliveryPolicy, PhoneNumber, ReloadableSecret, UserId},
     get_postgres_pool,
-    //services::data_stores::hashmap_two_fa_code_store::HashmapTwoFACodeStore,
-    //services::data_stores::hashmap_user_store::HashmapUserStore,
-    services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore,
+    routes::POSTMARK_WEBHOOK_SECRET_HEADER,
+    services::data_stores::PostgresAuditLogStore,
+    services::data_stores::PostgresEmailOutboxStore,
+    services::data_stores::PostgresPasskeyStore,
     services::data_stores::PostgresUserStore,
+    services::data_stores::PostgresWebhookStore,
     services::data_stores::RedisBannedTokenStore,
+    services::data_stores::RedisPhoneVerificationStore,
+    services::data_stores::RedisSessionStore,
     services::data_stores::RedisTwoFACodeStore,
-    services::mock_email_client::MockEmailClient,
+    services::email_outbox::deliver_due_emails,
+    services::health_checks::{EmailHealthCheck, PostgresHealthCheck, RedisHealthCheck},
     services::postmark_email_client::PostmarkEmailClient,
-    utils::constants::{test, DATABASE_URL, REDIS_HOST_NAME},
+    services::suppressing_email_client::SuppressingEmailClient,
+    services::twilio_sms_client::TwilioSmsClient,
+    services::webhooks::deliver_due_webhooks,
+    settings::Settings,
+    utils::{
+        constants::{env, test, JWT_COOKIE_NAME},
+        metrics::prometheus_handle,
+        shutdown::Shutdown,
+        webauthn::build_webauthn,
+    },
     Application,
 };
 
@@ -33,9 +53,20 @@ pub struct TestApp {
     pub cookie_jar: Arc<Jar>,
     pub http_client: reqwest::Client,
     pub email_server: MockServer,
+    pub user_store: UserStoreType,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub passkey_store: PasskeyStoreType,
+    pub audit_log_store: AuditLogStoreType,
+    pub webhook_store: WebhookStoreType,
+    pub webhook_server: MockServer,
+    pub email_outbox_store: EmailOutboxStoreType,
+    pub pg_pool: PgPool,
     pub email_client: EmailClientType,
+    pub sms_server: MockServer,
+    pub settings: Arc<Settings>,
+    // Fails the readiness check, and the server stops once the drain delay is over.
+    pub shutdown: Shutdown,
     pub clean_up_called: bool,
     pub db_name: String,
 }
@@ -44,36 +75,81 @@ impl TestApp {
     pub async fn new() -> Self {
         // We are creating a new database for each test case, and we need to ensure each database has a unique name!
         let db_name = Uuid::new_v4().to_string();
-        let pg_pool = configure_postgresql(&db_name).await;
+        let settings = Arc::new(configure_settings().await);
+        let pg_pool = configure_postgresql(&settings, &db_name).await;
 
-        //let user_store = Arc::new(RwLock::new(HashmapUserStore::default()));
-        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+        //let user_store = Arc::new(HashmapUserStore::default());
+        let user_store: UserStoreType = Arc::new(PostgresUserStore::new(
+            pg_pool.clone(),
+            settings.password_hashing.params(),
+        ));
 
-        //    let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let redis_connection = Arc::new(RwLock::new(configure_redis()));
-        let banned_token_store: BannedTokenStoreType = Arc::new(RwLock::new(
-            RedisBannedTokenStore::new(redis_connection.clone()),
+        //    let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let redis_connection = configure_redis(&settings).await;
+        let banned_token_store: BannedTokenStoreType = Arc::new(RedisBannedTokenStore::new(
+            redis_connection.clone(),
+            settings.jwt.token_ttl_seconds,
         ));
 
-        //let two_fa_code_store = Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
-        let redis_connection2 = Arc::new(RwLock::new(configure_redis()));
-        let two_fa_code_store: TwoFACodeStoreType = Arc::new(RwLock::new(
-            RedisTwoFACodeStore::new(redis_connection2.clone()),
+        //let two_fa_code_store = Arc::new(HashmapTwoFACodeStore::default());
+        let two_fa_code_store: TwoFACodeStoreType =
+            Arc::new(RedisTwoFACodeStore::new(redis_connection.clone()));
+
+        let session_store: SessionStoreType = Arc::new(RedisSessionStore::new(
+            redis_connection.clone(),
+            settings.jwt.token_ttl_seconds,
         ));
 
+        let phone_verification_store: PhoneVerificationStoreType =
+            Arc::new(RedisPhoneVerificationStore::new(redis_connection.clone()));
+
+        let passkey_store: PasskeyStoreType = Arc::new(PostgresPasskeyStore::new(pg_pool.clone()));
+
+        let audit_log_store: AuditLogStoreType =
+            Arc::new(PostgresAuditLogStore::new(pg_pool.clone()));
+
+        let webhook_store: WebhookStoreType = Arc::new(PostgresWebhookStore::new(pg_pool.clone()));
+        let webhook_server = MockServer::start().await;
+
+        let email_outbox_store: EmailOutboxStoreType =
+            Arc::new(PostgresEmailOutboxStore::new(pg_pool.clone()));
+
         let email_server = MockServer::start().await;
         let base_url = email_server.uri();
-        let email_client = Arc::new(RwLock::new(configure_postmark_email_client(base_url)));
-        //        let email_client = Arc::new(RwLock::new(MockEmailClient));
+        let email_client: EmailClientType = Arc::new(SuppressingEmailClient::new(
+            configure_postmark_email_client(base_url),
+            user_store.clone(),
+        ));
+        //        let email_client = Arc::new(MockEmailClient);
 
+        let sms_server = MockServer::start().await;
+        let sms_client: SmsClientType = Arc::new(configure_twilio_sms_client(sms_server.uri()));
+
+        let shutdown = Shutdown::new();
         let app_state = AppState {
-            user_store,
+            user_store: user_store.clone(),
             banned_token_store: banned_token_store.clone(),
             two_fa_code_store: two_fa_code_store.clone(),
+            session_store,
+            passkey_store: passkey_store.clone(),
+            audit_log_store: audit_log_store.clone(),
+            webhook_store: webhook_store.clone(),
+            email_outbox_store: email_outbox_store.clone(),
             email_client: email_client.clone(),
+            phone_verification_store,
+            sms_client,
+            health_checks: vec![
+                Arc::new(PostgresHealthCheck::new(pg_pool.clone())),
+                Arc::new(RedisHealthCheck::new(redis_connection.clone())),
+                Arc::new(EmailHealthCheck::new(email_client.clone())),
+            ],
+            shutdown: shutdown.clone(),
+            metrics: prometheus_handle(),
+            settings: settings.clone(),
+            webauthn: Arc::new(build_webauthn(&settings.application.base_url)),
         };
 
-        let app = Application::build(app_state, test::APP_ADDRESS)
+        let app = Application::build(app_state)
             .await
             .expect("Failed to build app");
 
@@ -82,7 +158,11 @@ impl TestApp {
         // Run the auth service in a separate async task
         // to avoid blocking the main test thread.
         #[allow(clippy::let_underscore_future)]
-        let _ = tokio::spawn(app.run());
+        let _ = tokio::spawn(app.run_until_shutdown(
+            shutdown.clone(),
+            settings.application.shutdown_drain_delay(),
+            settings.application.shutdown_timeout(),
+        ));
 
         let cookie_jar = Arc::new(Jar::default());
         let http_client = reqwest::Client::builder()
@@ -95,22 +175,97 @@ impl TestApp {
             cookie_jar,
             http_client,
             email_server,
+            user_store,
             banned_token_store,
             two_fa_code_store,
+            passkey_store,
+            audit_log_store,
+            webhook_store,
+            webhook_server,
+            email_outbox_store,
+            pg_pool,
             email_client,
+            sms_server,
+            settings,
+            shutdown,
             clean_up_called: false,
             db_name,
         }
     }
 
     pub async fn clean_up(&mut self) {
-        delete_database(&self.db_name).await;
+        delete_database(&self.settings, &self.db_name).await;
         self.clean_up_called = true
     }
 
+    // Stores are keyed by user id, which clients never see.
+    pub async fn get_user_id(&self, email: &str) -> UserId {
+        let email = Email::parse(Secret::new(email.to_owned())).unwrap();
+        self.user_store
+            .get_user(&email)
+            .await
+            .expect("User not found")
+            .id
+    }
+
+    // Best-effort emails wait in the outbox until the dispatcher runs, which the tests do by hand.
+    pub async fn deliver_emails(&self, now: DateTime<Utc>) -> usize {
+        deliver_due_emails(&self.email_outbox_store, &self.email_client, now)
+            .await
+            .expect("Failed to deliver emails")
+    }
+
+    // Webhook deliveries are sent the same way.
+    pub async fn deliver_webhooks(&self, now: DateTime<Utc>) -> usize {
+        deliver_due_webhooks(&self.webhook_store, &self.http_client, now)
+            .await
+            .expect("Failed to deliver webhooks")
+    }
+
+    // Makes the mock Postmark server accept every email.
+    pub async fn mount_email_server(&self) {
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&self.email_server)
+            .await;
+    }
+
     pub async fn get_root(&self) -> reqwest::Response {
         self.http_client
-            .get(&format!("{}/", &self.address))
+            .get(format!("{}/", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_openapi(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/openapi.json", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_docs(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/docs", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_health(&self, probe: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/health/{}", &self.address, probe))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_metrics(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/metrics", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -128,7 +283,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/signup", &self.address))
+            .post(format!("{}/signup", &self.address))
             .json(body)
             .send()
             .await
@@ -141,16 +296,373 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Signs up a user with the password `password123`.
+    pub async fn signup(&self, email: &str, requires_2fa: bool) {
+        let response = self
+            .post_signup(&serde_json::json!({
+                "email": email,
+                "password": "password123",
+                "requires2FA": requires_2fa
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 201);
+    }
+
+    // Signs up and logs in a user without 2FA, with the password `password123`, returning the
+    // auth token of that session.
+    pub async fn signup_and_login(&self, email: &str) -> String {
+        self.signup(email, false).await;
+        self.login(email, "password123").await
+    }
+
+    // Logs in a user without 2FA and returns the auth token of the new session.
+    pub async fn login(&self, email: &str, password: &str) -> String {
+        let response = self.post_login(&login_body(email, password)).await;
+        assert_eq!(response.status().as_u16(), 200);
+
+        let token = response
+            .cookies()
+            .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+            .expect("No auth cookie found")
+            .value()
+            .to_owned();
+        token
+    }
+
+    pub async fn post_magic_link<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/login/magic-link", &self.address))
             .json(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_magic_link_callback(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/login/magic-link/callback", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_magic_link_callback<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/login/magic-link/callback", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_passkey_register_start(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/passkeys/register/start", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_passkey_register_finish<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/passkeys/register/finish", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_passkey_authenticate_start<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/passkeys/authenticate/start", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login_passkey<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/login/passkey", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_account<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .delete(format!("{}/account", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_restore_account<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/restore", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_account(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/account", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_account_export(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/account/export", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // `query` is appended as is, so it is expected to be url encoded already.
+    pub async fn get_audit_events(&self, query: &str, token: Option<&str>) -> reqwest::Response {
+        let mut request = self
+            .http_client
+            .get(format!("{}/admin/audit-events?{}", &self.address, query));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn post_webhook_subscription<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/webhooks", &self.address))
+            .bearer_auth(test::ADMIN_API_TOKEN)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_webhook_subscriptions(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/webhooks", &self.address))
+            .bearer_auth(test::ADMIN_API_TOKEN)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_webhook_subscription(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/admin/webhooks/{}", &self.address, id))
+            .bearer_auth(test::ADMIN_API_TOKEN)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_webhook_deliveries(&self, query: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!(
+                "{}/admin/webhooks/deliveries?{}",
+                &self.address, query
+            ))
+            .bearer_auth(test::ADMIN_API_TOKEN)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_replay_webhook_delivery(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!(
+                "{}/admin/webhooks/deliveries/{}/replay",
+                &self.address, id
+            ))
+            .bearer_auth(test::ADMIN_API_TOKEN)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_undeliverable_emails(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/undeliverable-emails", &self.address))
+            .bearer_auth(test::ADMIN_API_TOKEN)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_email_undeliverable(&self, user_id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!(
+                "{}/admin/users/{}/email-undeliverable",
+                &self.address, user_id
+            ))
+            .bearer_auth(test::ADMIN_API_TOKEN)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_postmark_event<Body>(
+        &self,
+        body: &Body,
+        secret: Option<&str>,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut request = self
+            .http_client
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .json(body);
+        if let Some(secret) = secret {
+            request = request.header(POSTMARK_WEBHOOK_SECRET_HEADER, secret);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/email", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_confirm_email_change<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/email/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_cancel_email_change<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/email/cancel", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_phone_number<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/phone", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_phone_number<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/phone/verify", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_two_fa_channel<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/2fa-channel", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // The form fields of every SMS sent through the mock Twilio server, oldest first.
+    pub async fn sent_sms(&self) -> Vec<HashMap<String, String>> {
+        self.sms_server
+            .received_requests()
+            .await
+            .expect("Request recording is disabled")
+            .iter()
+            .map(|request| {
+                let body = String::from_utf8_lossy(&request.body);
+                reqwest::Url::parse(&format!("http://sms.test/?{}", body))
+                    .unwrap()
+                    .query_pairs()
+                    .into_owned()
+                    .collect()
+            })
+            .collect()
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.http_client
-            .post(&format!("{}/logout", &self.address))
+            .post(format!("{}/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -168,12 +680,24 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_verify_2fa_passkey<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-2fa/passkey", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_verify_token<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/verify-token", &self.address))
+            .post(format!("{}/verify-token", &self.address))
             .json(body)
             .send()
             .await
@@ -185,23 +709,54 @@ pub fn get_random_email() -> String {
     format!("{}@example.com", Uuid::new_v4())
 }
 
-async fn configure_postgresql(db_name: &str) -> PgPool {
-    let postgresql_conn_url = DATABASE_URL.to_owned();
+pub fn login_body(email: &str, password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "email": email,
+        "password": password,
+    })
+}
 
-    configure_database(&postgresql_conn_url, &db_name).await;
+// Loaded like the service loads them, so the tests use the same database and Redis. Emails and
+// SMS go to mock servers, so no provider needs to be configured.
+async fn configure_settings() -> Settings {
+    dotenvy::dotenv().ok();
+    let mut vars: HashMap<String, String> = std::env::vars().collect();
+    vars.insert(env::EMAIL_PROVIDERS_ENV_VAR.to_owned(), "mock".to_owned());
+    vars.insert(env::SMS_PROVIDER_ENV_VAR.to_owned(), "mock".to_owned());
 
-    let postgresql_conn_url_with_db = format!("{}/{}", postgresql_conn_url, db_name);
+    let mut settings = Settings::from_env(vars)
+        .await
+        .expect("Failed to load settings");
+    settings.application.address = test::APP_ADDRESS.to_owned();
+    settings.application.shutdown_drain_delay_seconds = 1;
+    settings.admin.api_token = Some(ReloadableSecret::new(Secret::new(
+        test::ADMIN_API_TOKEN.to_owned(),
+    )));
+    settings.email.postmark.webhook_secret = Some(ReloadableSecret::new(Secret::new(
+        test::POSTMARK_WEBHOOK_SECRET.to_owned(),
+    )));
+    settings.email.delivery_policy = EmailDeliveryPolicy::default();
+    settings
+}
+
+async fn configure_postgresql(settings: &Settings, db_name: &str) -> PgPool {
+    let connection_options = settings
+        .database
+        .connect_options()
+        .expect("Failed to parse PostgreSQL connection string");
+
+    configure_database(&connection_options, db_name).await;
 
     // Create a new connection pool and return it
-    get_postgres_pool(&postgresql_conn_url_with_db)
+    get_postgres_pool(connection_options.database(db_name))
         .await
         .expect("Failed to create Postgres connection pool!")
 }
 
-async fn configure_database(db_conn_string: &str, db_name: &str) {
+async fn configure_database(connection_options: &PgConnectOptions, db_name: &str) {
     // Create database connection
     let connection = PgPoolOptions::new()
-        .connect(db_conn_string)
+        .connect_with(connection_options.clone())
         .await
         .expect("Failed to create Postgres connection pool.");
 
@@ -212,10 +767,8 @@ async fn configure_database(db_conn_string: &str, db_name: &str) {
         .expect("Failed to create database.");
 
     // Connect to new database
-    let db_conn_string = format!("{}/{}", db_conn_string, db_name);
-
     let connection = PgPoolOptions::new()
-        .connect(&db_conn_string)
+        .connect_with(connection_options.clone().database(db_name))
         .await
         .expect("Failed to create Postgres connection pool.");
 
@@ -226,10 +779,10 @@ async fn configure_database(db_conn_string: &str, db_name: &str) {
         .expect("Failed to migrate the database");
 }
 
-async fn delete_database(db_name: &str) {
-    let postgresql_conn_url: String = DATABASE_URL.to_owned();
-
-    let connection_options = PgConnectOptions::from_str(&postgresql_conn_url)
+async fn delete_database(settings: &Settings, db_name: &str) {
+    let connection_options = settings
+        .database
+        .connect_options()
         .expect("Failed to parse PostgreSQL connection string");
 
     let mut connection = PgConnection::connect_with(&connection_options)
@@ -260,15 +813,16 @@ async fn delete_database(db_name: &str) {
         .expect("Failed to drop the database.");
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
+async fn configure_redis(settings: &Settings) -> ConnectionManager {
+    let client =
+        get_redis_client(settings.redis.host_name.to_owned()).expect("Failed to get Redis client");
+    get_redis_connection(client)
+        .await
         .expect("Failed to get Redis connection")
 }
 
 fn configure_postmark_email_client(base_url: String) -> PostmarkEmailClient {
-    let postmark_auth_token = Secret::new("auth_token".to_owned());
+    let postmark_auth_token = ReloadableSecret::new(Secret::new("auth_token".to_owned()));
 
     let sender = Email::parse(Secret::new(test::email_client::SENDER.to_owned())).unwrap();
 
@@ -279,3 +833,20 @@ fn configure_postmark_email_client(base_url: String) -> PostmarkEmailClient {
 
     PostmarkEmailClient::new(base_url, sender, postmark_auth_token, http_client)
 }
+
+fn configure_twilio_sms_client(base_url: String) -> TwilioSmsClient {
+    let sender = PhoneNumber::parse(Secret::new(test::sms_client::SENDER.to_owned())).unwrap();
+
+    let http_client = Client::builder()
+        .timeout(test::sms_client::TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client");
+
+    TwilioSmsClient::new(
+        base_url,
+        test::sms_client::ACCOUNT_SID.to_owned(),
+        ReloadableSecret::new(Secret::new("auth_token".to_owned())),
+        sender,
+        http_client,
+    )
+}
@@ -0,0 +1,178 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use auth_service::{
+    domain::data_stores::TwoFACode,
+    routes::TwoFactorAuthResponse,
+    utils::{
+        constants::JWT_COOKIE_NAME,
+        tracing::{fmt_layer, otel_layer, otlp_tracer_provider, LogFormat},
+    },
+};
+use tracing_subscriber::{fmt::MakeWriter, prelude::*, EnvFilter};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Everything written to the logs, to look for secrets in afterwards.
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLogs {
+    type Writer = CapturedLogs;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+impl io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// A code can't be told apart from digits in a timestamp, so it only counts as a whole word.
+fn contains_word(haystack: &str, word: &str) -> bool {
+    haystack.match_indices(word).any(|(start, _)| {
+        let before = haystack[..start].chars().next_back();
+        let after = haystack[start + word.len()..].chars().next();
+        !before.is_some_and(|c| c.is_alphanumeric()) && !after.is_some_and(|c| c.is_alphanumeric())
+    })
+}
+
+// Goes through signup, login with 2FA, token verification and a magic link with every log
+// line and span recorded, and fails if any secret of the flow shows up in them.
+#[tokio::test]
+async fn should_not_leak_secrets_into_logs_or_traces() {
+    let compact_logs = CapturedLogs::default();
+    let json_logs = CapturedLogs::default();
+    let collector = MockServer::start().await;
+    Mock::given(path("/v1/traces"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&collector)
+        .await;
+    let tracer_provider = otlp_tracer_provider(&format!("{}/v1/traces", collector.uri())).unwrap();
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::registry()
+            .with(EnvFilter::new("debug"))
+            .with(fmt_layer(LogFormat::Compact, compact_logs.clone()))
+            .with(fmt_layer(LogFormat::Json, json_logs.clone()))
+            .with(otel_layer(&tracer_provider)),
+    );
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let password = format!("Pass-{}", Uuid::new_v4());
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": password,
+        "requires2FA": true
+    });
+    assert_eq!(app.post_signup(&signup_body).await.status().as_u16(), 201);
+
+    app.mount_email_server().await;
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": password,
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id;
+
+    let code: TwoFACode = app
+        .two_fa_code_store
+        .get_code(&app.get_user_id(&email).await)
+        .await
+        .expect("No 2FA code stored")
+        .1;
+    let verify_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code.as_ref(),
+    });
+    let response = app.post_verify_2fa(&verify_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let jwt = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": jwt }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let magic_link_token = Uuid::new_v4().to_string();
+    let response = app.get_magic_link_callback(&magic_link_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Lets the last request span close before the spans are sent.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    tracer_provider.force_flush();
+    let traces = collector
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|request| String::from_utf8_lossy(&request.body).into_owned())
+        .collect::<String>();
+
+    let compact_logs = compact_logs.contents();
+    let json_logs = json_logs.contents();
+    assert!(compact_logs.contains("/login/magic-link/callback"));
+    assert!(traces.contains("/login/magic-link/callback"));
+    for line in json_logs.lines() {
+        serde_json::from_str::<serde_json::Value>(line)
+            .unwrap_or_else(|e| panic!("Log line is not JSON ({}): {}", e, line));
+    }
+
+    let secrets = [
+        ("email", email.as_str()),
+        ("password", password.as_str()),
+        ("2FA code", code.as_ref()),
+        ("JWT", jwt.as_str()),
+        ("magic link token", magic_link_token.as_str()),
+    ];
+    for (output, contents) in [
+        ("compact logs", &compact_logs),
+        ("JSON logs", &json_logs),
+        ("traces", &traces),
+    ] {
+        for (secret, value) in secrets {
+            assert!(
+                !contains_word(contents, value),
+                "The {} leaked into the {}",
+                secret,
+                output
+            );
+        }
+    }
+
+    app.clean_up().await;
+}
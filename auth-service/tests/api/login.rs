@@ -1,8 +1,8 @@
 use crate::helpers::{get_random_email, TestApp};
-use auth_service::domain::Email;
+use auth_service::domain::OutboxEmailStatus;
 use auth_service::routes::TwoFactorAuthResponse;
-use auth_service::{routes::SignupResponse, utils::constants::JWT_COOKIE_NAME, ErrorResponse};
-use secrecy::{ExposeSecret, Secret};
+use auth_service::utils::{constants::JWT_COOKIE_NAME, problem::ProblemDetails};
+use chrono::Utc;
 use wiremock::matchers::method;
 use wiremock::matchers::path;
 use wiremock::Mock;
@@ -70,11 +70,11 @@ async fn should_return_400_if_invalid_input() {
 
         assert_eq!(
             response
-                .json::<ErrorResponse>()
+                .json::<ProblemDetails>()
                 .await
-                .expect("Could not deserialize response body to ErrorResponse")
-                .error,
-            "Invalid credentials".to_owned()
+                .expect("Could not deserialize response body to ProblemDetails")
+                .code,
+            "request.validation_failed".to_owned()
         );
     }
 
@@ -132,11 +132,11 @@ async fn should_return_401_if_incorrect_credentials() {
 
         assert_eq!(
             response
-                .json::<ErrorResponse>()
+                .json::<ProblemDetails>()
                 .await
-                .expect("Could not deserialize response body to ErrorResponse")
-                .error,
-            "Incorrect credentials".to_owned()
+                .expect("Could not deserialize response body to ProblemDetails")
+                .code,
+            "auth.incorrect_credentials".to_owned()
         );
     }
 
@@ -211,6 +211,12 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
 
     assert_eq!(response.status().as_u16(), 206);
 
+    // The auth cookie is only issued after the 2FA code is verified.
+    assert!(response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .is_none());
+
     assert_eq!(
         response
             .json::<TwoFactorAuthResponse>()
@@ -222,13 +228,113 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
 
     {
         // TODO: assert that `json_body.login_attempt_id` is stored inside `app.two_fa_code_store`
-        let two_fa_code_store = app.two_fa_code_store.read().await;
+        let two_fa_code_store = &app.two_fa_code_store;
         let actual = two_fa_code_store
-            .get_code(&Email::parse(Secret::new(random_email)).unwrap())
+            .get_code(&app.get_user_id(&random_email).await)
             .await;
 
-        assert_eq!(actual.is_ok(), true);
+        assert!(actual.is_ok());
     }
 
     app.clean_up().await;
 }
+
+#[tokio::test]
+async fn should_send_the_2fa_code_in_the_users_language() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    let response = app
+        .http_client
+        .post(format!("{}/signup", &app.address))
+        .header("Accept-Language", "pt-BR, de;q=0.9, en;q=0.8")
+        .json(&signup_body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let requests = app
+        .email_server
+        .received_requests()
+        .await
+        .expect("Request recording is disabled");
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["Subject"], "Ihr Anmeldecode");
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("<html lang=\"de\">"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Anmeldung abzuschließen"));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_503_if_2fa_code_cannot_be_sent() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    assert_eq!(
+        response
+            .json::<ProblemDetails>()
+            .await
+            .expect("Could not deserialize response body to ProblemDetails")
+            .code,
+        "email.delivery_failed".to_owned()
+    );
+
+    // The failure is recorded, and the stale code is never sent later.
+    let emails = app.email_outbox_store.get_emails(None, 10).await.unwrap();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].status, OutboxEmailStatus::Failed);
+    assert_eq!(app.deliver_emails(Utc::now()).await, 0);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,282 @@
+use auth_service::{
+    routes::{MagicLinkResponse, TwoFactorAuthResponse},
+    utils::{constants::JWT_COOKIE_NAME, problem::ProblemDetails},
+};
+use chrono::Utc;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Pulls the magic link token out of the last email sent through the mock Postmark server.
+async fn get_token_from_last_email(app: &TestApp) -> String {
+    app.deliver_emails(Utc::now()).await;
+    let requests = app
+        .email_server
+        .received_requests()
+        .await
+        .expect("Request recording is disabled");
+    let body: serde_json::Value =
+        serde_json::from_slice(&requests.last().expect("No email was sent").body).unwrap();
+    let text = body["TextBody"].as_str().unwrap();
+
+    text.split("token=")
+        .nth(1)
+        .expect("No token in email")
+        .split_whitespace()
+        .next()
+        .unwrap()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_magic_link(&serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    let response = app.post_magic_link_callback(&serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_email() {
+    let mut app = TestApp::new().await;
+
+    let test_cases = [
+        serde_json::json!({ "email": "" }),
+        serde_json::json!({ "email": "fooexample.com" }),
+    ];
+
+    for test_case in test_cases.iter() {
+        let response = app.post_magic_link(test_case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            test_case
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_email_if_user_exists() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, false).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_magic_link(&serde_json::json!({ "email": random_email }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.json::<MagicLinkResponse>().await.is_ok());
+    assert_eq!(app.deliver_emails(Utc::now()).await, 1);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_send_email_if_user_does_not_exist() {
+    let mut app = TestApp::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_magic_link(&serde_json::json!({ "email": get_random_email() }))
+        .await;
+
+    // Same answer as for a known user so accounts can't be enumerated.
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.deliver_emails(Utc::now()).await, 0);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_consume_token_on_get() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, false).await;
+
+    app.mount_email_server().await;
+
+    app.post_magic_link(&serde_json::json!({ "email": random_email }))
+        .await;
+    let token = get_token_from_last_email(&app).await;
+
+    // Simulate an email scanner following the link before the user does.
+    let response = app.get_magic_link_callback(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get("content-type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    assert!(response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .is_none());
+
+    let response = app
+        .post_magic_link_callback(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_and_set_cookie_if_valid_token_and_2fa_disabled() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, false).await;
+
+    app.mount_email_server().await;
+
+    app.post_magic_link(&serde_json::json!({ "email": random_email }))
+        .await;
+    let token = get_token_from_last_email(&app).await;
+
+    let response = app
+        .post_magic_link_callback(&serde_json::json!({ "token": token }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    assert!(!auth_cookie.value().is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_206_if_valid_token_and_2fa_enabled() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, true).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_magic_link(&serde_json::json!({ "email": random_email }))
+        .await;
+    let token = get_token_from_last_email(&app).await;
+
+    let response = app
+        .post_magic_link_callback(&serde_json::json!({ "token": token }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 206);
+    assert!(response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .is_none());
+    assert_eq!(
+        response
+            .json::<TwoFactorAuthResponse>()
+            .await
+            .expect("Could not deserialize response body to TwoFactorAuthResponse")
+            .message,
+        "2FA required".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_token_used_twice() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, false).await;
+
+    app.mount_email_server().await;
+
+    app.post_magic_link(&serde_json::json!({ "email": random_email }))
+        .await;
+    let token = get_token_from_last_email(&app).await;
+
+    let response = app
+        .post_magic_link_callback(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_magic_link_callback(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, false).await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    let auth_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    // An auth token must not be accepted as a magic link.
+    let test_cases = ["invalid_token".to_owned(), auth_token];
+
+    for token in test_cases.iter() {
+        let response = app
+            .post_magic_link_callback(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response
+                .json::<ProblemDetails>()
+                .await
+                .expect("Could not deserialize response body to ProblemDetails")
+                .code,
+            "auth.invalid_token".to_owned()
+        );
+    }
+
+    app.clean_up().await;
+}
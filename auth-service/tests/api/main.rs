@@ -1,8 +1,24 @@
 mod helpers;
 
+mod audit_log;
+mod change_email;
+mod change_password;
+mod delete_account;
+mod email_events;
+mod email_outbox;
+mod export_account;
+mod health;
+mod log_redaction;
 mod login;
 mod logout;
+mod magic_link;
+mod metrics;
+mod openapi;
+mod passkey;
+mod phone;
 mod root;
 mod signup;
+mod trace_propagation;
 mod verify_2fa;
 mod verify_token;
+mod webhooks;
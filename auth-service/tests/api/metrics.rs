@@ -0,0 +1,83 @@
+use auth_service::services::metrics_sampler::sample_pool_metrics;
+
+use crate::helpers::TestApp;
+
+// The recorder is shared by every app in the test binary, so the tests only look for the series
+// their requests add to, never at the exact counts.
+fn has_series(metrics: &str, name: &str, labels: &[&str]) -> bool {
+    metrics.lines().any(|line| {
+        line.starts_with(&format!("{}{{", name)) && labels.iter().all(|label| line.contains(label))
+    })
+}
+
+#[tokio::test]
+async fn should_return_metrics_in_the_prometheus_format() {
+    let mut app = TestApp::new().await;
+
+    app.get_root().await;
+
+    let response = app.get_metrics().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/plain")));
+
+    let metrics = response.text().await.expect("Failed to read the metrics");
+    assert!(has_series(
+        &metrics,
+        "http_requests_total",
+        &[r#"route="/""#, r#"status="200""#]
+    ));
+    assert!(has_series(
+        &metrics,
+        "http_request_duration_seconds_bucket",
+        &[r#"route="/""#]
+    ));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_count_failed_logins_by_reason() {
+    let mut app = TestApp::new().await;
+
+    let login = serde_json::json!({
+        "email": "nobody@example.com",
+        "password": "password123",
+    });
+    let response = app.post_login(&login).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let metrics = app.get_metrics().await.text().await.unwrap();
+    assert!(has_series(
+        &metrics,
+        "logins_total",
+        &[
+            r#"outcome="failure""#,
+            r#"reason="auth.incorrect_credentials""#
+        ]
+    ));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_report_the_postgres_pool_usage() {
+    let mut app = TestApp::new().await;
+
+    sample_pool_metrics(&app.pg_pool);
+
+    let metrics = app.get_metrics().await.text().await.unwrap();
+    assert!(has_series(
+        &metrics,
+        "db_pool_connections",
+        &[r#"state="idle""#]
+    ));
+    assert!(metrics
+        .lines()
+        .any(|line| line.starts_with("db_pool_max_connections ")));
+
+    app.clean_up().await;
+}
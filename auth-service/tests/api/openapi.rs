@@ -0,0 +1,45 @@
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn should_serve_the_checked_in_openapi_description() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_openapi().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let served = response
+        .json::<serde_json::Value>()
+        .await
+        .expect("Could not deserialize response body to JSON");
+    let checked_in: serde_json::Value = serde_json::from_str(include_str!("../../openapi.json"))
+        .expect("openapi.json is not valid JSON");
+
+    // Fails when a handler or one of its types changed but the checked-in copy wasn't updated.
+    assert!(
+        served == checked_in,
+        "openapi.json is out of date. Regenerate it with \
+         `cargo run --bin export_openapi > openapi.json`"
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_serve_the_api_docs_page() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_api_docs().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "text/html; charset=utf-8"
+    );
+    assert!(response
+        .text()
+        .await
+        .expect("Failed to read response body")
+        .contains("/openapi.json"));
+
+    app.clean_up().await;
+}
@@ -0,0 +1,392 @@
+use auth_service::{
+    routes::{
+        StartPasskeyAuthenticationResponse, StartPasskeyRegistrationResponse, TwoFactorAuthResponse,
+    },
+    utils::{constants::JWT_COOKIE_NAME, problem::ProblemDetails},
+};
+use webauthn_authenticator_rs::{softpasskey::SoftPasskey, WebauthnAuthenticator};
+use webauthn_rs::prelude::{PublicKeyCredential, Url};
+
+use crate::helpers::{get_random_email, TestApp};
+
+fn origin(app: &TestApp) -> Url {
+    Url::parse(&app.settings.application.base_url).unwrap()
+}
+
+fn new_authenticator() -> WebauthnAuthenticator<SoftPasskey> {
+    WebauthnAuthenticator::new(SoftPasskey::new(true))
+}
+
+// Registers a passkey for the logged in user of `app`.
+async fn register_passkey(app: &TestApp, authenticator: &mut WebauthnAuthenticator<SoftPasskey>) {
+    let response = app.post_passkey_register_start().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let start = response
+        .json::<StartPasskeyRegistrationResponse>()
+        .await
+        .expect("Could not deserialize response body to StartPasskeyRegistrationResponse");
+
+    let credential = authenticator
+        .do_registration(origin(app), start.challenge)
+        .expect("Software authenticator failed to register");
+
+    let response = app
+        .post_passkey_register_finish(&serde_json::json!({
+            "state": start.state,
+            "credential": credential,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+// Runs an assertion ceremony, returning the ceremony state and the signed assertion.
+async fn authenticate(
+    app: &TestApp,
+    authenticator: &mut WebauthnAuthenticator<SoftPasskey>,
+    email: &str,
+) -> (String, PublicKeyCredential) {
+    let response = app
+        .post_passkey_authenticate_start(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let start = response
+        .json::<StartPasskeyAuthenticationResponse>()
+        .await
+        .expect("Could not deserialize response body to StartPasskeyAuthenticationResponse");
+
+    let credential = authenticator
+        .do_authentication(origin(app), start.challenge)
+        .expect("Software authenticator failed to authenticate");
+
+    (start.state, credential)
+}
+
+#[tokio::test]
+async fn should_return_400_if_registering_without_auth_cookie() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_passkey_register_start().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_reveal_whether_an_account_exists_or_has_passkeys() {
+    let mut app = TestApp::new().await;
+
+    // A passkey of another account.
+    let other_email = get_random_email();
+    app.signup_and_login(&other_email).await;
+    let mut authenticator = new_authenticator();
+    register_passkey(&app, &mut authenticator).await;
+    let (_, credential) = authenticate(&app, &mut authenticator, &other_email).await;
+
+    let without_passkeys = get_random_email();
+    app.signup_and_login(&without_passkeys).await;
+    let unknown = get_random_email();
+
+    let mut codes = Vec::new();
+    for email in [&without_passkeys, &unknown] {
+        let response = app
+            .post_passkey_authenticate_start(&serde_json::json!({ "email": email }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+        let start = response
+            .json::<StartPasskeyAuthenticationResponse>()
+            .await
+            .unwrap();
+        assert!(start.challenge.public_key.allow_credentials.is_empty());
+
+        let response = app
+            .post_login_passkey(&serde_json::json!({
+                "email": email,
+                "state": start.state,
+                "credential": credential,
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+        codes.push(response.json::<ProblemDetails>().await.unwrap().code);
+    }
+    assert_eq!(codes[0], codes[1]);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_login_with_registered_passkey() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+
+    let mut authenticator = new_authenticator();
+    register_passkey(&app, &mut authenticator).await;
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let (state, credential) = authenticate(&app, &mut authenticator, &random_email).await;
+    let response = app
+        .post_login_passkey(&serde_json::json!({
+            "email": random_email,
+            "state": state,
+            "credential": credential,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(!auth_cookie.value().is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_store_the_public_key_of_a_registered_passkey() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+
+    let mut authenticator = new_authenticator();
+    register_passkey(&app, &mut authenticator).await;
+
+    let user_id = app.get_user_id(&random_email).await;
+    let stored = app.passkey_store.get_passkeys(&user_id).await.unwrap();
+    let (credential_id, public_key, sign_count): (Vec<u8>, serde_json::Value, i64) =
+        sqlx::query_as("SELECT credential_id, public_key, sign_count FROM passkeys")
+            .fetch_one(&app.pg_pool)
+            .await
+            .unwrap();
+
+    assert_eq!(credential_id, stored[0].passkey.cred_id().as_ref());
+    assert_eq!(
+        public_key,
+        serde_json::to_value(stored[0].passkey.get_public_key()).unwrap()
+    );
+    assert_eq!(sign_count, 0);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_registration_state_is_replayed() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+
+    let start = app
+        .post_passkey_register_start()
+        .await
+        .json::<StartPasskeyRegistrationResponse>()
+        .await
+        .unwrap();
+    let credential = new_authenticator()
+        .do_registration(origin(&app), start.challenge)
+        .unwrap();
+    let body = serde_json::json!({
+        "state": start.state,
+        "credential": credential,
+    });
+
+    let response = app.post_passkey_register_finish(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_passkey_register_finish(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_assertion_is_replayed() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+
+    let mut authenticator = new_authenticator();
+    register_passkey(&app, &mut authenticator).await;
+
+    let (state, credential) = authenticate(&app, &mut authenticator, &random_email).await;
+    let body = serde_json::json!({
+        "email": random_email,
+        "state": state,
+        "credential": credential,
+    });
+
+    let response = app.post_login_passkey(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login_passkey(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_state_was_issued_for_another_user() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+
+    let mut authenticator = new_authenticator();
+    register_passkey(&app, &mut authenticator).await;
+
+    let (state, credential) = authenticate(&app, &mut authenticator, &random_email).await;
+    let response = app
+        .post_login_passkey(&serde_json::json!({
+            "email": get_random_email(),
+            "state": state,
+            "credential": credential,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_sign_count_regresses() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+
+    let mut authenticator = new_authenticator();
+    register_passkey(&app, &mut authenticator).await;
+
+    // Pretend a cloned authenticator has already been used far more often than this one.
+    let user_id = app.get_user_id(&random_email).await;
+    {
+        let passkey_store = &app.passkey_store;
+        let stored = passkey_store
+            .get_passkeys(&user_id)
+            .await
+            .unwrap()
+            .remove(0);
+        passkey_store
+            .update_passkey(&user_id, &stored.passkey, 100)
+            .await
+            .unwrap();
+    }
+
+    let (state, credential) = authenticate(&app, &mut authenticator, &random_email).await;
+    let response = app
+        .post_login_passkey(&serde_json::json!({
+            "email": random_email,
+            "state": state,
+            "credential": credential,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_satisfy_2fa_with_passkey() {
+    let mut app = TestApp::new().await;
+
+    app.mount_email_server().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let user_id = app.get_user_id(&random_email).await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    // Log in once with the emailed code so a passkey can be registered.
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id;
+    let (_, code) = app.two_fa_code_store.get_code(&user_id).await.unwrap();
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code.as_ref(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let mut authenticator = new_authenticator();
+    register_passkey(&app, &mut authenticator).await;
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id;
+
+    let (state, credential) = authenticate(&app, &mut authenticator, &random_email).await;
+    let response = app
+        .post_verify_2fa_passkey(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "state": state,
+            "credential": credential,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .cookies()
+        .any(|cookie| cookie.name() == JWT_COOKIE_NAME));
+
+    // The emailed code for the finished attempt is no longer valid.
+    assert!(app.two_fa_code_store.get_code(&user_id).await.is_err());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_2fa_passkey_has_wrong_login_attempt_id() {
+    let mut app = TestApp::new().await;
+
+    app.mount_email_server().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+
+    let mut authenticator = new_authenticator();
+    register_passkey(&app, &mut authenticator).await;
+
+    // No login attempt is pending for this user.
+    let (state, credential) = authenticate(&app, &mut authenticator, &random_email).await;
+    let response = app
+        .post_verify_2fa_passkey(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": uuid::Uuid::new_v4().to_string(),
+            "state": state,
+            "credential": credential,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,255 @@
+use auth_service::{
+    domain::FieldError,
+    routes::{AccountResponse, TwoFactorAuthResponse},
+    utils::problem::ProblemDetails,
+};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PHONE_NUMBER: &str = "+4915123456789";
+
+async fn mount_sms_server(app: &TestApp) {
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .mount(&app.sms_server)
+        .await;
+}
+
+// Pulls the 6 digit code out of the last SMS that was sent.
+async fn get_code_from_last_sms(app: &TestApp) -> String {
+    let sms = app.sent_sms().await.pop().expect("No SMS was sent");
+    sms["Body"]
+        .split_whitespace()
+        .next()
+        .expect("The SMS is empty")
+        .to_owned()
+}
+
+// Registers and verifies `PHONE_NUMBER` for the logged in user.
+async fn verify_phone_number(app: &TestApp) {
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let code = get_code_from_last_sms(app).await;
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_phone_number_is_invalid() {
+    let mut app = TestApp::new().await;
+    mount_sms_server(&app).await;
+
+    app.signup_and_login(&get_random_email()).await;
+
+    for phone_number in ["015123456789", "+49 151 23456789", "+49", "phone"] {
+        let response = app
+            .post_phone_number(&serde_json::json!({ "phoneNumber": phone_number }))
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            phone_number
+        );
+        assert_eq!(
+            response
+                .json::<ProblemDetails>()
+                .await
+                .expect("Could not deserialize response body to ProblemDetails")
+                .errors,
+            vec![FieldError::invalid_phone_number("phoneNumber")]
+        );
+    }
+    assert!(app.sent_sms().await.is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_save_the_phone_number_once_the_code_is_entered() {
+    let mut app = TestApp::new().await;
+    mount_sms_server(&app).await;
+
+    app.signup_and_login(&get_random_email()).await;
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let sms = app.sent_sms().await;
+    assert_eq!(sms.len(), 1);
+    assert_eq!(sms[0]["To"], PHONE_NUMBER);
+
+    // Nothing is saved before the code comes back.
+    let account: AccountResponse = app.get_account().await.json().await.unwrap();
+    assert_eq!(account.phone_number, None);
+
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({ "code": "000000" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({ "code": "999999" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let code = get_code_from_last_sms(&app).await;
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let account: AccountResponse = app.get_account().await.json().await.unwrap();
+    assert_eq!(account.phone_number.as_deref(), Some(PHONE_NUMBER));
+    assert_eq!(account.two_fa_channel, "email");
+
+    // A code can only be used once.
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_429_if_a_code_is_requested_again_too_soon() {
+    let mut app = TestApp::new().await;
+    mount_sms_server(&app).await;
+
+    app.signup_and_login(&get_random_email()).await;
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": "+14155552671" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(app.sent_sms().await.len(), 1);
+
+    // The code that was sent still works.
+    let code = get_code_from_last_sms(&app).await;
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_503_if_the_sms_cannot_be_sent() {
+    let mut app = TestApp::new().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.sms_server)
+        .await;
+
+    app.signup_and_login(&get_random_email()).await;
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER }))
+        .await;
+    assert_eq!(response.status().as_u16(), 503);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_409_if_sms_is_chosen_without_a_verified_phone_number() {
+    let mut app = TestApp::new().await;
+
+    app.signup_and_login(&get_random_email()).await;
+
+    let response = app
+        .post_two_fa_channel(&serde_json::json!({ "channel": "sms" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    let response = app
+        .post_two_fa_channel(&serde_json::json!({ "channel": "pigeon" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_the_2fa_code_by_sms_once_chosen() {
+    let mut app = TestApp::new().await;
+    mount_sms_server(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+    verify_phone_number(&app).await;
+
+    let response = app
+        .post_two_fa_channel(&serde_json::json!({ "channel": "sms" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // 2FA is only asked for when the account requires it, so flip that directly.
+    sqlx::query("UPDATE users SET requires_2fa = TRUE WHERE email = $1")
+        .bind(&random_email)
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id;
+
+    let sms = app.sent_sms().await;
+    assert_eq!(sms.len(), 2);
+    assert_eq!(sms[1]["To"], PHONE_NUMBER);
+    let code = get_code_from_last_sms(&app).await;
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
@@ -1,5 +1,9 @@
 use crate::helpers::{get_random_email, TestApp};
-use auth_service::{routes::SignupResponse, ErrorResponse};
+use auth_service::{
+    domain::FieldError,
+    routes::SignupResponse,
+    utils::problem::{ProblemDetails, PROBLEM_JSON},
+};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
@@ -110,17 +114,102 @@ async fn should_return_400_if_invalid_input() {
 
         assert_eq!(
             response
-                .json::<ErrorResponse>()
+                .json::<ProblemDetails>()
                 .await
-                .expect("Could not deserialize response body to ErrorResponse")
-                .error,
-            "Invalid credentials".to_owned()
+                .expect("Could not deserialize response body to ProblemDetails")
+                .code,
+            "request.validation_failed".to_owned()
         );
     }
 
     app.clean_up().await;
 }
 
+#[tokio::test]
+async fn should_return_problem_details_for_every_invalid_field() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/signup", &app.address))
+        .header("x-request-id", "test-request-id")
+        .json(&serde_json::json!({
+            "email": "fooexample.com",
+            "password": "p123",
+            "requires2FA": true
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok()),
+        Some(PROBLEM_JSON)
+    );
+
+    let problem = response
+        .json::<ProblemDetails>()
+        .await
+        .expect("Could not deserialize response body to ProblemDetails");
+    assert_eq!(problem.status, 400);
+    assert_eq!(problem.title, "Bad Request");
+    assert_eq!(problem.code, "request.validation_failed");
+    assert_eq!(problem.request_id.as_deref(), Some("test-request-id"));
+    assert_eq!(
+        problem.errors,
+        vec![
+            FieldError::invalid_email("email"),
+            FieldError::invalid_password("password"),
+        ]
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_problem_details_for_a_malformed_body() {
+    let mut app = TestApp::new().await;
+
+    let test_cases = [
+        // not JSON
+        ("application/json", "{\"email\":", 400),
+        // wrong content type
+        ("text/plain", "{}", 415),
+        // missing fields
+        ("application/json", "{}", 422),
+    ];
+
+    for (content_type, body, expected) in test_cases {
+        let response = app
+            .http_client
+            .post(format!("{}/signup", &app.address))
+            .header("content-type", content_type)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(
+            response.status().as_u16(),
+            expected,
+            "Failed for body: {body}"
+        );
+
+        let problem = response
+            .json::<ProblemDetails>()
+            .await
+            .expect("Could not deserialize response body to ProblemDetails");
+        assert_eq!(problem.status, expected);
+        assert_eq!(problem.code, "request.malformed_body");
+        assert!(problem.request_id.is_some());
+    }
+
+    app.clean_up().await;
+}
+
 #[tokio::test]
 async fn should_return_409_if_email_already_exists() {
     // Call the signup route twice. The second request should fail with a 409 HTTP status code
@@ -139,12 +228,29 @@ async fn should_return_409_if_email_already_exists() {
 
     assert_eq!(
         actual
-            .json::<ErrorResponse>()
+            .json::<ProblemDetails>()
             .await
-            .expect("Could not deserialize response body to ErrorResponse")
-            .error,
-        "User already exists".to_owned()
+            .expect("Could not deserialize response body to ProblemDetails")
+            .code,
+        "auth.user_already_exists".to_owned()
     );
 
     app.clean_up().await;
 }
+
+#[tokio::test]
+async fn should_create_only_one_user_for_concurrent_signups() {
+    let mut app = TestApp::new().await;
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let (first, second) = tokio::join!(app.post_signup(&user), app.post_signup(&user));
+    let mut statuses = [first.status().as_u16(), second.status().as_u16()];
+    statuses.sort();
+    assert_eq!(statuses, [201, 409]);
+
+    app.clean_up().await;
+}
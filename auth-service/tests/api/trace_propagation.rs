@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use auth_service::utils::tracing::{otel_layer, otlp_tracer_provider};
+use opentelemetry_sdk::trace::TracerProvider;
+use tracing_subscriber::prelude::*;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+fn traceparent() -> String {
+    format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID)
+}
+
+// An OTLP/HTTP endpoint that accepts every export, like a collector would.
+async fn start_collector() -> (MockServer, TracerProvider) {
+    let collector = MockServer::start().await;
+    Mock::given(path("/v1/traces"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&collector)
+        .await;
+
+    let tracer_provider = otlp_tracer_provider(&format!("{}/v1/traces", collector.uri()))
+        .expect("Failed to build the tracer provider");
+    (collector, tracer_provider)
+}
+
+// The spans of `TRACE_ID` the collector received. The request span closes only after the
+// response was sent, so this flushes until it shows up.
+async fn exported_spans(
+    collector: &MockServer,
+    tracer_provider: &TracerProvider,
+) -> Vec<serde_json::Value> {
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        tracer_provider.force_flush();
+
+        let spans: Vec<serde_json::Value> = collector
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|request| serde_json::from_slice::<serde_json::Value>(&request.body).ok())
+            .flat_map(|export| {
+                let mut spans = vec![];
+                for resource_spans in export["resourceSpans"].as_array().into_iter().flatten() {
+                    for scope_spans in resource_spans["scopeSpans"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                    {
+                        spans.extend(
+                            scope_spans["spans"]
+                                .as_array()
+                                .into_iter()
+                                .flatten()
+                                .cloned(),
+                        );
+                    }
+                }
+                spans
+            })
+            .filter(|span| span["traceId"] == TRACE_ID)
+            .collect();
+        if !spans.is_empty() {
+            return spans;
+        }
+    }
+    panic!("The collector didn't receive any span of the trace");
+}
+
+#[tokio::test]
+async fn should_export_the_request_span_as_part_of_the_callers_trace() {
+    let (collector, tracer_provider) = start_collector().await;
+    // The test runtime runs the app on this thread, so it records into this subscriber.
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(otel_layer(&tracer_provider)),
+    );
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/", &app.address))
+        .header("traceparent", traceparent())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    // Without an `X-Request-Id` of its own, the caller can find our logs by its trace ID.
+    assert_eq!(
+        response
+            .headers()
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok()),
+        Some(TRACE_ID)
+    );
+
+    let spans = exported_spans(&collector, &tracer_provider).await;
+    assert!(spans
+        .iter()
+        .any(|span| span["name"] == "[REQUEST]" && span["parentSpanId"] == PARENT_SPAN_ID));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_the_callers_request_id() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/", &app.address))
+        .header("traceparent", traceparent())
+        .header("x-request-id", "from-the-caller")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(
+        response
+            .headers()
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok()),
+        Some("from-the-caller")
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_propagate_the_trace_to_postmark() {
+    let (_collector, tracer_provider) = start_collector().await;
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(otel_layer(&tracer_provider)),
+    );
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    assert_eq!(app.post_signup(&signup_body).await.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app
+        .http_client
+        .post(format!("{}/login", &app.address))
+        .header("traceparent", traceparent())
+        .json(&login_body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 206);
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let sent_traceparent = requests
+        .last()
+        .and_then(|request| request.headers.get("traceparent"))
+        .and_then(|value| value.to_str().ok())
+        .expect("The email request didn't carry a traceparent");
+    assert!(sent_traceparent.starts_with(&format!("00-{}-", TRACE_ID)));
+    // Postmark's side hangs off our span, not the caller's.
+    assert!(!sent_traceparent.contains(PARENT_SPAN_ID));
+
+    app.clean_up().await;
+}
@@ -1,12 +1,53 @@
 use auth_service::{
-    domain::{Email, LoginAttemptId, TwoFACode, TwoFACodeStore},
+    domain::{LoginAttemptId, TwoFACode},
     routes::TwoFactorAuthResponse,
-    utils::constants::JWT_COOKIE_NAME,
-    ErrorResponse,
+    utils::{constants::JWT_COOKIE_NAME, problem::ProblemDetails},
 };
 
 use crate::helpers::{get_random_email, TestApp};
 
+// Signs up a 2FA user and logs them in, returning the login attempt id from the response.
+async fn login_with_2fa(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id
+}
+
+async fn setup_2fa_user(app: &TestApp) -> String {
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    app.mount_email_server().await;
+
+    random_email
+}
+
+async fn get_stored_code(app: &TestApp, email: &str) -> TwoFACode {
+    app.two_fa_code_store
+        .get_code(&app.get_user_id(email).await)
+        .await
+        .expect("No 2FA code stored")
+        .1
+}
+
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
     let mut app = TestApp::new().await;
@@ -57,7 +98,6 @@ async fn should_return_422_if_malformed_input() {
 async fn should_return_400_if_invalid_input() {
     let mut app = TestApp::new().await;
 
-    let random_email = get_random_email();
     let login_attempt_id = LoginAttemptId::default().as_ref().to_owned();
 
     let test_cases = [
@@ -94,30 +134,149 @@ async fn should_return_400_if_invalid_input() {
 
 #[tokio::test]
 async fn should_return_401_if_incorrect_credentials() {
-    //TODO:    todo!()
+    let mut app = TestApp::new().await;
+
+    let random_email = setup_2fa_user(&app).await;
+    let login_attempt_id = login_with_2fa(&app, &random_email).await;
+    let code = get_stored_code(&app, &random_email).await;
+
+    let wrong_code = if code.as_ref() == "123456" {
+        "654321"
+    } else {
+        "123456"
+    };
+
+    let test_cases = [
+        // wrong code
+        serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": wrong_code,
+        }),
+        // wrong login attempt id
+        serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": LoginAttemptId::default().as_ref(),
+            "2FACode": code.as_ref(),
+        }),
+    ];
+
+    for test_case in test_cases.iter() {
+        let response = app.post_verify_2fa(test_case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for input: {:?}",
+            test_case
+        );
+        assert_eq!(
+            response
+                .json::<ProblemDetails>()
+                .await
+                .expect("Could not deserialize response body to ProblemDetails")
+                .code,
+            "auth.incorrect_credentials".to_owned()
+        );
+    }
 
-    //app.clean_up().await;
+    app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_401_if_old_code() {
     // Call login twice. Then, attempt to call verify-fa with the 2FA code from the first login requet. This should fail.
-    //TODO:    todo!()
+    let mut app = TestApp::new().await;
+
+    let random_email = setup_2fa_user(&app).await;
+
+    let first_login_attempt_id = login_with_2fa(&app, &random_email).await;
+    let first_code = get_stored_code(&app, &random_email).await;
+
+    let _ = login_with_2fa(&app, &random_email).await;
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": first_login_attempt_id,
+            "2FACode": first_code.as_ref(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
 
-    //app.clean_up().await;
+    app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_200_if_correct_code() {
     // Make sure to assert the auth cookie gets set
-    //TODO: todo!()
+    let mut app = TestApp::new().await;
+
+    let random_email = setup_2fa_user(&app).await;
+    let login_attempt_id = login_with_2fa(&app, &random_email).await;
+    let code = get_stored_code(&app, &random_email).await;
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code.as_ref(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
 
-    //app.clean_up().await;
+    assert!(!auth_cookie.value().is_empty());
+
+    app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_401_if_same_code_twice() {
-    //TODO: todo!()
+    let mut app = TestApp::new().await;
+
+    let random_email = setup_2fa_user(&app).await;
+    let login_attempt_id = login_with_2fa(&app, &random_email).await;
+    let code = get_stored_code(&app, &random_email).await;
+
+    let body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code.as_ref(),
+    });
 
-    //app.clean_up().await;
+    let response = app.post_verify_2fa(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_verify_2fa(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_accept_a_code_only_once_when_used_concurrently() {
+    let mut app = TestApp::new().await;
+
+    let random_email = setup_2fa_user(&app).await;
+    let login_attempt_id = login_with_2fa(&app, &random_email).await;
+    let code = get_stored_code(&app, &random_email).await;
+
+    let body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code.as_ref(),
+    });
+
+    let (first, second) = tokio::join!(app.post_verify_2fa(&body), app.post_verify_2fa(&body));
+    let mut statuses = [first.status().as_u16(), second.status().as_u16()];
+    statuses.sort();
+    assert_eq!(statuses, [200, 401]);
+
+    app.clean_up().await;
 }
@@ -1,8 +1,7 @@
 use crate::helpers::{get_random_email, TestApp};
-use auth_service::domain::Email;
+use auth_service::domain::UserId;
 use auth_service::utils::auth::generate_auth_cookie;
-use auth_service::{utils::constants::JWT_COOKIE_NAME, ErrorResponse};
-use secrecy::{ExposeSecret, Secret};
+use auth_service::utils::{constants::JWT_COOKIE_NAME, problem::ProblemDetails};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
@@ -24,9 +23,7 @@ async fn should_return_200_valid_token() {
     let expected = 200;
     let mut app = TestApp::new().await;
 
-    let auth_cookie =
-        generate_auth_cookie(&Email::parse(Secret::new("foo@example.com".to_string())).unwrap())
-            .unwrap();
+    let auth_cookie = generate_auth_cookie(&UserId::default(), &app.settings.jwt).unwrap();
 
     let verify_token_body = serde_json::json!({
         "token": auth_cookie.value()
@@ -45,10 +42,6 @@ async fn should_return_401_if_invalid_token() {
     let expected = 401;
     let mut app = TestApp::new().await;
 
-    let auth_cookie =
-        generate_auth_cookie(&Email::parse(Secret::new("foo@example.com".to_string())).unwrap())
-            .unwrap();
-
     let verify_token_body = serde_json::json!({
         "token": "a_bad_token"
     });
@@ -111,11 +104,11 @@ async fn should_return_401_if_banned_token() {
 
     assert_eq!(
         response
-            .json::<ErrorResponse>()
+            .json::<ProblemDetails>()
             .await
-            .expect("Could not deserialize response body to ErrorResponse")
-            .error,
-        "Invalid auth token".to_owned()
+            .expect("Could not deserialize response body to ProblemDetails")
+            .code,
+        "auth.invalid_token".to_owned()
     );
 
     app.clean_up().await;
@@ -0,0 +1,457 @@
+use auth_service::{
+    domain::{sign_webhook_payload, Email, TwoFACode, WEBHOOK_MAX_ATTEMPTS},
+    routes::{
+        CreateWebhookSubscriptionResponse, TwoFactorAuthResponse, WebhookDeliveriesResponse,
+        WebhookDeliveryResponse,
+    },
+    services::account_purger::purge_deleted_accounts,
+    services::webhooks::{
+        WEBHOOK_EVENT_HEADER, WEBHOOK_SIGNATURE_HEADER, WEBHOOK_TIMESTAMP_HEADER,
+    },
+    utils::{
+        auth::generate_email_change_confirm_token, constants::ACCOUNT_DELETION_GRACE_PERIOD_DAYS,
+    },
+};
+use chrono::Utc;
+use secrecy::Secret;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, Request, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn subscribe(app: &TestApp, event_types: &[&str]) -> CreateWebhookSubscriptionResponse {
+    let response = app
+        .post_webhook_subscription(&serde_json::json!({
+            "url": format!("{}/hooks", app.webhook_server.uri()),
+            "eventTypes": event_types,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    response
+        .json::<CreateWebhookSubscriptionResponse>()
+        .await
+        .expect("Could not deserialize response body to CreateWebhookSubscriptionResponse")
+}
+
+async fn get_deliveries(app: &TestApp, query: &str) -> Vec<WebhookDeliveryResponse> {
+    let response = app.get_webhook_deliveries(query).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<WebhookDeliveriesResponse>()
+        .await
+        .expect("Could not deserialize response body to WebhookDeliveriesResponse")
+        .deliveries
+}
+
+// Checks the signature header the way a subscriber would.
+struct SignatureMatcher(Secret<String>);
+
+impl wiremock::Match for SignatureMatcher {
+    fn matches(&self, request: &Request) -> bool {
+        let header = |name: &str| {
+            request
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let (Some(timestamp), Some(signature)) = (
+            header(WEBHOOK_TIMESTAMP_HEADER).and_then(|value| value.parse().ok()),
+            header(WEBHOOK_SIGNATURE_HEADER),
+        ) else {
+            return false;
+        };
+        let Ok(payload) = std::str::from_utf8(&request.body) else {
+            return false;
+        };
+
+        signature
+            == format!(
+                "sha256={}",
+                sign_webhook_payload(&self.0, timestamp, payload)
+            )
+    }
+}
+
+#[tokio::test]
+async fn should_create_list_and_delete_subscriptions() {
+    let mut app = TestApp::new().await;
+
+    let created = subscribe(&app, &["user.signed_up", "user.deleted"]).await;
+    assert!(created.secret.starts_with("whsec_"));
+    assert_eq!(
+        created.subscription.event_types,
+        vec!["user.signed_up", "user.deleted"]
+    );
+
+    let response = app.get_webhook_subscriptions().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let subscriptions = body["subscriptions"].as_array().unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0]["id"], created.subscription.id.to_string());
+    // The secret is only shown once.
+    assert!(subscriptions[0].get("secret").is_none());
+
+    let id = created.subscription.id.to_string();
+    let response = app.delete_webhook_subscription(&id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.delete_webhook_subscription(&id).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_subscription_is_invalid() {
+    let mut app = TestApp::new().await;
+
+    let test_cases = [
+        serde_json::json!({ "url": "not a url", "eventTypes": ["user.signed_up"] }),
+        serde_json::json!({ "url": "ftp://example.com/hooks", "eventTypes": ["user.signed_up"] }),
+        serde_json::json!({ "url": "https://example.com/hooks", "eventTypes": ["user.unknown"] }),
+        serde_json::json!({ "url": "https://example.com/hooks", "eventTypes": [] }),
+    ];
+
+    for test_case in test_cases.iter() {
+        let response = app.post_webhook_subscription(test_case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            test_case
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_the_admin_token() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/admin/webhooks", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .http_client
+        .get(format!("{}/admin/webhooks/deliveries", &app.address))
+        .bearer_auth("wrong-token")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_deliver_signed_events() {
+    let mut app = TestApp::new().await;
+
+    let subscription = subscribe(&app, &["user.signed_up"]).await;
+
+    Mock::given(path("/hooks"))
+        .and(method("POST"))
+        .and(SignatureMatcher(Secret::new(subscription.secret.clone())))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.webhook_server)
+        .await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, false).await;
+    let user_id = app.get_user_id(&random_email).await;
+
+    assert_eq!(app.deliver_webhooks(Utc::now()).await, 1);
+
+    let requests = app.webhook_server.received_requests().await.unwrap();
+    assert_eq!(
+        requests[0].headers.get(WEBHOOK_EVENT_HEADER).unwrap(),
+        "user.signed_up"
+    );
+    let payload: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(payload["type"], "user.signed_up");
+    assert_eq!(payload["data"]["userId"], user_id.to_string());
+    assert_eq!(payload["data"]["email"], random_email);
+
+    let deliveries = get_deliveries(&app, "").await;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].status, "delivered");
+    assert_eq!(deliveries[0].attempts, 1);
+
+    // Nothing is sent twice.
+    assert_eq!(app.deliver_webhooks(Utc::now()).await, 0);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_deliver_subscribed_events() {
+    let mut app = TestApp::new().await;
+
+    subscribe(&app, &["user.deleted"]).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.webhook_server)
+        .await;
+
+    app.signup(&get_random_email(), false).await;
+
+    assert_eq!(app.deliver_webhooks(Utc::now()).await, 0);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_retry_with_backoff_then_dead_letter() {
+    let mut app = TestApp::new().await;
+
+    subscribe(&app, &["user.signed_up"]).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.webhook_server)
+        .await;
+
+    app.signup(&get_random_email(), false).await;
+
+    let now = Utc::now();
+    assert_eq!(app.deliver_webhooks(now).await, 1);
+
+    let deliveries = get_deliveries(&app, "status=pending").await;
+    assert_eq!(deliveries[0].attempts, 1);
+    assert!(deliveries[0].next_attempt_at > now);
+    assert!(deliveries[0].last_error.is_some());
+
+    // Not due again yet.
+    assert_eq!(app.deliver_webhooks(now).await, 0);
+
+    let mut later = now;
+    for _ in 1..WEBHOOK_MAX_ATTEMPTS {
+        later += chrono::Duration::try_days(1).unwrap();
+        assert_eq!(app.deliver_webhooks(later).await, 1);
+    }
+
+    let dead = get_deliveries(&app, "status=dead").await;
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].attempts, WEBHOOK_MAX_ATTEMPTS);
+
+    // Dead deliveries are left alone.
+    later += chrono::Duration::try_days(1).unwrap();
+    assert_eq!(app.deliver_webhooks(later).await, 0);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_replay_dead_deliveries() {
+    let mut app = TestApp::new().await;
+
+    subscribe(&app, &["user.signed_up"]).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.webhook_server)
+        .await;
+
+    app.signup(&get_random_email(), false).await;
+
+    let mut now = Utc::now();
+    for _ in 0..WEBHOOK_MAX_ATTEMPTS {
+        app.deliver_webhooks(now).await;
+        now += chrono::Duration::try_days(1).unwrap();
+    }
+    let dead = get_deliveries(&app, "status=dead").await;
+    assert_eq!(dead.len(), 1);
+
+    // The subscriber is back up.
+    app.webhook_server.reset().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.webhook_server)
+        .await;
+
+    let response = app
+        .post_replay_webhook_delivery(&dead[0].id.to_string())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(app.deliver_webhooks(Utc::now()).await, 1);
+
+    let delivered = get_deliveries(&app, "status=delivered").await;
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].id, dead[0].id);
+    assert_eq!(delivered[0].attempts, 1);
+
+    let response = app
+        .post_replay_webhook_delivery(&uuid::Uuid::new_v4().to_string())
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_publish_account_lifecycle_events() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+
+    subscribe(
+        &app,
+        &[
+            "user.signed_up",
+            "user.verified",
+            "user.email_changed",
+            "user.deleted",
+            "user.restored",
+        ],
+    )
+    .await;
+
+    let random_email = get_random_email();
+    app.signup(&random_email, true).await;
+    let user_id = app.get_user_id(&random_email).await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id;
+    let code: TwoFACode = app.two_fa_code_store.get_code(&user_id).await.unwrap().1;
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code.as_ref(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let new_email = get_random_email();
+    let token = generate_email_change_confirm_token(
+        &user_id,
+        &Email::parse(Secret::new(new_email.clone())).unwrap(),
+        &uuid::Uuid::new_v4().to_string(),
+        &app.settings.jwt,
+    )
+    .unwrap();
+    let response = app
+        .post_confirm_email_change(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": new_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id;
+    let code: TwoFACode = app.two_fa_code_store.get_code(&user_id).await.unwrap().1;
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": new_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code.as_ref(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_restore_account(&serde_json::json!({
+            "email": new_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let mut event_types: Vec<String> = get_deliveries(&app, "")
+        .await
+        .into_iter()
+        .map(|delivery| delivery.event_type)
+        .collect();
+    event_types.sort();
+    assert_eq!(
+        event_types,
+        vec![
+            "user.deleted",
+            "user.email_changed",
+            "user.restored",
+            "user.signed_up",
+            "user.verified",
+        ]
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_redact_deliveries_of_purged_accounts() {
+    let mut app = TestApp::new().await;
+    app.mount_email_server().await;
+    subscribe(&app, &["user.signed_up"]).await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email).await;
+    let user_id = app.get_user_id(&random_email).await;
+    let other_email = get_random_email();
+    app.signup(&other_email, false).await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let after_grace_period =
+        Utc::now() + chrono::Duration::try_days(ACCOUNT_DELETION_GRACE_PERIOD_DAYS + 1).unwrap();
+    let purged = purge_deleted_accounts(
+        &app.user_store,
+        &app.audit_log_store,
+        &app.email_outbox_store,
+        &app.webhook_store,
+        after_grace_period,
+    )
+    .await
+    .unwrap();
+    assert_eq!(purged, vec![user_id]);
+
+    let deliveries = get_deliveries(&app, "").await;
+    assert_eq!(deliveries.len(), 2);
+    for delivery in deliveries {
+        let payload: serde_json::Value = serde_json::from_str(&delivery.payload).unwrap();
+        if payload["data"]["userId"] == user_id.to_string() {
+            assert!(payload["data"]["email"].is_null());
+        } else {
+            assert_eq!(payload["data"]["email"], other_email);
+        }
+    }
+
+    app.clean_up().await;
+}